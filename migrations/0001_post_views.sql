@@ -0,0 +1,9 @@
+-- Embeds served per post and UTC day (days since the Unix epoch).
+CREATE TABLE IF NOT EXISTS post_views (
+    post_id TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (post_id, day)
+);
+
+CREATE INDEX IF NOT EXISTS post_views_by_day ON post_views (day, count DESC);
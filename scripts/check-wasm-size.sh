@@ -0,0 +1,30 @@
+#!/bin/sh
+# Builds the release worker and fails if the WASM bundle exceeds the size budget.
+#
+# Usage: scripts/check-wasm-size.sh [--no-build]
+# Budget (gzipped bytes) can be overridden with WASM_GZIP_BUDGET.
+set -eu
+
+cd "$(dirname "$0")/.."
+
+BUDGET="${WASM_GZIP_BUDGET:-1048576}" # 1 MiB, the free-plan Worker limit
+
+if [ "${1:-}" != "--no-build" ]; then
+    worker-build --release
+fi
+
+WASM="$(find build -name '*.wasm' | head -n 1)"
+if [ -z "$WASM" ]; then
+    echo "no .wasm found under build/ — run worker-build first" >&2
+    exit 1
+fi
+
+RAW="$(wc -c < "$WASM" | tr -d ' ')"
+GZIP="$(gzip -9 -c "$WASM" | wc -c | tr -d ' ')"
+
+echo "$WASM: ${RAW} bytes raw, ${GZIP} bytes gzipped (budget ${BUDGET})"
+
+if [ "$GZIP" -gt "$BUDGET" ]; then
+    echo "WASM bundle is over budget by $((GZIP - BUDGET)) bytes" >&2
+    exit 1
+fi
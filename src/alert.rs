@@ -0,0 +1,199 @@
+//! Operator alerts posted to a Discord or Slack webhook (`ALERT_WEBHOOK_URL`).
+//!
+//! Each isolate tracks how long every post scrape has been failing. Once that
+//! streak passes `FAILURE_WINDOW_SECS`, or a re-check finds `IG_COOKIE` logged
+//! out, an alert is posted from `wait_until`. The session object
+//! (`crate::session`) also alerts when it flags the session. A KV marker per
+//! alert kind keeps the many isolates of one deployment from posting the same
+//! alert repeatedly.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::json;
+use worker::*;
+
+use crate::scraper::cache::{alert_recently_sent, mark_alert_sent};
+use crate::scraper::http::{HttpClient, HttpRequest};
+use crate::scraper::instrument::instrumented;
+use crate::scraper::papi::check_cookie;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// How long every scrape must keep failing before `BackendsDown` fires.
+pub const FAILURE_WINDOW_SECS: u64 = 5 * 60;
+
+/// Minimum gap between two posts of the same alert kind.
+const ALERT_COOLDOWN_SECS: u64 = 60 * 60;
+
+/// Minimum gap between cookie checks in one isolate; each check is a live request.
+const COOKIE_CHECK_INTERVAL_SECS: u64 = 10 * 60;
+
+/// Unix time of the first failure since the last success, or 0 when healthy.
+static FAILING_SINCE: AtomicU64 = AtomicU64::new(0);
+
+/// Unix time of this isolate's last cookie check.
+static LAST_COOKIE_CHECK: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alert {
+    /// Every backend has been failing for `FAILURE_WINDOW_SECS` or more.
+    BackendsDown,
+    /// `IG_COOKIE` is configured but no longer logged in.
+    CookieDead,
+    /// Instagram is holding the `IG_COOKIE` account at a challenge or checkpoint.
+    SessionChallenge,
+}
+
+impl Alert {
+    /// Name used for the KV cooldown marker.
+    pub fn name(self) -> &'static str {
+        match self {
+            Alert::BackendsDown => "backends_down",
+            Alert::CookieDead => "cookie_dead",
+            Alert::SessionChallenge => "session_challenge",
+        }
+    }
+
+    pub fn message(self, site_name: &str, failing_secs: u64) -> String {
+        match self {
+            Alert::BackendsDown => format!(
+                "🚨 {site_name}: every scraper backend has been failing for {} minutes. Embeds are redirecting to Instagram.",
+                failing_secs / 60
+            ),
+            Alert::CookieDead => format!(
+                "🍪 {site_name}: IG_COOKIE is no longer logged in. The Private API backend is down until it's replaced."
+            ),
+            Alert::SessionChallenge => format!(
+                "🔒 {site_name}: Instagram wants the IG_COOKIE account to pass a challenge. Resolve it in the Instagram app; the cookie is benched until then."
+            ),
+        }
+    }
+}
+
+/// The start of the failure streak after one more scrape. 0 means healthy.
+fn streak_start(failing_since: u64, succeeded: bool, now_secs: u64) -> u64 {
+    match (succeeded, failing_since) {
+        (true, _) => 0,
+        (false, 0) => now_secs,
+        (false, since) => since,
+    }
+}
+
+/// Webhook body understood by both Discord (`content`) and Slack (`text`).
+fn payload(message: &str) -> String {
+    json!({ "content": message, "text": message }).to_string()
+}
+
+/// Posts one message to the webhook.
+pub async fn post_alert<C: HttpClient>(client: &C, webhook_url: &str, message: &str) -> Result<()> {
+    let request = HttpRequest::post(webhook_url, payload(message)).header("Content-Type", "application/json");
+    let resp = client.send(request).await?;
+    if !(200..300).contains(&resp.status) {
+        return Err(Error::RustError(format!("webhook returned {}", resp.status)));
+    }
+    Ok(())
+}
+
+/// Posts an alert, unless one of its kind went out within the cooldown.
+pub async fn send_alert(alert: Alert, env: &Env, webhook_url: &str, message: &str) {
+    if alert_recently_sent(alert.name(), env).await.unwrap_or(false) {
+        return;
+    }
+    // Marked first, so a slow webhook can't let other isolates double-post
+    if let Err(e) = mark_alert_sent(alert.name(), env, ALERT_COOLDOWN_SECS).await {
+        console_log!("[alert] cooldown write failed: {:?}", e);
+    }
+    console_log!("[alert] sending {}", alert.name());
+    if let Err(e) = post_alert(&instrumented(env), webhook_url, message).await {
+        console_log!("[alert] webhook failed: {:?}", e);
+    }
+}
+
+/// Records the outcome of a post scrape, and schedules any alert it triggers.
+///
+/// A no-op without `ALERT_WEBHOOK_URL`. Note that a post that no longer exists
+/// also counts as a failure; one success anywhere resets the streak.
+pub fn record_scrape(succeeded: bool, env: &Env, state: &AppState, now_secs: u64) {
+    let config = &state.config;
+    let Some(webhook_url) = config.alert_webhook_url.clone() else {
+        return;
+    };
+
+    let since = streak_start(FAILING_SINCE.load(Ordering::Relaxed), succeeded, now_secs);
+    FAILING_SINCE.store(since, Ordering::Relaxed);
+    if succeeded {
+        return;
+    }
+
+    let failing_secs = now_secs - since;
+    let backends_down = failing_secs >= FAILURE_WINDOW_SECS;
+    let cookie = config.cookie.clone().filter(|_| config.papi_enabled).filter(|_| {
+        let last = LAST_COOKIE_CHECK.load(Ordering::Relaxed);
+        now_secs.saturating_sub(last) >= COOKIE_CHECK_INTERVAL_SECS
+            && LAST_COOKIE_CHECK.compare_exchange(last, now_secs, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+    });
+    if !backends_down && cookie.is_none() {
+        return;
+    }
+
+    let env = env.clone();
+    let site_name = config.site_name.clone();
+    state.ctx.wait_until(async move {
+        let mut alerts = Vec::new();
+        if backends_down {
+            alerts.push(Alert::BackendsDown);
+        }
+        if let Some(cookie) = cookie {
+            match check_cookie(&instrumented(&env), &cookie).await {
+                Ok(false) => alerts.push(Alert::CookieDead),
+                Ok(true) => {}
+                Err(e) => console_log!("[alert] cookie check failed: {:?}", e),
+            }
+        }
+
+        for alert in alerts {
+            send_alert(alert, &env, &webhook_url, &alert.message(&site_name, failing_secs)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn streak_starts_on_first_failure_and_resets_on_success() {
+        let since = streak_start(0, false, 1000);
+        assert_eq!(since, 1000);
+        assert_eq!(streak_start(since, false, 1400), 1000);
+        assert_eq!(streak_start(since, true, 1500), 0);
+    }
+
+    #[test]
+    fn messages_name_the_site_and_duration() {
+        let message = Alert::BackendsDown.message("Cattgram", 420);
+        assert!(message.contains("Cattgram"));
+        assert!(message.contains("7 minutes"));
+        assert!(Alert::CookieDead.message("Cattgram", 0).contains("IG_COOKIE"));
+        assert!(Alert::SessionChallenge.message("Cattgram", 0).contains("challenge"));
+    }
+
+    #[test]
+    fn posts_discord_and_slack_compatible_json() {
+        let client = FixtureClient::new().route("hooks.example.com", 204, "");
+        block_on(post_alert(&client, "https://hooks.example.com/abc", "down")).unwrap();
+
+        let requests = client.requests.borrow();
+        let body: serde_json::Value = serde_json::from_str(requests[0].body.as_deref().unwrap()).unwrap();
+        assert_eq!(body["content"], "down");
+        assert_eq!(body["text"], "down");
+        assert_eq!(requests[0].header_value("content-type"), Some("application/json"));
+    }
+
+    #[test]
+    fn webhook_errors_are_reported() {
+        let client = FixtureClient::new().route("hooks.example.com", 400, "invalid payload");
+        assert!(block_on(post_alert(&client, "https://hooks.example.com/abc", "down")).is_err());
+    }
+}
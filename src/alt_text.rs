@@ -0,0 +1,120 @@
+//! Image descriptions for captionless posts.
+//!
+//! A post with neither a caption nor Instagram's `accessibility_caption`
+//! leaves the embed with nothing to say and the image with no alt text.
+//! With `AI_ALT_TEXT` set, the selected image is described by a Workers AI
+//! image-to-text model instead. Needs the same `AI` binding as translation.
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::scraper::cache::{self, set_background, Kind};
+use crate::scraper::instrument::instrumented;
+use crate::templates::embed_html::truncate;
+use crate::translate::BINDING;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+const MODEL: &str = "@cf/llava-hf/llava-1.5-7b-hf";
+
+const PROMPT: &str = "Describe this image in one sentence, as alt text for someone who can't see it.";
+
+/// Enough for a sentence; alt text is meant to be short.
+const MAX_TOKENS: u32 = 64;
+
+/// Longest description kept.
+const MAX_ALT_LEN: usize = 200;
+
+/// Larger images are left undescribed rather than sent to the model.
+const MAX_IMAGE_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Serialize)]
+struct DescribeInput<'a> {
+    /// The image file's bytes, which the model takes as an array of numbers.
+    image: Vec<u8>,
+    prompt: &'a str,
+    max_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct DescribeOutput {
+    description: String,
+}
+
+/// Tidies the model's reply into a single line. `None` if nothing is left.
+fn clean(description: &str) -> Option<String> {
+    let line = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!line.is_empty()).then(|| truncate(&line, MAX_ALT_LEN))
+}
+
+/// Returns `true` if a response's declared length rules its body out.
+/// Without a `Content-Length`, the body is checked once read.
+fn too_large(content_length: Option<&str>) -> bool {
+    content_length
+        .and_then(|length| length.trim().parse::<usize>().ok())
+        .is_some_and(|length| length > MAX_IMAGE_BYTES)
+}
+
+async fn fetch_image(url: &str, env: &Env) -> Result<Option<Vec<u8>>> {
+    let url = Url::parse(url).map_err(|e| Error::RustError(e.to_string()))?;
+    let mut resp = instrumented(env).fetch(Fetch::Url(url)).await?;
+    if resp.status_code() != 200 || too_large(resp.headers().get("Content-Length")?.as_deref()) {
+        return Ok(None);
+    }
+    let bytes = resp.bytes().await?;
+    Ok((bytes.len() <= MAX_IMAGE_BYTES).then_some(bytes))
+}
+
+/// A description of the image at `image_url`, slide `slide` (1-based) of a
+/// post, or `None` if it couldn't be made. Pass the smallest rendition
+/// there is: the model doesn't need the full-size image.
+pub async fn describe_image(post_id: &str, slide: usize, image_url: &str, env: &Env, state: &AppState) -> Option<String> {
+    let ai = env.ai(BINDING).ok()?;
+
+    match cache::get::<String>(Kind::Alt, &format!("{post_id}:{slide}"), env).await {
+        Ok(Some(cached)) => return Some(cached),
+        Ok(None) => {}
+        Err(e) => console_log!("[alt_text] cache read failed: {:?}", e),
+    }
+
+    let image = match fetch_image(image_url, env).await {
+        Ok(Some(image)) => image,
+        Ok(None) => return None,
+        Err(e) => {
+            console_log!("[alt_text] image fetch failed for {}: {:?}", post_id, e);
+            return None;
+        }
+    };
+    let input = DescribeInput { image, prompt: PROMPT, max_tokens: MAX_TOKENS };
+    match ai.run::<_, DescribeOutput>(MODEL, input).await {
+        Ok(output) => {
+            let alt = clean(&output.description)?;
+            set_background(Kind::Alt, &format!("{post_id}:{slide}"), &alt, env, state);
+            Some(alt)
+        }
+        Err(e) => {
+            console_log!("[alt_text] description failed for {}: {:?}", post_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_are_tidied_into_one_line() {
+        assert_eq!(clean(" A cat\n sleeping on a  sofa. ").as_deref(), Some("A cat sleeping on a sofa."));
+        assert_eq!(clean("\n"), None);
+        assert!(clean(&"cat ".repeat(100)).unwrap().len() <= MAX_ALT_LEN + 3);
+    }
+
+    #[test]
+    fn oversized_images_are_refused_by_their_declared_length() {
+        assert!(too_large(Some("5000000")));
+        assert!(!too_large(Some("120000")));
+        assert!(!too_large(None));
+        assert!(!too_large(Some("junk")));
+    }
+}
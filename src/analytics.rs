@@ -0,0 +1,84 @@
+//! Per-post request counts in D1, for the `/admin/top-posts` report.
+//!
+//! Embeds served are counted per post and UTC day. Each isolate buffers its
+//! counts (see `utils::buffer`) and flushes them as one D1 batch of upserts.
+//!
+//! Everything is a no-op unless the `ANALYTICS` D1 database is bound.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use worker::wasm_bindgen::JsValue;
+use worker::*;
+
+use crate::auth::usage_day;
+use crate::utils::buffer::{self, CountBuffer};
+use crate::utils::log::console_log;
+
+/// The D1 binding; see `migrations/` for its schema.
+pub const BINDING: &str = "ANALYTICS";
+
+/// Flush once this many embeds are buffered. A batch has at most this many
+/// upserts.
+const FLUSH_AFTER_VIEWS: u64 = 50;
+
+const UPSERT: &str = "INSERT INTO post_views (post_id, day, count) VALUES (?1, ?2, ?3) \
+    ON CONFLICT (post_id, day) DO UPDATE SET count = count + excluded.count";
+
+const TOP_POSTS: &str = "SELECT post_id, count FROM post_views WHERE day = ?1 ORDER BY count DESC LIMIT ?2";
+
+/// One row of the top posts report.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PostViews {
+    pub post_id: String,
+    pub count: u64,
+}
+
+thread_local! {
+    static BUFFER: RefCell<CountBuffer<(String, u64), u64>> = const { RefCell::new(CountBuffer::new(FLUSH_AFTER_VIEWS)) };
+}
+
+/// Counts one embed served for `post_id`, flushing the isolate's buffer to
+/// D1 in the background when it's due.
+pub fn record_view(post_id: &str, env: &Env, ctx: &Context, now_secs: u64) {
+    let Ok(db) = env.d1(BINDING) else {
+        return;
+    };
+    let key = (post_id.to_string(), usage_day(now_secs));
+    let rows = buffer::record(&BUFFER, key, now_secs, |count| *count += 1);
+    if let Some(rows) = rows {
+        ctx.wait_until(async move {
+            if let Err(e) = flush(&db, rows).await {
+                console_log!("[analytics] flush failed: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn flush(db: &D1Database, rows: BTreeMap<(String, u64), u64>) -> Result<()> {
+    let statements = rows
+        .into_iter()
+        .map(|((post_id, day), count)| {
+            db.prepare(UPSERT)
+                .bind(&[post_id.into(), JsValue::from(day as f64), JsValue::from(count as f64)])
+        })
+        .collect::<Result<Vec<_>>>()?;
+    db.batch(statements).await?;
+    Ok(())
+}
+
+/// The most-requested posts on the UTC day containing `now_secs`, busiest
+/// first. `None` when no `ANALYTICS` database is bound.
+pub async fn top_posts(env: &Env, now_secs: u64, limit: u32) -> Result<Option<Vec<PostViews>>> {
+    let Ok(db) = env.d1(BINDING) else {
+        return Ok(None);
+    };
+    let day = usage_day(now_secs);
+    let result = db
+        .prepare(TOP_POSTS)
+        .bind(&[JsValue::from(day as f64), JsValue::from(limit)])?
+        .all()
+        .await?;
+    Ok(Some(result.results()?))
+}
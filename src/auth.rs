@@ -0,0 +1,239 @@
+//! Optional API keys for the JSON API.
+//!
+//! With `REQUIRE_API_KEY` set, `/api/*` and `/links/*` need a key that exists
+//! in KV under `apikey:<key>`. Embeds, media proxies and oEmbed stay public:
+//! chat apps fetch those and can't send keys.
+//!
+//! Each keyed request is counted per UTC day, and keys with a `daily_quota`
+//! are refused once they reach it. Counts are exact with the `QUOTAS`
+//! Durable Object bound (see `crate::quota`). Without it they are kept in
+//! KV, read then written, so concurrent requests can undercount and a quota
+//! is only a budget.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+use worker::*;
+
+use crate::config::Config;
+use crate::quota;
+use crate::scraper::cache::{get_api_key, get_api_usage, set_api_usage_background};
+use crate::utils::log::console_log;
+
+/// Path prefixes that need a key when keys are required.
+const PROTECTED_PREFIXES: [&str; 2] = ["/api/", "/links/"];
+
+/// Usage report route. Not counted or quota-limited, so a key that ran out
+/// can still see why.
+pub const USAGE_PATH: &str = "/api/usage";
+
+const SECS_PER_DAY: u64 = 86400;
+
+/// Per-key metadata, stored as the KV value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// Who the key was issued to; only used in logs.
+    pub name: String,
+    /// Revokes the key without deleting its record.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Requests allowed per UTC day; unlimited when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_quota: Option<u64>,
+}
+
+/// A key's usage for the current UTC day, as served by `/api/usage`.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub name: String,
+    pub used: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_quota: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<u64>,
+    /// Unix time (seconds) of the next UTC midnight, when `used` resets.
+    pub resets_at: u64,
+}
+
+impl Usage {
+    pub fn new(api_key: &ApiKey, used: u64, now_secs: u64) -> Self {
+        Self {
+            name: api_key.name.clone(),
+            used,
+            daily_quota: api_key.daily_quota,
+            remaining: api_key.daily_quota.map(|q| q.saturating_sub(used)),
+            resets_at: next_reset(now_secs),
+        }
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+/// Days since the Unix epoch; usage counters roll over at UTC midnight.
+pub fn usage_day(now_secs: u64) -> u64 {
+    now_secs / SECS_PER_DAY
+}
+
+fn next_reset(now_secs: u64) -> u64 {
+    (usage_day(now_secs) + 1) * SECS_PER_DAY
+}
+
+/// Returns `true` if a (normalized) path is behind the API key check.
+pub fn is_protected(path: &str) -> bool {
+    PROTECTED_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Reads the key from the `X-Api-Key` header, or the `api_key` query
+/// parameter for clients that can't set headers.
+pub fn extract_key(header: Option<String>, url: &Url) -> Option<String> {
+    header
+        .or_else(|| url.query_pairs().find(|(k, _)| k == "api_key").map(|(_, v)| v.into_owned()))
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+}
+
+/// Looks up the request's API key.
+///
+/// Returns the key and its metadata, or the response to send instead (401
+/// without a key, 403 for an unknown or disabled one).
+pub async fn authenticate(
+    req: &Request,
+    env: &Env,
+) -> Result<std::result::Result<(String, ApiKey), Response>> {
+    let url = req.url()?;
+    let Some(key) = extract_key(req.headers().get("X-Api-Key")?, &url) else {
+        return Response::error("API key required", 401).map(Err);
+    };
+
+    match get_api_key(&key, env).await {
+        Ok(Some(api_key)) if !api_key.disabled => Ok(Ok((key, api_key))),
+        Ok(_) => Response::error("Invalid API key", 403).map(Err),
+        Err(e) => {
+            console_log!("[auth] key lookup failed: {:?}", e);
+            Response::error("API key check unavailable", 503).map(Err)
+        }
+    }
+}
+
+/// Reads today's request count for a key; a failed read counts as zero.
+pub async fn current_usage(key: &str, env: &Env, now_secs: u64) -> u64 {
+    match quota::used(key, usage_day(now_secs), env).await {
+        Some(used) => used,
+        None => kv_usage(key, env, now_secs).await,
+    }
+}
+
+/// The KV count, kept when `QUOTAS` isn't bound.
+async fn kv_usage(key: &str, env: &Env, now_secs: u64) -> u64 {
+    match get_api_usage(key, usage_day(now_secs), env).await {
+        Ok(used) => used.unwrap_or(0),
+        Err(e) => {
+            console_log!("[auth] usage read failed: {:?}", e);
+            0
+        }
+    }
+}
+
+/// Checks the request's API key and quota, and counts the request.
+///
+/// Returns the response to send instead of the handler (see `authenticate`, plus
+/// 429 once the daily quota is used up), or `None` to let the request through.
+pub async fn authorize(
+    req: &Request,
+    env: &Env,
+    ctx: &Context,
+    config: &Config,
+    now_secs: u64,
+) -> Result<Option<Response>> {
+    let url = req.url()?;
+    if !config.api_keys_required || !is_protected(url.path()) {
+        return Ok(None);
+    }
+
+    let (key, api_key) = match authenticate(req, env).await? {
+        Ok(found) => found,
+        Err(denied) => return Ok(Some(denied)),
+    };
+    if url.path() == USAGE_PATH {
+        return Ok(None);
+    }
+
+    let day = usage_day(now_secs);
+    let counted = quota::take(&key, day, api_key.daily_quota, env).await;
+    let used = match counted {
+        Some(used) => used,
+        None => kv_usage(&key, env, now_secs).await,
+    };
+    let usage = Usage::new(&api_key, used, now_secs);
+    console_log!("[auth] {} {} used={}", api_key.name, url.path(), used);
+    if usage.exhausted() {
+        let resp = Response::error("Daily quota exceeded", 429)?;
+        resp.headers().set("Retry-After", &(usage.resets_at - now_secs).to_string())?;
+        return Ok(Some(resp));
+    }
+
+    if counted.is_none() {
+        set_api_usage_background(&key, day, used + 1, env, ctx);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protects_api_and_links_only() {
+        assert!(is_protected("/api/openapi.json"));
+        assert!(is_protected("/links/ABC123"));
+        assert!(!is_protected("/p/ABC123"));
+        assert!(!is_protected("/images/ABC123/1"));
+        assert!(!is_protected("/oembed"));
+        assert!(!is_protected("/apiary"));
+    }
+
+    #[test]
+    fn key_comes_from_header_then_query() {
+        let url = Url::parse("https://cattgram.com/links/ABC?api_key=from-query").unwrap();
+        assert_eq!(extract_key(Some("from-header".to_string()), &url).as_deref(), Some("from-header"));
+        assert_eq!(extract_key(None, &url).as_deref(), Some("from-query"));
+
+        let bare = Url::parse("https://cattgram.com/links/ABC").unwrap();
+        assert_eq!(extract_key(Some("  ".to_string()), &bare), None);
+        assert_eq!(extract_key(None, &bare), None);
+    }
+
+    #[test]
+    fn key_metadata_defaults_to_enabled() {
+        let key: ApiKey = serde_json::from_str(r#"{"name":"acme"}"#).unwrap();
+        assert_eq!(key.name, "acme");
+        assert!(!key.disabled);
+        assert_eq!(key.daily_quota, None);
+    }
+
+    #[test]
+    fn usage_counts_down_to_the_quota() {
+        let key: ApiKey = serde_json::from_str(r#"{"name":"acme","daily_quota":100}"#).unwrap();
+        // 2023-11-14T22:13:20Z
+        let now = 1700000000;
+
+        let usage = Usage::new(&key, 99, now);
+        assert_eq!(usage.remaining, Some(1));
+        assert!(!usage.exhausted());
+        assert_eq!(usage.resets_at, 1700006400);
+
+        assert!(Usage::new(&key, 100, now).exhausted());
+        assert!(Usage::new(&key, 250, now).exhausted());
+    }
+
+    #[test]
+    fn unlimited_keys_are_never_exhausted() {
+        let key: ApiKey = serde_json::from_str(r#"{"name":"acme"}"#).unwrap();
+        let usage = Usage::new(&key, 1_000_000, 1700000000);
+        assert!(!usage.exhausted());
+        let json = serde_json::to_value(&usage).unwrap();
+        assert!(json.get("remaining").is_none());
+        assert_eq!(json["used"], 1_000_000);
+    }
+}
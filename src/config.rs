@@ -0,0 +1,608 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use worker::Env;
+
+use crate::i18n::Lang;
+use crate::scraper::browser::BrowserCredentials;
+use crate::scraper::cache::Store;
+use crate::scraper::canary::Canary;
+use crate::scraper::graphql::{self, SHORTCODE_PLACEHOLDER};
+use crate::scraper::proxy::{ProxyCredentials, DEFAULT_COUNTRY};
+use crate::scraper::stats::Backend;
+use crate::scraper::session::DeviceIds;
+use crate::utils::base64;
+use crate::utils::log::console_log;
+
+/// Runtime configuration, read from vars and secrets once per request.
+///
+/// `Default` holds the built-in defaults; `from_env` overrides whatever the
+/// deployment sets. Nothing else in the crate reads `env.var`/`env.secret`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `GRAPHQL_DOC_IDS` (or the older single `GRAPHQL_DOC_ID`): persisted
+    /// query IDs for the GraphQL backend, in fallback order. Never empty.
+    pub doc_ids: Vec<String>,
+    /// `GRAPHQL_PATH`: GraphQL endpoint path on www.instagram.com, e.g. `/graphql/query`.
+    pub graphql_path: String,
+    /// `GRAPHQL_VARIABLES`: JSON template for the query variables, with
+    /// `{shortcode}` standing in for the post ID.
+    pub graphql_variables: String,
+    /// `IG_COOKIE` secret, normalized to `name=value` form.
+    pub cookie: Option<String>,
+    /// Device IDs to send with `cookie`. Never read from the environment:
+    /// set per scrape when the cookie is leased from the session object.
+    pub device: Option<DeviceIds>,
+    /// `PROXY_USERNAME` + `PROXY_PASSWORD` secrets. Cleared by `DISABLE_PROXY`.
+    pub proxy: Option<ProxyCredentials>,
+    /// `PROXY_GEO_RETRY_COUNTRY`: proxy exit country (ISO code) to retry
+    /// geo-blocked posts from.
+    pub geo_retry_country: Option<String>,
+    /// `DISABLE_GRAPHQL`: skip the GraphQL backend entirely.
+    pub graphql_enabled: bool,
+    /// `DISABLE_LEGACY`: skip the legacy `?__a=1` JSON backend entirely.
+    pub legacy_enabled: bool,
+    /// `DISABLE_POST_PAGE`: skip the full post page (`/p/{id}/`) backend entirely.
+    pub post_page_enabled: bool,
+    /// `DISABLE_PAPI`: skip the Private API backend entirely.
+    pub papi_enabled: bool,
+    /// `BROWSER_RENDERING_ACCOUNT_ID` + `BROWSER_RENDERING_TOKEN` secret:
+    /// turns on the Browser Rendering backend, tried after every other one.
+    pub browser: Option<BrowserCredentials>,
+    /// `CANARY_PERCENT` and the `CANARY_*` settings: an experimental scrape
+    /// path for a slice of live scrapes (see `crate::scraper::canary`).
+    pub canary: Option<Canary>,
+    /// `SHADOW_BACKEND`: backend asked again after a live scrape, for
+    /// comparison (see `crate::scraper::shadow`). A `Backend::from_key` name.
+    pub shadow_backend: Option<Backend>,
+    /// `SHADOW_PERCENT`: share of live scrapes, 0–100, that get a shadow call.
+    pub shadow_percent: u8,
+    /// `CACHE_TTL_SECONDS`: KV expiration for scraped posts.
+    pub cache_ttl: u64,
+    /// `STORY_CACHE_TTL_SECONDS`: KV expiration for stories, whose CDN URLs expire quickly.
+    pub story_cache_ttl: u64,
+    /// `SCRAPE_BUDGET_MS`: time the scrape chain may take before the embed
+    /// is answered with whatever was found. Crawlers give up after 5–10s.
+    pub scrape_budget_ms: u64,
+    /// `FAST_PATH_MS`: after this long, an embed is answered with the embed
+    /// page thumbnail if that's all there is so far. `0` turns it off.
+    pub fast_path_ms: u64,
+    /// `PACE_PER_MINUTE`: PAPI or GraphQL calls that may start per minute,
+    /// instance-wide, when the `PACER` Durable Object is bound.
+    pub pace_per_minute: u32,
+    /// `PACE_CONCURRENCY`: PAPI or GraphQL calls that may be in flight at once.
+    pub pace_concurrency: u32,
+    /// `PACE_MAX_WAIT_MS`: how long a call waits for its turn before the
+    /// backend is skipped.
+    pub pace_max_wait_ms: u64,
+    /// `BROWSER_PER_MINUTE`: Browser Rendering renders that may start per
+    /// minute, instance-wide, when `PACER` is bound. Each one is billed.
+    pub browser_per_minute: u32,
+    /// `CORS_ALLOW_ORIGINS`: comma-separated list, `*` by default.
+    pub cors_origins: Vec<String>,
+    /// `SITE_NAME`: shown in page titles, `og:site_name` and oEmbed.
+    pub site_name: String,
+    /// `DEFAULT_LANG`: language of the human-facing pages when the
+    /// visitor's `Accept-Language` names none we have.
+    pub default_lang: Lang,
+    /// `THEME_COLOR`: embed accent color.
+    pub theme_color: String,
+    /// `ANIMATED_PREVIEW`: use a Media Transformations clip as the video `og:image`.
+    pub animated_preview: bool,
+    /// `SHOW_SENSITIVE_MEDIA`: preview sensitive posts unblurred.
+    pub show_sensitive: bool,
+    /// `VALIDATE_MEDIA`: HEAD-check the selected media before rendering an
+    /// embed, and fall back to a slide or thumbnail that still loads.
+    pub validate_media: bool,
+    /// `SUMMARIZE_CAPTIONS`: put a Workers AI summary in front of captions
+    /// too long for an embed. Needs the `AI` binding.
+    pub summarize_captions: bool,
+    /// `AI_ALT_TEXT`: describe the image of posts with neither a caption nor
+    /// alt text with Workers AI. Needs the `AI` binding.
+    pub ai_alt_text: bool,
+    /// `STRICT_IMG_INDEX`: answer slide numbers past the end with 404 instead
+    /// of the nearest slide, as `?strict=true` does per request.
+    pub strict_img_index: bool,
+    /// `ADMIN_TOKEN` secret guarding the admin routes (`/config-check` and
+    /// `/admin/*`, see `Layer::Admin`); they 404 without it.
+    pub admin_token: Option<String>,
+    /// `REQUIRE_API_KEY`: `/api/*` and `/links/*` need a key from KV.
+    pub api_keys_required: bool,
+    /// `MOCK_MODE`: serve canned posts from `fixtures/` instead of scraping. Local development only.
+    pub mock_mode: bool,
+    /// `REDIRECT_ONLY`: kill switch. Every route that would scrape redirects
+    /// to Instagram instead, cache or not (see `crate::killswitch`).
+    pub redirect_only: bool,
+    /// `MAINTENANCE`: like `REDIRECT_ONLY`, but bots asking for an embed get
+    /// a card saying the instance is down for maintenance.
+    pub maintenance: bool,
+    /// `STATUS_PAGE_URL`: linked from the maintenance card. This instance's
+    /// own `/status` page by default.
+    pub status_page_url: Option<String>,
+    /// `ALERT_WEBHOOK_URL` secret: Discord or Slack webhook for failure alerts.
+    pub alert_webhook_url: Option<String>,
+    /// `TURNSTILE_SITE_KEY`: public key of the Turnstile widget shown before
+    /// `/view` and `/links`. Needs `turnstile_secret` as well.
+    pub turnstile_site_key: Option<String>,
+    /// `TURNSTILE_SECRET_KEY` secret, for verifying widget tokens.
+    pub turnstile_secret: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            doc_ids: vec!["25531498899829322".to_string()],
+            graphql_path: graphql::DEFAULT_PATH.to_string(),
+            graphql_variables: graphql::DEFAULT_VARIABLES.to_string(),
+            cookie: None,
+            device: None,
+            proxy: None,
+            geo_retry_country: None,
+            graphql_enabled: true,
+            legacy_enabled: true,
+            post_page_enabled: true,
+            papi_enabled: true,
+            browser: None,
+            canary: None,
+            shadow_backend: None,
+            shadow_percent: 10,
+            cache_ttl: 86400, // 24 hours
+            story_cache_ttl: 1800, // 30 minutes
+            scrape_budget_ms: 8000,
+            fast_path_ms: 4000,
+            pace_per_minute: 60,
+            pace_concurrency: 4,
+            pace_max_wait_ms: 2000,
+            browser_per_minute: 10,
+            cors_origins: vec!["*".to_string()],
+            site_name: "Cattgram".to_string(),
+            default_lang: Lang::En,
+            theme_color: "#E1306C".to_string(),
+            animated_preview: false,
+            show_sensitive: false,
+            validate_media: false,
+            summarize_captions: false,
+            ai_alt_text: false,
+            strict_img_index: false,
+            admin_token: None,
+            api_keys_required: false,
+            mock_mode: false,
+            redirect_only: false,
+            maintenance: false,
+            status_page_url: None,
+            alert_webhook_url: None,
+            turnstile_site_key: None,
+            turnstile_secret: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env(env: &Env) -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| env.var(name).ok().map(|v| v.to_string());
+        let secret = |name: &str| env.secret(name).ok().map(|v| v.to_string());
+        let flag = |name: &str| var(name).is_some_and(|v| parse_flag(&v));
+
+        // DISABLE_PROXY drops the credentials so every backend fetches directly
+        let proxy = match (secret("PROXY_USERNAME"), secret("PROXY_PASSWORD")) {
+            (Some(username), Some(password)) if !flag("DISABLE_PROXY") => {
+                Some(ProxyCredentials { username, password, country: DEFAULT_COUNTRY.to_string() })
+            }
+            _ => None,
+        };
+
+        Self {
+            doc_ids: var("GRAPHQL_DOC_IDS")
+                .or_else(|| var("GRAPHQL_DOC_ID"))
+                .map(|v| parse_list(&v))
+                .filter(|ids| !ids.is_empty())
+                .unwrap_or(defaults.doc_ids),
+            graphql_path: var("GRAPHQL_PATH")
+                .map(|v| normalize_path(&v))
+                .filter(|p| p != "/")
+                .unwrap_or(defaults.graphql_path),
+            graphql_variables: var("GRAPHQL_VARIABLES")
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .unwrap_or(defaults.graphql_variables),
+            cookie: secret("IG_COOKIE").map(|c| normalize_cookie(&c)),
+            device: None,
+            proxy,
+            geo_retry_country: var("PROXY_GEO_RETRY_COUNTRY")
+                .map(|v| v.trim().to_ascii_lowercase())
+                .filter(|v| v.len() == 2 && v.bytes().all(|b| b.is_ascii_lowercase())),
+            graphql_enabled: !flag("DISABLE_GRAPHQL"),
+            legacy_enabled: !flag("DISABLE_LEGACY"),
+            post_page_enabled: !flag("DISABLE_POST_PAGE"),
+            papi_enabled: !flag("DISABLE_PAPI"),
+            browser: match (var("BROWSER_RENDERING_ACCOUNT_ID"), secret("BROWSER_RENDERING_TOKEN")) {
+                (Some(account_id), Some(token)) if !account_id.trim().is_empty() && !token.is_empty() => {
+                    Some(BrowserCredentials { account_id: account_id.trim().to_string(), token })
+                }
+                _ => None,
+            },
+            canary: var("CANARY_PERCENT")
+                .and_then(|v| v.trim().parse::<u8>().ok())
+                .filter(|&percent| percent > 0)
+                .map(|percent| Canary {
+                    percent: percent.min(100),
+                    doc_ids: var("CANARY_GRAPHQL_DOC_IDS").map(|v| parse_list(&v)).filter(|ids| !ids.is_empty()),
+                    graphql_path: var("CANARY_GRAPHQL_PATH").map(|v| normalize_path(&v)).filter(|p| p != "/"),
+                    proxy: match (secret("CANARY_PROXY_USERNAME"), secret("CANARY_PROXY_PASSWORD")) {
+                        (Some(username), Some(password)) if !flag("DISABLE_PROXY") => {
+                            Some(ProxyCredentials { username, password, country: DEFAULT_COUNTRY.to_string() })
+                        }
+                        _ => None,
+                    },
+                }),
+            shadow_backend: var("SHADOW_BACKEND").and_then(|v| Backend::from_key(&v)),
+            shadow_percent: var("SHADOW_PERCENT")
+                .and_then(|v| v.trim().parse::<u8>().ok())
+                .map(|percent| percent.min(100))
+                .unwrap_or(defaults.shadow_percent),
+            cache_ttl: var("CACHE_TTL_SECONDS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.cache_ttl),
+            story_cache_ttl: var("STORY_CACHE_TTL_SECONDS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.story_cache_ttl),
+            scrape_budget_ms: var("SCRAPE_BUDGET_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.scrape_budget_ms),
+            fast_path_ms: var("FAST_PATH_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.fast_path_ms),
+            pace_per_minute: var("PACE_PER_MINUTE")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.pace_per_minute),
+            pace_concurrency: var("PACE_CONCURRENCY")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.pace_concurrency),
+            pace_max_wait_ms: var("PACE_MAX_WAIT_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.pace_max_wait_ms),
+            browser_per_minute: var("BROWSER_PER_MINUTE")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.browser_per_minute),
+            cors_origins: var("CORS_ALLOW_ORIGINS")
+                .map(|v| parse_origins(&v))
+                .unwrap_or(defaults.cors_origins),
+            site_name: var("SITE_NAME").unwrap_or(defaults.site_name),
+            default_lang: var("DEFAULT_LANG")
+                .and_then(|v| Lang::from_tag(&v))
+                .unwrap_or(defaults.default_lang),
+            theme_color: var("THEME_COLOR").unwrap_or(defaults.theme_color),
+            animated_preview: flag("ANIMATED_PREVIEW"),
+            show_sensitive: flag("SHOW_SENSITIVE_MEDIA"),
+            validate_media: flag("VALIDATE_MEDIA"),
+            summarize_captions: flag("SUMMARIZE_CAPTIONS"),
+            ai_alt_text: flag("AI_ALT_TEXT"),
+            strict_img_index: flag("STRICT_IMG_INDEX"),
+            admin_token: secret("ADMIN_TOKEN").filter(|t| !t.is_empty()),
+            api_keys_required: flag("REQUIRE_API_KEY"),
+            mock_mode: flag("MOCK_MODE"),
+            redirect_only: flag("REDIRECT_ONLY"),
+            maintenance: flag("MAINTENANCE"),
+            status_page_url: var("STATUS_PAGE_URL").map(|u| u.trim().to_string()).filter(|u| !u.is_empty()),
+            alert_webhook_url: secret("ALERT_WEBHOOK_URL").filter(|u| !u.is_empty()),
+            turnstile_site_key: var("TURNSTILE_SITE_KEY").map(|k| k.trim().to_string()).filter(|k| !k.is_empty()),
+            turnstile_secret: secret("TURNSTILE_SECRET_KEY").filter(|k| !k.is_empty()),
+        }
+    }
+
+    /// Configuration problems that can be spotted without any network calls.
+    pub fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.mock_mode {
+            warnings.push("MOCK_MODE is on; every post comes from built-in fixtures, not Instagram");
+        }
+        if self.redirect_only {
+            warnings.push("REDIRECT_ONLY is on; every embed and media route redirects to Instagram");
+        }
+        if self.maintenance {
+            warnings.push("MAINTENANCE is on; embeds show a maintenance card and every other route redirects to Instagram");
+        }
+        if self.papi_enabled && self.cookie.is_none() {
+            warnings.push("IG_COOKIE is not set; the PAPI backend will always be skipped");
+        }
+        if self.proxy.is_none() {
+            warnings.push("no proxy credentials; every backend fetches directly from datacenter IPs");
+        }
+        if self.geo_retry_country.is_some() && self.proxy.is_none() {
+            warnings.push("PROXY_GEO_RETRY_COUNTRY is set without proxy credentials; geo-blocked posts won't be retried");
+        }
+        if !self.graphql_enabled && !self.legacy_enabled && !self.post_page_enabled && !self.papi_enabled {
+            warnings.push("GraphQL, legacy, post page and PAPI are all disabled; videos can only come from the embed page");
+        }
+        if !self.graphql_variables.contains(SHORTCODE_PLACEHOLDER) {
+            warnings.push("GRAPHQL_VARIABLES has no {shortcode} placeholder; every GraphQL query asks for the same post");
+        }
+        let sample = graphql::render_variables(&self.graphql_variables, "C0000000000");
+        if serde_json::from_str::<serde_json::Value>(&sample).is_err() {
+            warnings.push("GRAPHQL_VARIABLES is not valid JSON; GraphQL queries will fail");
+        }
+        if self.cache_ttl < 60 {
+            // KV rejects expiration TTLs under 60 seconds
+            warnings.push("CACHE_TTL_SECONDS is below KV's 60 second minimum; cache writes will fail");
+        }
+        if self.story_cache_ttl < 60 {
+            warnings.push("STORY_CACHE_TTL_SECONDS is below KV's 60 second minimum; story cache writes will fail");
+        }
+        if self.scrape_budget_ms < 1000 {
+            warnings.push("SCRAPE_BUDGET_MS is under a second; most scrapes will be cut short");
+        } else if self.scrape_budget_ms > 25_000 {
+            warnings.push("SCRAPE_BUDGET_MS is over 25 seconds; crawlers will have given up long before, and Workers stop at 30");
+        }
+        if self.fast_path_ms >= self.scrape_budget_ms {
+            warnings.push("FAST_PATH_MS is not below SCRAPE_BUDGET_MS; the fast path never kicks in");
+        }
+        if self.canary.as_ref().is_some_and(|c| !c.changes_anything()) {
+            warnings.push("CANARY_PERCENT is set without any CANARY_* setting; canary scrapes take the stable path");
+        }
+        match self.shadow_backend {
+            Some(Backend::Papi) if self.cookie.is_none() => {
+                warnings.push("SHADOW_BACKEND is papi but IG_COOKIE is not set; shadow calls will find nothing")
+            }
+            Some(Backend::Browser) if self.browser.is_none() => {
+                warnings.push("SHADOW_BACKEND is browser without Browser Rendering credentials; shadow calls will find nothing")
+            }
+            _ => {}
+        }
+        if self.pace_per_minute == 0 || self.pace_concurrency == 0 {
+            warnings.push("PACE_PER_MINUTE or PACE_CONCURRENCY is 0; with PACER bound, PAPI and GraphQL are never called");
+        }
+        if self.browser.is_some() && self.browser_per_minute == 0 {
+            warnings.push("BROWSER_PER_MINUTE is 0; with PACER bound, Browser Rendering is never called");
+        }
+        if self.turnstile_site_key.is_some() != self.turnstile_secret.is_some() {
+            warnings.push("only one of TURNSTILE_SITE_KEY and TURNSTILE_SECRET_KEY is set; Turnstile stays off");
+        }
+        warnings
+    }
+
+    /// The proxy credentials pointed at `PROXY_GEO_RETRY_COUNTRY`, for
+    /// retrying a geo-blocked post. `None` without a proxy, or when the retry
+    /// country is the one already in use.
+    pub fn geo_retry_proxy(&self) -> Option<ProxyCredentials> {
+        let country = self.geo_retry_country.clone()?;
+        let proxy = self.proxy.as_ref()?;
+        (proxy.country != country).then(|| ProxyCredentials { country, ..proxy.clone() })
+    }
+
+    /// The Turnstile site key and secret, when both are set.
+    pub fn turnstile(&self) -> Option<(&str, &str)> {
+        Some((self.turnstile_site_key.as_deref()?, self.turnstile_secret.as_deref()?))
+    }
+
+    /// Returns `true` if an `Authorization` header carries the admin token:
+    /// as a bearer token, or as the password of Basic credentials (any user
+    /// name), which is what a browser's login prompt sends.
+    pub fn is_admin(&self, authorization: &str) -> bool {
+        let Some(expected) = self.admin_token.as_deref() else {
+            return false;
+        };
+        let basic = authorization
+            .strip_prefix("Basic ")
+            .and_then(|encoded| String::from_utf8(base64::decode(encoded.trim())?).ok());
+        let given = match (authorization.strip_prefix("Bearer "), &basic) {
+            (Some(token), _) => token,
+            (None, Some(credentials)) => match credentials.split_once(':') {
+                Some((_, password)) => password,
+                None => return false,
+            },
+            (None, None) => return false,
+        };
+        // Constant-time comparison so the token can't be guessed byte by byte
+        given.len() == expected.len()
+            && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+/// Set once the first request in this isolate has been validated.
+static VALIDATED: AtomicBool = AtomicBool::new(false);
+
+/// Logs configuration problems on the first request an isolate serves, so
+/// misconfiguration shows up in `wrangler tail` rather than as failed embeds.
+pub fn validate_once(config: &Config, env: &Env) {
+    if VALIDATED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    if !Store::Data.is_bound(env) {
+        console_log!("[config] WARNING: KV namespace CACHE is not bound; caching is disabled");
+    } else {
+        if !Store::Runtime.is_bound(env) {
+            console_log!("[config] KV namespace RUNTIME is not bound; runtime state shares CACHE");
+        }
+        if !Store::Pages.is_bound(env) {
+            console_log!("[config] KV namespace PAGES is not bound; rendered embeds share CACHE");
+        }
+    }
+    for warning in config.warnings() {
+        console_log!("[config] WARNING: {}", warning);
+    }
+    // An unknown name parses to no shadow backend at all, which warnings() can't tell from unset
+    if let Some(name) = env.var("SHADOW_BACKEND").ok().map(|v| v.to_string()).filter(|v| !v.trim().is_empty()) {
+        if Backend::from_key(&name).is_none() {
+            console_log!(
+                "[config] WARNING: SHADOW_BACKEND {:?} is not embed, graphql, legacy, post_page, papi or browser; shadow mode is off",
+                name
+            );
+        }
+    }
+}
+
+/// URL-decodes the cookie (wrangler may store it encoded) and wraps a raw
+/// session ID value as `sessionid=...`.
+pub fn normalize_cookie(raw: &str) -> String {
+    let decoded = raw.replace("%3A", ":").replace("%3a", ":");
+    if decoded.contains('=') {
+        decoded
+    } else {
+        format!("sessionid={}", decoded)
+    }
+}
+
+/// Trims a path and makes sure it starts with `/`.
+fn normalize_path(raw: &str) -> String {
+    let path = raw.trim();
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Accepts `1`, `true`, `yes` and `on` (case-insensitive) as set.
+fn parse_flag(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on")
+}
+
+/// Splits a comma-separated list, dropping empty entries.
+fn parse_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Splits a comma-separated origin list, falling back to `*` if it is empty.
+fn parse_origins(raw: &str) -> Vec<String> {
+    let origins = parse_list(raw);
+
+    if origins.is_empty() {
+        vec!["*".to_string()]
+    } else {
+        origins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_flag_accepts_common_truthy_values() {
+        for v in ["1", "true", "TRUE", " yes ", "on"] {
+            assert!(parse_flag(v), "{v:?}");
+        }
+        for v in ["", "0", "false", "off", "disabled"] {
+            assert!(!parse_flag(v), "{v:?}");
+        }
+    }
+
+    #[test]
+    fn normalizes_encoded_raw_session_id() {
+        assert_eq!(normalize_cookie("42%3Aabc%3A1"), "sessionid=42:abc:1");
+        assert_eq!(normalize_cookie("sessionid=42:abc"), "sessionid=42:abc");
+    }
+
+    #[test]
+    fn parses_origin_list() {
+        assert_eq!(
+            parse_origins("https://a.example, https://b.example"),
+            vec!["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[test]
+    fn empty_origin_list_allows_all() {
+        assert_eq!(parse_origins(" , "), vec!["*"]);
+    }
+
+    #[test]
+    fn default_config_warns_about_missing_secrets() {
+        let warnings = Config::default().warnings();
+        assert!(warnings.iter().any(|w| w.contains("IG_COOKIE")));
+        assert!(warnings.iter().any(|w| w.contains("proxy")));
+
+        let configured = Config {
+            cookie: Some("sessionid=1".to_string()),
+            proxy: Some(ProxyCredentials {
+                username: "u".to_string(),
+                password: "p".to_string(),
+                country: DEFAULT_COUNTRY.to_string(),
+            }),
+            ..Config::default()
+        };
+        assert!(configured.warnings().is_empty());
+    }
+
+    #[test]
+    fn warns_about_unworkable_scrape_budgets() {
+        let budget = |ms| Config { scrape_budget_ms: ms, ..Config::default() }.warnings();
+        assert!(budget(500).iter().any(|w| w.contains("SCRAPE_BUDGET_MS")));
+        assert!(budget(30_000).iter().any(|w| w.contains("SCRAPE_BUDGET_MS")));
+        assert!(!budget(8000).iter().any(|w| w.contains("SCRAPE_BUDGET_MS")));
+        assert!(budget(3000).iter().any(|w| w.contains("FAST_PATH_MS")));
+    }
+
+    #[test]
+    fn warns_about_a_canary_that_changes_nothing() {
+        let canary = Canary { percent: 10, doc_ids: None, graphql_path: None, proxy: None };
+        let idle = Config { canary: Some(canary.clone()), ..Config::default() };
+        assert!(idle.warnings().iter().any(|w| w.contains("CANARY_PERCENT")));
+        let trying = Config { canary: Some(Canary { doc_ids: Some(vec!["999".to_string()]), ..canary }), ..Config::default() };
+        assert!(!trying.warnings().iter().any(|w| w.contains("CANARY_PERCENT")));
+    }
+
+    #[test]
+    fn warns_about_a_shadow_backend_that_cannot_run() {
+        let papi = Config { shadow_backend: Some(Backend::Papi), ..Config::default() };
+        assert!(papi.warnings().iter().any(|w| w.contains("SHADOW_BACKEND")));
+        let graphql = Config { shadow_backend: Some(Backend::Graphql), ..Config::default() };
+        assert!(!graphql.warnings().iter().any(|w| w.contains("SHADOW_BACKEND")));
+    }
+
+    #[test]
+    fn warns_when_pacing_allows_nothing() {
+        let stalled = Config { pace_concurrency: 0, ..Config::default() };
+        assert!(stalled.warnings().iter().any(|w| w.contains("PACE_")));
+        assert!(!Config::default().warnings().iter().any(|w| w.contains("PACE_")));
+    }
+
+    #[test]
+    fn turnstile_needs_both_keys() {
+        let site_key_only = Config { turnstile_site_key: Some("0x4AAA".to_string()), ..Config::default() };
+        assert_eq!(site_key_only.turnstile(), None);
+        assert!(site_key_only.warnings().iter().any(|w| w.contains("TURNSTILE")));
+
+        let both = Config { turnstile_secret: Some("0x4BBB".to_string()), ..site_key_only };
+        assert_eq!(both.turnstile(), Some(("0x4AAA", "0x4BBB")));
+        assert!(!both.warnings().iter().any(|w| w.contains("TURNSTILE")));
+    }
+
+    #[test]
+    fn graphql_path_gets_a_leading_slash() {
+        assert_eq!(normalize_path(" graphql/query "), "/graphql/query");
+        assert_eq!(normalize_path("/api/graphql"), "/api/graphql");
+    }
+
+    #[test]
+    fn warns_about_broken_graphql_variables() {
+        let missing = Config { graphql_variables: r#"{"shortcode":"ABC"}"#.to_string(), ..Config::default() };
+        assert!(missing.warnings().iter().any(|w| w.contains("placeholder")));
+
+        let invalid = Config { graphql_variables: r#"{"shortcode":"{shortcode}""#.to_string(), ..Config::default() };
+        assert!(invalid.warnings().iter().any(|w| w.contains("not valid JSON")));
+    }
+
+    #[test]
+    fn admin_requires_matching_bearer_or_basic_credentials() {
+        let config = Config {
+            admin_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        assert!(config.is_admin("Bearer s3cret"));
+        assert!(!config.is_admin("Bearer s3cre"));
+        assert!(!config.is_admin("s3cret"));
+        assert!(!Config::default().is_admin("Bearer "));
+
+        // admin:s3cret, and a user name of its own
+        assert!(config.is_admin("Basic YWRtaW46czNjcmV0"));
+        assert!(config.is_admin("Basic OnMzY3JldA=="));
+        // admin:wrong, and no colon at all
+        assert!(!config.is_admin("Basic YWRtaW46d3Jvbmc="));
+        assert!(!config.is_admin("Basic czNjcmV0"));
+    }
+}
@@ -0,0 +1,184 @@
+//! The versioned JSON API.
+//!
+//! Each version keeps its response schema once released; changes that
+//! would break a client go into the next version. `/api/v1/...` and
+//! `/api/v2/...` pick a version by path. The unversioned `/api/...` form
+//! reads the `Api-Version` request header and otherwise stays on v1, so a
+//! client that never asked for a version never sees its schema change.
+//! Every response names the version it was built with in `Api-Version`.
+
+use std::rc::Rc;
+
+use serde::Serialize;
+use worker::*;
+
+use crate::scraper::fetch_post_data;
+use crate::scraper::stats::Backend;
+use crate::scraper::types::InstaData;
+use crate::templates::links_html::{MediaLink, PostLinks};
+use crate::utils::http::{revalidate, CacheStatus};
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// A released version of the JSON API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// The media list, as `/links` serves it.
+    V1,
+    /// v1 plus the post's caption, counts, timestamp and author.
+    V2,
+}
+
+impl ApiVersion {
+    /// Unversioned requests without an `Api-Version` header.
+    pub const DEFAULT: ApiVersion = ApiVersion::V1;
+
+    pub fn number(self) -> u8 {
+        match self {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        }
+    }
+
+    /// Parses `1`, `v1` or `V1`.
+    pub fn parse(value: &str) -> Option<ApiVersion> {
+        let value = value.trim();
+        let number = value.strip_prefix(['v', 'V']).unwrap_or(value);
+        match number {
+            "1" => Some(ApiVersion::V1),
+            "2" => Some(ApiVersion::V2),
+            _ => None,
+        }
+    }
+}
+
+/// The version a request asks for: the path's `/api/vN/` prefix, else the
+/// `Api-Version` header, else `ApiVersion::DEFAULT`. `None` for a version
+/// that doesn't exist.
+pub fn requested_version(path: &str, header: Option<&str>) -> Option<ApiVersion> {
+    let segment = path.strip_prefix("/api/").and_then(|rest| rest.split('/').next());
+    match segment.filter(|s| s.starts_with('v')) {
+        Some(segment) => ApiVersion::parse(segment),
+        None => header.map_or(Some(ApiVersion::DEFAULT), ApiVersion::parse),
+    }
+}
+
+/// A post as served by v2: v1's media list plus the post's own details.
+#[derive(Debug, Serialize)]
+pub struct PostDetails {
+    pub post_id: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    pub is_verified: bool,
+    pub instagram_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Unix time the post was published.
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub like_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_view_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub play_count: Option<u64>,
+    pub media: Vec<MediaLink>,
+    pub source: Source,
+}
+
+/// Where a v2 post came from, for support reports about bad data.
+#[derive(Debug, Serialize)]
+pub struct Source {
+    /// The backend the post was scraped from, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    /// The post was served from the cache rather than scraped for this request.
+    pub cached: bool,
+}
+
+impl PostDetails {
+    pub fn new(data: &InstaData, host: &str) -> Self {
+        let links = PostLinks::new(data, host);
+        Self {
+            post_id: links.post_id,
+            username: links.username,
+            full_name: data.full_name.clone(),
+            is_verified: data.is_verified,
+            instagram_url: links.instagram_url,
+            caption: data.caption.clone(),
+            timestamp: data.timestamp,
+            like_count: data.like_count,
+            comment_count: data.comment_count,
+            video_view_count: data.video_view_count,
+            play_count: data.play_count,
+            media: links.media,
+            source: Source { backend: data.backend, cached: data.cached },
+        }
+    }
+}
+
+/// Marks a response from an endpoint that a versioned route replaces
+/// (RFC 9745 `Deprecation`, with the replacement as `successor-version`).
+pub fn deprecate(resp: Response, successor: &str) -> Result<Response> {
+    let headers = resp.headers().clone();
+    headers.set("Deprecation", "true")?;
+    headers.set("Link", &format!("<{successor}>; rel=\"successor-version\""))?;
+    Ok(resp.with_headers(headers))
+}
+
+/// One post's media, and from v2 its details.
+///
+/// Routes: `/api/v1/posts/:postID`, `/api/v2/posts/:postID`, and
+/// `/api/posts/:postID` with the version from `Api-Version`.
+pub async fn post(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let header = req.headers().get("Api-Version")?;
+    let Some(version) = requested_version(&req.path(), header.as_deref()) else {
+        return Response::error("Unsupported API version", 400);
+    };
+    let post_id = ctx.param("postID").cloned().unwrap_or_default();
+
+    let data = match fetch_post_data(&post_id, &ctx.env, &ctx.data).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return Response::error("Post not found", 404),
+        Err(e) => {
+            console_log!("[api] fetch error: {:?}", e);
+            return Response::error("Failed to fetch post", 502);
+        }
+    };
+
+    let req_url = req.url()?;
+    let host = req_url.host_str().unwrap_or("cattgram.com");
+    let resp = match version {
+        ApiVersion::V1 => Response::from_json(&PostLinks::new(&data, host))?,
+        ApiVersion::V2 => Response::from_json(&PostDetails::new(&data, host))?,
+    };
+    resp.headers().set("Api-Version", &version.number().to_string())?;
+    let now_secs = Date::now().as_millis() / 1000;
+    resp.headers().set(CacheStatus::HEADER, CacheStatus::of(&data, now_secs).as_str())?;
+    // No ETag: v1 and v2 bodies differ, but they were scraped at the same time
+    if revalidate(&req, resp.headers(), None, data.scraped_at)? {
+        return Ok(Response::empty()?.with_status(304).with_headers(resp.headers().clone()));
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_prefix_wins_over_the_header() {
+        assert_eq!(requested_version("/api/v2/posts/ABC", Some("1")), Some(ApiVersion::V2));
+        assert_eq!(requested_version("/api/v1/posts/ABC", None), Some(ApiVersion::V1));
+        assert_eq!(requested_version("/api/v9/posts/ABC", Some("2")), None);
+    }
+
+    #[test]
+    fn unversioned_paths_read_the_header() {
+        assert_eq!(requested_version("/api/posts/ABC", None), Some(ApiVersion::V1));
+        assert_eq!(requested_version("/api/posts/ABC", Some(" v2 ")), Some(ApiVersion::V2));
+        assert_eq!(requested_version("/api/posts/ABC", Some("latest")), None);
+    }
+}
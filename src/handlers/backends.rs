@@ -0,0 +1,45 @@
+use std::rc::Rc;
+
+use futures_util::future::join_all;
+use worker::*;
+
+use crate::metrics::RouteStats;
+use crate::scraper::cache::{get_backend_stats, get_route_stats};
+use crate::scraper::stats::{recent_buckets, BackendStats, Track};
+use crate::templates::backends_html::render_backends;
+use crate::utils::minify::minify_html;
+use crate::AppState;
+
+/// Backend health and traffic dashboard.
+///
+/// Route: `/admin/backends`, behind the admin layer.
+pub async fn handle(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let now_secs = Date::now().as_millis() / 1000;
+    let buckets: Vec<u64> = recent_buckets(now_secs).collect();
+    let stats = join_all(buckets.iter().map(|&bucket| get_backend_stats(bucket, Track::Stable, &ctx.env))).await;
+    // Only shown while a canary is configured
+    let canary = match ctx.data.config.canary {
+        Some(_) => {
+            let canary: Vec<BackendStats> = join_all(buckets.iter().map(|&bucket| get_backend_stats(bucket, Track::Canary, &ctx.env)))
+                .await
+                .into_iter()
+                .filter_map(|stats| stats.ok().flatten())
+                .collect();
+            Some(BackendStats::sum(&canary))
+        }
+        None => None,
+    };
+    let routes: Vec<RouteStats> = join_all(buckets.iter().map(|&bucket| get_route_stats(bucket, &ctx.env)))
+        .await
+        .into_iter()
+        .filter_map(|stats| stats.ok().flatten())
+        .collect();
+    let routes = RouteStats::sum(&routes);
+    let buckets: Vec<_> = buckets
+        .into_iter()
+        .zip(stats)
+        .map(|(bucket, stats)| (bucket, stats.ok().flatten().unwrap_or_default()))
+        .collect();
+
+    Response::from_html(minify_html(&render_backends(&buckets, canary.as_ref(), &routes, &ctx.data.config)))
+}
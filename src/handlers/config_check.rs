@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use serde::Serialize;
+use worker::*;
+
+use crate::scraper::cache::Store;
+use crate::scraper::instrument::{instrumented, BINDING as FETCH_METRICS};
+use crate::scraper::papi::check_cookie;
+use crate::scraper::proxy::check_proxy;
+use crate::session::{session_report, SessionReport};
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// Result of checking one optional dependency.
+#[derive(Serialize)]
+struct Check {
+    configured: bool,
+    /// `None` when not configured (nothing to check).
+    working: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn not_configured() -> Self {
+        Self { configured: false, working: None, error: None }
+    }
+
+    fn from_result(result: Result<bool>) -> Self {
+        match result {
+            Ok(working) => Self { configured: true, working: Some(working), error: None },
+            Err(e) => Self { configured: true, working: Some(false), error: Some(e.to_string()) },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    kv_bound: bool,
+    /// Which KV namespaces have a binding of their own, by binding name.
+    kv_namespaces: BTreeMap<&'static str, bool>,
+    ig_cookie: Check,
+    /// The session object's view of `IG_COOKIE`, when `SESSIONS` is bound.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ig_session: Option<SessionReport>,
+    proxy: Check,
+    /// Whether outbound fetch timings go to Analytics Engine.
+    fetch_metrics_bound: bool,
+    graphql_enabled: bool,
+    legacy_enabled: bool,
+    post_page_enabled: bool,
+    papi_enabled: bool,
+    /// Browser Rendering credentials are set. Not checked live, since every
+    /// render is billed.
+    browser_rendering_configured: bool,
+    warnings: Vec<&'static str>,
+}
+
+/// Configuration self-check.
+///
+/// Route: `/config-check`, behind the admin layer. Reports which bindings
+/// and secrets are present, and makes one live request each to verify the IG
+/// cookie and proxy credentials.
+pub async fn handle(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let config = &ctx.data.config;
+    let ig_cookie = match config.cookie.as_deref() {
+        Some(cookie) => Check::from_result(check_cookie(&instrumented(&ctx.env), cookie).await),
+        None => Check::not_configured(),
+    };
+    let ig_session = session_report(&ctx.env).await.unwrap_or_else(|e| {
+        console_log!("[config_check] session report failed: {:?}", e);
+        None
+    });
+    let proxy = match config.proxy.as_ref() {
+        Some(creds) => Check::from_result(check_proxy(&instrumented(&ctx.env), creds).await),
+        None => Check::not_configured(),
+    };
+
+    let report = Report {
+        kv_bound: Store::Data.is_bound(&ctx.env),
+        kv_namespaces: Store::ALL.iter().map(|store| (store.binding(), store.is_bound(&ctx.env))).collect(),
+        ig_cookie,
+        ig_session,
+        proxy,
+        fetch_metrics_bound: ctx.env.analytics_engine(FETCH_METRICS).is_ok(),
+        graphql_enabled: config.graphql_enabled,
+        legacy_enabled: config.legacy_enabled,
+        post_page_enabled: config.post_page_enabled,
+        papi_enabled: config.papi_enabled,
+        browser_rendering_configured: config.browser.is_some(),
+        warnings: config.warnings(),
+    };
+
+    Response::from_json(&report)
+}
@@ -0,0 +1,187 @@
+//! What an embed or media request asks for, parsed once.
+//!
+//! The embed and media handlers build an `EmbedContext` from the request
+//! before doing anything else, and pass it down (to the scraper too) instead
+//! of the URL, the User-Agent and a flag for every query parameter.
+
+use url::Url;
+use worker::*;
+
+use crate::config::Config;
+use crate::scraper::types::Comment;
+use crate::templates::embed_html::{EmbedOptions, Playback};
+use crate::utils::bot_detect::{is_bot, Platform};
+
+/// The query parameters that switch behaviour on, e.g. `?direct=true`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flags {
+    /// `?direct=true`: redirect to the media itself instead of embedding it.
+    pub direct: bool,
+    /// The `/gif/:postID` route or `?gif=true`: videos as muted loops.
+    pub gif: bool,
+    /// `?preview=true`: send browsers to the viewer page.
+    pub preview: bool,
+    /// `?comments=1` (or `true`): show the top comment under the caption.
+    pub top_comment: bool,
+    /// Slide numbers past the end are errors rather than clamped to the last
+    /// slide: `?strict=true`, or `STRICT_IMG_INDEX`.
+    pub strict: bool,
+    /// `?download=true`: serve the media as an attachment.
+    pub download: bool,
+    /// `?proxy=true`: stream the video through the worker, for apps that
+    /// won't play Instagram's CDN URLs.
+    pub proxy: bool,
+}
+
+impl Flags {
+    pub fn from_url(url: &Url, config: &Config) -> Self {
+        let is = |name: &str| url.query_pairs().any(|(k, v)| k == name && v == "true");
+        Self {
+            direct: is("direct"),
+            gif: url.path().starts_with("/gif/") || is("gif"),
+            preview: is("preview"),
+            top_comment: url.query_pairs().any(|(k, v)| k == "comments" && (v == "1" || v == "true")),
+            strict: config.strict_img_index || is("strict"),
+            download: is("download"),
+            proxy: is("proxy"),
+        }
+    }
+}
+
+/// Extracts the `img_index` query parameter (1-based) from a URL.
+pub fn parse_img_index(url: &Url) -> Option<usize> {
+    url.query_pairs()
+        .find(|(k, _)| k == "img_index")
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .filter(|&n| n >= 1)
+}
+
+/// Returns the request's User-Agent, or an empty string.
+pub fn user_agent(req: &Request) -> String {
+    req.headers()
+        .get("User-Agent")
+        .unwrap_or(None)
+        .unwrap_or_default()
+}
+
+/// An embed or media request, as the scraper and templates need it.
+pub struct EmbedContext<'a> {
+    /// The post's shortcode. Handlers update it as a numeric ID, share link
+    /// or hashtag resolves to a post.
+    pub post_id: String,
+    /// 1-based slide: `?img_index` on embeds, `:mediaNum` on media routes.
+    pub slide: Option<usize>,
+    pub flags: Flags,
+    pub user_agent: String,
+    pub platform: Platform,
+    /// This instance's host, for the URLs pointing back at it.
+    pub host: String,
+    pub url: Url,
+    pub config: &'a Config,
+}
+
+impl<'a> EmbedContext<'a> {
+    /// Parses the request. The slide is `?img_index`; media routes replace it.
+    pub fn new(req: &Request, post_id: String, config: &'a Config) -> Result<Self> {
+        let url = req.url().map_err(|e| Error::RustError(e.to_string()))?;
+        let user_agent = user_agent(req);
+        Ok(Self {
+            post_id,
+            slide: parse_img_index(&url),
+            flags: Flags::from_url(&url, config),
+            platform: Platform::from_user_agent(&user_agent),
+            user_agent,
+            host: url.host_str().unwrap_or("cattgram.com").to_string(),
+            url,
+            config,
+        })
+    }
+
+    pub fn is_bot(&self) -> bool {
+        is_bot(&self.user_agent)
+    }
+
+    /// The `img_index` asked for, if the post has no such slide. Values that
+    /// aren't numbers are ignored, as they are outside strict mode.
+    pub fn missing_slide(&self, media_count: usize) -> Option<usize> {
+        let (_, value) = self.url.query_pairs().find(|(k, _)| k == "img_index")?;
+        let requested = value.parse::<usize>().ok()?;
+        (requested == 0 || requested > media_count).then_some(requested)
+    }
+
+    /// The embed page's options, showing `comment` over the post if given.
+    pub fn embed_options<'b>(&'b self, comment: Option<&'b Comment>) -> EmbedOptions<'b> {
+        EmbedOptions {
+            host: &self.host,
+            img_index: self.slide,
+            playback: Playback { gif: self.flags.gif, platform: self.platform },
+            comment,
+            show_top_comment: self.flags.top_comment,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn context<'a>(url: Url, config: &'a Config) -> EmbedContext<'a> {
+        EmbedContext {
+            post_id: "CxTest123".to_string(),
+            slide: parse_img_index(&url),
+            flags: Flags::from_url(&url, config),
+            user_agent: String::new(),
+            platform: Platform::Other,
+            host: "cattgram.com".to_string(),
+            url,
+            config,
+        }
+    }
+
+    #[test]
+    fn reads_flags_from_the_query_and_path() {
+        let config = Config::default();
+        let flags = Flags::from_url(&url("https://cattgram.com/p/CxTest123/?direct=true&comments=1"), &config);
+        assert!(flags.direct && flags.top_comment);
+        assert!(!flags.gif && !flags.preview && !flags.strict && !flags.download && !flags.proxy);
+
+        assert!(Flags::from_url(&url("https://cattgram.com/gif/CxTest123"), &config).gif);
+        assert!(Flags::from_url(&url("https://cattgram.com/p/CxTest123/?comments=true"), &config).top_comment);
+        // Only `true` switches a flag on
+        assert!(!Flags::from_url(&url("https://cattgram.com/p/CxTest123/?direct=1&comments=yes"), &config).direct);
+        assert!(!Flags::from_url(&url("https://cattgram.com/p/CxTest123/?comments=yes"), &config).top_comment);
+    }
+
+    #[test]
+    fn strict_comes_from_the_query_or_config() {
+        let config = Config::default();
+        assert!(Flags::from_url(&url("https://cattgram.com/p/CxTest123/?strict=true"), &config).strict);
+
+        let config = Config { strict_img_index: true, ..Config::default() };
+        assert!(Flags::from_url(&url("https://cattgram.com/p/CxTest123/"), &config).strict);
+    }
+
+    #[test]
+    fn parses_img_index_from_one() {
+        assert_eq!(parse_img_index(&url("https://cattgram.com/p/CxTest123/?img_index=3")), Some(3));
+        assert_eq!(parse_img_index(&url("https://cattgram.com/p/CxTest123/?img_index=0")), None);
+        assert_eq!(parse_img_index(&url("https://cattgram.com/p/CxTest123/?img_index=two")), None);
+        assert_eq!(parse_img_index(&url("https://cattgram.com/p/CxTest123/")), None);
+    }
+
+    #[test]
+    fn reports_slides_the_post_does_not_have() {
+        let config = Config::default();
+        let missing = |query: &str| context(url(&format!("https://cattgram.com/p/CxTest123/{query}")), &config).missing_slide(3);
+        assert_eq!(missing("?img_index=2"), None);
+        assert_eq!(missing("?img_index=3"), None);
+        assert_eq!(missing("?img_index=4"), Some(4));
+        assert_eq!(missing("?img_index=0"), Some(0));
+        assert_eq!(missing("?img_index=two"), None);
+        assert_eq!(missing(""), None);
+    }
+}
@@ -1,10 +1,36 @@
+use std::rc::Rc;
+
+use futures_util::future::{join, join_all};
+
 use url::Url;
 use worker::*;
 
-use crate::scraper::fetch_post_data;
-use crate::templates::embed_html::render_embed;
-use crate::utils::bot_detect::is_bot;
-use crate::utils::instagram::{extract_post_id, mediaid_to_code};
+use crate::scraper::cache::{self, evict_cached_background, set_background, Kind};
+use crate::scraper::instrument::instrumented;
+use crate::scraper::types::{InstaData, MediaType, PostKind};
+use crate::scraper::{fetch_comment_data, fetch_embed, fetch_hashtag_top_post, fetch_screenshot, Fetched};
+use crate::alt_text::describe_image;
+use crate::summarize::{needs_summary, summarize_caption};
+use crate::translate::{detect_language, translate_caption, translation_target};
+use crate::templates::embed_html::{
+    render_embed_chunks, render_expired_story, render_geo_blocked, render_missing_slide, render_screenshot, render_search_stub,
+    render_timed_out,
+};
+use crate::utils::bot_detect::{is_search_crawler, is_telegram};
+use crate::utils::http::{embed_etag, revalidate, CachePolicy, CacheStatus, EmbedVariant};
+use crate::utils::instagram::{extract_post_id, mediaid_to_code, parse_hashtag, parse_media_id, share_params, share_url};
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+use crate::utils::transform::{blur_sensitive_media, telegram_oversized, telegram_safe_images};
+use crate::analytics::record_view;
+use crate::config::Config;
+use crate::AppState;
+
+use super::context::EmbedContext;
+
+/// Names the backend behind an embed's data, and whether it was cached
+/// (see `InstaData::source`).
+const SOURCE_HEADER: &str = "X-Cattgram-Source";
 
 /// Redirect to the original Instagram post.
 fn redirect_to_instagram(post_id: &str) -> Result<Response> {
@@ -12,28 +38,122 @@ fn redirect_to_instagram(post_id: &str) -> Result<Response> {
     Response::redirect(Url::parse(&url).map_err(|e| Error::RustError(e.to_string()))?)
 }
 
-/// Resolves a numeric story ID to a shortcode, or returns the input unchanged.
+fn hashtag_url(tag: &str) -> String {
+    format!("https://www.instagram.com/explore/tags/{}/", tag)
+}
+
+/// Redirect to a hashtag's page on Instagram.
+fn redirect_to_hashtag(tag: &str) -> Result<Response> {
+    Response::redirect(Url::parse(&hashtag_url(tag)).map_err(|e| Error::RustError(e.to_string()))?)
+}
+
+/// Returns the `commentID` route param of a comment permalink, if it is numeric.
+fn route_comment_id(ctx: &RouteContext<Rc<AppState>>) -> Option<String> {
+    ctx.param("commentID")
+        .filter(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        .cloned()
+}
+
+/// Resolves a numeric story or media ID to a shortcode, or returns the input unchanged.
 fn resolve_post_id(raw: &str) -> String {
-    if raw.chars().all(|c| c.is_ascii_digit()) {
-        if let Ok(numeric_id) = raw.parse::<u64>() {
-            return mediaid_to_code(numeric_id);
+    match parse_media_id(raw) {
+        Some(numeric_id) => mediaid_to_code(numeric_id),
+        None => raw.to_string(),
+    }
+}
+
+/// Most HEAD requests `validate_media` makes for one embed.
+const MAX_MEDIA_PROBES: usize = 4;
+
+/// Sends a HEAD request for a media URL.
+async fn probe(url: &str, env: &Env) -> Result<Response> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Head);
+    instrumented(env).fetch(Fetch::Request(Request::new_with_init(url, &init)?)).await
+}
+
+/// Returns `false` if the CDN says a media URL is gone. Our own network
+/// errors don't count, so they never cause a swap.
+async fn media_loads(url: &str, env: &Env) -> bool {
+    match probe(url, env).await.map(|resp| resp.status_code()) {
+        Ok(status) => !matches!(status, 403 | 404 | 410),
+        Err(e) => {
+            console_log!("[embed] media probe error: {:?}", e);
+            true
         }
     }
-    raw.to_string()
 }
 
-/// Extracts the `img_index` query parameter (1-based) from a URL.
-fn parse_img_index(url: &Url) -> Option<usize> {
-    url.query_pairs()
-        .find(|(k, _)| k == "img_index")
-        .and_then(|(_, v)| v.parse::<usize>().ok())
-        .filter(|&n| n >= 1)
+/// Checks that the selected slide's media still loads (`VALIDATE_MEDIA`).
+/// A dead video falls back to its thumbnail, like a withheld one; a dead
+/// image to the next slide that loads. Returns the slide to render
+/// (1-based) and whether anything was dead.
+async fn validate_media(data: &mut InstaData, img_index: Option<usize>, env: &Env) -> (Option<usize>, bool) {
+    let count = data.media.len();
+    let index = img_index.map(|i| i.saturating_sub(1)).unwrap_or(0).min(count.saturating_sub(1));
+    let Some(media) = data.media.get(index).filter(|m| !m.is_blocked_video()) else {
+        return (img_index, false);
+    };
+    if media_loads(&media.url, env).await {
+        return (img_index, false);
+    }
+
+    let mut probes = 1;
+    if media.media_type == MediaType::Video {
+        if let Some(thumbnail) = media.thumbnail_url.clone() {
+            probes += 1;
+            if media_loads(&thumbnail, env).await {
+                data.media[index].url.clear();
+                return (img_index, true);
+            }
+        }
+    }
+    for other in (1..count).map(|offset| (index + offset) % count) {
+        if probes >= MAX_MEDIA_PROBES {
+            break;
+        }
+        let candidate = &data.media[other];
+        if candidate.media_type == MediaType::Image {
+            probes += 1;
+            if media_loads(&candidate.url, env).await {
+                return (Some(other + 1), true);
+            }
+        }
+    }
+    (img_index, true)
 }
 
-/// Returns `true` if the `direct` query parameter is set to "true".
-fn is_direct(url: &Url) -> bool {
-    url.query_pairs()
-        .any(|(k, v)| k == "direct" && v == "true")
+/// The `Content-Length` of each slide's preview image, for Telegram (see
+/// `telegram_safe_images`). Only images whose dimensions look fine are
+/// probed, at most `MAX_MEDIA_PROBES` from the selected slide on, all at
+/// once; the rest are `None`.
+async fn preview_sizes(data: &InstaData, img_index: Option<usize>, env: &Env) -> Vec<Option<u64>> {
+    let start = img_index.map(|i| i.saturating_sub(1)).unwrap_or(0);
+    let probed = data
+        .media
+        .iter()
+        .enumerate()
+        .skip(start)
+        .filter(|(_, m)| !telegram_oversized(m))
+        .filter_map(|(i, m)| Some((i, m.still_url()?)))
+        .take(MAX_MEDIA_PROBES);
+    let lengths = join_all(probed.map(|(i, url)| async move {
+        let length = match probe(url, env).await {
+            Ok(resp) => resp.headers().get("Content-Length").ok().flatten().and_then(|l| l.parse().ok()),
+            Err(e) => {
+                console_log!("[embed] size probe error: {:?}", e);
+                None
+            }
+        };
+        (i, length)
+    }))
+    .await;
+
+    let mut sizes = vec![None; data.media.len()];
+    for (i, length) in lengths {
+        sizes[i] = length;
+    }
+    sizes
 }
 
 /// Maximum number of redirects to follow when resolving share URLs.
@@ -42,9 +162,11 @@ const MAX_REDIRECTS: u8 = 5;
 /// Follows a share URL redirect chain to extract the real post ID.
 ///
 /// Uses `RequestRedirect::Manual` to intercept 3xx responses and read the
-/// `Location` header. Follows up to `MAX_REDIRECTS` hops.
-async fn resolve_share_url(share_path: &str) -> Result<Option<String>> {
-    let mut current_url = format!("https://www.instagram.com/{}", share_path);
+/// `Location` header. Follows up to `MAX_REDIRECTS` hops. The inbound share
+/// identifiers (`igsh`) go on the first request; some share links don't
+/// resolve without them.
+async fn resolve_share_url(share_path: &str, params: &[(String, String)], env: &Env) -> Result<Option<String>> {
+    let mut current_url = share_url(share_path, params);
 
     for _ in 0..MAX_REDIRECTS {
         let headers = Headers::new();
@@ -56,7 +178,7 @@ async fn resolve_share_url(share_path: &str) -> Result<Option<String>> {
             .with_redirect(RequestRedirect::Manual);
 
         let request = Request::new_with_init(&current_url, &init)?;
-        let resp = Fetch::Request(request).send().await?;
+        let resp = instrumented(env).fetch(Fetch::Request(request)).await?;
 
         let status = resp.status_code();
         if (300..400).contains(&status) {
@@ -86,91 +208,444 @@ async fn resolve_share_url(share_path: &str) -> Result<Option<String>> {
     Ok(None)
 }
 
-pub async fn handle(req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    // 1. Extract post ID from route params
-    let raw_post_id = ctx
-        .param("postID")
+/// Like `resolve_share_url`, through the cache: a share link seen before
+/// needs no redirect round trips. New resolutions are remembered after the
+/// response is sent.
+///
+/// The scrape still waits for the resolution. Resolving stops at the first
+/// hop that names the shortcode, so there are no later hops to overlap it
+/// with.
+async fn resolve_share(share_path: &str, params: &[(String, String)], env: &Env, state: &AppState) -> Result<Option<String>> {
+    let share_id = share_path.trim_start_matches("share/");
+    match cache::get::<String>(Kind::Share, share_id, env).await {
+        Ok(Some(post_id)) => {
+            console_log!("[embed] share cache HIT for {} -> {}", share_id, post_id);
+            return Ok(Some(post_id));
+        }
+        Ok(None) => {}
+        Err(e) => console_log!("[embed] share cache error: {:?}", e),
+    }
+
+    let resolved = resolve_share_url(share_path, params, env).await?;
+    if let Some(post_id) = &resolved {
+        set_background(Kind::Share, share_id, post_id, env, state);
+    }
+    Ok(resolved)
+}
+
+/// Returns the raw post ID route param (`postID`, `storyID` for stories, or
+/// `mediaID` for numeric media IDs).
+fn route_post_id(ctx: &RouteContext<Rc<AppState>>) -> String {
+    ctx.param("postID")
         .or_else(|| ctx.param("storyID"))
+        .or_else(|| ctx.param("mediaID"))
         .cloned()
-        .unwrap_or_default();
+        .unwrap_or_default()
+}
+
+/// The cache kind of a story route's item, or `None` for posts. Items of
+/// `/stories/highlights/:id` belong to a highlight, not to a user's stories.
+fn story_kind(ctx: &RouteContext<Rc<AppState>>) -> Option<Kind> {
+    ctx.param("storyID")?;
+    match ctx.param("username") {
+        Some(username) if username == "highlights" => Some(Kind::Highlight),
+        _ => Some(Kind::Story),
+    }
+}
+
+/// HEAD handler for embed routes.
+///
+/// Several link-preview crawlers probe with HEAD before GET. This answers from
+/// the cache when the post is cached and never triggers a scrape — the GET
+/// that follows does that.
+pub async fn handle_head(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let config = &ctx.data.config;
+    let embed = EmbedContext::new(&req, resolve_post_id(&route_post_id(&ctx)), config)?;
 
-    if raw_post_id.is_empty() {
+    // A hashtag has no post until the GET looks up its top post
+    if let Some(raw) = ctx.param("tag") {
+        let Some(tag) = parse_hashtag(raw) else {
+            return Response::error("Not Found", 404);
+        };
+        if !embed.is_bot() {
+            return redirect_to_hashtag(&tag);
+        }
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/html; charset=utf-8")?;
+        return Ok(Response::empty()?.with_headers(headers));
+    }
+
+    if embed.post_id.is_empty() || !embed.is_bot() {
+        return redirect_to_instagram(&embed.post_id);
+    }
+
+    let headers = Headers::new();
+    headers.set("Content-Type", "text/html; charset=utf-8")?;
+
+    let cached = cache::get::<InstaData>(story_kind(&ctx).unwrap_or(Kind::Post), &embed.post_id, &ctx.env).await;
+    if let Ok(Some(data)) = cached {
+        if embed.flags.strict && embed.missing_slide(data.media.len()).is_some() {
+            return Ok(Response::empty()?.with_status(404).with_headers(headers));
+        }
+        let etag = embed_etag(&data, embed.slide, embed.platform);
+        if revalidate(&req, &headers, Some(&etag), data.scraped_at)? {
+            return Ok(Response::empty()?.with_status(304).with_headers(headers));
+        }
+    }
+
+    Ok(Response::empty()?.with_headers(headers))
+}
+
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    // 1. Extract post ID from route params
+    let raw_post_id = route_post_id(&ctx);
+    handle_post(req, ctx, raw_post_id).await
+}
+
+/// Like `handle`, for a post the route doesn't name, e.g. a profile's
+/// newest post.
+pub async fn handle_post(req: Request, ctx: RouteContext<Rc<AppState>>, raw_post_id: String) -> Result<Response> {
+    let hashtag = match ctx.param("tag").map(|raw| parse_hashtag(raw)) {
+        Some(None) => return Response::error("Not Found", 404),
+        tag => tag.flatten(),
+    };
+
+    if raw_post_id.is_empty() && hashtag.is_none() {
         return redirect_to_instagram("");
     }
 
-    // 2. Resolve numeric story IDs to shortcodes
-    let mut post_id = resolve_post_id(&raw_post_id);
+    // 2. Resolve numeric story IDs to shortcodes, and parse the rest of the
+    // request (query flags, slide, platform, host) once
+    let config = &ctx.data.config;
+    let mut embed = EmbedContext::new(&req, resolve_post_id(&raw_post_id), config)?;
 
-    // 3. Parse query params
-    let req_url = req.url().map_err(|e| Error::RustError(e.to_string()))?;
-    let img_index = parse_img_index(&req_url);
-    let direct = is_direct(&req_url);
+    // Search engines would only index a mirror of Instagram: they get a
+    // stub, before any share link is resolved or anything scraped
+    if is_search_crawler(&embed.user_agent) {
+        console_log!("[embed] search crawler for {}, serving the noindex stub", embed.post_id);
+        let instagram_url = match (&hashtag, ctx.param("username").filter(|_| ctx.param("storyID").is_some())) {
+            (Some(tag), _) => hashtag_url(tag),
+            (None, Some(username)) => format!("https://www.instagram.com/stories/{}/{}/", username, raw_post_id),
+            (None, None) => format!("https://www.instagram.com/p/{}/", embed.post_id),
+        };
+        return Response::from_html(minify_html(&render_search_stub(&instagram_url, config)));
+    }
 
-    // 4. Handle share URLs (post_id starts with "share")
-    if post_id.starts_with("share") {
+    // 3. Handle share URLs (post_id starts with "share")
+    if embed.post_id.starts_with("share") {
         // The route would match /p/share/... so the param would be "share"
         // and the extra segment holds the share ID. Reconstruct the share path.
         let extra = ctx.param("extra").cloned().unwrap_or_default();
         let share_path = if extra.is_empty() {
-            format!("share/{}", post_id.trim_start_matches("share/").trim_start_matches("share"))
+            format!("share/{}", embed.post_id.trim_start_matches("share/").trim_start_matches("share"))
         } else {
             format!("share/{}", extra)
         };
 
-        match resolve_share_url(&share_path).await {
-            Ok(Some(resolved)) => post_id = resolved,
-            _ => return redirect_to_instagram(&post_id),
+        let params = share_params(&embed.url);
+        if !params.is_empty() {
+            console_log!("[embed] share {} with params {:?}", share_path, params);
+        }
+        match resolve_share(&share_path, &params, &ctx.env, &ctx.data).await {
+            Ok(Some(resolved)) => embed.post_id = resolved,
+            // Let Instagram try the share link itself, identifiers included
+            _ => return Response::redirect(Url::parse(&share_url(&share_path, &params)).map_err(|e| Error::RustError(e.to_string()))?),
         }
     }
 
-    // 5. Bot detection: non-bots get redirected to Instagram
-    let ua = req
-        .headers()
-        .get("User-Agent")
-        .unwrap_or(None)
-        .unwrap_or_default();
+    // 4. Bot detection: non-bots get redirected to Instagram, or to the viewer
+    // page in preview mode
+    console_log!("[embed] post_id={} ua={} is_bot={}", embed.post_id, embed.user_agent, embed.is_bot());
 
-    console_log!("[embed] post_id={} ua={} is_bot={}", post_id, ua, is_bot(&ua));
+    let comment_id = route_comment_id(&ctx);
+
+    if !embed.is_bot() {
+        if let Some(tag) = &hashtag {
+            return redirect_to_hashtag(tag);
+        }
+        if let Some(comment_id) = &comment_id {
+            let url = format!("https://www.instagram.com/p/{}/c/{}/", embed.post_id, comment_id);
+            return Response::redirect(Url::parse(&url).map_err(|e| Error::RustError(e.to_string()))?);
+        }
+        if embed.flags.preview {
+            let mut url = embed.url.join(&format!("/view/{}", embed.post_id)).map_err(|e| Error::RustError(e.to_string()))?;
+            url.set_query(embed.slide.map(|i| format!("img_index={i}")).as_deref());
+            return Response::redirect(url);
+        }
+        return redirect_to_instagram(&embed.post_id);
+    }
 
-    if !is_bot(&ua) {
-        return redirect_to_instagram(&post_id);
+    // 4b. Hashtag pages embed their top post, or redirect to the page
+    // itself when it has none (or it can't be looked up)
+    if let Some(tag) = &hashtag {
+        match fetch_hashtag_top_post(tag, &ctx.env, &ctx.data).await {
+            Ok(Some(top_post)) => {
+                console_log!("[embed] top post of #{} is {}", tag, top_post);
+                embed.post_id = top_post;
+            }
+            Ok(None) => return redirect_to_hashtag(tag),
+            Err(e) => {
+                console_log!("[embed] hashtag lookup error: {:?}", e);
+                return redirect_to_hashtag(tag);
+            }
+        }
     }
 
-    // 6. Fetch Instagram data
-    let data = match fetch_post_data(&post_id, &ctx.env).await {
+    // 5. Fetch Instagram data. Stories have their own cache policy, and get an
+    // "expired" card rather than a redirect once they're gone. Highlights
+    // keep their items past the 24 hours. Geo-blocked posts get a card
+    // saying so, as do scrapes that ran out of time. Posts only the browser
+    // could see get a screenshot card.
+    let story_username = ctx.param("storyID").map(|_| ctx.param("username").cloned().unwrap_or_default());
+    let story_kind = story_kind(&ctx);
+    let started_ms = Date::now().as_millis();
+    let now_secs = started_ms / 1000;
+    let story = story_kind.map(|kind| (kind, story_username.as_deref()));
+    let fetched = fetch_embed(&embed, story, &ctx.env, &ctx.data, now_secs).await;
+    let fetched = match fetched {
+        Ok(Fetched::GeoBlocked) if !embed.flags.direct && story_username.is_none() => {
+            console_log!("[embed] post {} is geo-blocked", embed.post_id);
+            return Response::from_html(minify_html(&render_geo_blocked(&embed.post_id, config)));
+        }
+        Ok(Fetched::TimedOut) if !embed.flags.direct => {
+            console_log!("[embed] ran out of time for {}, sending a degraded card", embed.post_id);
+            let instagram_url = match &story_username {
+                Some(username) => format!("https://www.instagram.com/stories/{}/{}/", username, raw_post_id),
+                None => format!("https://www.instagram.com/p/{}/", embed.post_id),
+            };
+            // The next attempt may well succeed, so nobody should keep this
+            let html = minify_html(&render_timed_out(&instagram_url, config));
+            return CachePolicy::NoStore.apply(Response::from_html(html)?);
+        }
+        Ok(Fetched::Unreadable) if !embed.flags.direct && story_username.is_none() => {
+            let spent = Date::now().as_millis().saturating_sub(started_ms);
+            return screenshot_or_redirect(&embed, &ctx, config.scrape_budget_ms.saturating_sub(spent)).await;
+        }
+        // Only a story no backend has is gone; errors take the usual path
+        Ok(Fetched::Missing) if story_username.is_some() => {
+            console_log!("[embed] no data for story {}, assuming it has expired", embed.post_id);
+            return expired_story(story_username.as_deref().unwrap_or_default(), config);
+        }
+        fetched => fetched.map(Fetched::into_data),
+    };
+
+    let mut data = match fetched {
+        Ok(Some(data)) if story_kind == Some(Kind::Story) && data.story_remaining_secs(now_secs) == Some(0) => {
+            console_log!("[embed] story {} has expired", embed.post_id);
+            return expired_story(&data.username, config);
+        }
         Ok(Some(data)) => {
             console_log!("[embed] got data: username={} media_count={}", data.username, data.media.len());
             data
         }
         Ok(None) => {
             console_log!("[embed] no data found, redirecting to instagram");
-            return redirect_to_instagram(&post_id);
+            return redirect_to_instagram(&embed.post_id);
         }
         Err(e) => {
-            console_log!("[embed] fetch error: {:?}", e);
-            return redirect_to_instagram(&post_id);
+            console_log!("[embed] fetch error, redirecting to instagram: {:?}", e);
+            return redirect_to_instagram(&embed.post_id);
+        }
+    };
+    // Scrapes that don't say what the post is go by the route it came in on
+    data.kind = data.kind.or_else(|| PostKind::from_path(embed.url.path()));
+    data.hashtag = hashtag;
+    if story_username.is_none() {
+        record_view(&embed.post_id, &ctx.env, &ctx.data.ctx, now_secs);
+    }
+
+    // Strict mode: a slide past the end is a 404, for bots enumerating slides
+    if let Some(requested) = embed.missing_slide(data.media.len()).filter(|_| embed.flags.strict) {
+        console_log!("[embed] {} has no slide {}", embed.post_id, requested);
+        let html = minify_html(&render_missing_slide(&data.instagram_url(), requested, data.media.len(), config));
+        return Ok(Response::from_html(html)?.with_status(404));
+    }
+
+    // 6. Comment permalinks: the comment is shown over the post's media.
+    // If it can't be fetched, the post embed is still better than nothing.
+    // With VALIDATE_MEDIA, the selected media is checked meanwhile.
+    let comment = async {
+        match &comment_id {
+            Some(comment_id) if !embed.flags.direct => {
+                match fetch_comment_data(&embed.post_id, comment_id, &ctx.env, &ctx.data).await {
+                    Ok(comment) => comment,
+                    Err(e) => {
+                        console_log!("[embed] comment fetch error: {:?}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    };
+    let comment = if config.validate_media && !embed.flags.direct {
+        let (comment, (slide, dead)) = join(comment, validate_media(&mut data, embed.slide, &ctx.env)).await;
+        if dead && story_username.is_none() {
+            // Its other URLs are likely just as dead; scrape fresh ones next time
+            console_log!("[embed] media for {} no longer loads, evicting it from the cache", embed.post_id);
+            evict_cached_background(&embed.post_id, &ctx.env, &ctx.data.ctx);
         }
+        embed.slide = slide;
+        comment
+    } else {
+        comment.await
     };
 
     // 7. Direct media redirect
-    if direct {
-        let media_index = img_index
+    if embed.flags.direct {
+        let media_index = embed
+            .slide
             .map(|i| i.saturating_sub(1))
             .unwrap_or(0)
             .min(data.media.len().saturating_sub(1));
 
-        if let Some(media) = data.media.get(media_index) {
-            let redirect_url =
-                Url::parse(&media.url).map_err(|e| Error::RustError(e.to_string()))?;
+        // A withheld video still has its cover frame
+        if let Some(url) = data.media.get(media_index).and_then(|m| m.video_url().or_else(|| m.still_url())) {
+            let redirect_url = Url::parse(url).map_err(|e| Error::RustError(e.to_string()))?;
             return Response::redirect(redirect_url);
         }
 
-        return redirect_to_instagram(&post_id);
+        return redirect_to_instagram(&embed.post_id);
+    }
+
+    // 8. Revalidation: crawlers re-fetching an unchanged embed get a 304.
+    // Every answer from here on says where its data came from
+    let etag = embed_etag(&data, embed.slide, embed.platform);
+    let headers = Headers::new();
+    headers.set(SOURCE_HEADER, &data.source())?;
+    headers.set(CacheStatus::HEADER, CacheStatus::of(&data, now_secs).as_str())?;
+    if revalidate(&req, &headers, Some(&etag), data.scraped_at)? {
+        console_log!("[embed] {} not modified, returning 304", embed.post_id);
+        return Ok(Response::empty()?.with_status(304).with_headers(headers));
+    }
+
+    // 9. Rendered HTML cache, one entry per platform class and language.
+    // Comment permalinks show the comment instead of the caption, which
+    // is neither translated nor cached. Hashtag embeds, titled with the
+    // hashtag, aren't cached either. Cached variants report the HTML
+    // cache's outcome in X-Cache rather than the post data's.
+    let accept_language = req.headers().get("Accept-Language")?.unwrap_or_default();
+    let translate_to = translation_target(&embed.url, &accept_language)
+        .filter(|&target| data.caption.as_deref().and_then(detect_language).is_some_and(|source| source != target))
+        .filter(|_| comment_id.is_none());
+    let variant = EmbedVariant {
+        etag: &etag,
+        img_index: embed.slide,
+        platform: embed.platform,
+        gif: embed.flags.gif,
+        top_comment: embed.flags.top_comment,
+        lang: translate_to,
+        host: &embed.host,
+    }
+    .key();
+    let html_key = format!("{}:{variant}", embed.post_id);
+    let cache_html = comment_id.is_none() && story_username.is_none() && data.hashtag.is_none() && !data.canary;
+    if cache_html {
+        match cache::get::<[String; 2]>(Kind::Html, &html_key, &ctx.env).await {
+            Ok(Some(chunks)) => {
+                console_log!("[embed] HTML cache HIT for {} ({})", embed.post_id, variant);
+                headers.set(CacheStatus::HEADER, CacheStatus::Hit.as_str())?;
+                return stream_html(chunks, headers);
+            }
+            Ok(None) => {}
+            Err(e) => console_log!("[embed] HTML cache error: {:?}", e),
+        }
+        // Rendered for this request, whatever the post data's outcome
+        headers.set(CacheStatus::HEADER, CacheStatus::Miss.as_str())?;
+    }
+
+    // 10. Translate the caption into the reader's language, summarize
+    // captions still too long for the embed (SUMMARIZE_CAPTIONS), and
+    // describe the image of captionless posts (AI_ALT_TEXT). Pages missing a
+    // failed translation, summary or description aren't cached, so the next
+    // request tries again.
+    let mut complete = true;
+    if let (Some(caption), Some(target)) = (data.caption.as_deref(), translate_to) {
+        match translate_caption(caption, &embed.post_id, target, &ctx.env, &ctx.data).await {
+            Some(caption) => data.caption = Some(caption),
+            None => complete = false,
+        }
+    }
+    if let Some(caption) = data.caption.as_deref().filter(|c| config.summarize_captions && needs_summary(c)) {
+        // The summary is in the caption's language unless it was meant to
+        // be translated, so the HTML cache variant still covers it
+        let lang = translate_to.or_else(|| detect_language(caption)).unwrap_or_default();
+        match summarize_caption(caption, &embed.post_id, lang, &ctx.env, &ctx.data).await {
+            Some(caption) => data.caption = Some(caption),
+            None => complete = false,
+        }
+    }
+    if let Some(index) = needs_alt_text(&data, embed.slide, config) {
+        let media = &data.media[index];
+        let image_url = media.thumbnail_url.as_deref().unwrap_or(&media.url);
+        match describe_image(&embed.post_id, index + 1, image_url, &ctx.env, &ctx.data).await {
+            Some(alt) => data.media[index].alt_text = Some(alt),
+            None => complete = false,
+        }
     }
 
-    // 8. Generate embed HTML
-    let host = req_url.host_str().unwrap_or("cattgram.com").to_string();
-    let html = render_embed(&data, &host, img_index);
-    console_log!("[embed] returning HTML, first 1000 chars: {}", &html[..html.len().min(1000)]);
-    Response::from_html(html)
+    // 11. Generate embed HTML
+    if is_telegram(&embed.user_agent) {
+        let sizes = preview_sizes(&data, embed.slide, &ctx.env).await;
+        telegram_safe_images(&mut data, &embed.host, &sizes);
+    }
+    if data.sensitive && !config.show_sensitive {
+        blur_sensitive_media(&mut data, &embed.host);
+    }
+    let options = embed.embed_options(comment.as_ref());
+    let chunks = render_embed_chunks(&data, config, &options).map(|chunk| minify_html(&chunk));
+    console_log!("[embed] returning HTML, first 1000 chars: {}", &chunks[0][..chunks[0].len().min(1000)]);
+    if cache_html && complete {
+        set_background(Kind::Html, &html_key, &chunks, &ctx.env, &ctx.data);
+    }
+    stream_html(chunks, headers)
+}
+
+/// For a post that exists but nothing could be scraped from: a card with a
+/// screenshot of its page, when Browser Rendering can take one within
+/// `budget_millis`, else the redirect to Instagram.
+async fn screenshot_or_redirect(
+    embed: &EmbedContext<'_>,
+    ctx: &RouteContext<Rc<AppState>>,
+    budget_millis: u64,
+) -> Result<Response> {
+    let post_id = &embed.post_id;
+    match fetch_screenshot(post_id, &ctx.env, &ctx.data, budget_millis).await {
+        Ok(Some(_)) => {
+            console_log!("[embed] sending the screenshot card for {}", post_id);
+            let image_url = format!("https://{}/screenshot/{}", embed.host, post_id);
+            return Response::from_html(minify_html(&render_screenshot(post_id, &image_url, embed.config)));
+        }
+        Ok(None) => {}
+        Err(e) => console_log!("[embed] screenshot error: {:?}", e),
+    }
+    console_log!("[embed] redirecting to instagram");
+    redirect_to_instagram(post_id)
+}
+
+/// The index of the selected slide, if it's an image to describe with
+/// `AI_ALT_TEXT`: the post has no caption and the image no alt text.
+/// Sensitive posts are left alone.
+fn needs_alt_text(data: &InstaData, img_index: Option<usize>, config: &Config) -> Option<usize> {
+    if !config.ai_alt_text || data.sensitive || data.caption.as_deref().is_some_and(|c| !c.trim().is_empty()) {
+        return None;
+    }
+    let index = img_index.map(|i| i.saturating_sub(1)).unwrap_or(0).min(data.media.len().saturating_sub(1));
+    data.media
+        .get(index)
+        .filter(|m| m.media_type == MediaType::Image && !m.url.is_empty() && m.alt_text.is_none())
+        .map(|_| index)
+}
+
+fn expired_story(username: &str, config: &Config) -> Result<Response> {
+    Response::from_html(minify_html(&render_expired_story(username, config)))
+}
+
+/// Streams pre-rendered HTML chunks as the response body, in order.
+fn stream_html(chunks: [String; 2], headers: Headers) -> Result<Response> {
+    let stream = futures_util::stream::iter(chunks.map(|chunk| Ok::<_, Error>(chunk.into_bytes())));
+
+    headers.set("Content-Type", "text/html; charset=utf-8")?;
+
+    Ok(Response::from_stream(stream)?.with_headers(headers))
 }
@@ -1,7 +1,13 @@
+use std::rc::Rc;
+
 use worker::*;
 
+use crate::i18n::request_lang;
 use crate::templates::home_html::render_home;
+use crate::utils::minify::minify_html;
+use crate::AppState;
 
-pub fn handle(_req: Request, _ctx: RouteContext<()>) -> Result<Response> {
-    Response::from_html(render_home())
+pub fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let lang = request_lang(&req, &ctx.data.config)?;
+    Response::from_html(minify_html(&render_home(&ctx.data.config, lang)))
 }
@@ -0,0 +1,67 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::handlers::api::deprecate;
+use crate::i18n::request_lang;
+use crate::scraper::fetch_post_data;
+use crate::templates::links_html::{render_links, PostLinks};
+use crate::turnstile;
+use crate::utils::bot_detect::is_bot;
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+use crate::AppState;
+
+/// Lists every media item of a post with proxy links, dimensions and types.
+///
+/// Route: `/links/:postID`. Humans get an HTML page; bots, `?format=json` and
+/// `Accept: application/json` get JSON.
+///
+/// The HTML page is behind a Turnstile challenge when one is configured,
+/// unless API keys already guard the route (POST redeems the challenge).
+/// JSON clients can't solve one, so they get JSON as before.
+pub async fn handle(mut req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let json = wants_json(&req, &req.url()?)?;
+    if !json && !ctx.data.config.api_keys_required {
+        if let Some(challenge) = turnstile::guard(&mut req, &ctx.env, &ctx.data.config).await? {
+            return Ok(challenge);
+        }
+    }
+    respond(req, ctx, json).await
+}
+
+/// Returns `true` if the client asked for JSON, or is a bot.
+fn wants_json(req: &Request, url: &Url) -> Result<bool> {
+    if url.query_pairs().any(|(k, v)| k == "format" && v == "json") {
+        return Ok(true);
+    }
+    let accept = req.headers().get("Accept")?.unwrap_or_default();
+    let user_agent = req.headers().get("User-Agent")?.unwrap_or_default();
+    Ok(accept.contains("application/json") || is_bot(&user_agent))
+}
+
+async fn respond(req: Request, ctx: RouteContext<Rc<AppState>>, json: bool) -> Result<Response> {
+    let post_id = ctx.param("postID").cloned().unwrap_or_default();
+    let req_url = req.url()?;
+
+    let data = match fetch_post_data(&post_id, &ctx.env, &ctx.data).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return Response::error("Post not found", 404),
+        Err(e) => {
+            console_log!("[links] fetch error: {:?}", e);
+            return Response::error("Failed to fetch post", 502);
+        }
+    };
+
+    let host = req_url.host_str().unwrap_or("cattgram.com");
+    let links = PostLinks::new(&data, host);
+    let resp = if json {
+        // Same schema as v1 of the versioned API, which is where JSON clients should go
+        deprecate(Response::from_json(&links)?, &format!("/api/v1/posts/{}", links.post_id))?
+    } else {
+        let lang = request_lang(&req, &ctx.data.config)?;
+        Response::from_html(minify_html(&render_links(&links, &ctx.data.config, lang)))?
+    };
+
+    Ok(resp)
+}
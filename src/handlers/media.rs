@@ -1,8 +1,20 @@
+use std::rc::Rc;
+
 use url::Url;
 use worker::*;
 
-use crate::scraper::fetch_post_data;
-use crate::scraper::types::MediaType;
+use crate::config::Config;
+use crate::handlers::context::EmbedContext;
+use crate::scraper::cache::evict_cached_background;
+use crate::scraper::{fetch_cached_post, fetch_fresh_post_data};
+use crate::scraper::instrument::instrumented;
+use crate::scraper::types::{InstaData, MediaType};
+use crate::templates::embed_html::missing_slide_message;
+use crate::utils::http::{CachePolicy, CacheStatus};
+use crate::utils::log::console_log;
+use crate::utils::mime::{extension_for, from_extension, sniff, split_extension};
+use crate::utils::transform::{frame_url, parse_timestamp, ImageFormat, ImageTransform};
+use crate::AppState;
 
 /// Redirect to the original Instagram post.
 fn redirect_to_instagram(post_id: &str) -> Result<Response> {
@@ -10,67 +22,413 @@ fn redirect_to_instagram(post_id: &str) -> Result<Response> {
     Response::redirect(Url::parse(&url).map_err(|e| Error::RustError(e.to_string()))?)
 }
 
+/// For a slide past the end: a 404 saying so in strict mode (see
+/// `context::Flags`), else the redirect to Instagram.
+fn missing_media(embed: &EmbedContext, params: &MediaParams, media_count: usize) -> Result<Response> {
+    if embed.flags.strict {
+        return Response::error(missing_slide_message(params.media_num, media_count), 404);
+    }
+    redirect_to_instagram(&params.post_id)
+}
+
 /// Redirect to a media URL.
 fn redirect_to_url(media_url: &str) -> Result<Response> {
     let parsed = Url::parse(media_url).map_err(|e| Error::RustError(e.to_string()))?;
     Response::redirect(parsed)
 }
 
-/// Extracts the `postID` and `mediaNum` (1-based) from route params.
-fn extract_params(ctx: &RouteContext<()>) -> Option<(String, usize)> {
+/// Route params of a media request.
+struct MediaParams {
+    post_id: String,
+    /// 1-based media index.
+    media_num: usize,
+    /// MIME type of the extension in `/:mediaNum.ext`, if one was given.
+    ext_mime: Option<&'static str>,
+}
+
+impl MediaParams {
+    /// `{post_id}_{media_num}`, the stem of served filenames.
+    fn file_stem(&self) -> String {
+        format!("{}_{}", self.post_id, self.media_num)
+    }
+}
+
+/// Extracts the `postID` and `mediaNum` (1-based, optionally with a file
+/// extension like `2.jpg`) from route params.
+///
+/// Returns `None` for a bad index, or an extension whose MIME type doesn't
+/// start with `mime_prefix` (e.g. `/videos/X/1.jpg`).
+fn extract_params(ctx: &RouteContext<Rc<AppState>>, mime_prefix: &str) -> Option<MediaParams> {
     let post_id = ctx.param("postID")?.to_string();
-    let media_num: usize = ctx.param("mediaNum")?.parse().ok()?;
-    if media_num >= 1 {
-        Some((post_id, media_num))
-    } else {
-        None
+    let (num, ext) = split_extension(ctx.param("mediaNum")?);
+    let media_num: usize = num.parse().ok().filter(|&n| n >= 1)?;
+
+    let ext_mime = match ext {
+        Some(ext) => Some(from_extension(ext).filter(|mime| mime.starts_with(mime_prefix))?),
+        None => None,
+    };
+
+    Some(MediaParams { post_id, media_num, ext_mime })
+}
+
+/// The rest of a media request, with the route's `mediaNum` as its slide.
+fn media_context<'a>(req: &Request, params: &MediaParams, config: &'a Config) -> Result<EmbedContext<'a>> {
+    Ok(EmbedContext {
+        slide: Some(params.media_num),
+        ..EmbedContext::new(req, params.post_id.clone(), config)?
+    })
+}
+
+/// The post a media request is served from, for evicting it from the cache
+/// once the CDN stops serving its media.
+struct CachedPost<'a> {
+    post_id: &'a str,
+    env: &'a Env,
+    state: &'a AppState,
+}
+
+impl CachedPost<'_> {
+    /// Evicts the post if the CDN answered 403 or 410: its signed URLs are
+    /// dead, so the next request should scrape fresh ones.
+    fn check_status(&self, status: u16) {
+        if matches!(status, 403 | 410) {
+            console_log!("[media] CDN answered {} for {}, evicting it from the cache", status, self.post_id);
+            evict_cached_background(self.post_id, self.env, &self.state.ctx);
+        }
+    }
+}
+
+/// Sends a response built from a stale post (one whose re-scrape failed)
+/// with `no-store`, so the edge doesn't keep a link that's about to die.
+fn keep_stale_out_of_edge(status: CacheStatus, resp: Response) -> Result<Response> {
+    match status {
+        CacheStatus::Stale => CachePolicy::NoStore.apply(resp),
+        CacheStatus::Hit | CacheStatus::Miss => Ok(resp),
+    }
+}
+
+/// How proxied media should be presented by the client.
+#[derive(Debug, Clone, Copy)]
+enum Disposition {
+    /// Render in place (`Content-Disposition: inline`).
+    Inline,
+    /// Save to disk (`?download=true`).
+    Attachment,
+}
+
+impl Disposition {
+    /// The `Content-Disposition` value. The filename is built from scraped
+    /// data and the request path, so anything but ASCII letters, digits,
+    /// `.`, `_` and `-` becomes `_`: no quotes, line breaks or path
+    /// separators reach the header.
+    fn header(self, filename: &str) -> String {
+        let kind = match self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        };
+        let filename: String = filename
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' })
+            .collect();
+        format!("{kind}; filename=\"{filename}\"")
+    }
+}
+
+/// HEAD handler for `/images`, answered from the cache only.
+pub async fn images_head(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    head(&ctx, "image/").await
+}
+
+/// HEAD handler for `/videos`, answered from the cache only.
+pub async fn videos_head(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    head(&ctx, "video/").await
+}
+
+/// Answers a HEAD request without scraping: a cached post redirects to the
+/// slide's media, as the GET would (downloads and resizes excepted, which
+/// the GET serves itself). An uncached post, or one whose CDN URLs have
+/// expired, gets a bare `200` nobody keeps; the GET that follows does the
+/// scrape. `X-Cache` says which it was.
+async fn head(ctx: &RouteContext<Rc<AppState>>, mime_prefix: &str) -> Result<Response> {
+    let Some(params) = extract_params(ctx, mime_prefix) else {
+        return Response::error("Bad Request", 400);
+    };
+
+    let Ok(Some(data)) = fetch_cached_post(&params.post_id, &ctx.env).await else {
+        return CacheStatus::Miss.apply(CachePolicy::NoStore.apply(Response::empty()?)?);
+    };
+    let status = CacheStatus::of(&data, Date::now().as_millis() / 1000);
+    if status == CacheStatus::Stale {
+        return status.apply(CachePolicy::NoStore.apply(Response::empty()?)?);
+    }
+    status.apply(match plain_location(&data, params.media_num, mime_prefix) {
+        Some(url) => redirect_to_url(url)?,
+        None => redirect_to_instagram(&params.post_id)?,
+    })
+}
+
+/// Where a plain GET for slide `media_num` redirects: the video on
+/// `/videos`, else (or for image slides) the still image.
+fn plain_location<'a>(data: &'a InstaData, media_num: usize, mime_prefix: &str) -> Option<&'a str> {
+    let media = data.media.get(media_num.checked_sub(1)?)?;
+    match mime_prefix {
+        "video/" => media.video_url().or_else(|| media.still_url()),
+        _ => media.still_url(),
     }
 }
 
 /// Direct image redirect handler.
 ///
-/// Route: `/images/:postID/:mediaNum`
+/// Route: `/images/:postID/:mediaNum` (or `:mediaNum.jpg`, `.png`, `.webp`, ...)
 /// Fetches the post, selects the Nth media item (1-based), and redirects to its image URL.
-pub async fn images(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    let (post_id, media_num) = match extract_params(&ctx) {
-        Some(params) => params,
-        None => return Response::error("Bad Request", 400),
+/// Video slides redirect to their cover frame. With resize options (`?w=800&format=webp`), serves a resized copy instead.
+/// For videos, `?t=N` redirects to the frame N seconds in. A `.png`, `.webp`
+/// or `.avif` extension implies that output format. `?download=true` proxies
+/// the image as an attachment named `<username>_<postID>_<n>.<ext>`.
+pub async fn images(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let Some(params) = extract_params(&ctx, "image/") else {
+        return Response::error("Bad Request", 400);
     };
+    let embed = media_context(&req, &params, &ctx.data.config)?;
 
-    let data = match fetch_post_data(&post_id, &ctx.env).await {
+    let data = match fetch_fresh_post_data(&embed, &ctx.env, &ctx.data).await {
         Ok(Some(data)) => data,
-        _ => return redirect_to_instagram(&post_id),
+        _ => return redirect_to_instagram(&embed.post_id),
+    };
+
+    let status = CacheStatus::of(&data, Date::now().as_millis() / 1000);
+    status.apply(keep_stale_out_of_edge(status, serve_image(&embed, &params, &data, &ctx).await?)?)
+}
+
+/// Answers an `/images` request for a post that was found.
+async fn serve_image(
+    embed: &EmbedContext<'_>,
+    params: &MediaParams,
+    data: &InstaData,
+    ctx: &RouteContext<Rc<AppState>>,
+) -> Result<Response> {
+    let post_id = &params.post_id;
+    let index = params.media_num - 1;
+    let mut transform = ImageTransform::from_query(&embed.url);
+
+    // Instagram serves JPEG; any other extension asks for a re-encode
+    let ext_format = params.ext_mime.and_then(extension_for).and_then(ImageFormat::parse);
+    if let Some(format) = ext_format.filter(|&f| f != ImageFormat::Jpeg) {
+        transform.get_or_insert_default().format.get_or_insert(format);
+    }
+
+    // Video frame at `?t=N` seconds, for when the cover frame is a poor thumbnail
+    if let (Some(media), Some(seconds)) = (data.media.get(index), parse_timestamp(&embed.url)) {
+        if media.media_type == MediaType::Video && !media.is_blocked_video() {
+            return redirect_to_url(&frame_url(&embed.host, &media.url, seconds, transform));
+        }
+    }
+
+    let post = CachedPost { post_id, env: &ctx.env, state: &ctx.data };
+    let Some(media) = data.media.get(index) else {
+        return missing_media(embed, params, data.media.len());
     };
+    // A video slide is served as its cover frame, else as its first frame
+    let Some(image_url) = media.still_url() else {
+        return match media.video_url() {
+            Some(video_url) => redirect_to_url(&frame_url(&embed.host, video_url, 0, transform)),
+            None => redirect_to_instagram(post_id),
+        };
+    };
+
+    if embed.flags.download {
+        let file_stem = format!("{}_{}", data.username, params.file_stem());
+        return match transform {
+            Some(transform) => fetch_resized(&post, image_url, transform, &file_stem, Disposition::Attachment).await,
+            None => proxy_media(&post, image_url, &file_stem, Disposition::Attachment).await,
+        };
+    }
+
+    match transform {
+        Some(transform) => fetch_resized(&post, image_url, transform, &params.file_stem(), Disposition::Inline).await,
+        None => redirect_to_url(image_url),
+    }
+}
 
-    let index = media_num - 1;
-    match data.media.get(index) {
-        Some(media) if media.media_type == MediaType::Image => redirect_to_url(&media.url),
-        Some(media) if media.thumbnail_url.is_some() => {
-            // Video with a thumbnail: return the thumbnail as the "image"
-            redirect_to_url(media.thumbnail_url.as_ref().unwrap())
+/// Fetches `image_url` through Cloudflare Image Resizing.
+///
+/// Falls back to a plain redirect if resizing fails (e.g. not enabled on the
+/// zone), so clients always get the image. Blurred requests fail instead, so
+/// a sensitive image is never served unblurred.
+async fn fetch_resized(
+    post: &CachedPost<'_>,
+    image_url: &str,
+    transform: ImageTransform,
+    file_stem: &str,
+    disposition: Disposition,
+) -> Result<Response> {
+    let mut init = RequestInit::new();
+    init.with_cf_properties(CfProperties {
+        image: Some(transform.to_resize_config()),
+        ..Default::default()
+    });
+
+    let request = Request::new_with_init(image_url, &init)?;
+    let failed = match instrumented(post.env).fetch(Fetch::Request(request)).await {
+        Ok(resp) if resp.status_code() == 200 => return buffered_media(resp, file_stem, disposition).await,
+        Ok(resp) => {
+            post.check_status(resp.status_code());
+            format!("status {}", resp.status_code())
         }
-        _ => redirect_to_instagram(&post_id),
+        Err(e) => format!("{:?}", e),
+    };
+    if transform.blur.is_some() {
+        console_log!("[media] blur failed ({}) for {}", failed, image_url);
+        return Response::error("Image transform unavailable", 502);
     }
+    console_log!("[media] resize failed ({}) for {}, redirecting", failed, image_url);
+    redirect_to_url(image_url)
+}
+
+/// Proxies `media_url` as-is, falling back to a redirect if the CDN refuses.
+///
+/// Videos are streamed through; anything else is small enough to buffer and sniff.
+async fn proxy_media(post: &CachedPost<'_>, media_url: &str, file_stem: &str, disposition: Disposition) -> Result<Response> {
+    let mut resp = match instrumented(post.env).fetch(Fetch::Url(Url::parse(media_url)?)).await {
+        Ok(resp) if resp.status_code() == 200 => resp,
+        Ok(resp) => {
+            post.check_status(resp.status_code());
+            console_log!("[media] proxy returned {} for {}, redirecting", resp.status_code(), media_url);
+            return redirect_to_url(media_url);
+        }
+        Err(e) => {
+            console_log!("[media] proxy error: {:?}, redirecting", e);
+            return redirect_to_url(media_url);
+        }
+    };
+
+    let content_type = resp.headers().get("Content-Type")?.unwrap_or_default();
+    if !content_type.starts_with("video/") {
+        return buffered_media(resp, file_stem, disposition).await;
+    }
+
+    let ext = extension_for(&content_type).unwrap_or("mp4");
+    let headers = Headers::new();
+    headers.set("Content-Type", &content_type)?;
+    headers.set("Content-Disposition", &disposition.header(&format!("{file_stem}.{ext}")))?;
+    if let Some(length) = resp.headers().get("Content-Length")? {
+        headers.set("Content-Length", &length)?;
+    }
+
+    Ok(Response::from_stream(resp.stream()?)?.with_headers(headers))
+}
+
+/// Re-serves fetched media with a sniffed `Content-Type` and a
+/// `Content-Disposition` carrying a proper filename, since some clients
+/// won't render media without either.
+async fn buffered_media(mut resp: Response, file_stem: &str, disposition: Disposition) -> Result<Response> {
+    let bytes = resp.bytes().await?;
+    let content_type = match sniff(&bytes) {
+        Some(mime) => mime.to_string(),
+        None => resp
+            .headers()
+            .get("Content-Type")?
+            .unwrap_or_else(|| "application/octet-stream".to_string()),
+    };
+
+    let headers = Headers::new();
+    headers.set("Content-Type", &content_type)?;
+    if let Some(ext) = extension_for(&content_type) {
+        headers.set("Content-Disposition", &disposition.header(&format!("{file_stem}.{ext}")))?;
+    }
+
+    Ok(Response::from_bytes(bytes)?.with_headers(headers))
 }
 
 /// Direct video redirect handler.
 ///
-/// Route: `/videos/:postID/:mediaNum`
+/// Route: `/videos/:postID/:mediaNum` (or `:mediaNum.mp4`)
 /// Fetches the post, selects the Nth media item (1-based), and redirects to its video URL.
-pub async fn videos(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    let (post_id, media_num) = match extract_params(&ctx) {
-        Some(params) => params,
-        None => return Response::error("Bad Request", 400),
+/// Image slides, and videos Instagram withheld, redirect to their still image instead.
+/// `?download=true` streams the video as an attachment named `<username>_<postID>_<n>.mp4`,
+/// and `?proxy=true` streams it inline.
+pub async fn videos(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let Some(params) = extract_params(&ctx, "video/") else {
+        return Response::error("Bad Request", 400);
     };
+    let embed = media_context(&req, &params, &ctx.data.config)?;
 
-    let data = match fetch_post_data(&post_id, &ctx.env).await {
+    let data = match fetch_fresh_post_data(&embed, &ctx.env, &ctx.data).await {
         Ok(Some(data)) => data,
-        _ => return redirect_to_instagram(&post_id),
+        _ => return redirect_to_instagram(&embed.post_id),
+    };
+
+    let status = CacheStatus::of(&data, Date::now().as_millis() / 1000);
+    status.apply(keep_stale_out_of_edge(status, serve_video(&embed, &params, &data, &ctx).await?)?)
+}
+
+/// Answers a `/videos` request for a post that was found.
+async fn serve_video(
+    embed: &EmbedContext<'_>,
+    params: &MediaParams,
+    data: &InstaData,
+    ctx: &RouteContext<Rc<AppState>>,
+) -> Result<Response> {
+    let post_id = &params.post_id;
+    let index = params.media_num - 1;
+    let Some(media) = data.media.get(index) else {
+        return missing_media(embed, params, data.media.len());
     };
+    let Some(video_url) = media.video_url() else {
+        return match media.still_url() {
+            Some(image_url) => redirect_to_url(image_url),
+            None => redirect_to_instagram(post_id),
+        };
+    };
+
+    let disposition = if embed.flags.download {
+        Some(Disposition::Attachment)
+    } else if embed.flags.proxy {
+        Some(Disposition::Inline)
+    } else {
+        None
+    };
+    if let Some(disposition) = disposition {
+        let file_stem = format!("{}_{}", data.username, params.file_stem());
+        let post = CachedPost { post_id, env: &ctx.env, state: &ctx.data };
+        return proxy_media(&post, video_url, &file_stem, disposition).await;
+    }
+    redirect_to_url(video_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::from_cache;
+    use crate::scraper::http::fixtures::{image, post, video};
+
+    #[test]
+    fn disposition_filenames_are_sanitized() {
+        assert_eq!(Disposition::Attachment.header("cat_CxAbC_1.jpg"), "attachment; filename=\"cat_CxAbC_1.jpg\"");
+        assert_eq!(
+            Disposition::Inline.header("a\"b\r\nSet-Cookie: x/../é_1.mp4"),
+            "inline; filename=\"a_b__Set-Cookie__x_..___1.mp4\""
+        );
+    }
+
+    #[test]
+    fn head_redirects_where_get_does_for_a_cached_post() {
+        // Cached before URLs were normalized
+        let tracked = "https://scontent.cdninstagram.com/v/t51/1.jpg?stp=dst-jpg&_nc_gid=abc&oe=7FFFFFFF";
+        let clean = "https://scontent.cdninstagram.com/v/t51/1.jpg?stp=dst-jpg&oe=7FFFFFFF";
+        let clip = "https://scontent.cdninstagram.com/o1/v/t16/2.mp4?_nc_gid=abc&oe=7FFFFFFF";
+        let mut cached = post("CxAbc", "catlover", vec![image(tracked), video(clip)]);
+        cached.media[1].thumbnail_url = Some(tracked.to_string());
 
-    let index = media_num - 1;
-    match data.media.get(index) {
-        Some(media) if media.media_type == MediaType::Video => redirect_to_url(&media.url),
-        _ => redirect_to_instagram(&post_id),
+        // GET serves the post as fetch_post reads it from the cache, and so does HEAD
+        let data = from_cache(cached);
+        assert_eq!(plain_location(&data, 1, "image/"), data.media[0].still_url());
+        assert_eq!(plain_location(&data, 1, "image/"), Some(clean));
+        assert_eq!(plain_location(&data, 2, "image/"), Some(clean));
+        assert_eq!(plain_location(&data, 2, "video/"), data.media[1].video_url());
+        assert!(!plain_location(&data, 2, "video/").unwrap().contains("_nc_gid"));
+        assert_eq!(plain_location(&data, 3, "video/"), None);
+        assert_eq!(plain_location(&data, 0, "image/"), None);
+        assert_eq!(CacheStatus::of(&data, 1_700_000_000), CacheStatus::Hit);
     }
 }
@@ -0,0 +1,59 @@
+use std::rc::Rc;
+
+use futures_util::future::join_all;
+use serde::Serialize;
+use worker::*;
+
+use crate::metrics::{Route, RouteStats};
+use crate::scraper::cache::get_route_stats;
+use crate::scraper::stats::recent_buckets;
+use crate::AppState;
+
+/// One route's totals over the window.
+#[derive(Serialize)]
+struct Summary {
+    route: &'static str,
+    requests: u64,
+    errors: u64,
+    avg_millis: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Hour {
+    /// Hours since the Unix epoch.
+    bucket: u64,
+    routes: RouteStats,
+}
+
+#[derive(Serialize)]
+struct Report {
+    totals: Vec<Summary>,
+    hours: Vec<Hour>,
+}
+
+fn summarize(totals: &RouteStats) -> Vec<Summary> {
+    Route::ALL
+        .into_iter()
+        .map(|route| {
+            let counter = totals.get(route);
+            Summary { route: route.name(), requests: counter.total(), errors: counter.failed, avg_millis: counter.avg_millis() }
+        })
+        .collect()
+}
+
+/// Per-route request counts, errors and latency over the last 24 hours.
+///
+/// Route: `/admin/metrics`, behind the admin layer.
+pub async fn handle(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let now_secs = Date::now().as_millis() / 1000;
+    let buckets: Vec<u64> = recent_buckets(now_secs).collect();
+    let stats = join_all(buckets.iter().map(|&bucket| get_route_stats(bucket, &ctx.env))).await;
+    let hours: Vec<Hour> = buckets
+        .into_iter()
+        .zip(stats)
+        .map(|(bucket, stats)| Hour { bucket, routes: stats.ok().flatten().unwrap_or_default() })
+        .collect();
+    let totals = RouteStats::sum(hours.iter().map(|hour| &hour.routes));
+
+    Response::from_json(&Report { totals: summarize(&totals), hours })
+}
@@ -1,4 +1,20 @@
+pub mod api;
+pub mod backends;
+pub mod config_check;
+pub mod context;
 pub mod embed;
 pub mod home;
+pub mod links;
 pub mod media;
+pub mod metrics;
 pub mod oembed;
+pub mod openapi;
+pub mod options;
+pub mod profile;
+pub mod qr;
+pub mod screenshot;
+pub mod shortlinks;
+pub mod status;
+pub mod top_posts;
+pub mod usage;
+pub mod view;
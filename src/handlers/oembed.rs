@@ -1,23 +1,57 @@
+use std::rc::Rc;
+
+use serde::Serialize;
 use url::Url;
 use worker::*;
 
-pub async fn handle(req: Request, _ctx: RouteContext<()>) -> Result<Response> {
+use crate::utils::instagram::is_cdn_url;
+use crate::AppState;
+
+/// Side length of the `profile_pic_url` Instagram serves with posts.
+const AVATAR_SIZE: u32 = 150;
+
+/// oEmbed `link` response.
+#[derive(Debug, Serialize)]
+pub struct OEmbed {
+    pub author_name: String,
+    pub author_url: String,
+    pub provider_name: String,
+    pub provider_url: String,
+    pub title: &'static str,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_height: Option<u32>,
+}
+
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
     let req_url = req.url().map_err(|e| Error::RustError(e.to_string()))?;
+    let host = req_url.host_str().unwrap_or("cattgram.com");
 
-    let text = get_query_param(&req_url, "text").unwrap_or_default();
-    let url = get_query_param(&req_url, "url").unwrap_or_default();
+    // The author's avatar doubles as the thumbnail; only Instagram's own CDN
+    // is accepted, so the endpoint can't be used to point cards elsewhere.
+    let avatar = get_query_param(&req_url, "avatar").filter(|a| is_cdn_url(a));
+    let size = avatar.as_ref().map(|_| AVATAR_SIZE);
 
-    let json = serde_json::json!({
-        "author_name": text,
-        "author_url": url,
-        "provider_name": "Cattgram",
-        "provider_url": "https://cattgram.com",
-        "title": "Instagram",
-        "type": "link",
-        "version": "1.0"
-    });
+    let oembed = OEmbed {
+        author_name: get_query_param(&req_url, "text").unwrap_or_default(),
+        author_url: get_query_param(&req_url, "url").unwrap_or_default(),
+        provider_name: ctx.data.config.site_name.clone(),
+        provider_url: format!("https://{host}"),
+        title: "Instagram",
+        kind: "link",
+        version: "1.0",
+        thumbnail_url: avatar,
+        thumbnail_width: size,
+        thumbnail_height: size,
+    };
 
-    let body = serde_json::to_string(&json)
+    let body = serde_json::to_string(&oembed)
         .map_err(|e| Error::RustError(format!("JSON serialization error: {e}")))?;
 
     let headers = Headers::new();
@@ -0,0 +1,15 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::openapi::document;
+use crate::AppState;
+
+/// Serves the OpenAPI document for the JSON endpoints.
+///
+/// Route: `/api/openapi.json`.
+pub fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let req_url = req.url()?;
+    let host = req_url.host_str().unwrap_or("cattgram.com");
+    Response::from_json(&document(&ctx.data.config, host))
+}
@@ -0,0 +1,21 @@
+use worker::*;
+
+use crate::utils::cors::ALLOWED_METHODS;
+
+/// Global OPTIONS handler: answers preflight and capability probes for any
+/// path with the allowed methods. CORS headers are added by the caller.
+pub fn handle(cors: &Cors) -> Result<Response> {
+    let allow = ALLOWED_METHODS
+        .iter()
+        .map(|m| m.as_ref())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let headers = Headers::new();
+    headers.set("Allow", &allow)?;
+
+    Response::empty()?
+        .with_status(204)
+        .with_headers(headers)
+        .with_cors(cors)
+}
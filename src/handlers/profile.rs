@@ -0,0 +1,219 @@
+use std::rc::Rc;
+
+use url::Url;
+use worker::*;
+
+use crate::handlers::embed;
+use crate::scraper::instrument::instrumented;
+use crate::scraper::{fetch_latest_post, fetch_profile_data};
+use crate::templates::embed_html::render_search_stub;
+use crate::templates::profile_html::render_profile;
+use crate::templates::rss_xml::render_feed;
+use crate::utils::mosaic::{Mosaic, MAX_TILES};
+use crate::utils::bot_detect::{is_bot, is_search_crawler};
+use crate::utils::http::CachePolicy;
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+use crate::AppState;
+
+/// Well-known root files that look like usernames but never are.
+const RESERVED: [&str; 3] = ["favicon.ico", "robots.txt", "apple-touch-icon.png"];
+
+/// Returns `true` for a syntactically valid Instagram username.
+fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && !RESERVED.contains(&username)
+        && username.len() <= 30
+        && username.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_')
+}
+
+fn redirect_to_profile(username: &str) -> Result<Response> {
+    let url = format!("https://www.instagram.com/{}/", username);
+    Response::redirect(Url::parse(&url).map_err(|e| Error::RustError(e.to_string()))?)
+}
+
+/// Profile embed handler.
+///
+/// Route: `/:username`. Bots get a profile card from `web_profile_info`,
+/// search crawlers a `noindex` stub; everyone else is redirected to the
+/// profile on Instagram.
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let username = ctx.param("username").cloned().unwrap_or_default();
+    if !is_valid_username(&username) {
+        return Response::error("Not Found", 404);
+    }
+
+    let ua = req.headers().get("User-Agent")?.unwrap_or_default();
+    if is_search_crawler(&ua) {
+        let instagram_url = format!("https://www.instagram.com/{}/", username);
+        return Response::from_html(minify_html(&render_search_stub(&instagram_url, &ctx.data.config)));
+    }
+    if !is_bot(&ua) {
+        return redirect_to_profile(&username);
+    }
+
+    let req_url = req.url()?;
+    let host = req_url.host_str().unwrap_or("cattgram.com");
+
+    match fetch_profile_data(&username, &ctx.env, &ctx.data).await {
+        Ok(Some(profile)) => Response::from_html(minify_html(&render_profile(&profile, &ctx.data.config, host))),
+        Ok(None) => redirect_to_profile(&username),
+        Err(e) => {
+            console_log!("[profile] fetch error: {:?}", e);
+            redirect_to_profile(&username)
+        }
+    }
+}
+
+/// The post `/latest/:username` embeds, or `None` to send the client to
+/// the profile: it's private or unknown, or the lookup failed.
+fn latest_post_id(username: &str, lookup: Result<Option<String>>) -> Option<String> {
+    match lookup {
+        Ok(Some(post_id)) => {
+            console_log!("[profile] latest post of {} is {}", username, post_id);
+            Some(post_id)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            console_log!("[profile] latest post error: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Latest-post embed, for "what did X just post" bot commands.
+///
+/// Route: `/latest/:username`. Serves the embed of the user's newest post,
+/// as `/p/:postID` would with the same query (`img_index`, `gif`, ...). The
+/// post is looked up again every few minutes. Private or unknown profiles
+/// redirect to Instagram, uncached, so a profile that starts posting isn't
+/// hidden behind a stale redirect.
+pub async fn latest(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let username = ctx.param("username").cloned().unwrap_or_default();
+    if !is_valid_username(&username) {
+        return Response::error("Not Found", 404);
+    }
+
+    let lookup = fetch_latest_post(&username, &ctx.env, &ctx.data).await;
+    match latest_post_id(&username, lookup) {
+        Some(post_id) => embed::handle_post(req, ctx, post_id).await,
+        None => CachePolicy::NoStore.apply(redirect_to_profile(&username)?),
+    }
+}
+
+/// RSS feed of a profile's recent posts, for feed readers and bots that
+/// post new ones to a chat.
+///
+/// Route: `/rss/:username`. Served to everyone, bot or not. Unknown
+/// profiles (or failed lookups) redirect to Instagram, uncached.
+pub async fn rss(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let username = ctx.param("username").cloned().unwrap_or_default();
+    if !is_valid_username(&username) {
+        return Response::error("Not Found", 404);
+    }
+
+    let profile = match fetch_profile_data(&username, &ctx.env, &ctx.data).await {
+        Ok(Some(profile)) => profile,
+        result => {
+            if let Err(e) = result {
+                console_log!("[profile] feed fetch error: {:?}", e);
+            }
+            return CachePolicy::NoStore.apply(redirect_to_profile(&username)?);
+        }
+    };
+
+    let req_url = req.url()?;
+    let host = req_url.host_str().unwrap_or("cattgram.com");
+    let headers = Headers::new();
+    headers.set("Content-Type", "application/rss+xml; charset=utf-8")?;
+    Ok(Response::ok(render_feed(&profile, &ctx.data.config, host))?.with_headers(headers))
+}
+
+/// Recent-posts grid for profile cards.
+///
+/// Route: `/grid/:username` (optionally `.jpg`). Composes the newest post
+/// thumbnails into one JPEG with Image Resizing. Falls back to redirecting to
+/// the avatar, or 404s when there's nothing to show.
+pub async fn grid(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let raw = ctx.param("username").cloned().unwrap_or_default();
+    let username = raw.strip_suffix(".jpg").unwrap_or(&raw);
+    if !is_valid_username(username) {
+        return Response::error("Not Found", 404);
+    }
+
+    let profile = match fetch_profile_data(username, &ctx.env, &ctx.data).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return Response::error("Not Found", 404),
+        Err(e) => {
+            console_log!("[profile] fetch error: {:?}", e);
+            return Response::error("Not Found", 404);
+        }
+    };
+
+    let thumbnails: Vec<String> = profile
+        .recent_posts
+        .iter()
+        .take(MAX_TILES)
+        .map(|post| post.thumbnail_url.clone())
+        .collect();
+    let fallback = profile.avatar_url.as_deref();
+
+    let Some(mosaic) = Mosaic::new(&thumbnails).filter(|_| !profile.is_private) else {
+        return match fallback {
+            Some(avatar) => Response::redirect(Url::parse(avatar)?),
+            None => Response::error("Not Found", 404),
+        };
+    };
+
+    match fetch_mosaic(&mosaic, &ctx.env).await {
+        Ok(mut resp) if resp.status_code() == 200 => {
+            let headers = Headers::new();
+            headers.set("Content-Type", "image/jpeg")?;
+            headers.set("Content-Disposition", &format!("inline; filename=\"{username}_grid.jpg\""))?;
+            Ok(Response::from_stream(resp.stream()?)?.with_headers(headers))
+        }
+        result => {
+            match result {
+                Ok(resp) => console_log!("[profile] mosaic returned {}", resp.status_code()),
+                Err(e) => console_log!("[profile] mosaic error: {:?}", e),
+            }
+            match fallback {
+                Some(avatar) => Response::redirect(Url::parse(avatar)?),
+                None => Response::redirect(Url::parse(mosaic.base_url())?),
+            }
+        }
+    }
+}
+
+/// Fetches the mosaic's base image with its `cf.image` options.
+///
+/// `RequestInit::with_cf_properties` can't carry `draw` lists, so the
+/// request is built on the raw `web_sys` types.
+async fn fetch_mosaic(mosaic: &Mosaic, env: &Env) -> Result<Response> {
+    let cf = js_sys::JSON::parse(&mosaic.cf_properties().to_string())?;
+    let init = web_sys::RequestInit::new();
+    js_sys::Reflect::set(init.as_ref(), &"cf".into(), &cf)?;
+    let req = web_sys::Request::new_with_str_and_init(mosaic.base_url(), &init)?;
+    instrumented(env).fetch(Fetch::Request(req.into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_usernames() {
+        assert!(is_valid_username("cat.lover_99"));
+        assert!(!is_valid_username(""));
+        assert!(!is_valid_username("favicon.ico"));
+        assert!(!is_valid_username("cat!"));
+        assert!(!is_valid_username(&"a".repeat(31)));
+    }
+
+    #[test]
+    fn latest_embeds_the_newest_post_or_falls_back_to_the_profile() {
+        assert_eq!(latest_post_id("catlover", Ok(Some("CxNew1".to_string()))).as_deref(), Some("CxNew1"));
+        assert_eq!(latest_post_id("catlover", Ok(None)), None);
+        assert_eq!(latest_post_id("catlover", Err(Error::RustError("timeout".to_string()))), None);
+    }
+}
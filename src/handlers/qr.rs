@@ -0,0 +1,123 @@
+use std::rc::Rc;
+
+use url::Url;
+use worker::*;
+
+use crate::handlers::context::parse_img_index;
+use crate::utils::instagram::is_shortcode;
+use crate::utils::qr::QrCode;
+use crate::AppState;
+
+/// Pixels per module in PNGs, unless `?scale=` says otherwise.
+const DEFAULT_SCALE: usize = 8;
+
+/// Largest `?scale=`: a version 10 code is then about 1000 pixels wide.
+const MAX_SCALE: usize = 16;
+
+/// Where a QR code sends whoever scans it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QrTarget {
+    /// The post on Instagram, which opens in the app on phones.
+    Instagram,
+    /// This worker's embed URL, e.g. to share on from the phone.
+    Embed,
+}
+
+/// The query options of a QR request.
+#[derive(Debug, PartialEq)]
+struct QrParams {
+    target: QrTarget,
+    img_index: Option<usize>,
+    /// PNG pixels per module, or `None` for SVG.
+    png_scale: Option<usize>,
+}
+
+impl QrParams {
+    fn from_url(url: &Url) -> Self {
+        let param = |key: &str| url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned());
+        let target = match param("target").as_deref() {
+            Some("embed") => QrTarget::Embed,
+            _ => QrTarget::Instagram,
+        };
+        let png_scale = (param("format").as_deref() == Some("png")).then(|| {
+            param("scale")
+                .and_then(|s| s.parse::<usize>().ok())
+                .map_or(DEFAULT_SCALE, |s| s.clamp(1, MAX_SCALE))
+        });
+        Self { target, img_index: parse_img_index(url), png_scale }
+    }
+
+    /// The URL encoded in the code. `origin` is this worker's, for embeds.
+    fn link(&self, post_id: &str, origin: &str) -> String {
+        let base = match self.target {
+            QrTarget::Instagram => "https://www.instagram.com",
+            QrTarget::Embed => origin,
+        };
+        match self.img_index {
+            Some(index) => format!("{base}/p/{post_id}/?img_index={index}"),
+            None => format!("{base}/p/{post_id}/"),
+        }
+    }
+}
+
+/// A QR code linking to a post, for carrying a link over to a phone.
+///
+/// Route: `/qr/:postID`. SVG by default, `?format=png` (with `?scale=`) for a
+/// PNG. Points at Instagram, or at the embed with `?target=embed`;
+/// `img_index` pins a slide. Nothing is fetched, so unknown posts still get
+/// a code.
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let post_id = ctx.param("postID").cloned().unwrap_or_default();
+    if !is_shortcode(&post_id) {
+        return Response::error("Not Found", 404);
+    }
+    let url = req.url()?;
+    let params = QrParams::from_url(&url);
+    let link = params.link(&post_id, &url.origin().ascii_serialization());
+    let Some(qr) = QrCode::encode(link.as_bytes()) else {
+        return Response::error("Link too long for a QR code", 400);
+    };
+
+    let headers = Headers::new();
+    let resp = match params.png_scale {
+        Some(scale) => {
+            headers.set("Content-Type", "image/png")?;
+            Response::from_bytes(qr.to_png(scale))?
+        }
+        None => {
+            headers.set("Content-Type", "image/svg+xml")?;
+            Response::ok(qr.to_svg())?
+        }
+    };
+    Ok(resp.with_headers(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(query: &str) -> QrParams {
+        QrParams::from_url(&Url::parse(&format!("https://cattgram.com/qr/ABC123{query}")).unwrap())
+    }
+
+    #[test]
+    fn links_to_instagram_as_svg_by_default() {
+        let p = params("");
+        assert_eq!(p, QrParams { target: QrTarget::Instagram, img_index: None, png_scale: None });
+        assert_eq!(p.link("ABC123", "https://cattgram.com"), "https://www.instagram.com/p/ABC123/");
+    }
+
+    #[test]
+    fn embed_target_keeps_the_slide() {
+        let p = params("?target=embed&img_index=3");
+        assert_eq!(p.link("ABC123", "https://cattgram.com"), "https://cattgram.com/p/ABC123/?img_index=3");
+    }
+
+    #[test]
+    fn png_scale_is_clamped() {
+        assert_eq!(params("?format=png").png_scale, Some(DEFAULT_SCALE));
+        assert_eq!(params("?format=png&scale=100").png_scale, Some(MAX_SCALE));
+        assert_eq!(params("?format=png&scale=0").png_scale, Some(1));
+        assert_eq!(params("?scale=4").png_scale, None);
+    }
+}
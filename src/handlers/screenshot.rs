@@ -0,0 +1,29 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::scraper::cache::get_cached_screenshot;
+use crate::utils::http::CachePolicy;
+use crate::utils::instagram::is_shortcode;
+use crate::AppState;
+
+/// The screenshot a post's embed used as its `og:image`.
+///
+/// Route: `/screenshot/:postID`. Served from KV only: screenshots are taken
+/// by the embed, never here, so this route can't be used to spend browser
+/// time. Not found until the embed has taken one.
+pub async fn handle(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let post_id = ctx.param("postID").cloned().unwrap_or_default();
+    if !is_shortcode(&post_id) {
+        return Response::error("Not Found", 404);
+    }
+    match get_cached_screenshot(&post_id, &ctx.env).await? {
+        Some(jpeg) => {
+            let headers = Headers::new();
+            headers.set("Content-Type", "image/jpeg")?;
+            Ok(Response::from_bytes(jpeg)?.with_headers(headers))
+        }
+        // It may exist a moment later
+        None => CachePolicy::NoStore.apply(Response::error("Not Found", 404)?),
+    }
+}
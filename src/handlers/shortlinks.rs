@@ -0,0 +1,104 @@
+use std::rc::Rc;
+
+use serde::Serialize;
+use worker::*;
+
+use crate::auth::authenticate;
+use crate::scraper::cache::{get_short_link, put_short_link};
+use crate::shortlinks::{is_valid_code, new_code, ShortLink, StoredLink};
+use crate::utils::http::CachePolicy;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// The minting route.
+pub const MINT_PATH: &str = "/api/shortlinks";
+
+/// Codes tried before giving up on finding an unused one.
+const MINT_ATTEMPTS: usize = 3;
+
+/// The response to minting a link.
+#[derive(Debug, Serialize)]
+pub struct MintedLink {
+    pub code: String,
+    /// The short link itself.
+    pub url: String,
+    /// The embed URL it redirects to.
+    pub target: String,
+}
+
+/// Mints a short link for a post, slide and display options.
+///
+/// Route: `POST /api/shortlinks`, with a JSON `ShortLink` body. Needs an API
+/// key whether or not `REQUIRE_API_KEY` is set, since every link is a
+/// permanent KV entry.
+pub async fn create(mut req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let (_, api_key) = match authenticate(&req, &ctx.env).await? {
+        Ok(found) => found,
+        Err(denied) => return Ok(denied),
+    };
+    let link = match req.json::<ShortLink>().await {
+        Ok(link) => link,
+        Err(_) => return Response::error("Body must be a JSON short link", 400),
+    };
+    let link = match link.validate() {
+        Ok(link) => link,
+        Err(problem) => return Response::error(problem, 400),
+    };
+
+    let mut code = None;
+    for _ in 0..MINT_ATTEMPTS {
+        let candidate = new_code()?;
+        if get_short_link(&candidate, &ctx.env).await?.is_none() {
+            code = Some(candidate);
+            break;
+        }
+    }
+    let Some(code) = code else {
+        return Response::error("No free short code, try again", 503);
+    };
+
+    let target = link.target();
+    let stored = StoredLink { link, created_by: api_key.name, created_at: Date::now().as_millis() / 1000 };
+    put_short_link(&code, &stored, &ctx.env).await?;
+    console_log!("[shortlinks] {} minted {} -> {}", stored.created_by, code, target);
+
+    let base = req.url()?;
+    let url = base.join(&format!("/s/{code}")).map_err(|e| Error::RustError(e.to_string()))?;
+    let target = base.join(&target).map_err(|e| Error::RustError(e.to_string()))?;
+    let minted = MintedLink { code, url: url.to_string(), target: target.to_string() };
+    Ok(Response::from_json(&minted)?.with_status(201))
+}
+
+/// Sends a short link on to the embed URL it stands for.
+///
+/// Route: `/s/:code`.
+pub async fn resolve(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let code = ctx.param("code").cloned().unwrap_or_default();
+    let stored = if is_valid_code(&code) { get_short_link(&code, &ctx.env).await? } else { None };
+    let Some(stored) = stored else {
+        // A link minted a moment later must not be shadowed by a cached 404
+        return CachePolicy::NoStore.apply(Response::error("Not Found", 404)?);
+    };
+
+    let target = req.url()?.join(&stored.link.target()).map_err(|e| Error::RustError(e.to_string()))?;
+    Response::redirect(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+    use crate::utils::path::reroute;
+
+    #[test]
+    fn minting_on_a_trailing_slash_keeps_the_link() {
+        let body = r#"{"post_id":"CxAbc","img_index":2,"gif":true}"#.to_string();
+        let url = Url::parse(&format!("https://cattgram.com{MINT_PATH}/")).unwrap();
+        let rerouted = reroute(url, Method::Post, Some(body)).unwrap();
+        assert_eq!(rerouted.url.path(), MINT_PATH);
+
+        let link: ShortLink = serde_json::from_str(&rerouted.body.unwrap()).unwrap();
+        assert_eq!(link.validate().unwrap().target(), "/p/CxAbc/?img_index=2&gif=true");
+    }
+}
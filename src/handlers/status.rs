@@ -0,0 +1,23 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::status::current_status;
+use crate::templates::status_html::render_status;
+use crate::utils::minify::minify_html;
+use crate::AppState;
+
+/// Public status page.
+///
+/// Route: `/status`. Shows whether each backend, the Instagram session and
+/// the proxy are working, from a snapshot refreshed every few minutes (see
+/// `crate::status`). `?format=json` returns the snapshot itself.
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let now_secs = Date::now().as_millis() / 1000;
+    let snapshot = current_status(&ctx.data.config, &ctx.env, now_secs).await;
+
+    if req.url()?.query_pairs().any(|(k, v)| k == "format" && v == "json") {
+        return Response::from_json(&snapshot);
+    }
+    Response::from_html(minify_html(&render_status(&snapshot, &ctx.data.config, now_secs)))
+}
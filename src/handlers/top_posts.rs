@@ -0,0 +1,30 @@
+use std::rc::Rc;
+
+use serde::Serialize;
+use worker::*;
+
+use crate::analytics::{top_posts, PostViews};
+use crate::auth::usage_day;
+use crate::AppState;
+
+/// Posts listed in the report.
+const LIMIT: u32 = 50;
+
+#[derive(Serialize)]
+struct Report {
+    /// UTC day, as days since the Unix epoch.
+    day: u64,
+    posts: Vec<PostViews>,
+}
+
+/// Today's most-embedded posts, from the `ANALYTICS` D1 database.
+///
+/// Route: `/admin/top-posts`, behind the admin layer. Answers 404 when no
+/// database is bound.
+pub async fn handle(_req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let now_secs = Date::now().as_millis() / 1000;
+    let Some(posts) = top_posts(&ctx.env, now_secs, LIMIT).await? else {
+        return Response::error("Not Found", 404);
+    };
+    Response::from_json(&Report { day: usage_day(now_secs), posts })
+}
@@ -0,0 +1,21 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::auth::{authenticate, current_usage, Usage};
+use crate::AppState;
+
+/// Reports the calling key's usage for the current UTC day.
+///
+/// Route: `/api/usage`. Authenticated by the key itself, whether or not
+/// `REQUIRE_API_KEY` is set, and never counted against the quota.
+pub async fn handle(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let (key, api_key) = match authenticate(&req, &ctx.env).await? {
+        Ok(found) => found,
+        Err(denied) => return Ok(denied),
+    };
+
+    let now_secs = Date::now().as_millis() / 1000;
+    let used = current_usage(&key, &ctx.env, now_secs).await;
+    Response::from_json(&Usage::new(&api_key, used, now_secs))
+}
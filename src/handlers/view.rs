@@ -0,0 +1,48 @@
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::handlers::context::parse_img_index;
+use crate::i18n::request_lang;
+use crate::scraper::fetch_post_data;
+use crate::templates::view_html::render_view;
+use crate::turnstile;
+use crate::utils::http::CachePolicy;
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+use crate::AppState;
+
+/// Human-facing viewer: the selected slide, carousel controls, caption and a
+/// link back to Instagram.
+///
+/// Route: `/view/:postID`. Embed routes send non-bots here with `?preview=true`.
+/// Behind a Turnstile challenge when one is configured (POST redeems it).
+pub async fn handle(mut req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    if let Some(challenge) = turnstile::guard(&mut req, &ctx.env, &ctx.data.config).await? {
+        return Ok(challenge);
+    }
+    // Passes are per visitor, so a shared cache mustn't hand the page to others
+    let per_visitor = ctx.data.config.turnstile().is_some();
+    let resp = respond(req, ctx).await?;
+    if per_visitor {
+        return CachePolicy::Links.apply(resp);
+    }
+    Ok(resp)
+}
+
+async fn respond(req: Request, ctx: RouteContext<Rc<AppState>>) -> Result<Response> {
+    let post_id = ctx.param("postID").cloned().unwrap_or_default();
+    let img_index = parse_img_index(&req.url()?);
+    let lang = request_lang(&req, &ctx.data.config)?;
+
+    let data = match fetch_post_data(&post_id, &ctx.env, &ctx.data).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return Response::error("Post not found", 404),
+        Err(e) => {
+            console_log!("[view] fetch error: {:?}", e);
+            return Response::error("Failed to fetch post", 502);
+        }
+    };
+
+    Response::from_html(minify_html(&render_view(&data, &ctx.data.config, img_index, lang)))
+}
@@ -0,0 +1,437 @@
+//! Interface text for the human-facing pages (homepage, `/view`, `/links`
+//! and the Turnstile challenge) in the visitor's language.
+//!
+//! The language comes from `Accept-Language`, falling back to `DEFAULT_LANG`
+//! (English unless set). Embeds are read by crawlers and chat apps, and
+//! carry Instagram's own text, so their interface isn't translated; their
+//! caption may be (see `crate::translate`).
+
+use worker::{Request, Result};
+
+use crate::config::Config;
+
+/// A supported interface language.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    Es,
+    Pt,
+    De,
+    Fr,
+    Ru,
+}
+
+/// Every string the human-facing pages need.
+pub struct Strings {
+    pub tagline: &'static str,
+    pub usage: &'static str,
+    /// "Replace `instagram.com` with `cattgram.com` (...)", in three parts
+    /// around the two domains.
+    pub replace: [&'static str; 3],
+    pub example: &'static str,
+    pub url_formats: &'static str,
+    /// One label per homepage route, in the homepage's order.
+    pub routes: [&'static str; 8],
+    pub query_parameters: &'static str,
+    /// One description per homepage query parameter, in the homepage's order.
+    pub query_params: [&'static str; 5],
+    pub powered_by: &'static str,
+
+    pub previous: &'static str,
+    pub next: &'static str,
+    pub open_in_instagram: &'static str,
+    pub video_cover: &'static str,
+    pub video_only_on_instagram: &'static str,
+    pub scan_to_open: &'static str,
+
+    pub type_heading: &'static str,
+    pub size_heading: &'static str,
+    pub links_heading: &'static str,
+    pub image: &'static str,
+    pub video: &'static str,
+    pub open: &'static str,
+    pub download: &'static str,
+    pub thumbnail: &'static str,
+
+    pub checking_browser: &'static str,
+    pub one_moment: &'static str,
+    pub confirm_human: &'static str,
+    pub continue_button: &'static str,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 6] = [Lang::En, Lang::Es, Lang::Pt, Lang::De, Lang::Fr, Lang::Ru];
+
+    /// The ISO 639-1 code, as used in `lang` attributes and `DEFAULT_LANG`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Es => "es",
+            Lang::Pt => "pt",
+            Lang::De => "de",
+            Lang::Fr => "fr",
+            Lang::Ru => "ru",
+        }
+    }
+
+    /// Parses a language tag (`pt`, `pt-BR`, `PT_br`), ignoring the region.
+    pub fn from_tag(tag: &str) -> Option<Lang> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        Lang::ALL.into_iter().find(|lang| lang.code() == primary)
+    }
+
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Lang::En => &EN,
+            Lang::Es => &ES,
+            Lang::Pt => &PT,
+            Lang::De => &DE,
+            Lang::Fr => &FR,
+            Lang::Ru => &RU,
+        }
+    }
+}
+
+/// The language ranges of an `Accept-Language` value, most preferred first.
+fn ranked(accept_language: &str) -> Vec<(&str, f32)> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            Some((tag, quality))
+        })
+        .filter(|&(tag, quality)| !tag.is_empty() && quality > 0.0)
+        .collect();
+    // Stable, so equally weighted ranges keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges
+}
+
+/// Picks the visitor's most preferred supported language from an
+/// `Accept-Language` value, or `default` if none is supported.
+pub fn negotiate(accept_language: &str, default: Lang) -> Lang {
+    ranked(accept_language)
+        .into_iter()
+        .find_map(|(tag, _)| if tag == "*" { Some(default) } else { Lang::from_tag(tag) })
+        .unwrap_or(default)
+}
+
+/// Like `negotiate`, but `None` unless the client names a supported
+/// language ahead of any wildcard: for choices a default shouldn't make.
+pub fn preferred(accept_language: &str) -> Option<Lang> {
+    ranked(accept_language)
+        .into_iter()
+        .take_while(|&(tag, _)| tag != "*")
+        .find_map(|(tag, _)| Lang::from_tag(tag))
+}
+
+/// The language to render a page in for this request.
+pub fn request_lang(req: &Request, config: &Config) -> Result<Lang> {
+    let accept_language = req.headers().get("Accept-Language")?.unwrap_or_default();
+    Ok(negotiate(&accept_language, config.default_lang))
+}
+
+const EN: Strings = Strings {
+    tagline: "Fix Instagram embeds for Discord and Telegram",
+    usage: "Usage",
+    replace: ["Replace ", " with ", " (or whatever domain you deploy to) in any Instagram link."],
+    example: "Example:",
+    url_formats: "Supported URL Formats",
+    routes: [
+        "Posts",
+        "Reels",
+        "Reels (alternate)",
+        "IGTV",
+        "Stories",
+        "Short clips as muted, looping GIF-style videos",
+        "Numeric media IDs from the private API (e.g. 3141592653589793238_123)",
+        "A hashtag's top post",
+    ],
+    query_parameters: "Query Parameters",
+    query_params: [
+        "Redirect directly to the media file (image or video URL)",
+        "Select a specific slide in a carousel post (1-based index)",
+        "Show the top comment under the caption",
+        "Serve videos muted, for GIF-style looping (same as /gif/)",
+        "Send browsers to the viewer page instead of Instagram",
+    ],
+    powered_by: "Powered by Cloudflare Workers",
+    previous: "◀ Previous",
+    next: "Next ▶",
+    open_in_instagram: "Open in Instagram",
+    video_cover: "Video cover",
+    video_only_on_instagram: "This video can only be played on Instagram.",
+    scan_to_open: "Scan to open on your phone",
+    type_heading: "Type",
+    size_heading: "Size",
+    links_heading: "Links",
+    image: "Image",
+    video: "Video",
+    open: "Open",
+    download: "Download",
+    thumbnail: "Thumbnail",
+    checking_browser: "Checking your browser",
+    one_moment: "One moment",
+    confirm_human: "Confirm you're human to see this post. You won't be asked again for an hour.",
+    continue_button: "Continue",
+};
+
+const ES: Strings = Strings {
+    tagline: "Arregla las vistas previas de Instagram en Discord y Telegram",
+    usage: "Uso",
+    replace: ["Reemplaza ", " por ", " (o el dominio donde lo despliegues) en cualquier enlace de Instagram."],
+    example: "Ejemplo:",
+    url_formats: "Formatos de URL compatibles",
+    routes: [
+        "Publicaciones",
+        "Reels",
+        "Reels (alternativo)",
+        "IGTV",
+        "Historias",
+        "Clips cortos como vídeos silenciados en bucle, estilo GIF",
+        "IDs numéricos de la API privada (p. ej. 3141592653589793238_123)",
+        "La publicación destacada de un hashtag",
+    ],
+    query_parameters: "Parámetros de consulta",
+    query_params: [
+        "Redirige directamente al archivo multimedia (URL de la imagen o del vídeo)",
+        "Selecciona una diapositiva concreta de un carrusel (índice desde 1)",
+        "Muestra el comentario principal bajo el texto",
+        "Sirve los vídeos silenciados, en bucle estilo GIF (igual que /gif/)",
+        "Envía los navegadores al visor en lugar de a Instagram",
+    ],
+    powered_by: "Funciona con Cloudflare Workers",
+    previous: "◀ Anterior",
+    next: "Siguiente ▶",
+    open_in_instagram: "Abrir en Instagram",
+    video_cover: "Portada del vídeo",
+    video_only_on_instagram: "Este vídeo solo se puede reproducir en Instagram.",
+    scan_to_open: "Escanéalo para abrirlo en el móvil",
+    type_heading: "Tipo",
+    size_heading: "Tamaño",
+    links_heading: "Enlaces",
+    image: "Imagen",
+    video: "Vídeo",
+    open: "Abrir",
+    download: "Descargar",
+    thumbnail: "Miniatura",
+    checking_browser: "Comprobando tu navegador",
+    one_moment: "Un momento",
+    confirm_human: "Confirma que eres humano para ver esta publicación. No se te volverá a pedir en una hora.",
+    continue_button: "Continuar",
+};
+
+const PT: Strings = Strings {
+    tagline: "Corrige as prévias do Instagram no Discord e no Telegram",
+    usage: "Como usar",
+    replace: ["Substitua ", " por ", " (ou o domínio onde você publicar) em qualquer link do Instagram."],
+    example: "Exemplo:",
+    url_formats: "Formatos de URL suportados",
+    routes: [
+        "Publicações",
+        "Reels",
+        "Reels (alternativo)",
+        "IGTV",
+        "Stories",
+        "Clipes curtos como vídeos sem som em loop, estilo GIF",
+        "IDs numéricos da API privada (ex.: 3141592653589793238_123)",
+        "A publicação em destaque de uma hashtag",
+    ],
+    query_parameters: "Parâmetros de consulta",
+    query_params: [
+        "Redireciona direto para o arquivo de mídia (URL da imagem ou do vídeo)",
+        "Seleciona um slide específico de um carrossel (índice a partir de 1)",
+        "Mostra o comentário principal abaixo da legenda",
+        "Serve vídeos sem som, em loop estilo GIF (o mesmo que /gif/)",
+        "Envia navegadores para o visualizador em vez do Instagram",
+    ],
+    powered_by: "Feito com Cloudflare Workers",
+    previous: "◀ Anterior",
+    next: "Próximo ▶",
+    open_in_instagram: "Abrir no Instagram",
+    video_cover: "Capa do vídeo",
+    video_only_on_instagram: "Este vídeo só pode ser reproduzido no Instagram.",
+    scan_to_open: "Escaneie para abrir no celular",
+    type_heading: "Tipo",
+    size_heading: "Tamanho",
+    links_heading: "Links",
+    image: "Imagem",
+    video: "Vídeo",
+    open: "Abrir",
+    download: "Baixar",
+    thumbnail: "Miniatura",
+    checking_browser: "Verificando seu navegador",
+    one_moment: "Um momento",
+    confirm_human: "Confirme que você é humano para ver esta publicação. Não vamos pedir de novo por uma hora.",
+    continue_button: "Continuar",
+};
+
+const DE: Strings = Strings {
+    tagline: "Repariert Instagram-Vorschauen für Discord und Telegram",
+    usage: "Verwendung",
+    replace: ["Ersetze ", " durch ", " (oder die Domain, auf der du es betreibst) in einem beliebigen Instagram-Link."],
+    example: "Beispiel:",
+    url_formats: "Unterstützte URL-Formate",
+    routes: [
+        "Beiträge",
+        "Reels",
+        "Reels (alternativ)",
+        "IGTV",
+        "Storys",
+        "Kurze Clips als stumme, geloopte Videos im GIF-Stil",
+        "Numerische Medien-IDs aus der privaten API (z. B. 3141592653589793238_123)",
+        "Der Top-Beitrag eines Hashtags",
+    ],
+    query_parameters: "Query-Parameter",
+    query_params: [
+        "Direkt zur Mediendatei weiterleiten (Bild- oder Video-URL)",
+        "Eine bestimmte Folie eines Karussell-Beitrags wählen (Index ab 1)",
+        "Den Top-Kommentar unter der Bildunterschrift anzeigen",
+        "Videos stumm ausliefern, als Loop im GIF-Stil (wie /gif/)",
+        "Browser zur Ansicht statt zu Instagram schicken",
+    ],
+    powered_by: "Läuft auf Cloudflare Workers",
+    previous: "◀ Zurück",
+    next: "Weiter ▶",
+    open_in_instagram: "In Instagram öffnen",
+    video_cover: "Videovorschau",
+    video_only_on_instagram: "Dieses Video kann nur auf Instagram abgespielt werden.",
+    scan_to_open: "Zum Öffnen auf dem Handy scannen",
+    type_heading: "Typ",
+    size_heading: "Größe",
+    links_heading: "Links",
+    image: "Bild",
+    video: "Video",
+    open: "Öffnen",
+    download: "Herunterladen",
+    thumbnail: "Vorschaubild",
+    checking_browser: "Browser wird überprüft",
+    one_moment: "Einen Moment",
+    confirm_human: "Bestätige, dass du ein Mensch bist, um diesen Beitrag zu sehen. Du wirst eine Stunde lang nicht erneut gefragt.",
+    continue_button: "Weiter",
+};
+
+const FR: Strings = Strings {
+    tagline: "Répare les aperçus Instagram pour Discord et Telegram",
+    usage: "Utilisation",
+    replace: ["Remplacez ", " par ", " (ou le domaine où vous le déployez) dans n'importe quel lien Instagram."],
+    example: "Exemple :",
+    url_formats: "Formats d'URL pris en charge",
+    routes: [
+        "Publications",
+        "Reels",
+        "Reels (autre forme)",
+        "IGTV",
+        "Stories",
+        "Clips courts en vidéos muettes et en boucle, façon GIF",
+        "Identifiants numériques de l'API privée (ex. 3141592653589793238_123)",
+        "La publication phare d'un hashtag",
+    ],
+    query_parameters: "Paramètres de requête",
+    query_params: [
+        "Redirige directement vers le fichier (URL de l'image ou de la vidéo)",
+        "Choisit une diapositive d'un carrousel (index à partir de 1)",
+        "Affiche le commentaire principal sous la légende",
+        "Sert les vidéos sans son, en boucle façon GIF (comme /gif/)",
+        "Envoie les navigateurs vers la visionneuse au lieu d'Instagram",
+    ],
+    powered_by: "Propulsé par Cloudflare Workers",
+    previous: "◀ Précédent",
+    next: "Suivant ▶",
+    open_in_instagram: "Ouvrir dans Instagram",
+    video_cover: "Couverture de la vidéo",
+    video_only_on_instagram: "Cette vidéo ne peut être lue que sur Instagram.",
+    scan_to_open: "Scannez pour l'ouvrir sur votre téléphone",
+    type_heading: "Type",
+    size_heading: "Taille",
+    links_heading: "Liens",
+    image: "Image",
+    video: "Vidéo",
+    open: "Ouvrir",
+    download: "Télécharger",
+    thumbnail: "Miniature",
+    checking_browser: "Vérification de votre navigateur",
+    one_moment: "Un instant",
+    confirm_human: "Confirmez que vous êtes humain pour voir cette publication. Cela ne vous sera plus demandé pendant une heure.",
+    continue_button: "Continuer",
+};
+
+const RU: Strings = Strings {
+    tagline: "Исправляет превью Instagram в Discord и Telegram",
+    usage: "Как пользоваться",
+    replace: ["Замените ", " на ", " (или домен, на котором вы его развернули) в любой ссылке Instagram."],
+    example: "Пример:",
+    url_formats: "Поддерживаемые форматы URL",
+    routes: [
+        "Публикации",
+        "Reels",
+        "Reels (другой вариант)",
+        "IGTV",
+        "Истории",
+        "Короткие клипы как беззвучные зацикленные видео в стиле GIF",
+        "Числовые ID медиа из приватного API (например, 3141592653589793238_123)",
+        "Топ-публикация хэштега",
+    ],
+    query_parameters: "Параметры запроса",
+    query_params: [
+        "Перенаправить прямо на медиафайл (URL картинки или видео)",
+        "Выбрать конкретный слайд карусели (нумерация с 1)",
+        "Показать главный комментарий под подписью",
+        "Отдавать видео без звука, зацикленными в стиле GIF (как /gif/)",
+        "Отправлять браузеры в просмотрщик вместо Instagram",
+    ],
+    powered_by: "Работает на Cloudflare Workers",
+    previous: "◀ Назад",
+    next: "Вперёд ▶",
+    open_in_instagram: "Открыть в Instagram",
+    video_cover: "Обложка видео",
+    video_only_on_instagram: "Это видео можно посмотреть только в Instagram.",
+    scan_to_open: "Отсканируйте, чтобы открыть на телефоне",
+    type_heading: "Тип",
+    size_heading: "Размер",
+    links_heading: "Ссылки",
+    image: "Изображение",
+    video: "Видео",
+    open: "Открыть",
+    download: "Скачать",
+    thumbnail: "Миниатюра",
+    checking_browser: "Проверяем ваш браузер",
+    one_moment: "Секунду",
+    confirm_human: "Подтвердите, что вы человек, чтобы увидеть эту публикацию. В течение часа спрашивать снова не будем.",
+    continue_button: "Продолжить",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_match_on_the_primary_subtag() {
+        assert_eq!(Lang::from_tag("pt-BR"), Some(Lang::Pt));
+        assert_eq!(Lang::from_tag(" DE_at "), Some(Lang::De));
+        assert_eq!(Lang::from_tag("ja"), None);
+        assert_eq!(Lang::from_tag(""), None);
+    }
+
+    #[test]
+    fn negotiation_follows_quality_then_order() {
+        assert_eq!(negotiate("ja, fr-CH;q=0.9, de;q=0.9, en;q=0.8", Lang::En), Lang::Fr);
+        assert_eq!(negotiate("en;q=0.5, ru", Lang::En), Lang::Ru);
+        // Refused languages and unparseable weights are skipped
+        assert_eq!(negotiate("es;q=0, de;q=high, pt;q=0.1", Lang::En), Lang::Pt);
+        assert_eq!(negotiate("ja, *;q=0.5", Lang::De), Lang::De);
+        assert_eq!(negotiate("", Lang::Es), Lang::Es);
+    }
+
+    #[test]
+    fn preference_needs_a_named_language() {
+        assert_eq!(preferred("ja, de-AT;q=0.8"), Some(Lang::De));
+        assert_eq!(preferred("ja, *;q=0.5, fr;q=0.1"), None);
+        assert_eq!(preferred(""), None);
+    }
+}
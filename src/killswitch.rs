@@ -0,0 +1,136 @@
+//! Kill switch: redirect-only mode for Instagram-side incidents.
+//!
+//! When Instagram is blocking or challenging everything, scraping only burns
+//! proxy spend and fills the logs. Throwing the switch makes every route that
+//! would scrape answer with a redirect to the Instagram page it stands for,
+//! straight away and without touching the cache. It is thrown by either:
+//!
+//! - `REDIRECT_ONLY`, for a deploy that should never scrape, or
+//! - `true` under `killswitch` in the `RUNTIME` namespace, which takes effect
+//!   without a deploy and is undone by deleting the key. Each isolate reads
+//!   the key at most every `RECHECK_MS`, so it takes that long to spread.
+//!
+//! The redirects are `no-store`, so nothing keeps them once the switch is off.
+//! API clients can't do anything with a redirect to an Instagram page, so
+//! `/api/*` routes answer with a JSON 503 instead.
+//!
+//! `MAINTENANCE` throws the switch too, but a bare redirect makes people
+//! think their link was wrong. So bots asking for an embed get a card saying
+//! the instance is down for maintenance instead, linking the status page.
+
+use std::cell::Cell;
+
+use serde::Serialize;
+use worker::*;
+
+use crate::config::Config;
+use crate::scraper::cache::get_kill_switch;
+use crate::templates::embed_html::render_maintenance;
+use crate::utils::bot_detect::{is_bot, is_search_crawler};
+use crate::utils::http::CachePolicy;
+use crate::utils::instagram::instagram_url;
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+
+/// How long an isolate goes by the `killswitch` key it last read. Every
+/// scraping route checks the switch, so reading it each time would cost a
+/// KV read per request.
+const RECHECK_MS: u64 = 10_000;
+
+thread_local! {
+    /// The switch as this isolate last read it, with when.
+    static READ: Cell<Option<(u64, bool)>> = const { Cell::new(None) };
+}
+
+/// The switch as last read, if that was recently enough to go by.
+fn recent(read: Option<(u64, bool)>, now_ms: u64) -> Option<bool> {
+    read.filter(|&(read_ms, _)| now_ms.saturating_sub(read_ms) < RECHECK_MS).map(|(_, on)| on)
+}
+
+/// Returns `true` if the switch is thrown. A failed KV read leaves it off,
+/// and is tried again on the next request.
+async fn engaged(config: &Config, env: &Env) -> bool {
+    if config.redirect_only || config.maintenance {
+        return true;
+    }
+    let now_ms = Date::now().as_millis();
+    if let Some(on) = recent(READ.get(), now_ms) {
+        return on;
+    }
+    match get_kill_switch(env).await {
+        Ok(on) => {
+            READ.set(Some((now_ms, on)));
+            on
+        }
+        Err(e) => {
+            console_log!("[killswitch] read error: {:?}", e);
+            false
+        }
+    }
+}
+
+/// The body of the API's answer while the switch is thrown.
+#[derive(Debug, Serialize)]
+struct Unavailable {
+    error: &'static str,
+}
+
+/// Returns `true` for routes whose clients expect JSON, not a redirect.
+fn is_api(path: &str) -> bool {
+    path.starts_with("/api/")
+}
+
+/// The response to answer `req` with instead of going on, when the switch
+/// is thrown: a redirect, or a 503 for the API.
+pub async fn divert(req: &Request, config: &Config, env: &Env) -> Result<Option<Response>> {
+    if !engaged(config, env).await {
+        return Ok(None);
+    }
+    let path = req.path();
+    if is_api(&path) {
+        console_log!("[killswitch] redirect-only, refusing {}", path);
+        let body = Unavailable { error: "Scraping is paused, try again later" };
+        return CachePolicy::NoStore.apply(Response::from_json(&body)?.with_status(503)).map(Some);
+    }
+    let url = instagram_url(&path);
+    console_log!("[killswitch] redirect-only, sending {} to {}", path, url);
+    CachePolicy::NoStore.apply(Response::redirect(Url::parse(&url).map_err(|e| Error::RustError(e.to_string()))?)?).map(Some)
+}
+
+/// The maintenance card to answer an embed request with, when `req` comes
+/// from a bot in maintenance mode. Everyone else is left to `divert`.
+pub fn notice(req: &Request, config: &Config) -> Result<Option<Response>> {
+    if !config.maintenance {
+        return Ok(None);
+    }
+    let ua = req.headers().get("User-Agent")?.unwrap_or_default();
+    if !is_bot(&ua) || is_search_crawler(&ua) {
+        return Ok(None);
+    }
+    let status_url = match &config.status_page_url {
+        Some(url) => url.clone(),
+        None => format!("{}/status", req.url()?.origin().ascii_serialization()),
+    };
+    let html = minify_html(&render_maintenance(&instagram_url(&req.path()), &status_url, config));
+    CachePolicy::NoStore.apply(Response::from_html(html)?).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_by_the_last_read_for_a_while() {
+        assert_eq!(recent(None, 5000), None);
+        assert_eq!(recent(Some((5000, true)), 5000 + RECHECK_MS - 1), Some(true));
+        assert_eq!(recent(Some((5000, false)), 5000 + RECHECK_MS - 1), Some(false));
+        assert_eq!(recent(Some((5000, true)), 5000 + RECHECK_MS), None);
+    }
+
+    #[test]
+    fn api_routes_get_json_instead_of_a_redirect() {
+        assert!(is_api("/api/v1/posts/CxAbC"));
+        assert!(!is_api("/p/CxAbC"));
+        assert!(!is_api("/apiary"));
+    }
+}
@@ -1,59 +1,211 @@
+use std::rc::Rc;
+
 use worker::*;
 
+mod alert;
+mod alt_text;
+mod analytics;
+mod auth;
+mod config;
 mod handlers;
+mod i18n;
+mod killswitch;
+mod metrics;
+mod middleware;
+mod openapi;
+mod pacing;
+mod quota;
 mod scraper;
+mod session;
+mod shortlinks;
+mod status;
+mod summarize;
 mod templates;
+mod translate;
+mod turnstile;
 mod utils;
 
-fn embed_handler() -> impl Fn(Request, RouteContext<()>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response>>>> {
-    |req, ctx| Box::pin(async move { handlers::embed::handle(req, ctx).await })
+use config::Config;
+use metrics::Route;
+use middleware::{wrap, Layer};
+use utils::cors::build_cors;
+use utils::http::{is_indexable, noindex, CachePolicy};
+use utils::path::reroute;
+
+/// Per-request state shared with every handler through the router.
+pub struct AppState {
+    /// Lets handlers defer work (e.g. cache writes) past the response via `wait_until`.
+    pub ctx: Context,
+    pub config: Config,
 }
 
+/// Route patterns served by the embed handler.
+const EMBED_ROUTES: [&str; 11] = [
+    "/p/:postID",
+    "/p/:postID/:extra",
+    "/p/:postID/c/:commentID",
+    "/:username/p/:postID",
+    "/tv/:postID",
+    "/reel/:postID",
+    "/reels/:postID",
+    "/stories/:username/:storyID",
+    "/gif/:postID",
+    "/media/:mediaID",
+    "/explore/tags/:tag",
+];
+
 #[event(fetch)]
-async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+async fn fetch(req: Request, env: Env, ctx: Context) -> Result<Response> {
     console_error_panic_hook::set_once();
 
-    // Strip trailing slash (except root) and redirect-internally by rewriting
-    let url = req.url()?;
-    let path = url.path().to_string();
+    let config = Config::from_env(&env);
+    config::validate_once(&config, &env);
+    let cors = build_cors(&config);
+    if req.method() == Method::Options {
+        return handlers::options::handle(&cors);
+    }
 
-    if path.len() > 1 && path.ends_with('/') {
-        let trimmed = path.trim_end_matches('/');
-        let mut new_url = url.clone();
-        new_url.set_path(trimmed);
-        let new_req = Request::new_with_init(
-            new_url.as_str(),
+    // Normalize the path (slashes, keyword case) so routes only need one spelling.
+    // The router dispatches on `req.path()`, so a rewritten path needs a new request.
+    let req = match reroute(req.url()?, req.method(), req.inner().body()) {
+        Some(rerouted) => Request::new_with_init(
+            rerouted.url.as_str(),
             &RequestInit {
-                method: req.method(),
+                method: rerouted.method,
                 headers: req.headers().clone(),
+                body: rerouted.body.map(Into::into),
                 ..Default::default()
             },
-        )?;
-        let router = build_router();
-        return router.run(new_req, env).await;
-    }
+        )?,
+        None => req,
+    };
 
-    let router = build_router();
-    router.run(req, env).await
+    let indexable = is_indexable(&req.path());
+    // `Router::run` consumes the router, and its data (this request's
+    // `Context`) is fixed when it's built, so it's built per request
+    let resp = build_router(AppState { ctx, config }).run(req, env).await?;
+    let resp = if indexable { resp } else { noindex(resp)? };
+    resp.with_cors(&cors)
 }
 
-fn build_router() -> Router<'static, ()> {
-    Router::new()
-        .get("/", handlers::home::handle)
-        .get_async("/p/:postID", embed_handler())
-        .get_async("/p/:postID/:extra", embed_handler())
-        .get_async("/:username/p/:postID", embed_handler())
-        .get_async("/tv/:postID", embed_handler())
-        .get_async("/reel/:postID", embed_handler())
-        .get_async("/reels/:postID", embed_handler())
-        .get_async("/stories/:username/:storyID", embed_handler())
-        .get_async("/images/:postID/:mediaNum", |req, ctx| async move {
-            handlers::media::images(req, ctx).await
-        })
-        .get_async("/videos/:postID/:mediaNum", |req, ctx| async move {
-            handlers::media::videos(req, ctx).await
-        })
-        .get_async("/oembed", |req, ctx| async move {
-            handlers::oembed::handle(req, ctx).await
-        })
+// Every route that scrapes carries `Layer::KillSwitch`, so it redirects to
+// Instagram while the switch is thrown. Embeds also carry `Layer::Maintenance`.
+
+/// Human-facing pages are rendered in the visitor's language (see `i18n`).
+const PAGE: &[Layer] = &[
+    Layer::Log,
+    Layer::Cache(CachePolicy::Localized),
+    Layer::Header("Vary", "Accept-Language"),
+];
+const EMBED: &[Layer] = &[
+    Layer::Log,
+    Layer::Metrics(Route::Embed),
+    Layer::Maintenance,
+    Layer::KillSwitch,
+    Layer::Cache(CachePolicy::Embed),
+    Layer::Header("Vary", "Accept-Language"),
+];
+const MEDIA: &[Layer] = &[Layer::Log, Layer::KillSwitch, Layer::Cache(CachePolicy::MediaRedirect)];
+const IMAGES: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Images), Layer::KillSwitch, Layer::Cache(CachePolicy::MediaRedirect)];
+const VIDEOS: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Videos), Layer::KillSwitch, Layer::Cache(CachePolicy::MediaRedirect)];
+const OEMBED: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Oembed), Layer::Cache(CachePolicy::OEmbed)];
+const VIEWER: &[Layer] = &[
+    Layer::Log,
+    Layer::KillSwitch,
+    Layer::Cache(CachePolicy::Viewer),
+    Layer::Header("Vary", "Accept-Language"),
+];
+const API: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Api), Layer::ApiKey, Layer::Cache(CachePolicy::Home)];
+/// Post data changes, and keyed requests have to reach the quota check.
+/// The unversioned route answers by `Api-Version`.
+const API_POST: &[Layer] = &[
+    Layer::Log,
+    Layer::Metrics(Route::Api),
+    Layer::KillSwitch,
+    Layer::ApiKey,
+    Layer::Cache(CachePolicy::Links),
+    Layer::Header("Vary", "Api-Version"),
+];
+/// `/api/usage` authenticates by itself and isn't counted against the quota
+/// (see `auth::authorize`).
+const USAGE: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Api), Layer::ApiKey, Layer::Cache(CachePolicy::NoStore)];
+/// Minting writes a permanent KV entry, so it's never cached and counts
+/// against the key's quota.
+const MINT: &[Layer] = &[Layer::Log, Layer::Metrics(Route::Api), Layer::ApiKey, Layer::Cache(CachePolicy::NoStore)];
+/// A short link always redirects to the same place.
+const SHORT: &[Layer] = &[Layer::Log, Layer::Cache(CachePolicy::Home)];
+/// A QR code is a pure function of its URL.
+const QR: &[Layer] = &[Layer::Log, Layer::Cache(CachePolicy::Home)];
+/// An embed, whose post changes when the profile posts again.
+const LATEST: &[Layer] = &[
+    Layer::Log,
+    Layer::Metrics(Route::Embed),
+    Layer::Maintenance,
+    Layer::KillSwitch,
+    Layer::Cache(CachePolicy::Latest),
+    Layer::Header("Vary", "Accept-Language"),
+];
+/// A feed only changes when its profile posts.
+const FEED: &[Layer] = &[Layer::Log, Layer::KillSwitch, Layer::Cache(CachePolicy::Feed)];
+/// Screenshots are kept as long as the post they stand in for.
+const SCREENSHOT: &[Layer] = &[Layer::Log, Layer::KillSwitch, Layer::Cache(CachePolicy::Home)];
+const STATUS: &[Layer] = &[Layer::Log, Layer::Cache(CachePolicy::Status)];
+const ADMIN: &[Layer] = &[Layer::Log, Layer::Admin, Layer::Cache(CachePolicy::NoStore)];
+/// The same URL answers HTML or JSON depending on the client, in their language.
+const LINKS: &[Layer] = &[
+    Layer::Log,
+    Layer::KillSwitch,
+    Layer::ApiKey,
+    Layer::Cache(CachePolicy::Links),
+    Layer::Header("Vary", "Accept, Accept-Language, User-Agent"),
+];
+
+/// Builds the router. The worker `Context` and the parsed `Config` are shared
+/// with handlers as `AppState`; cross-cutting behaviour is declared per route
+/// as middleware layers.
+fn build_router(state: AppState) -> Router<'static, Rc<AppState>> {
+    let mut router = Router::with_data(Rc::new(state)).get_async(
+        "/",
+        wrap(PAGE, |req, ctx| async move { handlers::home::handle(req, ctx) }),
+    );
+
+    for pattern in EMBED_ROUTES {
+        router = router
+            .get_async(pattern, wrap(EMBED, handlers::embed::handle))
+            .head_async(pattern, wrap(EMBED, handlers::embed::handle_head));
+    }
+
+    // HEAD requests are answered from the cache, so a probe never scrapes
+    router
+        .get_async("/images/:postID/:mediaNum", wrap(IMAGES, handlers::media::images))
+        .head_async("/images/:postID/:mediaNum", wrap(IMAGES, handlers::media::images_head))
+        .get_async("/videos/:postID/:mediaNum", wrap(VIDEOS, handlers::media::videos))
+        .head_async("/videos/:postID/:mediaNum", wrap(VIDEOS, handlers::media::videos_head))
+        .get_async("/links/:postID", wrap(LINKS, handlers::links::handle))
+        .post_async("/links/:postID", wrap(LINKS, handlers::links::handle))
+        .get_async("/view/:postID", wrap(VIEWER, handlers::view::handle))
+        .post_async("/view/:postID", wrap(VIEWER, handlers::view::handle))
+        .get_async(
+            "/api/openapi.json",
+            wrap(API, |req, ctx| async move { handlers::openapi::handle(req, ctx) }),
+        )
+        .get_async("/api/posts/:postID", wrap(API_POST, handlers::api::post))
+        .get_async("/api/v1/posts/:postID", wrap(API_POST, handlers::api::post))
+        .get_async("/api/v2/posts/:postID", wrap(API_POST, handlers::api::post))
+        .get_async(auth::USAGE_PATH, wrap(USAGE, handlers::usage::handle))
+        .post_async(handlers::shortlinks::MINT_PATH, wrap(MINT, handlers::shortlinks::create))
+        .get_async("/s/:code", wrap(SHORT, handlers::shortlinks::resolve))
+        .head_async("/s/:code", wrap(SHORT, handlers::shortlinks::resolve))
+        .get_async("/qr/:postID", wrap(QR, handlers::qr::handle))
+        .get_async("/screenshot/:postID", wrap(SCREENSHOT, handlers::screenshot::handle))
+        .get_async("/oembed", wrap(OEMBED, handlers::oembed::handle))
+        .get_async("/status", wrap(STATUS, handlers::status::handle))
+        .get_async("/config-check", wrap(ADMIN, handlers::config_check::handle))
+        .get_async("/admin/backends", wrap(ADMIN, handlers::backends::handle))
+        .get_async("/admin/top-posts", wrap(ADMIN, handlers::top_posts::handle))
+        .get_async("/admin/metrics", wrap(ADMIN, handlers::metrics::handle))
+        .get_async("/grid/:username", wrap(MEDIA, handlers::profile::grid))
+        .get_async("/latest/:username", wrap(LATEST, handlers::profile::latest))
+        .get_async("/rss/:username", wrap(FEED, handlers::profile::rss))
+        .get_async("/:username", wrap(EMBED, handlers::profile::handle))
 }
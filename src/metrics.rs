@@ -0,0 +1,159 @@
+//! Per-route request counts, errors and latency.
+//!
+//! The `Metrics` middleware layer records every response of the routes it
+//! wraps. Each isolate buffers its counts (see `utils::buffer`) and adds
+//! them to the hourly KV buckets (`routes:{hour}`).
+
+use std::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::scraper::cache::add_route_stats_background;
+use crate::scraper::stats::{bucket_of, Counter};
+use crate::utils::buffer::{self, CountBuffer};
+
+/// Flush once this many requests are buffered.
+const FLUSH_AFTER_REQUESTS: u64 = 100;
+
+/// A group of routes counted together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    /// Post, story and profile embeds.
+    Embed,
+    Images,
+    Videos,
+    Oembed,
+    /// The JSON post API, its OpenAPI document and `/api/usage`.
+    Api,
+}
+
+impl Route {
+    pub const ALL: [Route; 5] = [Route::Embed, Route::Images, Route::Videos, Route::Oembed, Route::Api];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Route::Embed => "Embeds",
+            Route::Images => "/images",
+            Route::Videos => "/videos",
+            Route::Oembed => "/oembed",
+            Route::Api => "/api",
+        }
+    }
+}
+
+/// Per-route counters for one hourly bucket. A request `failed` when it was
+/// answered with a 5xx or its handler returned an error.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RouteStats {
+    #[serde(default)]
+    pub embed: Counter,
+    #[serde(default)]
+    pub images: Counter,
+    #[serde(default)]
+    pub videos: Counter,
+    #[serde(default)]
+    pub oembed: Counter,
+    #[serde(default)]
+    pub api: Counter,
+}
+
+impl RouteStats {
+    pub fn get(&self, route: Route) -> &Counter {
+        match route {
+            Route::Embed => &self.embed,
+            Route::Images => &self.images,
+            Route::Videos => &self.videos,
+            Route::Oembed => &self.oembed,
+            Route::Api => &self.api,
+        }
+    }
+
+    fn get_mut(&mut self, route: Route) -> &mut Counter {
+        match route {
+            Route::Embed => &mut self.embed,
+            Route::Images => &mut self.images,
+            Route::Videos => &mut self.videos,
+            Route::Oembed => &mut self.oembed,
+            Route::Api => &mut self.api,
+        }
+    }
+
+    pub fn record(&mut self, route: Route, failed: bool, millis: u64) {
+        let counter = self.get_mut(route);
+        if failed {
+            counter.failed += 1;
+        } else {
+            counter.ok += 1;
+        }
+        counter.total_millis += millis;
+    }
+
+    /// Adds another bucket's counts to this one.
+    pub fn add(&mut self, other: &RouteStats) {
+        for route in Route::ALL {
+            self.get_mut(route).add(other.get(route));
+        }
+    }
+
+    /// Sums buckets, e.g. for the 24 hour totals.
+    pub fn sum<'a>(buckets: impl IntoIterator<Item = &'a RouteStats>) -> Self {
+        let mut total = Self::default();
+        for bucket in buckets {
+            total.add(bucket);
+        }
+        total
+    }
+}
+
+thread_local! {
+    static BUFFER: RefCell<CountBuffer<u64, RouteStats>> = const { RefCell::new(CountBuffer::new(FLUSH_AFTER_REQUESTS)) };
+}
+
+/// Counts one request to `route`, flushing the isolate's buffer to KV in
+/// the background when it's due.
+pub fn record_request(route: Route, failed: bool, millis: u64, env: &Env, ctx: &Context, now_secs: u64) {
+    let buckets = buffer::record(&BUFFER, bucket_of(now_secs), now_secs, |stats| stats.record(route, failed, millis));
+    for (bucket, stats) in buckets.into_iter().flatten() {
+        add_route_stats_background(bucket, stats, env, ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3600;
+
+    #[test]
+    fn records_requests_errors_and_latency_per_route() {
+        let mut stats = RouteStats::default();
+        stats.record(Route::Videos, false, 800);
+        stats.record(Route::Videos, true, 200);
+        stats.record(Route::Embed, false, 50);
+
+        assert_eq!(stats.videos, Counter { ok: 1, failed: 1, total_millis: 1000 });
+        assert_eq!(stats.videos.avg_millis(), Some(500));
+        assert_eq!(stats.embed.total(), 1);
+
+        let total = RouteStats::sum([&stats, &stats]);
+        assert_eq!(total.videos, Counter { ok: 2, failed: 2, total_millis: 2000 });
+    }
+
+    #[test]
+    fn buffer_splits_counts_by_hour() {
+        let mut buffer = CountBuffer::<u64, RouteStats>::new(FLUSH_AFTER_REQUESTS);
+        for (route, failed, millis, now) in [
+            (Route::Images, false, 10, 10 * HOUR + 5),
+            (Route::Images, false, 30, 10 * HOUR + 50),
+            (Route::Api, true, 70, 11 * HOUR),
+        ] {
+            buffer.record(bucket_of(now), now, |stats| stats.record(route, failed, millis));
+        }
+
+        let buckets = buffer.take();
+        assert_eq!(buckets.keys().copied().collect::<Vec<_>>(), [10, 11]);
+        assert_eq!(buckets[&10].images, Counter { ok: 2, failed: 0, total_millis: 40 });
+        assert_eq!(buckets[&11].api.failed, 1);
+    }
+}
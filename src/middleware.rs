@@ -0,0 +1,248 @@
+//! Layers wrapped around the handlers registered in `build_router`.
+//!
+//! Cross-cutting behaviour (admin and API key checks, request logging,
+//! caching and other response headers) is declared per route in the router
+//! instead of being repeated in each handler. Layers run in order: a guard
+//! that answers early skips the layers after it and the handler, and its
+//! answer still goes through the response side of the layers before it.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use worker::*;
+
+use crate::auth;
+use crate::killswitch;
+use crate::config::Config;
+use crate::metrics::{record_request, Route};
+use crate::utils::http::CachePolicy;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<Response>>>>;
+
+/// One piece of behaviour around a handler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layer {
+    /// Logs method, path, status and time taken.
+    Log,
+    /// Counts the request, whether it failed and its latency for the route
+    /// (see `crate::metrics`).
+    Metrics(Route),
+    /// Redirects to Instagram while the kill switch is thrown (see `crate::killswitch`).
+    KillSwitch,
+    /// Answers bots with the maintenance card in maintenance mode; goes
+    /// before `KillSwitch`, which redirects everyone else.
+    Maintenance,
+    /// Admin pages: 404 without `ADMIN_TOKEN`, 401 without matching credentials.
+    Admin,
+    /// API key and daily quota check when `REQUIRE_API_KEY` is set (see `crate::auth`).
+    ApiKey,
+    /// Caching headers, unless the handler already chose a policy (degraded
+    /// answers set their own, shorter one).
+    Cache(CachePolicy),
+    /// A fixed response header.
+    Header(&'static str, &'static str),
+}
+
+/// What the response side of the layers needs to know about the request.
+struct Exchange {
+    method: Method,
+    path: String,
+    started_ms: u64,
+    env: Env,
+    state: Rc<AppState>,
+}
+
+impl Exchange {
+    fn elapsed_ms(&self) -> u64 {
+        Date::now().as_millis().saturating_sub(self.started_ms)
+    }
+
+    fn record(&self, route: Route, failed: bool) {
+        let now_ms = Date::now().as_millis();
+        let elapsed = now_ms.saturating_sub(self.started_ms);
+        record_request(route, failed, elapsed, &self.env, &self.state.ctx, now_ms / 1000);
+    }
+}
+
+/// The admin credentials of a request, from its `Authorization` header.
+///
+/// Never from the query string: URLs end up in request logs, browser
+/// history and `Referer` headers. Browsers get a Basic login prompt instead.
+pub fn admin_authorization(req: &Request) -> Result<String> {
+    Ok(req.headers().get("Authorization")?.unwrap_or_default())
+}
+
+/// The status an admin route answers with instead of its page, if any.
+fn admin_denial(config: &Config, authorization: &str) -> Option<u16> {
+    if config.admin_token.is_none() {
+        // The admin routes don't exist by default
+        Some(404)
+    } else if !config.is_admin(authorization) {
+        Some(401)
+    } else {
+        None
+    }
+}
+
+impl Layer {
+    /// The request side. Returns the response to send instead of going on.
+    async fn before(self, req: &Request, ctx: &RouteContext<Rc<AppState>>) -> Result<Option<Response>> {
+        match self {
+            Layer::Admin => match admin_denial(&ctx.data.config, &admin_authorization(req)?) {
+                Some(404) => Response::error("Not Found", 404).map(Some),
+                Some(status) => {
+                    let resp = Response::error("Unauthorized", status)?;
+                    resp.headers().set("WWW-Authenticate", r#"Basic realm="admin", charset="UTF-8""#)?;
+                    Ok(Some(resp))
+                }
+                None => Ok(None),
+            },
+            Layer::KillSwitch => killswitch::divert(req, &ctx.data.config, &ctx.env).await,
+            Layer::Maintenance => killswitch::notice(req, &ctx.data.config),
+            Layer::ApiKey => {
+                let now_secs = Date::now().as_millis() / 1000;
+                auth::authorize(req, &ctx.env, &ctx.data.ctx, &ctx.data.config, now_secs).await
+            }
+            Layer::Log | Layer::Metrics(_) | Layer::Cache(_) | Layer::Header(..) => Ok(None),
+        }
+    }
+
+    /// The response side.
+    fn after(self, resp: Response, exchange: &Exchange) -> Result<Response> {
+        match self {
+            Layer::Log => {
+                console_log!(
+                    "[http] {} {} -> {} ({}ms)",
+                    exchange.method,
+                    exchange.path,
+                    resp.status_code(),
+                    exchange.elapsed_ms()
+                );
+                Ok(resp)
+            }
+            Layer::Metrics(route) => {
+                exchange.record(route, resp.status_code() >= 500);
+                Ok(resp)
+            }
+            Layer::Cache(policy) => {
+                if resp.headers().has("Cache-Control")? {
+                    return Ok(resp);
+                }
+                policy.apply(resp)
+            }
+            Layer::Header(name, value) => {
+                // Redirect responses have immutable headers
+                let headers = resp.headers().clone();
+                headers.set(name, value)?;
+                Ok(resp.with_headers(headers))
+            }
+            Layer::KillSwitch | Layer::Maintenance | Layer::Admin | Layer::ApiKey => Ok(resp),
+        }
+    }
+
+    /// The response side when the handler, or a later layer's request side,
+    /// returned an error instead.
+    fn failed(self, error: &Error, exchange: &Exchange) {
+        match self {
+            Layer::Log => console_log!(
+                "[http] {} {} -> error: {} ({}ms)",
+                exchange.method,
+                exchange.path,
+                error,
+                exchange.elapsed_ms()
+            ),
+            Layer::Metrics(route) => exchange.record(route, true),
+            Layer::KillSwitch | Layer::Maintenance | Layer::Admin | Layer::ApiKey | Layer::Cache(_) | Layer::Header(..) => {}
+        }
+    }
+}
+
+/// Runs the error side of the `entered` layers, innermost first, and hands
+/// the error back.
+fn fail(entered: &[Layer], error: Error, exchange: &Exchange) -> Error {
+    for layer in entered.iter().rev() {
+        layer.failed(&error, exchange);
+    }
+    error
+}
+
+/// Runs a handler inside `layers`.
+async fn run<H, F>(
+    layers: &[Layer],
+    req: Request,
+    ctx: RouteContext<Rc<AppState>>,
+    handler: &H,
+) -> Result<Response>
+where
+    H: Fn(Request, RouteContext<Rc<AppState>>) -> F,
+    F: Future<Output = Result<Response>>,
+{
+    let exchange = Exchange {
+        method: req.method(),
+        path: req.path(),
+        started_ms: Date::now().as_millis(),
+        env: ctx.env.clone(),
+        state: ctx.data.clone(),
+    };
+
+    let mut entered = layers.len();
+    let mut early = None;
+    for (i, layer) in layers.iter().enumerate() {
+        match layer.before(&req, &ctx).await {
+            Ok(None) => {}
+            Ok(Some(resp)) => {
+                entered = i;
+                early = Some(resp);
+                break;
+            }
+            Err(e) => return Err(fail(&layers[..i], e, &exchange)),
+        }
+    }
+    let mut resp = match early {
+        Some(resp) => resp,
+        None => match handler(req, ctx).await {
+            Ok(resp) => resp,
+            Err(e) => return Err(fail(layers, e, &exchange)),
+        },
+    };
+
+    for layer in layers[..entered].iter().rev() {
+        resp = layer.after(resp, &exchange)?;
+    }
+    Ok(resp)
+}
+
+/// Wraps a handler in `layers` (outermost first), for registering with the router.
+pub fn wrap<H, F>(
+    layers: &'static [Layer],
+    handler: H,
+) -> impl Fn(Request, RouteContext<Rc<AppState>>) -> HandlerFuture
+where
+    H: Fn(Request, RouteContext<Rc<AppState>>) -> F + 'static,
+    F: Future<Output = Result<Response>> + 'static,
+{
+    let handler = Rc::new(handler);
+    move |req, ctx| {
+        let handler = handler.clone();
+        Box::pin(async move { run(layers, req, ctx, &*handler).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_routes_hide_without_a_token() {
+        let mut config = Config::default();
+        assert_eq!(admin_denial(&config, "Bearer anything"), Some(404));
+
+        config.admin_token = Some("s3cret".to_string());
+        assert_eq!(admin_denial(&config, ""), Some(401));
+        assert_eq!(admin_denial(&config, "Bearer wrong"), Some(401));
+        assert_eq!(admin_denial(&config, "Bearer s3cret"), None);
+    }
+}
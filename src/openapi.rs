@@ -0,0 +1,438 @@
+//! OpenAPI description of the JSON endpoints, served at `/api/openapi.json`.
+//!
+//! Each response struct describes itself through [`ApiSchema`], implemented
+//! alongside the document that references it. The tests serialize real values
+//! and check them against the schemas, so a field added to a struct without
+//! its schema fails the tests instead of silently drifting.
+
+use serde_json::{json, Value};
+
+use crate::auth::Usage;
+use crate::config::Config;
+use crate::handlers::api::{PostDetails, Source};
+use crate::handlers::oembed::OEmbed;
+use crate::handlers::shortlinks::MintedLink;
+use crate::scraper::stats::Backend;
+use crate::scraper::types::MediaType;
+use crate::shortlinks::ShortLink;
+use crate::templates::links_html::{MediaLink, PostLinks};
+
+/// A type that can describe its JSON form as an OpenAPI schema object.
+pub trait ApiSchema {
+    /// Name under `#/components/schemas`.
+    const NAME: &'static str;
+
+    fn schema() -> Value;
+
+    /// A `$ref` to this type's component schema.
+    fn reference() -> Value {
+        json!({ "$ref": format!("#/components/schemas/{}", Self::NAME) })
+    }
+}
+
+impl ApiSchema for MediaType {
+    const NAME: &'static str = "MediaType";
+
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["image", "video"] })
+    }
+}
+
+impl ApiSchema for MediaLink {
+    const NAME: &'static str = "MediaLink";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["index", "type", "url", "download_url"],
+            "properties": {
+                "index": { "type": "integer", "minimum": 1, "description": "1-based position, as used by /images and /videos" },
+                "type": MediaType::reference(),
+                "url": { "type": "string", "format": "uri" },
+                "download_url": { "type": "string", "format": "uri" },
+                "thumbnail_url": { "type": "string", "format": "uri", "description": "Video cover frame; videos only" },
+                "width": { "type": "integer" },
+                "height": { "type": "integer" },
+            }
+        })
+    }
+}
+
+impl ApiSchema for PostLinks {
+    const NAME: &'static str = "PostLinks";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["post_id", "username", "instagram_url", "media"],
+            "properties": {
+                "post_id": { "type": "string" },
+                "username": { "type": "string" },
+                "instagram_url": { "type": "string", "format": "uri" },
+                "media": { "type": "array", "items": MediaLink::reference() },
+            }
+        })
+    }
+}
+
+impl ApiSchema for PostDetails {
+    const NAME: &'static str = "PostDetails";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["post_id", "username", "is_verified", "instagram_url", "timestamp", "media", "source"],
+            "properties": {
+                "post_id": { "type": "string" },
+                "username": { "type": "string" },
+                "full_name": { "type": "string" },
+                "is_verified": { "type": "boolean" },
+                "instagram_url": { "type": "string", "format": "uri" },
+                "caption": { "type": "string" },
+                "timestamp": { "type": "integer", "description": "Unix time the post was published" },
+                "like_count": { "type": "integer" },
+                "comment_count": { "type": "integer" },
+                "video_view_count": { "type": "integer" },
+                "play_count": { "type": "integer", "description": "Plays, as reels report them" },
+                "media": { "type": "array", "items": MediaLink::reference() },
+                "source": Source::reference(),
+            }
+        })
+    }
+}
+
+impl ApiSchema for Source {
+    const NAME: &'static str = "Source";
+
+    fn schema() -> Value {
+        let backends: Vec<_> = Backend::ALL.into_iter().map(Backend::key).collect();
+        json!({
+            "type": "object",
+            "required": ["cached"],
+            "properties": {
+                "backend": { "type": "string", "enum": backends, "description": "Backend the post was scraped from, when known" },
+                "cached": { "type": "boolean", "description": "Served from the cache rather than scraped for this request" },
+            }
+        })
+    }
+}
+
+impl ApiSchema for OEmbed {
+    const NAME: &'static str = "OEmbed";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["author_name", "author_url", "provider_name", "provider_url", "title", "type", "version"],
+            "properties": {
+                "author_name": { "type": "string" },
+                "author_url": { "type": "string" },
+                "provider_name": { "type": "string" },
+                "provider_url": { "type": "string", "format": "uri" },
+                "title": { "type": "string" },
+                "type": { "type": "string", "enum": ["link"] },
+                "version": { "type": "string", "enum": ["1.0"] },
+                "thumbnail_url": { "type": "string", "format": "uri" },
+                "thumbnail_width": { "type": "integer" },
+                "thumbnail_height": { "type": "integer" },
+            }
+        })
+    }
+}
+
+impl ApiSchema for Usage {
+    const NAME: &'static str = "Usage";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["name", "used", "resets_at"],
+            "properties": {
+                "name": { "type": "string" },
+                "used": { "type": "integer", "description": "Requests counted today (UTC)" },
+                "daily_quota": { "type": "integer", "description": "Absent for unlimited keys" },
+                "remaining": { "type": "integer" },
+                "resets_at": { "type": "integer", "description": "Unix time of the next UTC midnight" },
+            }
+        })
+    }
+}
+
+impl ApiSchema for ShortLink {
+    const NAME: &'static str = "ShortLink";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["post_id"],
+            "properties": {
+                "post_id": { "type": "string", "description": "Instagram shortcode" },
+                "img_index": { "type": "integer", "minimum": 1, "maximum": 20, "description": "Carousel slide to pin" },
+                "gif": { "type": "boolean", "description": "Embed videos as GIFs" },
+                "top_comment": { "type": "boolean", "description": "Append the top comment" },
+                "lang": { "type": "string", "description": "Translate the caption into this language" },
+            }
+        })
+    }
+}
+
+impl ApiSchema for MintedLink {
+    const NAME: &'static str = "MintedLink";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["code", "url", "target"],
+            "properties": {
+                "code": { "type": "string" },
+                "url": { "type": "string", "format": "uri", "description": "The short link" },
+                "target": { "type": "string", "format": "uri", "description": "The embed URL it redirects to" },
+            }
+        })
+    }
+}
+
+/// Adds a type's schema to a `components.schemas` map.
+fn register<T: ApiSchema>(schemas: &mut serde_json::Map<String, Value>) {
+    schemas.insert(T::NAME.to_string(), T::schema());
+}
+
+fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({ "description": description, "content": { "application/json": { "schema": schema } } })
+}
+
+/// A `GET` of one post through the versioned API.
+fn post_operation(operation_id: &str, summary: &str, schema: Value, extra_parameters: &[Value]) -> Value {
+    let mut parameters = vec![json!({ "name": "postID", "in": "path", "required": true, "schema": { "type": "string" } })];
+    parameters.extend_from_slice(extra_parameters);
+    let version_header = json!({ "description": "API version the response was built with", "schema": { "type": "string" } });
+    json!({
+        "get": {
+            "operationId": operation_id,
+            "summary": summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "The post",
+                    "headers": { "Api-Version": version_header },
+                    "content": { "application/json": { "schema": schema } },
+                },
+                "400": { "description": "Unsupported API version" },
+                "404": { "description": "Post not found" },
+                "502": { "description": "Every backend failed" },
+                "503": { "description": "Scraping is paused by the kill switch" },
+            }
+        }
+    })
+}
+
+/// Operations that need an API key when `REQUIRE_API_KEY` is set.
+const KEYED_PATHS: [&str; 4] = ["/links/{postID}", "/api/posts/{postID}", "/api/v1/posts/{postID}", "/api/v2/posts/{postID}"];
+
+/// Builds the OpenAPI 3.1 document for this deployment.
+pub fn document(config: &Config, host: &str) -> Value {
+    let mut schemas = serde_json::Map::new();
+    register::<MediaType>(&mut schemas);
+    register::<MediaLink>(&mut schemas);
+    register::<PostLinks>(&mut schemas);
+    register::<PostDetails>(&mut schemas);
+    register::<Source>(&mut schemas);
+    register::<OEmbed>(&mut schemas);
+    register::<Usage>(&mut schemas);
+    register::<ShortLink>(&mut schemas);
+    register::<MintedLink>(&mut schemas);
+
+    let mut doc = json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": format!("{} API", config.site_name),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": format!("https://{host}") }],
+        "paths": {
+            "/links/{postID}": {
+                "get": {
+                    "operationId": "getPostLinks",
+                    "summary": "Every media item of a post, with proxy links, types and dimensions",
+                    "description": "The JSON form is superseded by /api/v1/posts/{postID}, which has the same schema.",
+                    "deprecated": true,
+                    "parameters": [
+                        { "name": "postID", "in": "path", "required": true, "schema": { "type": "string" } },
+                        query_param("format", "`json` forces JSON for clients that aren't bots", json!({ "type": "string", "enum": ["json"] })),
+                    ],
+                    "responses": {
+                        "200": json_response("The post's media", PostLinks::reference()),
+                        "404": { "description": "Post not found" },
+                        "502": { "description": "Every backend failed" },
+                    }
+                }
+            },
+            "/api/v1/posts/{postID}": post_operation(
+                "getPostV1",
+                "Every media item of a post (v1)",
+                PostLinks::reference(),
+                &[],
+            ),
+            "/api/v2/posts/{postID}": post_operation(
+                "getPostV2",
+                "A post's media and details (v2)",
+                PostDetails::reference(),
+                &[],
+            ),
+            "/api/posts/{postID}": post_operation(
+                "getPost",
+                "A post, in the version named by Api-Version (v1 without it)",
+                json!({ "oneOf": [PostLinks::reference(), PostDetails::reference()] }),
+                &[json!({ "name": "Api-Version", "in": "header", "required": false, "schema": { "type": "string", "enum": ["1", "2"] } })],
+            ),
+            "/api/usage": {
+                "get": {
+                    "operationId": "getUsage",
+                    "summary": "The calling API key's usage for the current UTC day",
+                    "security": [{ "apiKey": [] }],
+                    "responses": {
+                        "200": json_response("Usage report", Usage::reference()),
+                        "401": { "description": "API key required" },
+                        "403": { "description": "Invalid API key" },
+                    }
+                }
+            },
+            "/api/shortlinks": {
+                "post": {
+                    "operationId": "createShortLink",
+                    "summary": "Mints a /s/{code} link pinning a post, slide and display options",
+                    "security": [{ "apiKey": [] }],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": ShortLink::reference() } },
+                    },
+                    "responses": {
+                        "201": json_response("The new short link", MintedLink::reference()),
+                        "400": { "description": "Invalid short link" },
+                        "401": { "description": "API key required" },
+                        "403": { "description": "Invalid API key" },
+                    }
+                }
+            },
+            "/oembed": {
+                "get": {
+                    "operationId": "getOEmbed",
+                    "summary": "oEmbed link response, as advertised by embed pages",
+                    "parameters": [
+                        query_param("text", "Author name", json!({ "type": "string" })),
+                        query_param("url", "Original URL", json!({ "type": "string" })),
+                        query_param("avatar", "Author's profile picture; Instagram CDN URLs only", json!({ "type": "string", "format": "uri" })),
+                    ],
+                    "responses": {
+                        "200": json_response("oEmbed object", OEmbed::reference()),
+                    }
+                }
+            }
+        },
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+            },
+        },
+    });
+
+    // /usage and /shortlinks are always keyed; /links and posts only when keys are required, and /oembed never
+    if config.api_keys_required {
+        for path in KEYED_PATHS {
+            let operation = &mut doc["paths"][path]["get"];
+            operation["security"] = json!([{ "apiKey": [] }]);
+            operation["responses"]["401"] = json!({ "description": "API key required" });
+            operation["responses"]["403"] = json!({ "description": "Invalid API key" });
+            operation["responses"]["429"] = json!({ "description": "Daily quota exceeded; see Retry-After" });
+        }
+    }
+
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{post, video};
+    use crate::scraper::types::{InstaData, Media};
+    use serde::Serialize;
+
+    /// Checks a serialized value against a component schema: every key must be
+    /// declared, and every required property present.
+    fn assert_matches<T: ApiSchema + Serialize>(value: &T) {
+        let json = serde_json::to_value(value).unwrap();
+        let schema = T::schema();
+        let properties = schema["properties"].as_object().unwrap();
+        for key in json.as_object().unwrap().keys() {
+            assert!(properties.contains_key(key), "{}.{} missing from schema", T::NAME, key);
+        }
+        for required in schema["required"].as_array().unwrap() {
+            assert!(json.get(required.as_str().unwrap()).is_some(), "{}.{} not serialized", T::NAME, required);
+        }
+    }
+
+    #[test]
+    fn schemas_match_serialized_structs() {
+        let slide = Media { width: Some(1080), height: Some(1920), ..video("https://cdn.example.com/video.mp4") };
+        let data = InstaData { is_video: true, timestamp: 1700000000, ..post("ABC123", "testuser", vec![slide]) };
+        let links = PostLinks::new(&data, "cattgram.com");
+        assert_matches(&links);
+        let details = PostDetails::new(&data, "cattgram.com");
+        assert_matches(&details);
+        assert_matches(&details.source);
+        assert_matches(&Source { backend: Some(Backend::PostPage), ..details.source });
+        assert_matches(&links.media[0]);
+
+        let key = serde_json::from_str(r#"{"name":"acme","daily_quota":100}"#).unwrap();
+        assert_matches(&Usage::new(&key, 12, 1700000000));
+
+        let link = serde_json::from_str(r#"{"post_id":"ABC123","img_index":2,"gif":true}"#).unwrap();
+        assert_matches::<ShortLink>(&link);
+        assert_matches(&MintedLink {
+            code: "x7Kp2mQa".to_string(),
+            url: "https://cattgram.com/s/x7Kp2mQa".to_string(),
+            target: "https://cattgram.com/p/ABC123/?img_index=2&gif=true".to_string(),
+        });
+
+        assert_matches(&OEmbed {
+            author_name: "@testuser".to_string(),
+            author_url: "https://instagram.com/p/ABC123".to_string(),
+            provider_name: "Cattgram".to_string(),
+            provider_url: "https://cattgram.com".to_string(),
+            title: "Instagram",
+            kind: "link",
+            version: "1.0",
+            thumbnail_url: Some("https://scontent.cdninstagram.com/avatar.jpg".to_string()),
+            thumbnail_width: Some(150),
+            thumbnail_height: Some(150),
+        });
+    }
+
+    #[test]
+    fn document_references_resolve() {
+        let doc = document(&Config::default(), "cattgram.com");
+        assert_eq!(doc["servers"][0]["url"], "https://cattgram.com");
+        assert!(doc["paths"]["/links/{postID}"]["get"].get("security").is_none());
+
+        let text = doc.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(doc["components"]["schemas"].get(name).is_some(), "dangling $ref to {name}");
+        }
+    }
+
+    #[test]
+    fn keyed_deployments_declare_the_api_key() {
+        let config = Config { api_keys_required: true, ..Config::default() };
+        let doc = document(&config, "cattgram.com");
+        assert_eq!(doc["components"]["securitySchemes"]["apiKey"]["name"], "X-Api-Key");
+        assert_eq!(doc["paths"]["/links/{postID}"]["get"]["security"][0]["apiKey"], json!([]));
+        assert_eq!(doc["paths"]["/api/v2/posts/{postID}"]["get"]["responses"]["429"]["description"], "Daily quota exceeded; see Retry-After");
+        assert!(doc["paths"]["/oembed"]["get"].get("security").is_none());
+    }
+}
@@ -0,0 +1,362 @@
+//! Pacing of Private API, GraphQL and Browser Rendering calls, in a Durable
+//! Object (`PACER`).
+//!
+//! A viral link brings hundreds of crawlers within seconds, and every
+//! isolate that misses the cache scrapes on its own. Those bursts are what
+//! gets sessions challenged and IPs blocked, and what runs up the Browser
+//! Rendering bill. With the binding, each of the three backends gets an
+//! object that hands out permits, instance-wide:
+//!
+//! - At most `PACE_CONCURRENCY` calls are in flight at once.
+//! - Calls start no faster than `PACE_PER_MINUTE` (`BROWSER_PER_MINUTE` for
+//!   renders), from a token bucket that holds `PACE_CONCURRENCY` tokens, so
+//!   a short burst still goes straight out.
+//! - A call that can't get a permit within `PACE_MAX_WAIT_MS` isn't made, and
+//!   the scrape moves on to the next backend as if it had failed. The wait
+//!   comes out of the call's timeout, so under `Deadline` it counts toward
+//!   the scrape budget.
+//!
+//! Permits live in the object's memory. If it is evicted, pacing starts
+//! over with a full bucket. Without the binding, calls go out unpaced.
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+use worker::*;
+
+use crate::config::Config;
+use crate::scraper::deadline::MIN_CALL_MILLIS;
+use crate::scraper::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::scraper::proxy::proxied_url;
+use crate::scraper::stats::Backend;
+use crate::utils::log::console_log;
+
+/// The Durable Object namespace binding.
+pub const BINDING: &str = "PACER";
+
+/// A permit that was never released (its isolate died mid-call, say) is
+/// reclaimed after this long, well past any call a Worker can make.
+const PERMIT_TTL_MS: u64 = 30_000;
+
+/// Longest wait for a slot before asking again, since a release frees one
+/// long before its permit would be reclaimed.
+const MAX_POLL_MS: u64 = 250;
+
+/// What a paced backend may do, from `Config`. Sent with every request for
+/// a permit, so the object needs no config of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Limits {
+    per_minute: u32,
+    concurrency: u32,
+}
+
+impl Limits {
+    fn for_backend(backend: Backend, config: &Config) -> Self {
+        let per_minute = match backend {
+            Backend::Browser => config.browser_per_minute,
+            _ => config.pace_per_minute,
+        };
+        Self { per_minute, concurrency: config.pace_concurrency }
+    }
+}
+
+/// The pacer's answer to a call asking for a permit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Answer {
+    /// Go ahead, and release the permit afterwards.
+    Granted(u64),
+    /// Ask again in this many milliseconds.
+    Wait(u64),
+}
+
+/// One backend's token bucket and permits in flight.
+#[derive(Debug, Clone, PartialEq)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: u64,
+    /// Permits in flight, with the time each is reclaimed at.
+    in_flight: Vec<(u64, u64)>,
+    next_permit: u64,
+}
+
+impl Bucket {
+    /// Starts full.
+    fn new(limits: Limits, now_ms: u64) -> Self {
+        Self {
+            tokens: f64::from(limits.concurrency),
+            refilled_at: now_ms,
+            in_flight: Vec::new(),
+            next_permit: 0,
+        }
+    }
+
+    fn acquire(&mut self, limits: Limits, now_ms: u64) -> Answer {
+        self.in_flight.retain(|&(_, reclaimed_at)| reclaimed_at > now_ms);
+        let per_ms = f64::from(limits.per_minute) / 60_000.0;
+        let refill = now_ms.saturating_sub(self.refilled_at) as f64 * per_ms;
+        self.tokens = (self.tokens + refill).min(f64::from(limits.concurrency));
+        self.refilled_at = now_ms;
+
+        if self.in_flight.len() >= limits.concurrency as usize {
+            let next_reclaim = self.in_flight.iter().map(|&(_, at)| at).min().unwrap_or(now_ms);
+            return Answer::Wait(next_reclaim.saturating_sub(now_ms).clamp(1, MAX_POLL_MS));
+        }
+        if self.tokens < 1.0 {
+            // With no rate at all this is infinite, which saturates
+            return Answer::Wait(((1.0 - self.tokens) / per_ms).ceil().max(1.0) as u64);
+        }
+
+        self.tokens -= 1.0;
+        self.next_permit += 1;
+        self.in_flight.push((self.next_permit, now_ms + PERMIT_TTL_MS));
+        Answer::Granted(self.next_permit)
+    }
+
+    fn release(&mut self, permit: u64) {
+        self.in_flight.retain(|&(p, _)| p != permit);
+    }
+}
+
+/// Hands out one backend's permits. Requests are handled without awaiting
+/// in between reading and updating the bucket, so they never race.
+#[durable_object]
+pub struct Pacer {
+    bucket: RefCell<Option<Bucket>>,
+}
+
+impl DurableObject for Pacer {
+    fn new(_state: State, _env: Env) -> Self {
+        Self { bucket: RefCell::new(None) }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        let now_ms = Date::now().as_millis();
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/acquire") => {
+                let limits: Limits = req.json().await?;
+                let answer = self
+                    .bucket
+                    .borrow_mut()
+                    .get_or_insert_with(|| Bucket::new(limits, now_ms))
+                    .acquire(limits, now_ms);
+                Response::from_json(&answer)
+            }
+            (Method::Post, "/release") => {
+                let permit: u64 = req.json().await?;
+                if let Some(bucket) = self.bucket.borrow_mut().as_mut() {
+                    bucket.release(permit);
+                }
+                Response::empty()
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+}
+
+/// Endpoints on the Private API host that the web app calls without a
+/// session, for profiles and hashtags. They aren't Private API calls, and
+/// aren't paced.
+const WEB_ENDPOINTS: [&str; 2] = ["/api/v1/users/web_profile_info/", "/api/v1/tags/web_info/"];
+
+/// The paced backend a request calls, if any. A proxied request calls the
+/// URL it carries.
+fn paced_backend(request: &HttpRequest, graphql_path: &str) -> Option<Backend> {
+    let target = proxied_url(request).unwrap_or_else(|| request.url.clone());
+    let url = Url::parse(&target).ok()?;
+    match url.host_str()? {
+        "i.instagram.com" if !WEB_ENDPOINTS.contains(&url.path()) => Some(Backend::Papi),
+        "www.instagram.com" if url.path() == graphql_path => Some(Backend::Graphql),
+        "api.cloudflare.com" if url.path().contains("/browser-rendering/") => Some(Backend::Browser),
+        _ => None,
+    }
+}
+
+/// How long a call may wait for a permit: `PACE_MAX_WAIT_MS`, but never so
+/// long that its timeout has no time left for the call itself.
+fn max_wait(config: &Config, request: &HttpRequest) -> u64 {
+    let allowed = request.timeout_millis.map_or(u64::MAX, |t| t.saturating_sub(MIN_CALL_MILLIS));
+    config.pace_max_wait_ms.min(allowed)
+}
+
+/// Takes the time spent waiting for a permit off the call's timeout.
+fn after_wait(request: HttpRequest, waited: u64) -> HttpRequest {
+    match request.timeout_millis {
+        Some(timeout) => request.timeout(timeout.saturating_sub(waited)),
+        None => request,
+    }
+}
+
+/// The object pacing a backend.
+fn object_name(backend: Backend) -> &'static str {
+    match backend {
+        Backend::Papi => "papi",
+        Backend::Browser => "browser",
+        _ => "graphql",
+    }
+}
+
+async fn call(stub: &Stub, path: &str, body: String) -> Result<Response> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(body.into()));
+    stub.fetch_with_request(Request::new_with_init(&format!("https://pacer{path}"), &init)?).await
+}
+
+/// Wraps a client so Private API, GraphQL and Browser Rendering calls wait
+/// for a permit from their backend's pacer first. A call that gets none in
+/// time fails without being sent. When the pacer can't be reached, calls go
+/// out unpaced.
+///
+/// A call with a timeout (from `Deadline`, say) waits no longer than the
+/// timeout allows, and is sent with what is left of it.
+pub struct Paced<'a, C> {
+    inner: &'a C,
+    namespace: Option<ObjectNamespace>,
+    config: &'a Config,
+}
+
+impl<'a, C: HttpClient> Paced<'a, C> {
+    /// Paces nothing when `PACER` isn't bound.
+    pub fn new(inner: &'a C, env: &Env, config: &'a Config) -> Self {
+        Self { inner, namespace: env.durable_object(BINDING).ok(), config }
+    }
+
+    /// Asks for a permit until one is granted, or the next wait would take
+    /// the total past `max_wait`.
+    async fn permit(&self, stub: &Stub, backend: Backend, started: u64, max_wait: u64) -> Result<Option<u64>> {
+        let limits = serde_json::to_string(&Limits::for_backend(backend, self.config))?;
+        loop {
+            let answer: Answer = call(stub, "/acquire", limits.clone()).await?.json().await?;
+            let millis = match answer {
+                Answer::Granted(permit) => return Ok(Some(permit)),
+                Answer::Wait(millis) => millis,
+            };
+            let waited = self.inner.now_millis().saturating_sub(started);
+            if waited.saturating_add(millis) > max_wait {
+                return Ok(None);
+            }
+            Delay::from(Duration::from_millis(millis)).await;
+        }
+    }
+}
+
+impl<C: HttpClient> HttpClient for Paced<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let Some(backend) = paced_backend(&request, &self.config.graphql_path) else {
+            return self.inner.send(request).await;
+        };
+        let Some(stub) = self.namespace.as_ref().and_then(|ns| ns.get_by_name(object_name(backend)).ok()) else {
+            return self.inner.send(request).await;
+        };
+
+        let started = self.inner.now_millis();
+        match self.permit(&stub, backend, started, max_wait(self.config, &request)).await {
+            Ok(Some(permit)) => {
+                let request = after_wait(request, self.inner.now_millis().saturating_sub(started));
+                let result = self.inner.send(request).await;
+                if let Err(e) = call(&stub, "/release", permit.to_string()).await {
+                    console_log!("[pacing] release failed: {:?}", e);
+                }
+                result
+            }
+            Ok(None) => {
+                console_log!("[pacing] {} is busy, skipping {}", backend.name(), request.url);
+                Err(Error::RustError(format!("{} is busy", backend.name())))
+            }
+            Err(e) => {
+                console_log!("[pacing] pacer unreachable, calling unpaced: {:?}", e);
+                self.inner.send(request).await
+            }
+        }
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::proxy::{proxy_fetch, ProxyCredentials};
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    const LIMITS: Limits = Limits { per_minute: 60, concurrency: 2 };
+
+    #[test]
+    fn bursts_up_to_the_cap_then_paces() {
+        let mut bucket = Bucket::new(LIMITS, 0);
+        assert_eq!(bucket.acquire(LIMITS, 0), Answer::Granted(1));
+        assert_eq!(bucket.acquire(LIMITS, 0), Answer::Granted(2));
+        // Both slots taken
+        assert_eq!(bucket.acquire(LIMITS, 0), Answer::Wait(MAX_POLL_MS));
+
+        bucket.release(1);
+        bucket.release(2);
+        // Slots free, but the bucket refills at one token a second
+        assert_eq!(bucket.acquire(LIMITS, 400), Answer::Wait(600));
+        assert_eq!(bucket.acquire(LIMITS, 1000), Answer::Granted(3));
+    }
+
+    #[test]
+    fn unreleased_permits_are_reclaimed() {
+        let mut bucket = Bucket::new(LIMITS, 0);
+        bucket.acquire(LIMITS, 0);
+        bucket.acquire(LIMITS, 0);
+        assert!(matches!(bucket.acquire(LIMITS, PERMIT_TTL_MS - 1), Answer::Wait(1)));
+        assert_eq!(bucket.acquire(LIMITS, PERMIT_TTL_MS), Answer::Granted(3));
+    }
+
+    #[test]
+    fn browser_renders_have_a_rate_of_their_own() {
+        let config = Config { pace_per_minute: 60, browser_per_minute: 5, ..Config::default() };
+        assert_eq!(Limits::for_backend(Backend::Papi, &config).per_minute, 60);
+        assert_eq!(Limits::for_backend(Backend::Browser, &config).per_minute, 5);
+    }
+
+    #[test]
+    fn the_wait_comes_out_of_the_call_timeout() {
+        let config = Config { pace_max_wait_ms: 2000, ..Config::default() };
+        let request = HttpRequest::get("https://i.instagram.com/api/v1/media/1/info/");
+        assert_eq!(max_wait(&config, &request), 2000);
+
+        // Under a nearly spent budget, the wait leaves room for the call
+        let request = request.timeout(1000);
+        assert_eq!(max_wait(&config, &request), 1000 - MIN_CALL_MILLIS);
+        assert_eq!(after_wait(request, 300).timeout_millis, Some(700));
+        assert_eq!(after_wait(HttpRequest::get("https://example.com/"), 300).timeout_millis, None);
+    }
+
+    #[test]
+    fn no_rate_means_waiting_forever() {
+        let limits = Limits { per_minute: 0, ..LIMITS };
+        let mut bucket = Bucket { tokens: 0.0, ..Bucket::new(limits, 0) };
+        assert_eq!(bucket.acquire(limits, 5000), Answer::Wait(u64::MAX));
+    }
+
+    #[test]
+    fn paces_papi_graphql_and_browser_calls_only() {
+        let graphql = Config::default().graphql_path;
+        let papi = HttpRequest::get("https://i.instagram.com/api/v1/media/1/info/");
+        assert_eq!(paced_backend(&papi, &graphql), Some(Backend::Papi));
+        let query = HttpRequest::post(format!("https://www.instagram.com{graphql}"), "");
+        assert_eq!(paced_backend(&query, &graphql), Some(Backend::Graphql));
+        let embed = HttpRequest::get("https://www.instagram.com/p/CxReel123/embed/captioned/");
+        assert_eq!(paced_backend(&embed, &graphql), None);
+        let render = HttpRequest::post("https://api.cloudflare.com/client/v4/accounts/acct/browser-rendering/content", "");
+        assert_eq!(paced_backend(&render, &graphql), Some(Backend::Browser));
+        // Profile and hashtag lookups share the host, but aren't Private API calls
+        let profile = HttpRequest::get("https://i.instagram.com/api/v1/users/web_profile_info/?username=catlover");
+        assert_eq!(paced_backend(&profile, &graphql), None);
+        let tag = HttpRequest::get("https://i.instagram.com/api/v1/tags/web_info/?tag_name=cats");
+        assert_eq!(paced_backend(&tag, &graphql), None);
+
+        // Through the proxy, the call is to the URL it carries
+        let client = FixtureClient::new();
+        let creds = ProxyCredentials { username: "zone-x".into(), password: "t".into(), country: "us".into() };
+        block_on(proxy_fetch(&client, papi, Some(&creds))).unwrap();
+        assert_eq!(paced_backend(&client.requests.borrow()[0], &graphql), Some(Backend::Papi));
+    }
+}
@@ -0,0 +1,144 @@
+//! Per-key daily request counts, in a Durable Object (`QUOTAS`).
+//!
+//! KV has no increment: a counter that is read and then written lets every
+//! concurrent request see the same count, so a busy key can run well past
+//! its quota. With the binding, each key gets an object that counts its
+//! requests one at a time, and refuses them once the quota is used up.
+//! Without it, counts are kept in KV as before, and are approximate.
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::utils::log::console_log;
+
+/// The Durable Object namespace binding.
+pub const BINDING: &str = "QUOTAS";
+
+/// Storage key of the `DayCount`.
+const COUNT_KEY: &str = "count";
+
+/// A request to count, and the key's quota.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Take {
+    day: u64,
+    quota: Option<u64>,
+}
+
+/// A key's request count on one UTC day (days since the epoch). Only the
+/// current day is kept; the first request of a new day starts over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct DayCount {
+    day: u64,
+    used: u64,
+}
+
+impl DayCount {
+    fn used(&self, day: u64) -> u64 {
+        if self.day == day {
+            self.used
+        } else {
+            0
+        }
+    }
+
+    /// Counts a request unless it would go over `quota`. Returns the count
+    /// before this request, so callers can tell whether it was refused.
+    fn take(&mut self, day: u64, quota: Option<u64>) -> u64 {
+        let used = self.used(day);
+        if quota.is_none_or(|quota| used < quota) {
+            *self = Self { day, used: used + 1 };
+        }
+        used
+    }
+}
+
+/// Counts one key's requests. Storage calls hold other requests off until
+/// they finish, so a read and the write after it never interleave with
+/// another request's.
+#[durable_object]
+pub struct QuotaCounter {
+    state: State,
+}
+
+impl DurableObject for QuotaCounter {
+    fn new(state: State, _env: Env) -> Self {
+        Self { state }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        let mut count: DayCount = self.state.storage().get(COUNT_KEY).await?.unwrap_or_default();
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/take") => {
+                let take: Take = req.json().await?;
+                let used = count.take(take.day, take.quota);
+                self.state.storage().put(COUNT_KEY, count).await?;
+                Response::from_json(&used)
+            }
+            (Method::Post, "/used") => {
+                let day: u64 = req.json().await?;
+                Response::from_json(&count.used(day))
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+}
+
+async fn call(env: &Env, key: &str, path: &str, body: String) -> Option<u64> {
+    let namespace = env.durable_object(BINDING).ok()?;
+    let answer = async {
+        let stub = namespace.get_by_name(key)?;
+        let mut init = RequestInit::new();
+        init.with_method(Method::Post).with_body(Some(body.into()));
+        stub.fetch_with_request(Request::new_with_init(&format!("https://quota{path}"), &init)?)
+            .await?
+            .json::<u64>()
+            .await
+    };
+    match answer.await {
+        Ok(used) => Some(used),
+        Err(e) => {
+            console_log!("[quota] {} failed: {:?}", path, e);
+            None
+        }
+    }
+}
+
+/// Counts a request against `key`'s quota for `day`, unless it is used up.
+/// Returns the count before this request, or `None` when `QUOTAS` isn't
+/// bound or can't be reached.
+pub async fn take(key: &str, day: u64, quota: Option<u64>, env: &Env) -> Option<u64> {
+    let body = serde_json::to_string(&Take { day, quota }).ok()?;
+    call(env, key, "/take", body).await
+}
+
+/// `key`'s request count for `day`. `None` as for `take`.
+pub async fn used(key: &str, day: u64, env: &Env) -> Option<u64> {
+    call(env, key, "/used", day.to_string()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_up_to_the_quota_then_refuses() {
+        let mut count = DayCount::default();
+        assert_eq!(count.take(10, Some(2)), 0);
+        assert_eq!(count.take(10, Some(2)), 1);
+        assert_eq!(count.take(10, Some(2)), 2);
+        assert_eq!(count.take(10, Some(2)), 2);
+        assert_eq!(count.used(10), 2);
+
+        // Unlimited keys are counted all the same
+        assert_eq!(count.take(10, None), 2);
+        assert_eq!(count.used(10), 3);
+    }
+
+    #[test]
+    fn a_new_day_starts_over() {
+        let mut count = DayCount { day: 10, used: 5 };
+        assert_eq!(count.used(11), 0);
+        assert_eq!(count.take(11, Some(5)), 0);
+        assert_eq!(count, DayCount { day: 11, used: 1 });
+    }
+}
@@ -0,0 +1,247 @@
+//! Cloudflare Browser Rendering backend: the post page, loaded in a headless
+//! browser.
+//!
+//! A real Chrome runs Instagram's scripts, so it gets past the checks the
+//! plain-fetch backends trip over, and the rendered DOM carries the same
+//! inline JSON the post page backend reads. Every call spends browser time,
+//! so it runs last.
+//!
+//! Driven through the Browser Rendering REST API rather than a `[browser]`
+//! binding: the binding speaks the DevTools protocol to puppeteer, which only
+//! exists for JavaScript Workers.
+//!
+//! When not even the browser yields post data, but it did show the post's
+//! page (`PageWatch`), `capture_screenshot` takes a picture of the page
+//! instead, for the embed's `og:image`.
+
+use std::cell::Cell;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use worker::*;
+
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use super::post_page::parse_post_page;
+use super::types::InstaData;
+use crate::utils::log::console_log;
+
+/// Without this, the browser announces itself as `HeadlessChrome`.
+const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+
+const API_BASE: &str = "https://api.cloudflare.com/client/v4/accounts";
+
+/// Screenshot size: the 1.91:1 shape link previews crop images to.
+pub const SCREENSHOT_WIDTH: u32 = 1200;
+pub const SCREENSHOT_HEIGHT: u32 = 630;
+
+/// Screenshots are taken after the scrape chain has given up, with the
+/// crawler still waiting. A cap: the capture also gets no more than what is
+/// left of the scrape budget.
+const SCREENSHOT_TIMEOUT_MS: u64 = 6000;
+
+/// Browser Rendering credentials, loaded by `Config::from_env`.
+#[derive(Debug, Clone)]
+pub struct BrowserCredentials {
+    /// `BROWSER_RENDERING_ACCOUNT_ID`
+    pub account_id: String,
+    /// `BROWSER_RENDERING_TOKEN`: an API token with Browser Rendering edit rights.
+    pub token: String,
+}
+
+/// The envelope every Browser Rendering endpoint answers with.
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    success: bool,
+    result: Option<T>,
+    #[serde(default)]
+    errors: Vec<ApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    message: String,
+}
+
+/// Loads `/p/{shortcode}/` in Browser Rendering and extracts the post from
+/// the rendered page.
+///
+/// Images, media, fonts and stylesheets are blocked, since only the DOM is
+/// read. No cookie is sent: the browser's own fingerprint is the point.
+pub async fn fetch_browser<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    creds: &BrowserCredentials,
+) -> Result<Option<InstaData>> {
+    let body = json!({
+        "url": format!("https://www.instagram.com/p/{post_id}/"),
+        "userAgent": CHROME_UA,
+        "gotoOptions": { "waitUntil": "networkidle2" },
+        "rejectResourceTypes": ["image", "media", "font", "stylesheet"],
+    });
+    let request = api_request(creds, "content", &body);
+    let html: Option<String> = render(client, request).await?;
+    Ok(html.and_then(|html| parse_post_page(&html, post_id)))
+}
+
+/// The `/snapshot` endpoint's result.
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    /// Base64 JPEG.
+    screenshot: String,
+    /// The page's HTML when the screenshot was taken.
+    content: String,
+}
+
+/// Takes a JPEG screenshot of `/p/{shortcode}/`, base64 encoded. `None`
+/// unless the page rendered is the post's, rather than a "page not found"
+/// or a login form.
+pub async fn capture_screenshot<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    creds: &BrowserCredentials,
+) -> Result<Option<String>> {
+    let body = json!({
+        "url": format!("https://www.instagram.com/p/{post_id}/"),
+        "userAgent": CHROME_UA,
+        "viewport": { "width": SCREENSHOT_WIDTH, "height": SCREENSHOT_HEIGHT },
+        "gotoOptions": { "waitUntil": "networkidle2" },
+        "screenshotOptions": { "type": "jpeg", "quality": 80 },
+    });
+    let request = api_request(creds, "snapshot", &body).timeout(SCREENSHOT_TIMEOUT_MS);
+    let snapshot: Option<Snapshot> = render(client, request).await?;
+    Ok(snapshot.filter(|s| shows_post(&s.content, post_id)).map(|s| s.screenshot))
+}
+
+/// Returns `true` if the page's canonical URL (or `og:url`) is the post's.
+/// Instagram answers missing posts with a page that has neither.
+fn shows_post(html: &str, post_id: &str) -> bool {
+    let path = format!("/{post_id}/");
+    html.split('<')
+        .filter(|tag| tag.starts_with("link") || tag.starts_with("meta"))
+        .any(|tag| (tag.contains("canonical") || tag.contains("og:url")) && tag.contains(&path))
+}
+
+/// Wraps a client and notes whether a `/content` render showed the post's
+/// own page, whether or not its data could be read from it. Only then is
+/// the post known to exist, and worth a screenshot.
+pub struct PageWatch<'a, C> {
+    inner: &'a C,
+    post_id: &'a str,
+    shown: Cell<bool>,
+}
+
+impl<'a, C: HttpClient> PageWatch<'a, C> {
+    pub fn new(inner: &'a C, post_id: &'a str) -> Self {
+        Self { inner, post_id, shown: Cell::new(false) }
+    }
+
+    pub fn shown(&self) -> bool {
+        self.shown.get()
+    }
+}
+
+impl<C: HttpClient> HttpClient for PageWatch<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let content = request.url.ends_with("/browser-rendering/content");
+        let resp = self.inner.send(request).await?;
+        if content && resp.status == 200 {
+            let page = serde_json::from_str::<ApiResponse<String>>(&resp.body).ok().and_then(|r| r.result);
+            if page.is_some_and(|html| shows_post(&html, self.post_id)) {
+                self.shown.set(true);
+            }
+        }
+        Ok(resp)
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+fn api_request(creds: &BrowserCredentials, endpoint: &str, body: &serde_json::Value) -> HttpRequest {
+    let url = format!("{API_BASE}/{}/browser-rendering/{endpoint}", creds.account_id);
+    HttpRequest::post(url, body.to_string())
+        .header("Authorization", format!("Bearer {}", creds.token))
+        .header("Content-Type", "application/json")
+}
+
+/// Sends a Browser Rendering request and unwraps its result. `None` when
+/// the render failed, e.g. on a navigation timeout.
+async fn render<C: HttpClient, T: DeserializeOwned>(client: &C, request: HttpRequest) -> Result<Option<T>> {
+    let resp = client.send(request).await?;
+    console_log!("[browser] status={} len={}", resp.status, resp.body.len());
+    if resp.status != 200 {
+        return Ok(None);
+    }
+    let parsed: ApiResponse<T> = serde_json::from_str(&resp.body)
+        .map_err(|e| Error::RustError(format!("Browser Rendering response: {e}")))?;
+    if !parsed.success || parsed.result.is_none() {
+        let errors: Vec<&str> = parsed.errors.iter().map(|e| e.message.as_str()).collect();
+        console_log!("[browser] render failed: {}", errors.join("; "));
+        return Ok(None);
+    }
+    Ok(parsed.result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    fn creds() -> BrowserCredentials {
+        BrowserCredentials { account_id: "acct123".to_string(), token: "tok".to_string() }
+    }
+
+    #[test]
+    fn reads_the_post_from_the_rendered_page() {
+        let client = FixtureClient::new().route("/browser-rendering/content", 200, include_str!("../../fixtures/browser_content.json"));
+        let data = block_on(fetch_browser(&client, "CxPage1", &creds())).unwrap().unwrap();
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/o1/v/t16/page.mp4");
+
+        let requests = client.requests.borrow();
+        assert!(requests[0].url.starts_with("https://api.cloudflare.com/client/v4/accounts/acct123/"));
+        assert_eq!(requests[0].header_value("Authorization"), Some("Bearer tok"));
+        assert!(requests[0].body.as_deref().unwrap().contains(r#""url":"https://www.instagram.com/p/CxPage1/""#));
+    }
+
+    #[test]
+    fn failed_renders_yield_none() {
+        let client = FixtureClient::new()
+            .route("/browser-rendering/content", 200, r#"{"success":false,"errors":[{"code":2001,"message":"Navigation timeout"}]}"#);
+        assert!(block_on(fetch_browser(&client, "CxPage1", &creds())).unwrap().is_none());
+
+        let client = FixtureClient::new().route("/browser-rendering/content", 429, "");
+        assert!(block_on(fetch_browser(&client, "CxPage1", &creds())).unwrap().is_none());
+    }
+
+    #[test]
+    fn notes_renders_of_the_post_page() {
+        let unreadable = r#"{"success":true,"result":"<html><head><link rel=\"canonical\" href=\"https://www.instagram.com/p/CxPage1/\"></head></html>"}"#;
+        let client = FixtureClient::new().route("/browser-rendering/content", 200, unreadable);
+        let watch = PageWatch::new(&client, "CxPage1");
+        assert!(block_on(fetch_browser(&watch, "CxPage1", &creds())).unwrap().is_none());
+        assert!(watch.shown());
+
+        let missing = r#"{"success":true,"result":"<html><head><title>Page not found</title></head></html>"}"#;
+        let client = FixtureClient::new().route("/browser-rendering/content", 200, missing);
+        let watch = PageWatch::new(&client, "CxPage1");
+        assert!(block_on(fetch_browser(&watch, "CxPage1", &creds())).unwrap().is_none());
+        assert!(!watch.shown());
+    }
+
+    #[test]
+    fn screenshots_only_pages_that_show_the_post() {
+        let post = r#"{"success":true,"result":{"screenshot":"/9j/4AAQ","content":"<html><head><link rel=\"canonical\" href=\"https://www.instagram.com/p/CxPage1/\"></head></html>"}}"#;
+        let client = FixtureClient::new().route("/browser-rendering/snapshot", 200, post);
+        assert_eq!(block_on(capture_screenshot(&client, "CxPage1", &creds())).unwrap().as_deref(), Some("/9j/4AAQ"));
+        assert!(client.requests.borrow()[0].body.as_deref().unwrap().contains(r#""viewport":{"height":630,"width":1200}"#));
+
+        let missing = r#"{"success":true,"result":{"screenshot":"/9j/4AAQ","content":"<html><head><title>Page not found</title></head></html>"}}"#;
+        let client = FixtureClient::new().route("/browser-rendering/snapshot", 200, missing);
+        assert!(block_on(capture_screenshot(&client, "CxPage1", &creds())).unwrap().is_none());
+    }
+}
@@ -1,37 +1,507 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use worker::*;
 
-use super::types::InstaData;
+use crate::auth::ApiKey;
+use crate::config::Config;
+use crate::metrics::RouteStats;
+use crate::shortlinks::StoredLink;
+use crate::status::StatusSnapshot;
+use crate::utils::log::console_log;
+use crate::AppState;
 
-const TTL_SECONDS: u64 = 86400; // 24 hours
+use super::doc_ids::DocIdScores;
+use super::ratelimit::Cooldown;
+use super::stats::{Attempt, BackendStats, Track, BUCKETS, BUCKET_SECS};
 
-fn cache_key(post_id: &str) -> String {
-    format!("post:{post_id}")
+/// The KV namespaces, by what they hold, so each can be purged and
+/// budgeted on its own. A namespace whose binding is missing falls back to
+/// `CACHE`, so single-namespace deployments keep working.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Store {
+    /// `CACHE`: scraped posts, stories, profiles and comments. Everything in
+    /// it can be scraped again, so it is safe to purge.
+    Data,
+    /// `RUNTIME`: API keys and usage counters, short links, backend and
+    /// route stats, doc_id scores, the rate-limit cooldown, the kill switch,
+    /// alert markers and the `/status` snapshot.
+    Runtime,
+    /// `PAGES`: rendered embeds. One post has an entry per slide, platform
+    /// and variant, so these outnumber everything else; apart, they can be
+    /// purged without losing scraped data.
+    Pages,
 }
 
-pub async fn get_cached(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
-    let kv = env.kv("CACHE")?;
-    let key = cache_key(post_id);
+impl Store {
+    pub const ALL: [Store; 3] = [Store::Data, Store::Runtime, Store::Pages];
 
-    match kv.get(&key).text().await? {
+    pub fn binding(self) -> &'static str {
+        match self {
+            Store::Data => "CACHE",
+            Store::Runtime => "RUNTIME",
+            Store::Pages => "PAGES",
+        }
+    }
+
+    /// Returns `true` if the namespace has a binding of its own.
+    pub fn is_bound(self, env: &Env) -> bool {
+        env.kv(self.binding()).is_ok()
+    }
+
+    pub fn kv(self, env: &Env) -> Result<KvStore> {
+        env.kv(self.binding()).or_else(|_| env.kv(Store::Data.binding()))
+    }
+}
+
+/// KV expiration for profiles. Follower counts and the post grid go stale
+/// much faster than a post does.
+const PROFILE_TTL: u64 = 6 * 60 * 60;
+
+/// KV expiration for posts no backend could find. Long enough to absorb a
+/// crawler retrying a bad link, short enough that a post hidden by an outage
+/// isn't hidden for long.
+const MISSING_TTL: u64 = 5 * 60;
+
+/// How long `/latest` sticks with a post before looking again.
+const LATEST_TTL: u64 = 5 * 60;
+
+/// How long a hashtag embed sticks with a top post before looking again.
+/// Top posts change slowly, but a hashtag page is one scrape away.
+const HASHTAG_TTL: u64 = 30 * 60;
+
+/// A share link always points at the same post, so its resolution is kept
+/// well past the post's own TTL.
+const SHARE_TTL: u64 = 30 * 86400;
+
+/// The kinds of scraped content, and of what is derived from it, read and
+/// written through `get` and `set_background`. Each kind has a key prefix of
+/// its own, so a post, a story and a highlight item with the same ID never
+/// collide, a TTL of its own, and the namespace it is stored in.
+///
+/// Kinds keyed by more than one thing take an ID of the parts joined with
+/// `:`, e.g. `{postID}:{lang}` for a translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// `post:{postID}`: an `InstaData`.
+    Post,
+    /// `story:{shortcode}`: an `InstaData`.
+    Story,
+    /// `highlight:{shortcode}`: an `InstaData`, for a story item reached
+    /// through a highlight, which outlives the story's 24 hours.
+    Highlight,
+    /// `profile:{username}`: a `Profile`.
+    Profile,
+    /// `html:{postID}:{variant}`: a rendered embed, as its streamed chunks.
+    /// `variant` is an `EmbedVariant` key.
+    Html,
+    /// `missing:{postID}`: whether the browser saw the page of a post no
+    /// backend could scrape.
+    Missing,
+    /// `latest:{username}`: the shortcode of a user's newest post.
+    Latest,
+    /// `tag:{tag}`: the shortcode of a hashtag's top post. Tags arrive
+    /// lowercased from `parse_hashtag`.
+    Tag,
+    /// `comment:{postID}:{commentID}`: a `Comment`.
+    Comment,
+    /// `translation:{postID}:{lang}`: a caption translated into `lang`.
+    Translation,
+    /// `summary:{postID}:{lang}`: a caption's summary in `lang`.
+    Summary,
+    /// `alt:{postID}:{slide}`: a generated description of a slide's image
+    /// (1-based).
+    Alt,
+    /// `share:{shareID}`: the shortcode a share link (`share/<id>`, without
+    /// the prefix) resolved to.
+    Share,
+    /// `screenshot:{postID}`: JPEG bytes, not JSON; see `get_cached_screenshot`.
+    Screenshot,
+}
+
+impl Kind {
+    /// The KV key of the entry with this ID. Usernames are case-insensitive,
+    /// so profile and latest-post keys use the lowercase form.
+    pub fn key(self, id: &str) -> String {
+        match self {
+            Kind::Post => format!("post:{id}"),
+            Kind::Story => format!("story:{id}"),
+            Kind::Highlight => format!("highlight:{id}"),
+            Kind::Profile => format!("profile:{}", id.to_ascii_lowercase()),
+            Kind::Html => format!("html:{id}"),
+            Kind::Missing => format!("missing:{id}"),
+            Kind::Latest => format!("latest:{}", id.to_ascii_lowercase()),
+            Kind::Tag => format!("tag:{id}"),
+            Kind::Comment => format!("comment:{id}"),
+            Kind::Translation => format!("translation:{id}"),
+            Kind::Summary => format!("summary:{id}"),
+            Kind::Alt => format!("alt:{id}"),
+            Kind::Share => format!("share:{id}"),
+            Kind::Screenshot => format!("screenshot:{id}"),
+        }
+    }
+
+    /// Rendered pages go to `Store::Pages`, everything else to `Store::Data`.
+    pub fn store(self) -> Store {
+        match self {
+            Kind::Html => Store::Pages,
+            _ => Store::Data,
+        }
+    }
+
+    /// How long entries of this kind are kept. Stories are kept no longer
+    /// than they have left (see `scraper::story_ttl`).
+    pub fn ttl(self, config: &Config) -> u64 {
+        match self {
+            Kind::Story => config.story_cache_ttl,
+            Kind::Profile => PROFILE_TTL,
+            Kind::Missing => MISSING_TTL,
+            Kind::Latest => LATEST_TTL,
+            Kind::Tag => HASHTAG_TTL,
+            Kind::Share => SHARE_TTL,
+            Kind::Post
+            | Kind::Highlight
+            | Kind::Html
+            | Kind::Comment
+            | Kind::Translation
+            | Kind::Summary
+            | Kind::Alt
+            | Kind::Screenshot => config.cache_ttl,
+        }
+    }
+}
+
+/// Reads an entry of scraped content.
+pub async fn get<T: DeserializeOwned>(kind: Kind, id: &str, env: &Env) -> Result<Option<T>> {
+    get_json(kind.store(), &kind.key(id), env).await
+}
+
+/// Stores an entry of scraped content for its kind's TTL in the background,
+/// after the response has been sent.
+///
+/// Failures are logged and otherwise ignored — a missed cache write only
+/// costs a re-scrape on the next request.
+pub fn set_background<T: Serialize + Clone + 'static>(kind: Kind, id: &str, value: &T, env: &Env, state: &AppState) {
+    set_background_for(kind, id, value, kind.ttl(&state.config), env, &state.ctx);
+}
+
+/// Like `set_background`, for entries that must expire before their kind's
+/// TTL, like stories near the end of their 24 hours.
+pub fn set_background_for<T: Serialize + Clone + 'static>(kind: Kind, id: &str, value: &T, ttl: u64, env: &Env, ctx: &Context) {
+    put_json_background(kind.store(), kind.key(id), value.clone(), env, ctx, ttl);
+}
+
+/// Deletes a cached post after the response has been sent, so the next
+/// request scrapes it again.
+pub fn evict_cached_background(post_id: &str, env: &Env, ctx: &Context) {
+    let key = Kind::Post.key(post_id);
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        let result = match Store::Data.kv(&env) {
+            Ok(kv) => kv.delete(&key).await.map_err(Error::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            console_log!("[cache] background delete failed for {}: {:?}", key, e);
+        }
+    });
+}
+
+/// A JPEG screenshot of a post, taken when nothing could be scraped.
+pub async fn get_cached_screenshot(post_id: &str, env: &Env) -> Result<Option<Vec<u8>>> {
+    let kind = Kind::Screenshot;
+    Ok(kind.store().kv(env)?.get(&kind.key(post_id)).bytes().await?)
+}
+
+/// Stores a screenshot. Written before the response: the crawler fetches
+/// the image as soon as it has read the embed.
+pub async fn put_screenshot(post_id: &str, jpeg: &[u8], env: &Env, config: &Config) -> Result<()> {
+    let kind = Kind::Screenshot;
+    kind.store().kv(env)?.put_bytes(&kind.key(post_id), jpeg)?.expiration_ttl(kind.ttl(config)).execute().await?;
+    Ok(())
+}
+
+/// API keys are written by operators, never expire, and are never written
+/// by the worker.
+fn api_key_cache_key(key: &str) -> String {
+    format!("apikey:{key}")
+}
+
+pub async fn get_api_key(key: &str, env: &Env) -> Result<Option<ApiKey>> {
+    get_json(Store::Runtime, &api_key_cache_key(key), env).await
+}
+
+/// Day counters outlive their day a little, so a late write can't resurrect
+/// a rolled-over count.
+const API_USAGE_TTL: u64 = 2 * 86400;
+
+fn api_usage_cache_key(key: &str, day: u64) -> String {
+    format!("usage:{key}:{day}")
+}
+
+pub async fn get_api_usage(key: &str, day: u64, env: &Env) -> Result<Option<u64>> {
+    get_json(Store::Runtime, &api_usage_cache_key(key, day), env).await
+}
+
+/// Like `set_background`, for a key's request count on a UTC day
+/// (days since the epoch).
+pub fn set_api_usage_background(key: &str, day: u64, count: u64, env: &Env, ctx: &Context) {
+    put_json_background(Store::Runtime, api_usage_cache_key(key, day), count, env, ctx, API_USAGE_TTL);
+}
+
+fn stats_cache_key(bucket: u64, track: Track) -> String {
+    match track {
+        Track::Stable => format!("stats:{bucket}"),
+        Track::Canary => format!("stats:canary:{bucket}"),
+    }
+}
+
+pub async fn get_backend_stats(bucket: u64, track: Track, env: &Env) -> Result<Option<BackendStats>> {
+    get_json(Store::Runtime, &stats_cache_key(bucket, track), env).await
+}
+
+fn route_stats_cache_key(bucket: u64) -> String {
+    format!("routes:{bucket}")
+}
+
+pub async fn get_route_stats(bucket: u64, env: &Env) -> Result<Option<RouteStats>> {
+    get_json(Store::Runtime, &route_stats_cache_key(bucket), env).await
+}
+
+/// Adds an isolate's buffered route counts to an hourly bucket, after the
+/// response has been sent. Same read-modify-write and expiry as the backend
+/// stats below.
+pub fn add_route_stats_background(bucket: u64, counts: RouteStats, env: &Env, ctx: &Context) {
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        let key = route_stats_cache_key(bucket);
+        let mut stats: RouteStats = get_json(Store::Runtime, &key, &env).await.ok().flatten().unwrap_or_default();
+        stats.add(&counts);
+        if let Err(e) = put_json(Store::Runtime, &key, &stats, &env, (BUCKETS + 1) * BUCKET_SECS).await {
+            console_log!("[cache] background write failed for {}: {:?}", key, e);
+        }
+    });
+}
+
+/// Adds a scrape's backend attempts to an hourly stats bucket, after the
+/// response has been sent.
+///
+/// A read-modify-write, so concurrent scrapes can drop a count; the numbers
+/// are for spotting trends, not billing. Buckets expire an hour after they
+/// leave the dashboard's window.
+pub fn add_backend_stats_background(bucket: u64, track: Track, attempts: Vec<Attempt>, env: &Env, ctx: &Context) {
+    if attempts.is_empty() {
+        return;
+    }
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        let key = stats_cache_key(bucket, track);
+        let mut stats: BackendStats = get_json(Store::Runtime, &key, &env).await.ok().flatten().unwrap_or_default();
+        for attempt in &attempts {
+            stats.record(attempt);
+        }
+        if let Err(e) = put_json(Store::Runtime, &key, &stats, &env, (BUCKETS + 1) * BUCKET_SECS).await {
+            console_log!("[cache] background write failed for {}: {:?}", key, e);
+        }
+    });
+}
+
+const DOC_ID_SCORES_KEY: &str = "graphql:doc_ids";
+
+/// Refreshed on every write, so scores only lapse on an idle instance.
+const DOC_ID_SCORES_TTL: u64 = 30 * 86400;
+
+pub async fn get_doc_id_scores(env: &Env) -> Result<Option<DocIdScores>> {
+    get_json(Store::Runtime, DOC_ID_SCORES_KEY, env).await
+}
+
+/// Folds a scrape's GraphQL outcomes (`(doc_id, succeeded)`) into the stored
+/// scores, after the response has been sent. Like the backend stats, a
+/// read-modify-write that can lose an update under concurrency.
+pub fn update_doc_id_scores_background(outcomes: Vec<(String, bool)>, configured: Vec<String>, env: &Env, ctx: &Context) {
+    if outcomes.is_empty() {
+        return;
+    }
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        let mut scores: DocIdScores = get_json(Store::Runtime, DOC_ID_SCORES_KEY, &env).await.ok().flatten().unwrap_or_default();
+        for (doc_id, ok) in &outcomes {
+            scores.record(doc_id, *ok);
+        }
+        scores.retain(&configured);
+        if let Err(e) = put_json(Store::Runtime, DOC_ID_SCORES_KEY, &scores, &env, DOC_ID_SCORES_TTL).await {
+            console_log!("[cache] background write failed for {}: {:?}", DOC_ID_SCORES_KEY, e);
+        }
+    });
+}
+
+const KILL_SWITCH_KEY: &str = "killswitch";
+
+/// Whether the kill switch has been thrown at runtime, by writing `true` to
+/// `killswitch` (see `crate::killswitch`).
+pub async fn get_kill_switch(env: &Env) -> Result<bool> {
+    Ok(get_json(Store::Runtime, KILL_SWITCH_KEY, env).await?.unwrap_or(false))
+}
+
+const COOLDOWN_KEY: &str = "ratelimit:cooldown";
+
+pub async fn get_cooldown(env: &Env) -> Result<Option<Cooldown>> {
+    get_json(Store::Runtime, COOLDOWN_KEY, env).await
+}
+
+/// Starts (or escalates) the global rate-limit cooldown, after the response
+/// has been sent.
+pub fn start_cooldown_background(env: &Env, ctx: &Context, now_secs: u64) {
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        start_cooldown(&env, now_secs).await;
+    });
+}
+
+/// Like `start_cooldown_background`, for work already running after the
+/// response.
+pub async fn start_cooldown(env: &Env, now_secs: u64) {
+    let previous: Option<Cooldown> = get_json(Store::Runtime, COOLDOWN_KEY, env).await.ok().flatten();
+    let cooldown = Cooldown::after_hit(previous, now_secs);
+    console_log!("[cache] rate-limit cooldown until {} (strike {})", cooldown.until, cooldown.strikes);
+    if let Err(e) = put_json(Store::Runtime, COOLDOWN_KEY, &cooldown, env, cooldown.ttl(now_secs)).await {
+        console_log!("[cache] background write failed for {}: {:?}", COOLDOWN_KEY, e);
+    }
+}
+
+fn alert_cache_key(name: &str) -> String {
+    format!("alert:{name}")
+}
+
+/// Returns `true` if the alert was posted within its cooldown.
+pub async fn alert_recently_sent(name: &str, env: &Env) -> Result<bool> {
+    Ok(get_json::<bool>(Store::Runtime, &alert_cache_key(name), env).await?.is_some())
+}
+
+/// Starts an alert's cooldown; the marker expires after `ttl` seconds.
+pub async fn mark_alert_sent(name: &str, env: &Env, ttl: u64) -> Result<()> {
+    put_json(Store::Runtime, &alert_cache_key(name), &true, env, ttl).await
+}
+
+const STATUS_KEY: &str = "status:snapshot";
+
+pub async fn get_status_snapshot(env: &Env) -> Result<Option<StatusSnapshot>> {
+    get_json(Store::Runtime, STATUS_KEY, env).await
+}
+
+/// Stores the `/status` snapshot. Written before the response, so visitors
+/// arriving during the refresh find it sooner.
+pub async fn put_status_snapshot(snapshot: &StatusSnapshot, env: &Env, ttl: u64) -> Result<()> {
+    put_json(Store::Runtime, STATUS_KEY, snapshot, env, ttl).await
+}
+
+fn short_link_cache_key(code: &str) -> String {
+    format!("short:{code}")
+}
+
+pub async fn get_short_link(code: &str, env: &Env) -> Result<Option<StoredLink>> {
+    get_json(Store::Runtime, &short_link_cache_key(code), env).await
+}
+
+/// Stores a short link. Unlike everything else the worker writes, it never
+/// expires: links are meant to keep working once shared.
+pub async fn put_short_link(code: &str, link: &StoredLink, env: &Env) -> Result<()> {
+    let json = serde_json::to_string(link).map_err(|e| Error::RustError(format!("cache serialize error: {e}")))?;
+    Store::Runtime.kv(env)?.put(&short_link_cache_key(code), json)?.execute().await?;
+    Ok(())
+}
+
+fn human_pass_cache_key(token: &str) -> String {
+    format!("human:{token}")
+}
+
+/// Returns `true` if `token` is a Turnstile pass that hasn't expired.
+pub async fn human_pass_valid(token: &str, env: &Env) -> Result<bool> {
+    Ok(get_json::<bool>(Store::Runtime, &human_pass_cache_key(token), env).await?.is_some())
+}
+
+/// Records a Turnstile pass, valid for `ttl` seconds. Written before the
+/// response, so the redirect that follows already finds it.
+pub async fn store_human_pass(token: &str, env: &Env, ttl: u64) -> Result<()> {
+    put_json(Store::Runtime, &human_pass_cache_key(token), &true, env, ttl).await
+}
+
+async fn get_json<T: DeserializeOwned>(store: Store, key: &str, env: &Env) -> Result<Option<T>> {
+    let kv = store.kv(env)?;
+
+    match kv.get(key).text().await? {
         Some(json) => {
-            let data: InstaData = serde_json::from_str(&json)
+            let value = serde_json::from_str(&json)
                 .map_err(|e| Error::RustError(format!("cache deserialize error: {e}")))?;
-            Ok(Some(data))
+            Ok(Some(value))
         }
         None => Ok(None),
     }
 }
 
-pub async fn set_cached(post_id: &str, data: &InstaData, env: &Env) -> Result<()> {
-    let kv = env.kv("CACHE")?;
-    let key = cache_key(post_id);
-    let json = serde_json::to_string(data)
+async fn put_json<T: Serialize>(store: Store, key: &str, value: &T, env: &Env, ttl: u64) -> Result<()> {
+    let kv = store.kv(env)?;
+    let json = serde_json::to_string(value)
         .map_err(|e| Error::RustError(format!("cache serialize error: {e}")))?;
 
-    kv.put(&key, json)?
-        .expiration_ttl(TTL_SECONDS)
+    kv.put(key, json)?
+        .expiration_ttl(ttl)
         .execute()
         .await?;
 
     Ok(())
 }
+
+fn put_json_background<T: Serialize + 'static>(store: Store, key: String, value: T, env: &Env, ctx: &Context, ttl: u64) {
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        if let Err(e) = put_json(store, &key, &value, &env, ttl).await {
+            console_log!("[cache] background write failed for {}: {:?}", key, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_never_share_keys() {
+        let kinds = [
+            Kind::Post,
+            Kind::Story,
+            Kind::Highlight,
+            Kind::Profile,
+            Kind::Html,
+            Kind::Missing,
+            Kind::Latest,
+            Kind::Tag,
+            Kind::Comment,
+            Kind::Translation,
+            Kind::Summary,
+            Kind::Alt,
+            Kind::Share,
+            Kind::Screenshot,
+        ];
+        let keys: Vec<String> = kinds.iter().map(|kind| kind.key("abc")).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{key} is used twice");
+        }
+        assert_eq!(Kind::Profile.key("CatLover"), "profile:catlover");
+        assert_eq!(Kind::Post.key("CxAbC"), "post:CxAbC");
+        assert_eq!(Kind::Latest.key("CatLover"), "latest:catlover");
+        assert_eq!(Kind::Comment.key("CxAbC:17890"), "comment:CxAbC:17890");
+    }
+
+    #[test]
+    fn rendered_pages_are_kept_apart_from_scraped_data() {
+        assert_eq!(Kind::Html.store(), Store::Pages);
+        assert_eq!(Kind::Post.store(), Store::Data);
+        assert_eq!(Kind::Profile.store(), Store::Data);
+    }
+}
@@ -0,0 +1,100 @@
+//! Canary rollout of an experimental scrape path.
+//!
+//! A new doc_id, GraphQL endpoint or proxy account can break every scrape at
+//! once. With `CANARY_PERCENT` set, that share of live scrapes uses the
+//! `CANARY_*` settings in place of the stable ones, and the rest use the
+//! stable path. Canary scrapes are counted apart (`stats:canary:{hour}`), so
+//! `/admin/backends` shows whether the new path holds up before it is
+//! promoted. Their results, and embeds rendered from them, are never cached:
+//! a broken canary would otherwise keep serving what it got wrong, or keep
+//! posts it couldn't find missing, long after it is rolled back.
+
+use crate::config::Config;
+
+use super::proxy::ProxyCredentials;
+
+/// The experimental path, and how many scrapes take it.
+#[derive(Debug, Clone)]
+pub struct Canary {
+    /// `CANARY_PERCENT`: share of live scrapes that take the canary path, 1–100.
+    pub percent: u8,
+    /// `CANARY_GRAPHQL_DOC_IDS`: tried instead of `GRAPHQL_DOC_IDS`.
+    pub doc_ids: Option<Vec<String>>,
+    /// `CANARY_GRAPHQL_PATH`: used instead of `GRAPHQL_PATH`.
+    pub graphql_path: Option<String>,
+    /// `CANARY_PROXY_USERNAME` + `CANARY_PROXY_PASSWORD` secrets: another
+    /// proxy zone or provider account, used instead of `PROXY_*`. It exits
+    /// from the stable proxy's country.
+    pub proxy: Option<ProxyCredentials>,
+}
+
+impl Canary {
+    /// Returns `true` if a scrape that rolled `roll` (0–99) takes the canary path.
+    pub fn picks(&self, roll: u8) -> bool {
+        roll < self.percent
+    }
+
+    /// Returns `true` if the canary path differs from the stable one at all.
+    pub fn changes_anything(&self) -> bool {
+        self.doc_ids.is_some() || self.graphql_path.is_some() || self.proxy.is_some()
+    }
+
+    /// `config` with the canary's settings in place of the stable ones.
+    pub fn apply(&self, config: &Config) -> Config {
+        Config {
+            doc_ids: self.doc_ids.clone().unwrap_or_else(|| config.doc_ids.clone()),
+            graphql_path: self.graphql_path.clone().unwrap_or_else(|| config.graphql_path.clone()),
+            proxy: match (&self.proxy, &config.proxy) {
+                (Some(canary), Some(stable)) => Some(ProxyCredentials { country: stable.country.clone(), ..canary.clone() }),
+                (canary, stable) => canary.clone().or_else(|| stable.clone()),
+            },
+            ..config.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canary() -> Canary {
+        Canary {
+            percent: 10,
+            doc_ids: Some(vec!["999".to_string()]),
+            graphql_path: None,
+            proxy: None,
+        }
+    }
+
+    #[test]
+    fn picks_its_share_of_rolls() {
+        let picked = (0..100).filter(|&roll| canary().picks(roll)).count();
+        assert_eq!(picked, 10);
+        assert!(Canary { percent: 100, ..canary() }.picks(99));
+    }
+
+    #[test]
+    fn overrides_only_what_it_sets() {
+        let stable = Config::default();
+        let config = canary().apply(&stable);
+        assert_eq!(config.doc_ids, vec!["999".to_string()]);
+        assert_eq!(config.graphql_path, stable.graphql_path);
+        assert!(config.proxy.is_none());
+        assert!(!Canary { doc_ids: None, ..canary() }.changes_anything());
+    }
+
+    #[test]
+    fn its_proxy_exits_where_the_stable_one_does() {
+        let creds = |username: &str, country: &str| ProxyCredentials {
+            username: username.into(),
+            password: "t".into(),
+            country: country.into(),
+        };
+        let canary = Canary { proxy: Some(creds("zone-canary", "us")), ..canary() };
+        let stable = Config { proxy: Some(creds("zone-stable", "de")), ..Config::default() };
+
+        let proxy = canary.apply(&stable).proxy.unwrap();
+        assert_eq!((proxy.username.as_str(), proxy.country.as_str()), ("zone-canary", "de"));
+        assert_eq!(canary.apply(&Config::default()).proxy.unwrap().country, "us");
+    }
+}
@@ -0,0 +1,100 @@
+//! Per-request time budget for the scrape chain.
+//!
+//! Workers allow ~30s of wall time, but crawlers give up on a preview after
+//! 5–10s. A scrape that finishes after that is wasted, so the chain gets a
+//! budget: each call may only take what is left of it, and once it runs out
+//! the remaining backends are skipped and the handler answers with what it has.
+
+use std::cell::Cell;
+
+use worker::{Error, Result};
+
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::utils::log::console_log;
+
+/// A call with less time than this left isn't worth starting.
+pub const MIN_CALL_MILLIS: u64 = 200;
+
+/// Wraps a client so every call shares one time budget, and notes whether
+/// the budget ran out.
+pub struct Deadline<'a, C> {
+    inner: &'a C,
+    until: u64,
+    expired: Cell<bool>,
+}
+
+impl<'a, C: HttpClient> Deadline<'a, C> {
+    /// Starts a budget of `budget_millis` from now.
+    pub fn new(inner: &'a C, budget_millis: u64) -> Self {
+        Self {
+            inner,
+            until: inner.now_millis() + budget_millis,
+            expired: Cell::new(false),
+        }
+    }
+
+    /// Milliseconds left of the budget.
+    pub fn remaining(&self) -> u64 {
+        self.until.saturating_sub(self.inner.now_millis())
+    }
+
+    /// Returns `true` if a call was refused or cut short for lack of time.
+    pub fn expired(&self) -> bool {
+        self.expired.get()
+    }
+}
+
+impl<C: HttpClient> HttpClient for Deadline<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let remaining = self.remaining();
+        if remaining < MIN_CALL_MILLIS {
+            console_log!("[deadline] budget exhausted, skipping {}", request.url);
+            self.expired.set(true);
+            return Err(Error::RustError("scrape time budget exhausted".to_string()));
+        }
+
+        // A tighter timeout from the caller still applies
+        let timeout = request.timeout_millis.map_or(remaining, |t| t.min(remaining));
+        let result = self.inner.send(request.timeout(timeout)).await;
+        if result.is_err() && self.remaining() == 0 {
+            self.expired.set(true);
+        }
+        result
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn calls_get_the_remaining_budget_as_timeout() {
+        let client = FixtureClient::new().route("/a", 200, "ok");
+        let deadline = Deadline::new(&client, 1000);
+
+        block_on(deadline.send(HttpRequest::get("https://example.com/a"))).unwrap();
+        block_on(deadline.send(HttpRequest::get("https://example.com/a").timeout(50))).unwrap();
+
+        let timeouts: Vec<_> = client.requests.borrow().iter().map(|r| r.timeout_millis).collect();
+        assert_eq!(timeouts, [Some(1000), Some(50)]);
+        assert!(!deadline.expired());
+    }
+
+    #[test]
+    fn refuses_calls_once_the_budget_is_spent() {
+        // Each fixture request advances the clock by 100ms
+        let client = FixtureClient::new().route("/a", 200, "ok");
+        let deadline = Deadline::new(&client, 300);
+
+        assert!(block_on(deadline.send(HttpRequest::get("https://example.com/a"))).is_ok());
+        assert!(block_on(deadline.send(HttpRequest::get("https://example.com/a"))).is_ok());
+        assert!(block_on(deadline.send(HttpRequest::get("https://example.com/a"))).is_err());
+        assert_eq!(client.requests.borrow().len(), 2);
+        assert!(deadline.expired());
+    }
+}
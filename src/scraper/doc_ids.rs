@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Weight of the newest outcome in a doc_id's score. High enough that a
+/// retired doc_id drops below a working one after a single null.
+const NEW_RESULT_WEIGHT: f64 = 0.3;
+
+/// Score of a doc_id with no recorded results. Optimistic, so a newly added
+/// candidate gets tried in its configured position.
+const UNKNOWN_SCORE: f64 = 1.0;
+
+/// Recent success rates of the `GRAPHQL_DOC_IDS` candidates, as exponential
+/// moving averages in `0.0..=1.0`. Stored in KV as one entry.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocIdScores {
+    pub scores: BTreeMap<String, f64>,
+}
+
+impl DocIdScores {
+    pub fn score(&self, doc_id: &str) -> f64 {
+        self.scores.get(doc_id).copied().unwrap_or(UNKNOWN_SCORE)
+    }
+
+    /// Orders candidates by score, best first. Ties keep the configured order.
+    pub fn rank(&self, doc_ids: &[String]) -> Vec<String> {
+        let mut ranked = doc_ids.to_vec();
+        ranked.sort_by(|a, b| self.score(b).total_cmp(&self.score(a)));
+        ranked
+    }
+
+    /// Folds one outcome into a doc_id's score. A null response counts as a
+    /// failure.
+    pub fn record(&mut self, doc_id: &str, ok: bool) {
+        let outcome = if ok { 1.0 } else { 0.0 };
+        let score = self.score(doc_id) * (1.0 - NEW_RESULT_WEIGHT) + outcome * NEW_RESULT_WEIGHT;
+        self.scores.insert(doc_id.to_string(), score);
+    }
+
+    /// Drops scores for doc_ids no longer configured, so the entry doesn't
+    /// grow as candidates are rotated.
+    pub fn retain(&mut self, doc_ids: &[String]) {
+        self.scores.retain(|id, _| doc_ids.contains(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn unknown_candidates_keep_configured_order() {
+        let scores = DocIdScores::default();
+        assert_eq!(scores.rank(&ids(&["a", "b", "c"])), ids(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn a_null_demotes_below_working_candidates() {
+        let mut scores = DocIdScores::default();
+        scores.record("a", false);
+        scores.record("b", true);
+        assert_eq!(scores.rank(&ids(&["a", "b", "c"])), ids(&["b", "c", "a"]));
+    }
+
+    #[test]
+    fn recovered_candidate_climbs_back() {
+        let mut scores = DocIdScores::default();
+        for _ in 0..5 {
+            scores.record("a", false);
+        }
+        scores.record("b", false);
+        assert_eq!(scores.rank(&ids(&["a", "b"])), ids(&["b", "a"]));
+
+        for _ in 0..5 {
+            scores.record("a", true);
+        }
+        assert_eq!(scores.rank(&ids(&["a", "b"])), ids(&["a", "b"]));
+    }
+
+    #[test]
+    fn retain_drops_removed_candidates() {
+        let mut scores = DocIdScores::default();
+        scores.record("old", true);
+        scores.record("new", true);
+        scores.retain(&ids(&["new"]));
+        assert_eq!(scores.scores.len(), 1);
+    }
+}
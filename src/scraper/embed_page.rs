@@ -1,7 +1,10 @@
 use worker::*;
 
-use super::proxy::proxy_fetch;
-use super::types::{InstaData, Media, MediaType};
+use super::http::{HttpClient, HttpRequest};
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::{coauthor_usernames, ContextJson, MediaNode, ShortcodeMedia};
+use super::types::{Comment, InstaData, Media, MediaType, PostKind};
+use crate::utils::log::console_log;
 
 const CHROME_UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
                           (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
@@ -11,25 +14,31 @@ pub fn is_video_blocked(html: &str) -> bool {
     html.contains("WatchOnInstagram") || html.contains("EmbeddedMediaVideo")
 }
 
-pub async fn fetch_embed_page(post_id: &str, env: &Env) -> worker::Result<Option<(InstaData, bool)>> {
+/// Fetches and parses the `/embed/captioned/` page.
+///
+/// Returns the extracted data and whether the page marks the video as blocked.
+pub async fn fetch_embed_page<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    cookie: Option<&str>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<(InstaData, bool)>> {
     let url_str = format!("https://www.instagram.com/p/{post_id}/embed/captioned/?_fb_noscript=1");
 
-    let headers = Headers::new();
-    headers.set("User-Agent", CHROME_UA)?;
-    headers.set("Accept", "text/html,application/xhtml+xml")?;
-    headers.set("Accept-Language", "en-US,en;q=0.9")?;
+    let mut request = HttpRequest::get(url_str)
+        .header("User-Agent", CHROME_UA)
+        .header("Accept", "text/html,application/xhtml+xml")
+        .header("Accept-Language", "en-US,en;q=0.9");
 
     // Pass session cookie through proxy if available — helps bypass login walls
-    if let Ok(cookie_secret) = env.secret("IG_COOKIE") {
-        let raw = cookie_secret.to_string().replace("%3A", ":").replace("%3a", ":");
-        let cookie = if raw.contains('=') { raw } else { format!("sessionid={}", raw) };
-        headers.set("Cookie", &cookie)?;
+    if let Some(cookie) = cookie {
+        request = request.header("Cookie", cookie);
     }
 
-    let mut resp = proxy_fetch(&url_str, Method::Get, headers, None, env).await?;
+    let resp = proxy_fetch(client, request, proxy).await?;
 
-    let status = resp.status_code();
-    let html = resp.text().await?;
+    let status = resp.status;
+    let html = resp.body;
     console_log!("[embed_page] status={} html_len={} for {}", status, html.len(), post_id);
 
     if status != 200 {
@@ -70,10 +79,16 @@ pub async fn fetch_embed_page(post_id: &str, env: &Env) -> worker::Result<Option
 }
 
 /// Extracts post data from the embedded `shortcode_media` JSON blob in the page.
-fn extract_from_json(html: &str, post_id: &str) -> Option<InstaData> {
+pub(super) fn extract_from_json(html: &str, post_id: &str) -> Option<InstaData> {
     let json_obj = extract_shortcode_media_json(html)?;
-    let media_obj: serde_json::Value = serde_json::from_str(&json_obj).ok()?;
-    parse_shortcode_media(&media_obj, post_id)
+    let media: ShortcodeMedia = match serde_json::from_str(&json_obj) {
+        Ok(m) => m,
+        Err(e) => {
+            console_log!("[embed_page] shortcode_media parse error for {}: {}", post_id, e);
+            return None;
+        }
+    };
+    parse_shortcode_media(media, post_id)
 }
 
 /// Extracts post data from the double-encoded `contextJSON` in the embed page.
@@ -116,12 +131,16 @@ fn extract_from_context_json(html: &str, post_id: &str) -> Option<InstaData> {
     let inner_str: String = serde_json::from_str(json_str).ok()?;
 
     // Parse the inner string as JSON
-    let context: serde_json::Value = serde_json::from_str(&inner_str).ok()?;
+    let context: ContextJson = match serde_json::from_str(&inner_str) {
+        Ok(c) => c,
+        Err(e) => {
+            console_log!("[embed_page] contextJSON parse error for {}: {}", post_id, e);
+            return None;
+        }
+    };
 
     // Extract gql_data which contains shortcode_media structure
-    let gql_data = context.get("gql_data")?;
-    let media = gql_data.get("shortcode_media")
-        .or_else(|| gql_data.get("xdt_shortcode_media"))?;
+    let media = context.gql_data?.into_media()?;
 
     console_log!("[embed_page] contextJSON found gql_data for {}", post_id);
     parse_shortcode_media(media, post_id)
@@ -178,45 +197,46 @@ fn extract_shortcode_media_json(html: &str) -> Option<String> {
     None
 }
 
-/// Parses a `shortcode_media` JSON value into `InstaData`.
-pub fn parse_shortcode_media(media: &serde_json::Value, post_id: &str) -> Option<InstaData> {
-    let username = media
-        .get("owner")?
-        .get("username")?
-        .as_str()?
-        .to_string();
+/// Converts a deserialized `shortcode_media` object into `InstaData`.
+///
+/// Returns `None` if the owner's username is missing.
+pub fn parse_shortcode_media(media: ShortcodeMedia, post_id: &str) -> Option<InstaData> {
+    let sensitive = media.is_sensitive();
+    let kind = media.product_type.as_deref().and_then(PostKind::from_product_type);
+    let owner = media.owner?;
+    let username = owner.username?;
 
     let caption = media
-        .get("edge_media_to_caption")
-        .and_then(|c| c.get("edges"))
-        .and_then(|e| e.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|edge| edge.get("node"))
-        .and_then(|node| node.get("text"))
-        .and_then(|t| t.as_str())
-        .map(String::from);
-
-    let is_video = media.get("is_video").and_then(|v| v.as_bool()).unwrap_or(false);
-    let timestamp = media
-        .get("taken_at_timestamp")
-        .and_then(|t| t.as_u64())
-        .unwrap_or(0);
-
-    let like_count = media
-        .get("edge_media_preview_like")
-        .and_then(|l| l.get("count"))
-        .and_then(|c| c.as_u64());
-
-    let comment_count = media
-        .get("edge_media_to_comment")
-        .and_then(|l| l.get("count"))
-        .and_then(|c| c.as_u64());
-
-    let video_view_count = media
-        .get("video_view_count")
-        .and_then(|v| v.as_u64());
-
-    let media_items = build_media_list(media);
+        .edge_media_to_caption
+        .and_then(|c| c.edges.into_iter().next())
+        .and_then(|edge| edge.node.text);
+
+    let like_count = media.edge_media_preview_like.and_then(|l| l.count);
+    let comment_count = media.edge_media_to_comment.or(media.edge_media_preview_comment).and_then(|c| c.count);
+    let top_comment = media.edge_media_to_parent_comment.and_then(|c| {
+        Comment::most_liked(c.edges.into_iter().filter_map(|edge| {
+            let node = edge.node;
+            let comment = Comment {
+                id: node.id?.into_string(),
+                username: node.owner?.username?,
+                text: node.text?,
+            };
+            Some((comment, node.edge_liked_by.and_then(|l| l.count).unwrap_or(0)))
+        }))
+    });
+    let is_video = media.node.is_video();
+
+    // Carousel: edge_sidecar_to_children contains multiple items
+    let media_items: Vec<Media> = match media.edge_sidecar_to_children {
+        Some(children) => children
+            .edges
+            .into_iter()
+            .map(|edge| media_from_node(edge.node))
+            .collect(),
+        None => vec![media_from_node(media.node)],
+    };
+    // A carousel's own flag is false even when a slide is a video, as in PAPI
+    let is_video = is_video || media_items.iter().any(|m| m.media_type == MediaType::Video);
 
     Some(InstaData {
         post_id: post_id.to_string(),
@@ -226,79 +246,61 @@ pub fn parse_shortcode_media(media: &serde_json::Value, post_id: &str) -> Option
         like_count,
         comment_count,
         is_video,
-        video_view_count,
-        timestamp,
+        video_view_count: media.video_view_count,
+        play_count: media.video_play_count,
+        timestamp: media.taken_at_timestamp.unwrap_or(0),
+        top_comment,
+        full_name: owner.full_name.filter(|n| !n.is_empty()),
+        is_verified: owner.is_verified,
+        avatar_url: owner.profile_pic_url,
+        coauthors: coauthor_usernames(media.coauthor_producers),
+        sensitive,
+        kind,
+        stickers: Vec::new(),
+        hashtag: None,
+        backend: None,
+        scraped_at: 0,
+        cached: false,
+        canary: false,
     })
 }
 
-/// Builds a `Vec<Media>` from the shortcode_media JSON, handling carousels and single posts.
-fn build_media_list(media: &serde_json::Value) -> Vec<Media> {
-    // Carousel: edge_sidecar_to_children contains multiple items
-    if let Some(children) = media
-        .get("edge_sidecar_to_children")
-        .and_then(|c| c.get("edges"))
-        .and_then(|e| e.as_array())
-    {
-        return children
-            .iter()
-            .filter_map(|edge| {
-                let node = edge.get("node")?;
-                Some(media_from_node(node))
-            })
-            .collect();
-    }
-
-    // Single post
-    vec![media_from_node(media)]
-}
-
 /// Converts a single media node into a `Media` struct.
-fn media_from_node(node: &serde_json::Value) -> Media {
-    let is_video = node.get("is_video").and_then(|v| v.as_bool()).unwrap_or(false);
-
-    let (media_type, url, thumbnail_url) = if is_video {
-        let video_url = node
-            .get("video_url")
-            .and_then(|v| v.as_str())
-            .unwrap_or_default()
-            .to_string();
-        let thumb = node.get("display_url").and_then(|v| v.as_str()).map(String::from);
-        (MediaType::Video, video_url, thumb)
+fn media_from_node(node: MediaNode) -> Media {
+    let (media_type, url, thumbnail_url) = if node.is_video() {
+        (MediaType::Video, node.video_url.unwrap_or_default(), node.display_url)
     } else {
-        let display_url = node
-            .get("display_url")
-            .and_then(|v| v.as_str())
-            .unwrap_or_default()
-            .to_string();
-        (MediaType::Image, display_url, None)
+        (MediaType::Image, node.display_url.unwrap_or_default(), None)
     };
 
-    let width = node
-        .get("dimensions")
-        .and_then(|d| d.get("width"))
-        .and_then(|w| w.as_u64())
-        .map(|w| w as u32);
-
-    let height = node
-        .get("dimensions")
-        .and_then(|d| d.get("height"))
-        .and_then(|h| h.as_u64())
-        .map(|h| h as u32);
+    let dimensions = node.dimensions.unwrap_or_default();
 
     Media {
         media_type,
         url,
         thumbnail_url,
-        width,
-        height,
+        width: dimensions.width,
+        height: dimensions.height,
+        alt_text: node.accessibility_caption.filter(|alt| !alt.trim().is_empty()),
     }
 }
 
 /// Fallback: scrape basic info from the embed HTML markup when no JSON blob is found.
 fn extract_from_html(html: &str, post_id: &str) -> Option<InstaData> {
-    let image_url = extract_attr_from_class(html, "EmbeddedMediaImage", "src")?;
-    let username = extract_text_from_class(html, "UsernameText").unwrap_or_else(|| "unknown".to_string());
-    let caption = extract_caption_text(html);
+    let dom = tl::parse(html, tl::ParserOptions::default()).ok()?;
+    let parser = dom.parser();
+
+    let image_url = find_by_class(&dom, "EmbeddedMediaImage")
+        .and_then(|tag| tag.attributes().get("src").flatten())
+        .map(|src| unescape_html_entities(&src.as_utf8_str()))
+        .filter(|src| !src.is_empty())?;
+
+    let username = find_by_class(&dom, "UsernameText")
+        .map(|tag| tag.inner_text(parser).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let caption = find_by_class(&dom, "Caption").and_then(|tag| extract_caption_text(tag, parser));
 
     Some(InstaData {
         post_id: post_id.to_string(),
@@ -310,92 +312,137 @@ fn extract_from_html(html: &str, post_id: &str) -> Option<InstaData> {
             thumbnail_url: None,
             width: None,
             height: None,
+            alt_text: None,
         }],
         like_count: None,
         comment_count: None,
         is_video: false,
         video_view_count: None,
+        play_count: None,
         timestamp: 0,
+        top_comment: None,
+        full_name: None,
+        is_verified: false,
+        avatar_url: None,
+        coauthors: Vec::new(),
+        sensitive: false,
+        kind: None,
+        stickers: Vec::new(),
+        hashtag: None,
+        backend: None,
+        scraped_at: 0,
+        cached: false,
+        canary: false,
     })
 }
 
-/// Finds an element with the given class name and extracts a specific attribute value.
-fn extract_attr_from_class(html: &str, class_name: &str, attr: &str) -> Option<String> {
-    let class_pos = html.find(class_name)?;
-
-    // Walk backwards to find the opening `<` of this tag
-    let before = &html[..class_pos];
-    let tag_start = before.rfind('<')?;
-
-    // Search forward from the tag start for the target attribute
-    let tag_region = &html[tag_start..];
-    let tag_end = tag_region.find('>')?;
-    let tag_str = &tag_region[..tag_end + 1];
+/// Turns HTML fallback data from a video-blocked embed into a video with no
+/// stream URL: the scraped image is the poster frame, so it becomes the
+/// thumbnail.
+pub fn into_blocked_video(mut data: InstaData) -> InstaData {
+    data.is_video = true;
+    for media in &mut data.media {
+        media.media_type = MediaType::Video;
+        media.thumbnail_url = Some(std::mem::take(&mut media.url));
+    }
+    data
+}
 
-    let attr_needle = format!("{attr}=\"");
-    let attr_start = tag_str.find(&attr_needle)?;
-    let value_start = attr_start + attr_needle.len();
-    let value_end = tag_str[value_start..].find('"')?;
+/// Returns the first element that has `class_name` among its classes.
+fn find_by_class<'a>(dom: &'a tl::VDom<'a>, class_name: &str) -> Option<&'a tl::HTMLTag<'a>> {
+    dom.query_selector(&format!(".{class_name}"))?
+        .next()?
+        .get(dom.parser())?
+        .as_tag()
+}
 
-    let raw = &tag_str[value_start..value_start + value_end];
-    Some(unescape_html_entities(raw))
+/// Returns `true` if the element has `class_name` among its classes.
+fn has_class(tag: &tl::HTMLTag, class_name: &str) -> bool {
+    tag.attributes().is_class_member(class_name)
 }
 
 /// Unescapes common HTML entities back to their raw characters.
 fn unescape_html_entities(s: &str) -> String {
-    s.replace("&amp;", "&")
-        .replace("&lt;", "<")
+    s.replace("&lt;", "<")
         .replace("&gt;", ">")
         .replace("&quot;", "\"")
         .replace("&#x27;", "'")
         .replace("&#39;", "'")
+        .replace("&amp;", "&")
 }
 
-/// Extracts the inner text content of the first element with the given class name.
-fn extract_text_from_class(html: &str, class_name: &str) -> Option<String> {
-    let class_pos = html.find(class_name)?;
-
-    // Find the end of the opening tag
-    let rest = &html[class_pos..];
-    let tag_close = rest.find('>')?;
-    let content_start = class_pos + tag_close + 1;
-
-    // Find the next closing tag
-    let content_rest = &html[content_start..];
-    let next_tag = content_rest.find('<')?;
+/// Extracts the full caption text from the embed page's `Caption` element.
+///
+/// The element holds the author's `CaptionUsername` link, the caption text
+/// (with `<br>` line breaks and inline hashtag/mention links), and a trailing
+/// `CaptionComments` block; only the caption text is kept.
+fn extract_caption_text(caption: &tl::HTMLTag, parser: &tl::Parser) -> Option<String> {
+    let mut text = String::new();
+
+    for handle in caption.children().top().iter() {
+        match handle.get(parser) {
+            Some(tl::Node::Tag(tag)) => {
+                if tag.name().as_utf8_str().eq_ignore_ascii_case("br") {
+                    text.push('\n');
+                } else if !has_class(tag, "CaptionUsername") && !has_class(tag, "CaptionComments") {
+                    text.push_str(&tag.inner_text(parser));
+                }
+            }
+            Some(tl::Node::Raw(raw)) => text.push_str(&raw.as_utf8_str()),
+            _ => {}
+        }
+    }
 
-    let text = content_rest[..next_tag].trim();
+    let text = unescape_html_entities(text.trim());
     if text.is_empty() {
         None
     } else {
-        Some(text.to_string())
+        Some(text)
     }
 }
 
-/// Attempts to extract caption text from the embed page.
-/// Looks for text content near the "CaptionUsername" class.
-fn extract_caption_text(html: &str) -> Option<String> {
-    let marker = "CaptionUsername";
-    let pos = html.find(marker)?;
-
-    // Skip past the CaptionUsername element to find sibling text
-    let rest = &html[pos..];
-
-    // Find the closing tag of the username span
-    let first_close = rest.find("</")? ;
-    let after_username = &rest[first_close..];
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMBED_HTML: &str = r#"<html><body>
+<div class="Embed"><a class="Header"><span class="UsernameText">catlover</span></a>
+<img alt="" src="https://cdn.example.com/a.jpg?x=1&amp;y=2" class="EmbeddedMediaImage" />
+<div class="Caption"><a class="CaptionUsername" href="/catlover">catlover</a><br />
+First line &amp; more<br />second <a href="/explore/tags/cats/">#cats</a>
+<div class="CaptionComments"><a>View all 12 comments</a></div></div></div>
+</body></html>"#;
+
+    #[test]
+    fn sensitivity_markers_flag_the_post() {
+        let parse = |json: &str| parse_shortcode_media(serde_json::from_str(json).unwrap(), "ABC").unwrap();
+        let covered = parse(r#"{"owner":{"username":"cat"},"display_url":"https://cdn.example.com/a.jpg","media_overlay_info":{"title":"Sensitive content"}}"#);
+        assert!(covered.sensitive);
+        let friction = parse(r#"{"owner":{"username":"cat"},"sharing_friction_info":{"should_have_sharing_friction":true}}"#);
+        assert!(friction.sensitive);
+        let plain = parse(r#"{"owner":{"username":"cat"},"media_overlay_info":null,"sharing_friction_info":{"should_have_sharing_friction":false}}"#);
+        assert!(!plain.sensitive);
+    }
 
-    // Skip the closing tag itself
-    let after_tag = after_username.find('>')? + 1;
-    let content = &after_username[after_tag..];
+    #[test]
+    fn html_fallback_extracts_image_username_and_caption() {
+        let data = extract_from_html(EMBED_HTML, "ABC").unwrap();
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.media[0].url, "https://cdn.example.com/a.jpg?x=1&y=2");
+        assert_eq!(data.caption.as_deref(), Some("First line & more\nsecond #cats"));
+    }
 
-    // Grab text until the next HTML tag
-    let next_tag = content.find('<').unwrap_or(content.len());
-    let text = content[..next_tag].trim();
+    #[test]
+    fn html_fallback_tolerates_attribute_order() {
+        let html = r#"<img class="EmbeddedMediaImage" alt="" src="https://cdn.example.com/b.jpg">"#;
+        let data = extract_from_html(html, "ABC").unwrap();
+        assert_eq!(data.media[0].url, "https://cdn.example.com/b.jpg");
+        assert_eq!(data.username, "unknown");
+        assert!(data.caption.is_none());
+    }
 
-    if text.is_empty() {
-        None
-    } else {
-        Some(text.to_string())
+    #[test]
+    fn html_fallback_requires_image() {
+        assert!(extract_from_html("<div class=\"Caption\">hi</div>", "ABC").is_none());
     }
 }
@@ -0,0 +1,88 @@
+//! Detection of posts Instagram restricts by region.
+//!
+//! A geo-restricted post looks like a failure to every backend, but unlike a
+//! deleted post it can often still be fetched from another country, and the
+//! embed can at least say why there's no preview.
+
+use std::cell::Cell;
+
+use worker::Result;
+
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::utils::log::console_log;
+
+/// Lowercase phrases Instagram uses for region-restricted content, across the
+/// embed page, the web JSON endpoints and the mobile API.
+const MARKERS: [&str; 6] = [
+    "not available in your country",
+    "isn't available in your country",
+    "not available in your region",
+    "isn't available in your region",
+    "restricted in your country",
+    "\"geo_restricted\":true",
+];
+
+/// Returns `true` if a response says the content is blocked in the
+/// requester's region. 451 is "Unavailable For Legal Reasons".
+pub fn is_geo_blocked(resp: &HttpResponse) -> bool {
+    if resp.status == 451 {
+        return true;
+    }
+    let body = resp.body.to_ascii_lowercase();
+    MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+/// Wraps a client and notes whether any response was a geo-block.
+pub struct GeoBlockWatch<'a, C> {
+    inner: &'a C,
+    hit: Cell<bool>,
+}
+
+impl<'a, C: HttpClient> GeoBlockWatch<'a, C> {
+    pub fn new(inner: &'a C) -> Self {
+        Self { inner, hit: Cell::new(false) }
+    }
+
+    pub fn hit(&self) -> bool {
+        self.hit.get()
+    }
+}
+
+impl<C: HttpClient> HttpClient for GeoBlockWatch<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let resp = self.inner.send(request).await?;
+        if is_geo_blocked(&resp) {
+            console_log!("[geo] region-restricted response (status={})", resp.status);
+            self.hit.set(true);
+        }
+        Ok(resp)
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, body: &str) -> HttpResponse {
+        HttpResponse { status, body: body.to_string() }
+    }
+
+    #[test]
+    fn recognizes_region_messages() {
+        assert!(is_geo_blocked(&response(200, "<h2>This content isn't available in your country</h2>")));
+        assert!(is_geo_blocked(&response(200, "Sorry, this post is Not Available In Your Region.")));
+        assert!(is_geo_blocked(&response(400, r#"{"message":"media","geo_restricted":true}"#)));
+        assert!(is_geo_blocked(&response(451, "")));
+    }
+
+    #[test]
+    fn ordinary_failures_are_not_geo_blocks() {
+        assert!(!is_geo_blocked(&response(404, "Page Not Found")));
+        assert!(!is_geo_blocked(&response(200, r#"{"data":{"xdt_shortcode_media":null}}"#)));
+        assert!(!is_geo_blocked(&response(200, r#"{"geo_restricted":false}"#)));
+    }
+}
@@ -1,28 +1,50 @@
 use worker::*;
 
 use super::embed_page::parse_shortcode_media;
-use super::proxy::proxy_fetch;
+use super::http::{HttpClient, HttpRequest};
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::GraphqlResponse;
 use super::types::InstaData;
+use crate::utils::log::console_log;
 
 const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
                           (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
 const IG_APP_ID: &str = "936619743392459";
 
-pub async fn fetch_graphql(post_id: &str, doc_id: &str, env: &Env) -> Result<Option<InstaData>> {
-    let variables = format!(
-        r#"{{"shortcode":"{}","fetch_comment_count":40,"parent_comment_count":24,"child_comment_count":3,"fetch_like_count":10,"fetch_tagged_user_count":null,"fetch_preview_comment_count":2,"has_threaded_comments":true,"hoisted_comment_id":null,"hoisted_reply_id":null}}"#,
-        post_id
-    );
+/// Default endpoint path; `GRAPHQL_PATH` can switch it to e.g. `/graphql/query`.
+pub const DEFAULT_PATH: &str = "/api/graphql";
+
+/// Placeholder in the variables template that is replaced with the shortcode.
+pub const SHORTCODE_PLACEHOLDER: &str = "{shortcode}";
+
+/// Default `variables` template; `GRAPHQL_VARIABLES` can replace it.
+pub const DEFAULT_VARIABLES: &str = r#"{"shortcode":"{shortcode}","fetch_comment_count":40,"parent_comment_count":24,"child_comment_count":3,"fetch_like_count":10,"fetch_tagged_user_count":null,"fetch_preview_comment_count":2,"has_threaded_comments":true,"hoisted_comment_id":null,"hoisted_reply_id":null}"#;
+
+/// Fills a variables template in with a post's shortcode.
+pub fn render_variables(template: &str, post_id: &str) -> String {
+    template.replace(SHORTCODE_PLACEHOLDER, post_id)
+}
+
+pub async fn fetch_graphql<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    doc_id: &str,
+    path: &str,
+    variables_template: &str,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<InstaData>> {
+    let variables = render_variables(variables_template, post_id);
 
     let body = build_graphql_body(&variables, doc_id);
-    let target_url = "https://www.instagram.com/api/graphql";
+    let target_url = format!("https://www.instagram.com{}", path);
 
     // Try direct fetch first (usually returns null from datacenter IPs)
-    console_log!("[graphql] trying direct fetch for {} with doc_id={}", post_id, doc_id);
-    let result = match direct_graphql_fetch(target_url, &body).await {
-        Ok(mut r) => {
-            let status = r.status_code();
-            let text = r.text().await?;
+    console_log!("[graphql] trying direct fetch for {} via {} with doc_id={}", post_id, path, doc_id);
+    let request = build_graphql_request(&target_url, &body);
+    let result = match client.send(request.clone()).await {
+        Ok(r) => {
+            let status = r.status;
+            let text = r.body;
             console_log!("[graphql] direct status={} len={} first_200={}", status, text.len(), &text[..text.len().min(200)]);
             parse_graphql_response(&text, post_id)
         }
@@ -38,10 +60,9 @@ pub async fn fetch_graphql(post_id: &str, doc_id: &str, env: &Env) -> Result<Opt
 
     // Fall back to residential proxy
     console_log!("[graphql] trying via proxy");
-    let headers = build_graphql_headers()?;
-    let mut resp = proxy_fetch(target_url, Method::Post, headers, Some(body), env).await?;
-    let status = resp.status_code();
-    let text = resp.text().await?;
+    let resp = proxy_fetch(client, request, proxy).await?;
+    let status = resp.status;
+    let text = resp.body;
     console_log!("[graphql] proxy status={} len={} first_200={}", status, text.len(), &text[..text.len().min(200)]);
 
     Ok(parse_graphql_response(&text, post_id))
@@ -79,13 +100,13 @@ fn build_graphql_body(variables: &str, doc_id: &str) -> String {
 }
 
 /// Parses a GraphQL JSON response into InstaData.
-fn parse_graphql_response(text: &str, post_id: &str) -> Option<InstaData> {
+pub(super) fn parse_graphql_response(text: &str, post_id: &str) -> Option<InstaData> {
     if text.contains("require_login") || text.contains("not-logged-in") {
         console_log!("[graphql] response requires login");
         return None;
     }
 
-    let json: serde_json::Value = match serde_json::from_str(text) {
+    let resp: GraphqlResponse = match serde_json::from_str(text) {
         Ok(v) => v,
         Err(e) => {
             console_log!("[graphql] JSON parse error: {}", e);
@@ -93,63 +114,39 @@ fn parse_graphql_response(text: &str, post_id: &str) -> Option<InstaData> {
         }
     };
 
-    if let Some(obj) = json.as_object() {
-        console_log!("[graphql] top-level keys: {:?}", obj.keys().collect::<Vec<_>>());
-    }
-
-    let media_obj = json.get("data").and_then(|d| {
-        console_log!("[graphql] data keys: {:?}", d.as_object().map(|o| o.keys().collect::<Vec<_>>()));
-        d.get("xdt_shortcode_media")
-            .or_else(|| d.get("shortcode_media"))
-    })?;
-
     // xdt_shortcode_media can be JSON null when IP-blocked
-    if media_obj.is_null() {
-        console_log!("[graphql] media object is null (likely IP-blocked)");
+    let Some(media) = resp.data.and_then(|d| d.into_media()) else {
+        console_log!("[graphql] media object is null or missing (likely IP-blocked)");
         return None;
-    }
-
-    parse_shortcode_media(media_obj, post_id)
-}
+    };
 
-/// Builds the full set of browser-spoofing headers for GraphQL requests.
-fn build_graphql_headers() -> Result<Headers> {
-    let headers = Headers::new();
-    headers.set("Accept", "*/*")?;
-    headers.set("Accept-Language", "en-US,en;q=0.9")?;
-    headers.set("Content-Type", "application/x-www-form-urlencoded")?;
-    headers.set("Origin", "https://www.instagram.com")?;
-    headers.set("Referer", "https://www.instagram.com/")?;
-    headers.set("Priority", "u=1, i")?;
-    headers.set("Sec-Ch-Prefers-Color-Scheme", "dark")?;
-    headers.set("Sec-Ch-Ua", r#""Google Chrome";v="125", "Chromium";v="125", "Not.A/Brand";v="24""#)?;
-    headers.set("Sec-Ch-Ua-Full-Version-List", r#""Google Chrome";v="125.0.6422.142", "Chromium";v="125.0.6422.142", "Not.A/Brand";v="24.0.0.0""#)?;
-    headers.set("Sec-Ch-Ua-Mobile", "?0")?;
-    headers.set("Sec-Ch-Ua-Model", r#""""#)?;
-    headers.set("Sec-Ch-Ua-Platform", r#""macOS""#)?;
-    headers.set("Sec-Ch-Ua-Platform-Version", r#""12.7.4""#)?;
-    headers.set("Sec-Fetch-Dest", "empty")?;
-    headers.set("Sec-Fetch-Mode", "cors")?;
-    headers.set("Sec-Fetch-Site", "same-origin")?;
-    headers.set("User-Agent", CHROME_UA)?;
-    headers.set("X-Asbd-Id", "129477")?;
-    headers.set("X-Fb-Lsd", "AVoPBTXMX0Y")?;
-    headers.set("X-Fb-Friendly-Name", "PolarisPostActionLoadPostQueryQuery")?;
-    headers.set("X-Ig-App-Id", IG_APP_ID)?;
-    Ok(headers)
+    parse_shortcode_media(media, post_id)
 }
 
-/// Makes a direct GraphQL POST request from the CF Worker without any proxy.
-async fn direct_graphql_fetch(url: &str, body: &str) -> Result<worker::Response> {
-    let headers = build_graphql_headers()?;
-
-    let mut init = RequestInit::new();
-    init.with_method(Method::Post)
-        .with_headers(headers)
-        .with_body(Some(body.to_string().into()));
-
-    let request = Request::new_with_init(url, &init)?;
-    Fetch::Request(request).send().await
+/// Builds the GraphQL POST request with the full set of browser-spoofing headers.
+fn build_graphql_request(url: &str, body: &str) -> HttpRequest {
+    HttpRequest::post(url, body)
+        .header("Accept", "*/*")
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Origin", "https://www.instagram.com")
+        .header("Referer", "https://www.instagram.com/")
+        .header("Priority", "u=1, i")
+        .header("Sec-Ch-Prefers-Color-Scheme", "dark")
+        .header("Sec-Ch-Ua", r#""Google Chrome";v="125", "Chromium";v="125", "Not.A/Brand";v="24""#)
+        .header("Sec-Ch-Ua-Full-Version-List", r#""Google Chrome";v="125.0.6422.142", "Chromium";v="125.0.6422.142", "Not.A/Brand";v="24.0.0.0""#)
+        .header("Sec-Ch-Ua-Mobile", "?0")
+        .header("Sec-Ch-Ua-Model", r#""""#)
+        .header("Sec-Ch-Ua-Platform", r#""macOS""#)
+        .header("Sec-Ch-Ua-Platform-Version", r#""12.7.4""#)
+        .header("Sec-Fetch-Dest", "empty")
+        .header("Sec-Fetch-Mode", "cors")
+        .header("Sec-Fetch-Site", "same-origin")
+        .header("User-Agent", CHROME_UA)
+        .header("X-Asbd-Id", "129477")
+        .header("X-Fb-Lsd", "AVoPBTXMX0Y")
+        .header("X-Fb-Friendly-Name", "PolarisPostActionLoadPostQueryQuery")
+        .header("X-Ig-App-Id", IG_APP_ID)
 }
 
 /// Simple form URL encoding for key-value pairs.
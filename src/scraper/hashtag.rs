@@ -0,0 +1,99 @@
+use worker::*;
+
+use super::http::{HttpClient, HttpRequest};
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::{TagGrid, TagWebInfoResponse};
+use crate::utils::log::console_log;
+
+const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+/// Instagram web app ID; `tags/web_info` answers anonymous requests carrying it.
+const IG_APP_ID: &str = "936619743392459";
+
+/// Fetches the shortcode of a hashtag's top post from `api/v1/tags/web_info/`.
+///
+/// Like `web_profile_info`, this works without a session from many IPs.
+/// Tries direct fetch first, then falls back to the proxy. Hashtags too new
+/// (or too small) to have top posts fall back to their most recent one.
+pub async fn fetch_top_post<C: HttpClient>(
+    client: &C,
+    tag: &str,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<String>> {
+    let encoded: String = url::form_urlencoded::byte_serialize(tag.as_bytes()).collect();
+    let url = format!("https://i.instagram.com/api/v1/tags/web_info/?tag_name={encoded}");
+    let request = HttpRequest::get(&url)
+        .header("User-Agent", CHROME_UA)
+        .header("Accept", "*/*")
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .header("X-Ig-App-Id", IG_APP_ID);
+
+    console_log!("[hashtag] trying direct fetch for #{}", tag);
+    match client.send(request.clone()).await {
+        Ok(resp) if resp.status == 200 => {
+            if let Some(post_id) = parse_top_post_response(&resp.body) {
+                return Ok(Some(post_id));
+            }
+        }
+        Ok(resp) => console_log!("[hashtag] direct status={}", resp.status),
+        Err(e) => console_log!("[hashtag] direct fetch error: {:?}", e),
+    }
+
+    console_log!("[hashtag] trying via proxy");
+    let resp = proxy_fetch(client, request, proxy).await?;
+    console_log!("[hashtag] proxy status={} len={}", resp.status, resp.body.len());
+    if resp.status != 200 {
+        return Ok(None);
+    }
+    Ok(parse_top_post_response(&resp.body))
+}
+
+pub(super) fn parse_top_post_response(text: &str) -> Option<String> {
+    let resp: TagWebInfoResponse = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            console_log!("[hashtag] JSON parse error: {}", e);
+            return None;
+        }
+    };
+
+    let Some(data) = resp.data else {
+        console_log!("[hashtag] data object is null or missing");
+        return None;
+    };
+    first_post(data.top).or_else(|| first_post(data.recent))
+}
+
+/// The shortcode of a grid's first post, reading its rows left to right.
+fn first_post(grid: Option<TagGrid>) -> Option<String> {
+    grid?
+        .sections
+        .into_iter()
+        .filter_map(|section| section.layout_content)
+        .flat_map(|layout| layout.medias)
+        .find_map(|item| item.media?.code.filter(|code| !code.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn picks_the_first_top_post() {
+        let client = FixtureClient::new().route("tags/web_info", 200, include_str!("../../fixtures/tag_web_info.json"));
+        let post_id = block_on(fetch_top_post(&client, "cats", None)).unwrap();
+        assert_eq!(post_id.as_deref(), Some("CxCarousel1"));
+
+        let requests = client.requests.borrow();
+        assert!(requests[0].url.ends_with("tag_name=cats"));
+        assert_eq!(requests[0].header_value("x-ig-app-id"), Some(IG_APP_ID));
+    }
+
+    #[test]
+    fn falls_back_to_recent_posts() {
+        let body = r#"{"data":{"top":{"sections":[]},"recent":{"sections":[{"layout_content":{"medias":[{"media":{"code":"CxNew"}}]}}]}}}"#;
+        assert_eq!(parse_top_post_response(body).as_deref(), Some("CxNew"));
+        assert!(parse_top_post_response(r#"{"data":null,"status":"ok"}"#).is_none());
+    }
+}
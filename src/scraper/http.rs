@@ -0,0 +1,247 @@
+use std::pin::pin;
+use std::time::Duration;
+
+use futures_util::future::{select, Either};
+use worker::{AbortController, Date, Delay, Error, Fetch, Headers, Method, Request, RequestInit, Result};
+
+/// An outbound HTTP request.
+///
+/// Plain data rather than `worker::Request`, so scrapers can be driven by a
+/// fixture-backed client in tests without the Workers runtime.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Give up on the response after this long. Set by `Deadline`.
+    pub timeout_millis: Option<u64>,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::Get,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            timeout_millis: None,
+        }
+    }
+
+    pub fn post(url: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            method: Method::Post,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Some(body.into()),
+            timeout_millis: None,
+        }
+    }
+
+    /// Adds a header, builder-style.
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Sets the response timeout, builder-style.
+    pub fn timeout(mut self, millis: u64) -> Self {
+        self.timeout_millis = Some(millis);
+        self
+    }
+
+    /// Looks up a header value by case-insensitive name.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A fully-read HTTP response.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// The transport used by every scraper backend.
+///
+/// Production uses [`WorkerClient`]; tests use a fixture-backed client that
+/// replays recorded Instagram responses.
+pub trait HttpClient {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+
+    /// Wall-clock time in milliseconds, for timing backend calls.
+    fn now_millis(&self) -> u64;
+}
+
+/// `HttpClient` backed by the Workers `fetch` API.
+pub struct WorkerClient;
+
+impl HttpClient for WorkerClient {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let headers = Headers::new();
+        for (name, value) in &request.headers {
+            headers.set(name, value)?;
+        }
+
+        let mut init = RequestInit::new();
+        init.with_method(request.method).with_headers(headers);
+        if let Some(body) = request.body {
+            init.with_body(Some(body.into()));
+        }
+
+        let req = Request::new_with_init(&request.url, &init)?;
+        let fetch = Fetch::Request(req);
+        let mut resp = match request.timeout_millis {
+            None => fetch.send().await?,
+            Some(millis) => {
+                // Abort the fetch rather than leave it running once we stop waiting
+                let controller = AbortController::default();
+                let signal = controller.signal();
+                let send = pin!(fetch.send_with_signal(&signal));
+                let delay = pin!(Delay::from(Duration::from_millis(millis)));
+                match select(send, delay).await {
+                    Either::Left((resp, _)) => resp?,
+                    Either::Right(_) => {
+                        controller.abort();
+                        return Err(Error::RustError(format!("request timed out after {}ms", millis)));
+                    }
+                }
+            }
+        };
+
+        Ok(HttpResponse {
+            status: resp.status_code(),
+            body: resp.text().await?,
+        })
+    }
+
+    fn now_millis(&self) -> u64 {
+        Date::now().as_millis()
+    }
+}
+
+#[cfg(test)]
+pub mod fixtures {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::scraper::types::{InstaData, Media, MediaType};
+
+    /// Replays canned responses, matched by URL substring in registration order.
+    /// Unmatched requests get an empty 404. Every request URL is recorded.
+    #[derive(Default)]
+    pub struct FixtureClient {
+        routes: Vec<(&'static str, u16, &'static str)>,
+        pub requests: RefCell<Vec<HttpRequest>>,
+    }
+
+    impl FixtureClient {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Serves `body` with `status` for any URL containing `url_part`.
+        pub fn route(mut self, url_part: &'static str, status: u16, body: &'static str) -> Self {
+            self.routes.push((url_part, status, body));
+            self
+        }
+
+        /// Returns `true` if any request URL contained `url_part`.
+        pub fn requested(&self, url_part: &str) -> bool {
+            self.requests.borrow().iter().any(|r| r.url.contains(url_part))
+        }
+    }
+
+    impl HttpClient for FixtureClient {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            let matched = self
+                .routes
+                .iter()
+                .find(|(part, _, _)| request.url.contains(part))
+                .map(|(_, status, body)| HttpResponse {
+                    status: *status,
+                    body: body.to_string(),
+                });
+            self.requests.borrow_mut().push(request);
+
+            Ok(matched.unwrap_or(HttpResponse {
+                status: 404,
+                body: String::new(),
+            }))
+        }
+
+        /// Each call is one request's worth of "time" later, so timings are
+        /// deterministic.
+        fn now_millis(&self) -> u64 {
+            self.requests.borrow().len() as u64 * 100
+        }
+    }
+
+    /// A post with every optional field empty. Tests override what they need
+    /// with struct update syntax.
+    pub fn post(post_id: &str, username: &str, media: Vec<Media>) -> InstaData {
+        InstaData {
+            post_id: post_id.to_string(),
+            username: username.to_string(),
+            caption: None,
+            media,
+            like_count: None,
+            comment_count: None,
+            is_video: false,
+            video_view_count: None,
+            play_count: None,
+            timestamp: 0,
+            top_comment: None,
+            full_name: None,
+            is_verified: false,
+            avatar_url: None,
+            coauthors: Vec::new(),
+            sensitive: false,
+            kind: None,
+            stickers: Vec::new(),
+            hashtag: None,
+            backend: None,
+            scraped_at: 0,
+            cached: false,
+            canary: false,
+        }
+    }
+
+    /// An image slide without a thumbnail or dimensions.
+    pub fn image(url: &str) -> Media {
+        slide(MediaType::Image, url)
+    }
+
+    /// A video slide without a thumbnail or dimensions.
+    pub fn video(url: &str) -> Media {
+        slide(MediaType::Video, url)
+    }
+
+    fn slide(media_type: MediaType, url: &str) -> Media {
+        Media {
+            media_type,
+            url: url.to_string(),
+            thumbnail_url: None,
+            width: None,
+            height: None,
+            alt_text: None,
+        }
+    }
+
+    /// Drives a future to completion. Fixture clients never actually suspend,
+    /// so a no-op waker is enough.
+    pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(out) = future.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Timing for every outbound request.
+//!
+//! Wraps the transport so each call logs its host, status and elapsed time,
+//! and, when the `FETCH_METRICS` Analytics Engine dataset is bound, writes
+//! them as a data point: one per request, indexed by host. That makes a slow
+//! proxy or a degrading Instagram endpoint show up as a latency trend instead
+//! of a hunch. Without the binding, calls are only logged.
+
+use url::Url;
+use worker::{AnalyticsEngineDataPointBuilder, AnalyticsEngineDataset, Env, Fetch, Response, Result};
+
+use super::http::{HttpClient, HttpRequest, HttpResponse, WorkerClient};
+use crate::utils::log::console_log;
+
+/// The Analytics Engine binding.
+pub const BINDING: &str = "FETCH_METRICS";
+
+/// One outbound request, as recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchTiming {
+    pub host: String,
+    /// `None` when the request failed without a response (network error,
+    /// timeout).
+    pub status: Option<u16>,
+    pub elapsed_ms: u64,
+}
+
+impl FetchTiming {
+    fn new(url: &str, status: Option<u16>, elapsed_ms: u64) -> Self {
+        Self { host: host_of(url), status, elapsed_ms }
+    }
+
+    /// The status as a blob: the code, or "error".
+    fn outcome(&self) -> String {
+        self.status.map_or_else(|| "error".to_string(), |status| status.to_string())
+    }
+}
+
+/// The URL's host, or "invalid" if it doesn't parse.
+fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "invalid".to_string())
+}
+
+/// Wraps a client so every call is timed and reported.
+pub struct Instrumented<'a, C> {
+    inner: &'a C,
+    dataset: Option<AnalyticsEngineDataset>,
+}
+
+impl<'a, C: HttpClient> Instrumented<'a, C> {
+    /// Reports to `dataset`, or only to the log when `None`.
+    pub fn new(inner: &'a C, dataset: Option<AnalyticsEngineDataset>) -> Self {
+        Self { inner, dataset }
+    }
+
+    fn report(&self, timing: &FetchTiming) {
+        console_log!("[fetch] host={} status={} elapsed={}ms", timing.host, timing.outcome(), timing.elapsed_ms);
+        let Some(dataset) = &self.dataset else {
+            return;
+        };
+        let written = AnalyticsEngineDataPointBuilder::new()
+            .indexes([timing.host.as_str()])
+            .add_blob(timing.host.as_str())
+            .add_blob(timing.outcome())
+            .add_double(timing.elapsed_ms as f64)
+            .add_double(timing.status.unwrap_or(0))
+            .write_to(dataset);
+        if let Err(e) = written {
+            console_log!("[fetch] metrics write failed: {:?}", e);
+        }
+    }
+}
+
+/// The production transport, reporting to `FETCH_METRICS` if it's bound.
+pub fn instrumented(env: &Env) -> Instrumented<'static, WorkerClient> {
+    Instrumented::new(&WorkerClient, env.analytics_engine(BINDING).ok())
+}
+
+impl<C: HttpClient> HttpClient for Instrumented<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let url = request.url.clone();
+        let started = self.inner.now_millis();
+        let result = self.inner.send(request).await;
+        let elapsed = self.inner.now_millis().saturating_sub(started);
+        self.report(&FetchTiming::new(&url, result.as_ref().ok().map(|resp| resp.status), elapsed));
+        result
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+impl Instrumented<'_, WorkerClient> {
+    /// Sends a `worker::Fetch` as built, timed and reported like `send`, for
+    /// what an `HttpRequest` can't express: media that has to stay a
+    /// stream, `cf` image options, HEAD probes and manual redirects, whose
+    /// headers are the answer. Timed until the response headers arrive.
+    pub async fn fetch(&self, fetch: Fetch) -> Result<Response> {
+        let url = match &fetch {
+            Fetch::Url(url) => url.to_string(),
+            Fetch::Request(request) => request.url()?.to_string(),
+        };
+        let started = self.inner.now_millis();
+        let result = fetch.send().await;
+        let elapsed = self.inner.now_millis().saturating_sub(started);
+        self.report(&FetchTiming::new(&url, result.as_ref().ok().map(Response::status_code), elapsed));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn passes_responses_through() {
+        let client = FixtureClient::new().route("/robots.txt", 200, "ok");
+        let instrumented = Instrumented::new(&client, None);
+        let resp = block_on(instrumented.send(HttpRequest::get("https://www.instagram.com/robots.txt"))).unwrap();
+        assert_eq!((resp.status, resp.body.as_str()), (200, "ok"));
+        assert_eq!(client.requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn records_host_and_status() {
+        let timing = FetchTiming::new("https://www.instagram.com/api/v1/media/1/info/", Some(429), 100);
+        assert_eq!(timing, FetchTiming { host: "www.instagram.com".into(), status: Some(429), elapsed_ms: 100 });
+        assert_eq!(timing.outcome(), "429");
+    }
+
+    #[test]
+    fn failed_requests_have_no_status() {
+        let timing = FetchTiming::new("https://api.brightdata.com/request", None, 5000);
+        assert_eq!(timing.host, "api.brightdata.com");
+        assert_eq!(timing.status, None);
+        assert_eq!(timing.outcome(), "error");
+        assert_eq!(host_of("not a url"), "invalid");
+    }
+}
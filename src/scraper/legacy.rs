@@ -0,0 +1,111 @@
+use worker::*;
+
+use super::embed_page::parse_shortcode_media;
+use super::http::{HttpClient, HttpRequest};
+use super::papi::parse_papi_item;
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::LegacyResponse;
+use super::types::InstaData;
+use crate::utils::log::console_log;
+
+const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+const IG_APP_ID: &str = "936619743392459";
+
+/// Fetches post data from the legacy `/p/{shortcode}/?__a=1&__d=dis` JSON endpoint.
+///
+/// Long deprecated, but it still answers intermittently, mostly through
+/// residential IPs. Sends the session cookie when one is configured. Tries
+/// direct fetch first, then falls back to the proxy.
+pub async fn fetch_legacy<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    cookie: Option<&str>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<InstaData>> {
+    let url = format!("https://www.instagram.com/p/{post_id}/?__a=1&__d=dis");
+    let mut request = HttpRequest::get(&url)
+        .header("User-Agent", CHROME_UA)
+        .header("Accept", "*/*")
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .header("X-Ig-App-Id", IG_APP_ID)
+        .header("X-Requested-With", "XMLHttpRequest");
+    if let Some(cookie) = cookie {
+        request = request.header("Cookie", cookie);
+    }
+
+    console_log!("[legacy] trying direct fetch for {}", post_id);
+    match client.send(request.clone()).await {
+        Ok(resp) if resp.status == 200 => {
+            if let Some(data) = parse_legacy_response(&resp.body, post_id) {
+                return Ok(Some(data));
+            }
+        }
+        Ok(resp) => console_log!("[legacy] direct status={}", resp.status),
+        Err(e) => console_log!("[legacy] direct fetch error: {:?}", e),
+    }
+
+    console_log!("[legacy] trying via proxy");
+    let resp = proxy_fetch(client, request, proxy).await?;
+    console_log!("[legacy] proxy status={} len={}", resp.status, resp.body.len());
+    if resp.status != 200 {
+        return Ok(None);
+    }
+    Ok(parse_legacy_response(&resp.body, post_id))
+}
+
+/// Parses either shape the endpoint has served: the old web
+/// `{"graphql": {"shortcode_media": ...}}`, or the newer PAPI-style `{"items": [...]}`.
+fn parse_legacy_response(text: &str, post_id: &str) -> Option<InstaData> {
+    // Logged-out requests get the login page's HTML instead of JSON
+    if text.contains("require_login") || text.contains("not-logged-in") {
+        console_log!("[legacy] response requires login");
+        return None;
+    }
+
+    let resp: LegacyResponse = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            console_log!("[legacy] JSON parse error: {}", e);
+            return None;
+        }
+    };
+
+    if let Some(media) = resp.graphql.and_then(|g| g.into_media()) {
+        return parse_shortcode_media(media, post_id);
+    }
+    if let Some(item) = resp.items.into_iter().next() {
+        return parse_papi_item(item, post_id).ok().flatten();
+    }
+    console_log!("[legacy] no media in response");
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_graphql_shape() {
+        let data = parse_legacy_response(include_str!("../../fixtures/legacy_a1.json"), "CxReel123").unwrap();
+        assert_eq!(data.username, "catlover");
+        assert!(data.is_video);
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/o1/v/t16/legacy_reel.mp4");
+        assert_eq!(data.like_count, Some(5120));
+    }
+
+    #[test]
+    fn parses_items_shape() {
+        let data = parse_legacy_response(include_str!("../../fixtures/papi_carousel.json"), "CxPapi1234").unwrap();
+        assert_eq!(data.caption.as_deref(), Some("Private API carousel"));
+        assert_eq!(data.media.len(), 2);
+        assert_eq!(data.media[0].alt_text.as_deref(), Some("Photo by Cat Lover. May be an image of two cats sleeping."));
+        assert_eq!(data.media[1].alt_text, None);
+    }
+
+    #[test]
+    fn login_page_and_empty_responses_yield_none() {
+        assert!(parse_legacy_response("<html>not-logged-in</html>", "CxReel123").is_none());
+        assert!(parse_legacy_response("{}", "CxReel123").is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Merging policy for partial results from different backends.
+//!
+//! Backends fail in different ways: the embed page HTML fallback has the
+//! caption but only a thumbnail, while GraphQL may return full media with
+//! counts missing. Rather than discarding the weaker result, the richer media
+//! set is kept and every other field is filled from whichever side has it.
+
+use super::types::{InstaData, Media, MediaType};
+
+/// Merges two results for the same post into one.
+///
+/// The side with the better media set (see [`media_score`]) wins the media
+/// list; ties go to `a`. Scalar fields come from the media winner when
+/// present and fall back to the other side.
+pub fn merge(a: InstaData, b: InstaData) -> InstaData {
+    let (mut best, other) = if media_score(&b) > media_score(&a) { (b, a) } else { (a, b) };
+
+    if best.username.is_empty() || best.username == "unknown" {
+        best.username = other.username;
+    }
+    if best.caption.as_deref().is_none_or(str::is_empty) {
+        best.caption = other.caption;
+    }
+    best.like_count = best.like_count.or(other.like_count);
+    best.comment_count = best.comment_count.or(other.comment_count);
+    best.video_view_count = best.video_view_count.or(other.video_view_count);
+    best.play_count = best.play_count.or(other.play_count);
+    best.top_comment = best.top_comment.or(other.top_comment);
+    best.full_name = best.full_name.or(other.full_name);
+    best.is_verified |= other.is_verified;
+    best.sensitive |= other.sensitive;
+    best.avatar_url = best.avatar_url.or(other.avatar_url);
+    if best.coauthors.is_empty() {
+        best.coauthors = other.coauthors;
+    }
+    best.kind = best.kind.or(other.kind);
+    if best.stickers.is_empty() {
+        best.stickers = other.stickers;
+    }
+    if best.timestamp == 0 {
+        best.timestamp = other.timestamp;
+    }
+    best.is_video |= other.is_video;
+
+    // Same post, so items line up by position when both sides have them all
+    if best.media.len() == other.media.len() {
+        for (media, fallback) in best.media.iter_mut().zip(other.media) {
+            fill_media(media, fallback);
+        }
+    }
+
+    best
+}
+
+/// Ranks a media set: playable videos beat bare images, more items beat
+/// fewer, and known dimensions break ties.
+fn media_score(data: &InstaData) -> (usize, usize, usize) {
+    let playable = data
+        .media
+        .iter()
+        .filter(|m| m.media_type == MediaType::Video && !m.is_blocked_video())
+        .count();
+    let sized = data.media.iter().filter(|m| m.width.is_some() && m.height.is_some()).count();
+    (playable, data.media.len(), sized)
+}
+
+/// Fills a media item's missing thumbnail, dimensions and alt text from the
+/// other source.
+fn fill_media(media: &mut Media, fallback: Media) {
+    media.alt_text = media.alt_text.take().or(fallback.alt_text);
+    if media.thumbnail_url.is_none() && media.media_type == MediaType::Video {
+        // A blocked video's thumbnail, or the other side's image of the same frame
+        media.thumbnail_url = fallback.thumbnail_url.or_else(|| {
+            (fallback.media_type == MediaType::Image && !fallback.url.is_empty()).then_some(fallback.url)
+        });
+    }
+    if media.width.is_none() || media.height.is_none() {
+        media.width = media.width.or(fallback.width);
+        media.height = media.height.or(fallback.height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{image, post, video};
+
+    #[test]
+    fn video_media_wins_and_caption_is_kept() {
+        let mut thumbnail_only = post("ABC", "cat", vec![image("https://cdn/cover.jpg")]);
+        thumbnail_only.caption = Some("Zoomies".to_string());
+        thumbnail_only.like_count = Some(10);
+        thumbnail_only.timestamp = 1700000000;
+        let mut full = post("ABC", "cat", vec![video("https://cdn/reel.mp4")]);
+        full.is_video = true;
+        full.video_view_count = Some(500);
+
+        let merged = merge(thumbnail_only, full);
+        assert_eq!(merged.media[0].url, "https://cdn/reel.mp4");
+        assert_eq!(merged.media[0].thumbnail_url.as_deref(), Some("https://cdn/cover.jpg"));
+        assert_eq!(merged.caption.as_deref(), Some("Zoomies"));
+        assert_eq!(merged.like_count, Some(10));
+        assert_eq!(merged.video_view_count, Some(500));
+        assert_eq!(merged.timestamp, 1700000000);
+        assert!(merged.is_video);
+    }
+
+    #[test]
+    fn known_username_replaces_placeholder() {
+        let merged = merge(post("ABC", "unknown", vec![image("a")]), post("ABC", "cat", vec![]));
+        assert_eq!(merged.username, "cat");
+        assert_eq!(merged.media.len(), 1);
+    }
+
+    #[test]
+    fn carousel_beats_single_thumbnail() {
+        let merged = merge(post("ABC", "cat", vec![image("cover")]), post("ABC", "cat", vec![image("1"), image("2")]));
+        assert_eq!(merged.media.len(), 2);
+        // Different shapes, so nothing is borrowed across items
+        assert!(merged.media[0].thumbnail_url.is_none());
+    }
+
+    #[test]
+    fn blocked_video_does_not_beat_playable_one() {
+        let mut blocked = video("");
+        blocked.thumbnail_url = Some("https://cdn/poster.jpg".to_string());
+        let merged = merge(post("ABC", "cat", vec![blocked]), post("ABC", "cat", vec![video("https://cdn/reel.mp4")]));
+        assert_eq!(merged.media[0].url, "https://cdn/reel.mp4");
+        assert_eq!(merged.media[0].thumbnail_url.as_deref(), Some("https://cdn/poster.jpg"));
+    }
+}
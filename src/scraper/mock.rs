@@ -0,0 +1,96 @@
+//! `MOCK_MODE`: canned posts and profiles for offline development.
+//!
+//! Serves the recorded responses in `fixtures/` through the real parsers, so
+//! `wrangler dev` renders embeds, links and profiles without a proxy, a cookie
+//! or any Instagram traffic. Unknown shortcodes behave like a post every
+//! backend failed on.
+
+use super::embed_page::extract_from_json;
+use super::graphql::parse_graphql_response;
+use super::hashtag::parse_top_post_response;
+use super::papi::parse_papi_response;
+use super::profile::parse_profile_response;
+use super::types::{InstaData, Profile};
+
+/// Which backend's parser reads a fixture.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    EmbedPage,
+    Graphql,
+    Papi,
+}
+
+/// Canned posts by shortcode: a carousel, a reel, a mixed carousel, a
+/// collab, a post without a caption and one heavy with emoji and RTL text.
+const POSTS: [(&str, Source, &str); 6] = [
+    ("CxCarousel1", Source::EmbedPage, include_str!("../../fixtures/embed_carousel.html")),
+    ("CxReel123", Source::Graphql, include_str!("../../fixtures/graphql_video.json")),
+    ("CxPapi1234", Source::Papi, include_str!("../../fixtures/papi_carousel.json")),
+    ("CxCollab01", Source::Graphql, include_str!("../../fixtures/graphql_collab.json")),
+    ("CxNoCap001", Source::Graphql, include_str!("../../fixtures/graphql_no_caption.json")),
+    ("CxEmoji001", Source::Graphql, include_str!("../../fixtures/graphql_emoji.json")),
+];
+
+/// Canned profiles by username.
+const PROFILES: [(&str, &str); 1] = [("catlover", include_str!("../../fixtures/web_profile_info.json"))];
+
+/// Canned hashtag pages by tag. Their top post is a canned post.
+const TAGS: [(&str, &str); 1] = [("cats", include_str!("../../fixtures/tag_web_info.json"))];
+
+/// Returns the canned post for a shortcode, if there is one.
+pub fn mock_post(post_id: &str) -> Option<InstaData> {
+    let (_, source, fixture) = POSTS.iter().find(|(id, _, _)| *id == post_id)?;
+    match source {
+        Source::EmbedPage => extract_from_json(fixture, post_id),
+        Source::Graphql => parse_graphql_response(fixture, post_id),
+        Source::Papi => parse_papi_response(fixture, post_id).ok().flatten(),
+    }
+}
+
+/// Returns the canned profile for a username, if there is one.
+pub fn mock_profile(username: &str) -> Option<Profile> {
+    let (_, fixture) = PROFILES.iter().find(|(name, _)| name.eq_ignore_ascii_case(username))?;
+    parse_profile_response(fixture)
+}
+
+/// Returns the top post of a canned hashtag, if there is one.
+pub fn mock_top_post(tag: &str) -> Option<String> {
+    let (_, fixture) = TAGS.iter().find(|(name, _)| *name == tag)?;
+    parse_top_post_response(fixture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_canned_post_parses() {
+        for (post_id, _, _) in POSTS {
+            let data = mock_post(post_id).unwrap_or_else(|| panic!("{post_id} doesn't parse"));
+            assert_eq!(data.post_id, post_id);
+            assert!(!data.media.is_empty());
+        }
+        assert!(mock_post("CxUnknown1").is_none());
+    }
+
+    #[test]
+    fn collab_post_credits_its_coauthor() {
+        let data = mock_post("CxCollab01").unwrap();
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.coauthors, ["dogperson"]);
+        assert!(mock_post("CxNoCap001").unwrap().coauthors.is_empty());
+    }
+
+    #[test]
+    fn canned_profile_is_found_case_insensitively() {
+        assert_eq!(mock_profile("CatLover").unwrap().username, "catlover");
+        assert!(mock_profile("dogperson").is_none());
+    }
+
+    #[test]
+    fn canned_hashtag_leads_to_a_canned_post() {
+        let post_id = mock_top_post("cats").unwrap();
+        assert!(mock_post(&post_id).is_some());
+        assert!(mock_top_post("dogs").is_none());
+    }
+}
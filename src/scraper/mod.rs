@@ -1,40 +1,703 @@
+pub mod browser;
 pub mod cache;
+pub mod canary;
+pub mod deadline;
+pub mod doc_ids;
 pub mod embed_page;
+pub mod geo;
 pub mod graphql;
+pub mod hashtag;
+pub mod http;
+pub mod instrument;
+pub mod legacy;
+pub mod merge;
+pub mod mock;
 pub mod papi;
+pub mod post_page;
+pub mod profile;
 pub mod proxy;
+pub mod ratelimit;
+pub mod schema;
+pub mod session;
+pub mod shadow;
+pub mod stats;
 pub mod types;
 
+use std::cell::RefCell;
+use std::pin::pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures_util::future::{select, Either};
 use worker::*;
 
-use self::cache::{get_cached, set_cached};
-use self::embed_page::fetch_embed_page;
+use self::cache::{
+    add_backend_stats_background, get_cached_screenshot, get_cooldown, get_doc_id_scores, put_screenshot, set_background,
+    set_background_for, start_cooldown_background, update_doc_id_scores_background, Kind,
+};
+use self::browser::{capture_screenshot, fetch_browser, PageWatch};
+use self::deadline::Deadline;
+use self::embed_page::{fetch_embed_page, into_blocked_video};
+use self::geo::GeoBlockWatch;
 use self::graphql::fetch_graphql;
-use self::papi::fetch_papi;
-use self::types::InstaData;
+use self::hashtag::fetch_top_post;
+use self::http::HttpClient;
+use self::instrument::instrumented;
+use self::legacy::fetch_legacy;
+use self::merge::merge;
+use self::mock::{mock_post, mock_profile, mock_top_post};
+use self::papi::{fetch_comment, fetch_papi, fetch_story_item};
+use self::post_page::fetch_post_page;
+use self::profile::fetch_profile;
+use self::ratelimit::RateLimitWatch;
+use self::session::SessionWatch;
+use self::shadow::compare_background;
+use self::stats::{bucket_of, Attempt, Backend, Track};
+use self::types::{Comment, InstaData, Profile};
+use crate::alert::record_scrape;
+use crate::config::Config;
+use crate::handlers::context::EmbedContext;
+use crate::pacing::Paced;
+use crate::session::{lease_session, report_session_background};
+use crate::utils::base64;
+use crate::utils::random::random_percent;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// Outcome of fetching a post, for callers that explain a miss.
+#[derive(Debug)]
+pub enum Fetched {
+    Found(Box<InstaData>),
+    /// Every backend failed, and at least one said the post is restricted in
+    /// this region.
+    GeoBlocked,
+    /// The time budget (`SCRAPE_BUDGET_MS`) ran out before any backend
+    /// found the post.
+    TimedOut,
+    /// Instagram rate-limited the scrape, or the cooldown after one kept it
+    /// from running. Says nothing about the post.
+    RateLimited,
+    /// Every backend failed, but Browser Rendering showed the post's page:
+    /// the post exists, and a screenshot of it can stand in for its data.
+    Unreadable,
+    Missing,
+}
+
+impl Fetched {
+    pub fn into_data(self) -> Option<InstaData> {
+        match self {
+            Fetched::Found(data) => Some(*data),
+            Fetched::GeoBlocked | Fetched::TimedOut | Fetched::RateLimited | Fetched::Unreadable | Fetched::Missing => None,
+        }
+    }
+}
+
+/// Where `scrape_post_traced` leaves the embed page's thumbnail-level result
+/// as soon as it has one, while richer backends are still being tried.
+pub type Partial = RefCell<Option<InstaData>>;
 
 /// Orchestrator: cache -> embed page -> graphql fallback
 ///
-/// The embed page JSON extraction gives complete data (images + videos).
-/// The embed page HTML fallback only gives thumbnails — never video URLs.
-/// So when HTML fallback is used, we always try GraphQL for better data.
-pub async fn fetch_post_data(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
+/// Cache writes are deferred via `ctx.wait_until` so they don't delay the response.
+pub async fn fetch_post_data(post_id: &str, env: &Env, state: &Rc<AppState>) -> Result<Option<InstaData>> {
+    fetch_post_within(post_id, env, state, 0).await.map(Fetched::into_data)
+}
+
+/// Like `fetch_post_data`, for the media routes, which redirect to the media
+/// itself: a cached post whose signed CDN URLs have expired is re-scraped
+/// (and re-cached), rather than sending the client to a dead link.
+///
+/// If the re-scrape fails, the stale copy is still returned: its URLs are
+/// declared expired a little early, and may yet work.
+pub async fn fetch_fresh_post_data(embed: &EmbedContext<'_>, env: &Env, state: &Rc<AppState>) -> Result<Option<InstaData>> {
+    let post_id = &embed.post_id;
+    let data = fetch_post_data(post_id, env, state).await?;
+    let now_secs = Date::now().as_millis() / 1000;
+    match data {
+        Some(cached) if !state.config.mock_mode && cached.media_expired(now_secs) => {
+            console_log!("[scraper] CDN URLs for {} have expired, re-scraping", post_id);
+            let fresh = scrape_and_cache(post_id.clone(), env.clone(), state.clone(), Rc::default()).await;
+            Ok(Some(fresh.into_data().unwrap_or_else(|| {
+                console_log!("[scraper] re-scrape of {} failed, serving the stale copy", post_id);
+                cached
+            })))
+        }
+        _ => Ok(data),
+    }
+}
+
+/// Like `fetch_post_data`, telling geo-blocked posts apart from missing ones.
+///
+/// For embeds: a scrape still running after `Config::fast_path_ms` is
+/// answered with the embed page's thumbnail-level result, if there is one,
+/// and finished in the background so the full result is cached for next time.
+pub async fn fetch_post(post_id: &str, env: &Env, state: &Rc<AppState>) -> Result<Fetched> {
+    fetch_post_within(post_id, env, state, state.config.fast_path_ms).await
+}
+
+/// A post read from the cache, as every route serves it.
+pub fn from_cache(mut data: InstaData) -> InstaData {
+    // Entries cached before URLs were normalized still carry trackers
+    data.normalize_urls();
+    data.cached = true;
+    data
+}
+
+/// The cached post, if there is one, without scraping on a miss. For HEAD
+/// probes, which must not scrape.
+pub async fn fetch_cached_post(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
+    Ok(cache::get::<InstaData>(Kind::Post, post_id, env).await?.map(from_cache))
+}
+
+async fn fetch_post_within(post_id: &str, env: &Env, state: &Rc<AppState>, fast_path_ms: u64) -> Result<Fetched> {
     console_log!("[scraper] fetching post_id={}", post_id);
+    if state.config.mock_mode {
+        console_log!("[scraper] MOCK_MODE: serving fixture for {}", post_id);
+        return Ok(mock_post(post_id).map_or(Fetched::Missing, |data| Fetched::Found(Box::new(data))));
+    }
 
     // 1. Check cache
-    match get_cached(post_id, env).await {
-        Ok(Some(cached)) => {
+    match cache::get::<InstaData>(Kind::Post, post_id, env).await {
+        Ok(Some(data)) => {
             console_log!("[scraper] cache HIT for {}", post_id);
-            return Ok(Some(cached));
+            return Ok(Fetched::Found(Box::new(from_cache(data))));
         }
         Ok(None) => console_log!("[scraper] cache MISS for {}", post_id),
         Err(e) => console_log!("[scraper] cache error: {:?}", e),
     }
+    // Bogus shortcodes would otherwise run the whole chain, browser and
+    // all, every time a crawler retries them
+    if let Ok(Some(shown)) = cache::get::<bool>(Kind::Missing, post_id, env).await {
+        console_log!("[scraper] {} was missing on the last scrape, not scraping again", post_id);
+        return Ok(if shown { Fetched::Unreadable } else { Fetched::Missing });
+    }
+
+    let partial = Rc::new(Partial::default());
+    let live = Box::pin(scrape_and_cache(post_id.to_string(), env.clone(), state.clone(), partial.clone()));
+    if fast_path_ms == 0 {
+        return Ok(live.await);
+    }
+
+    let live = match select(live, pin!(Delay::from(Duration::from_millis(fast_path_ms)))).await {
+        Either::Left((fetched, _)) => return Ok(fetched),
+        Either::Right((_, live)) => live,
+    };
+    let Some(mut data) = partial.borrow_mut().take() else {
+        return Ok(live.await);
+    };
+    console_log!("[scraper] {} is slow, serving the embed page thumbnail and finishing in the background", post_id);
+    state.ctx.wait_until(async move {
+        live.await;
+    });
+    data.normalize_urls();
+    data.backend = Some(Backend::Embed);
+    Ok(Fetched::Found(Box::new(data)))
+}
+
+/// Scrapes a post live and caches the result. Owns its arguments so the
+/// fast path can hand it to `wait_until` unfinished.
+async fn scrape_and_cache(post_id: String, env: Env, state: Rc<AppState>, partial: Rc<Partial>) -> Fetched {
+    let now_secs = Date::now().as_millis() / 1000;
+    let (fetched, cacheable) = scrape_live(&post_id, &env, &state, now_secs, &partial).await;
+    // A geo-block is an answer from a working backend, not an outage
+    record_scrape(matches!(fetched, Fetched::Found(_) | Fetched::GeoBlocked), &env, &state, now_secs);
+
+    match fetched {
+        Fetched::Found(_) | Fetched::Missing | Fetched::Unreadable if !cacheable => {
+            console_log!("[scraper] scrape of {} was cut short or canary, not caching", post_id)
+        }
+        Fetched::Found(ref data) => set_background(Kind::Post, &post_id, data, &env, &state),
+        Fetched::Missing | Fetched::Unreadable => {
+            let shown = matches!(fetched, Fetched::Unreadable);
+            set_background(Kind::Missing, &post_id, &shown, &env, &state);
+        }
+        _ => {}
+    }
+    fetched
+}
+
+/// The post an embed asks for (`embed.post_id`): a story item when `story`
+/// gives its kind and, for live stories, its author; else a post.
+pub async fn fetch_embed(
+    embed: &EmbedContext<'_>,
+    story: Option<(Kind, Option<&str>)>,
+    env: &Env,
+    state: &Rc<AppState>,
+    now_secs: u64,
+) -> Result<Fetched> {
+    match story {
+        Some((kind, username)) => fetch_story(&embed.post_id, kind, username, env, state, now_secs).await,
+        None => fetch_post(&embed.post_id, env, state).await,
+    }
+}
+
+/// Like `fetch_post`, for story items (`kind` is `Kind::Story` or
+/// `Kind::Highlight`): cached in their own namespace. Stories are kept for
+/// `Config::story_cache_ttl`, and never past their 24 hour lifetime.
+///
+/// A story the scrape chain can't find is looked for among its author's
+/// current stories (`username`, from the route), whose ID `web_profile_info`
+/// resolves.
+pub async fn fetch_story(
+    story_id: &str,
+    kind: Kind,
+    username: Option<&str>,
+    env: &Env,
+    state: &AppState,
+    now_secs: u64,
+) -> Result<Fetched> {
+    if state.config.mock_mode {
+        return Ok(mock_post(story_id).map_or(Fetched::Missing, |data| Fetched::Found(Box::new(data))));
+    }
+    match cache::get::<InstaData>(kind, story_id, env).await {
+        Ok(Some(mut data)) => {
+            console_log!("[scraper] story cache HIT for {}", story_id);
+            data.normalize_urls();
+            data.cached = true;
+            return Ok(Fetched::Found(Box::new(data)));
+        }
+        Ok(None) => console_log!("[scraper] story cache MISS for {}", story_id),
+        Err(e) => console_log!("[scraper] story cache error: {:?}", e),
+    }
+
+    let (mut fetched, cacheable) = scrape_live(story_id, env, state, now_secs, &Partial::default()).await;
+    if let (Fetched::Missing, Kind::Story, Some(username)) = (&fetched, kind, username) {
+        if let Some(data) = scrape_story_reel(story_id, username, env, state, now_secs).await {
+            fetched = Fetched::Found(Box::new(data));
+        }
+    }
+
+    if let Fetched::Found(ref data) = fetched {
+        let ttl = match kind {
+            Kind::Story => story_ttl(data, kind.ttl(&state.config), now_secs),
+            _ => Some(kind.ttl(&state.config)),
+        };
+        if let Some(ttl) = ttl.filter(|_| cacheable) {
+            set_background_for(kind, story_id, data, ttl, env, &state.ctx);
+        }
+    }
+    Ok(fetched)
+}
+
+/// Runs the live scrape chain with the GraphQL doc_id candidates ranked by
+/// recent success, within `Config::scrape_budget_ms`, then records backend
+/// stats and doc_id outcomes in the background. Also returns whether the
+/// result may be cached: not when the budget ran out, in which case any data
+/// found may be partial, nor when the scrape took the canary path.
+async fn scrape_live(post_id: &str, env: &Env, state: &AppState, now_secs: u64, partial: &Partial) -> (Fetched, bool) {
+    if cooling_down(env, now_secs).await {
+        return (Fetched::RateLimited, false);
+    }
+
+    // A slice of scrapes tries the canary path instead. Its doc_ids aren't
+    // ranked, and its outcomes are counted apart
+    let canary = state.config.canary.as_ref().filter(|c| c.picks(random_percent()));
+    let track = if canary.is_some() { Track::Canary } else { Track::Stable };
+    let canaried;
+    let base = match canary {
+        Some(canary) => {
+            console_log!("[scraper] canary scrape for {}", post_id);
+            canaried = canary.apply(&state.config);
+            &canaried
+        }
+        None => &state.config,
+    };
+
+    // A single doc_id has nothing to rank, so skip the KV round trips
+    let ranking = track == Track::Stable && base.doc_ids.len() > 1 && base.graphql_enabled;
+    let ranked;
+    let config = if ranking {
+        let scores = get_doc_id_scores(env).await.unwrap_or_else(|e| {
+            console_log!("[scraper] doc_id scores error: {:?}", e);
+            None
+        });
+        ranked = Config {
+            doc_ids: scores.unwrap_or_default().rank(&base.doc_ids),
+            ..base.clone()
+        };
+        &ranked
+    } else {
+        base
+    };
+    let leased = with_leased_session(config, env).await;
+    let config = leased.as_ref().unwrap_or(config);
+
+    let mut attempts = Vec::new();
+    let timed = instrumented(env);
+    let session = SessionWatch::new(&timed);
+    let watch = RateLimitWatch::new(&session);
+    let paced = Paced::new(&watch, env, config);
+    let client = Deadline::new(&paced, config.scrape_budget_ms);
+    let mut fetched = scrape_post_checked(&client, post_id, config, &mut attempts, partial).await;
+    if watch.hit() {
+        start_cooldown_background(env, &state.ctx, now_secs);
+    }
+    if let Some(status) = session.flagged() {
+        report_session_background(status, env, &state.ctx);
+    }
+    // The backend that found the post is the last to succeed
+    let served_by = attempts.iter().rev().find(|a| a.ok).map(|a| a.backend);
+    match fetched {
+        Fetched::Found(ref mut data) => {
+            data.normalize_urls();
+            data.backend = served_by;
+            data.scraped_at = now_secs;
+            data.canary = track == Track::Canary;
+        }
+        Fetched::Missing if client.expired() => {
+            console_log!("[scraper] time budget ran out for {}", post_id);
+            fetched = Fetched::TimedOut;
+        }
+        Fetched::Missing if watch.hit() => fetched = Fetched::RateLimited,
+        _ => {}
+    }
+
+    if ranking {
+        let outcomes = attempts.iter().filter_map(|a| Some((a.doc_id.clone()?, a.ok))).collect();
+        update_doc_id_scores_background(outcomes, config.doc_ids.clone(), env, &state.ctx);
+    }
+    // A second opinion on what was found, unless it came from the shadow backend itself
+    let shadow = config.shadow_backend.filter(|&b| Some(b) != served_by && random_percent() < config.shadow_percent);
+    if let (Some(backend), Fetched::Found(data), false) = (shadow, &fetched, client.expired()) {
+        compare_background(backend, served_by, data, config, env, &state.ctx);
+    }
+    add_backend_stats_background(bucket_of(now_secs), track, attempts, env, &state.ctx);
+    (fetched, !client.expired() && track == Track::Stable)
+}
+
+/// Finds a story item among its author's current stories, resolving the
+/// author's numeric ID from their (cached) profile. Needs `IG_COOKIE`.
+async fn scrape_story_reel(story_id: &str, username: &str, env: &Env, state: &AppState, now_secs: u64) -> Option<InstaData> {
+    if !state.config.papi_enabled || state.config.cookie.is_none() {
+        return None;
+    }
+    let pk = match fetch_profile_data(username, env, state).await {
+        Ok(profile) => profile?.pk,
+        Err(e) => {
+            console_log!("[scraper] story author lookup error: {:?}", e);
+            return None;
+        }
+    };
+
+    let leased = with_leased_session(&state.config, env).await;
+    let config = leased.as_ref().unwrap_or(&state.config);
+    let timed = instrumented(env);
+    let session = SessionWatch::new(&timed);
+    let watch = RateLimitWatch::new(&session);
+    let paced = Paced::new(&watch, env, config);
+    let client = Deadline::new(&paced, config.scrape_budget_ms);
+    let (cookie, device) = (config.cookie.as_deref(), config.device.as_ref());
+    let data = fetch_story_item(&client, story_id, &pk, cookie, device, config.proxy.as_ref()).await;
+    if watch.hit() {
+        start_cooldown_background(env, &state.ctx, now_secs);
+    }
+    if let Some(status) = session.flagged() {
+        report_session_background(status, env, &state.ctx);
+    }
+    let mut data = data
+        .unwrap_or_else(|e| {
+            console_log!("[scraper] story reel error: {:?}", e);
+            None
+        })?;
+    data.normalize_urls();
+    data.backend = Some(Backend::Papi);
+    data.scraped_at = now_secs;
+    Some(data)
+}
+
+/// `config` as leased from the session object: with its device IDs, or
+/// without the cookie while the session is flagged. `None` unless
+/// `IG_COOKIE` is set and the `SESSIONS` binding is in use.
+async fn with_leased_session(config: &Config, env: &Env) -> Option<Config> {
+    config.cookie.as_ref()?;
+    let lease = lease_session(env).await?;
+    if lease.flagged {
+        console_log!("[scraper] session is flagged, scraping without IG_COOKIE");
+        return Some(Config { cookie: None, device: None, ..config.clone() });
+    }
+    Some(Config { device: Some(lease.device), ..config.clone() })
+}
+
+/// Returns `true` while a global rate-limit cooldown is in effect, in which
+/// case nothing should be fetched from Instagram.
+async fn cooling_down(env: &Env, now_secs: u64) -> bool {
+    match get_cooldown(env).await {
+        Ok(Some(cooldown)) if cooldown.active(now_secs) => {
+            console_log!("[scraper] rate-limit cooldown for {}s more, skipping live fetch", cooldown.until - now_secs);
+            true
+        }
+        Ok(_) => false,
+        Err(e) => {
+            console_log!("[scraper] cooldown read error: {:?}", e);
+            false
+        }
+    }
+}
+
+/// TTL for a story cache entry: the configured TTL, cut short at the
+/// story's expiry. `None` when it expires too soon for KV (60s minimum).
+fn story_ttl(data: &InstaData, configured: u64, now_secs: u64) -> Option<u64> {
+    let ttl = match data.story_remaining_secs(now_secs) {
+        Some(remaining) => configured.min(remaining),
+        None => configured,
+    };
+    (ttl >= 60).then_some(ttl)
+}
+
+/// Fetches a user profile through the cache, from `web_profile_info`.
+pub async fn fetch_profile_data(username: &str, env: &Env, state: &AppState) -> Result<Option<Profile>> {
+    if state.config.mock_mode {
+        return Ok(mock_profile(username));
+    }
+    match cache::get::<Profile>(Kind::Profile, username, env).await {
+        Ok(Some(mut cached)) => {
+            console_log!("[scraper] profile cache HIT for {}", username);
+            cached.normalize_urls();
+            return Ok(Some(cached));
+        }
+        Ok(None) => console_log!("[scraper] profile cache MISS for {}", username),
+        Err(e) => console_log!("[scraper] profile cache error: {:?}", e),
+    }
+    scrape_profile(username, env, state).await
+}
+
+/// The shortcode of a user's newest post, for `/latest/:username`. A cached
+/// profile can be hours old, so this scrapes the profile itself whenever its
+/// own few-minute entry has expired, refreshing the profile's entry too.
+pub async fn fetch_latest_post(username: &str, env: &Env, state: &AppState) -> Result<Option<String>> {
+    if state.config.mock_mode {
+        return Ok(mock_profile(username).and_then(|p| Some(p.latest_post()?.shortcode.clone())));
+    }
+    match cache::get::<String>(Kind::Latest, username, env).await {
+        Ok(Some(cached)) => return Ok(Some(cached)),
+        Ok(None) => {}
+        Err(e) => console_log!("[scraper] latest post cache error: {:?}", e),
+    }
+
+    let Some(profile) = scrape_profile(username, env, state).await? else {
+        return Ok(None);
+    };
+    let latest = profile.latest_post().map(|post| post.shortcode.clone());
+    if let Some(ref post_id) = latest {
+        set_background(Kind::Latest, username, post_id, env, state);
+    }
+    Ok(latest)
+}
+
+/// The shortcode of a hashtag's top post, for `/explore/tags/:tag`, through
+/// the cache. `tag` is as returned by `parse_hashtag`.
+pub async fn fetch_hashtag_top_post(tag: &str, env: &Env, state: &AppState) -> Result<Option<String>> {
+    if state.config.mock_mode {
+        return Ok(mock_top_post(tag));
+    }
+    match cache::get::<String>(Kind::Tag, tag, env).await {
+        Ok(Some(cached)) => return Ok(Some(cached)),
+        Ok(None) => {}
+        Err(e) => console_log!("[scraper] hashtag cache error: {:?}", e),
+    }
+
+    let now_secs = Date::now().as_millis() / 1000;
+    if cooling_down(env, now_secs).await {
+        return Ok(None);
+    }
+
+    let timed = instrumented(env);
+    let watch = RateLimitWatch::new(&timed);
+    let paced = Paced::new(&watch, env, &state.config);
+    let client = Deadline::new(&paced, state.config.scrape_budget_ms);
+    let post_id = fetch_top_post(&client, tag, state.config.proxy.as_ref()).await;
+    if watch.hit() {
+        start_cooldown_background(env, &state.ctx, now_secs);
+    }
+    let post_id = post_id?;
+    if let Some(ref post_id) = post_id {
+        set_background(Kind::Tag, tag, post_id, env, state);
+    }
+    Ok(post_id)
+}
+
+/// Scrapes a profile from `web_profile_info` and caches it.
+async fn scrape_profile(username: &str, env: &Env, state: &AppState) -> Result<Option<Profile>> {
+    let now_secs = Date::now().as_millis() / 1000;
+    if cooling_down(env, now_secs).await {
+        return Ok(None);
+    }
+
+    let timed = instrumented(env);
+    let watch = RateLimitWatch::new(&timed);
+    let paced = Paced::new(&watch, env, &state.config);
+    let client = Deadline::new(&paced, state.config.scrape_budget_ms);
+    let profile = fetch_profile(&client, username, state.config.proxy.as_ref()).await;
+    if watch.hit() {
+        start_cooldown_background(env, &state.ctx, now_secs);
+    }
+    let mut profile = profile?;
+    if let Some(ref mut profile) = profile {
+        profile.normalize_urls();
+        set_background(Kind::Profile, username, profile, env, state);
+    }
+    Ok(profile)
+}
+
+/// Fetches one comment of a post for a comment permalink embed, through the
+/// cache. Only PAPI serves single comments, so this needs `IG_COOKIE`.
+pub async fn fetch_comment_data(
+    post_id: &str,
+    comment_id: &str,
+    env: &Env,
+    state: &AppState,
+) -> Result<Option<Comment>> {
+    // Canned posts carry their top comment, which is all a comment embed needs
+    if state.config.mock_mode {
+        return Ok(mock_post(post_id).and_then(|d| d.top_comment).filter(|c| c.id == comment_id));
+    }
+    match cache::get::<Comment>(Kind::Comment, &format!("{post_id}:{comment_id}"), env).await {
+        Ok(Some(cached)) => return Ok(Some(cached)),
+        Ok(None) => {}
+        Err(e) => console_log!("[scraper] comment cache error: {:?}", e),
+    }
+
+    if !state.config.papi_enabled {
+        return Ok(None);
+    }
+
+    let now_secs = Date::now().as_millis() / 1000;
+    if cooling_down(env, now_secs).await {
+        return Ok(None);
+    }
+
+    let leased = with_leased_session(&state.config, env).await;
+    let config = leased.as_ref().unwrap_or(&state.config);
+    let timed = instrumented(env);
+    let session = SessionWatch::new(&timed);
+    let watch = RateLimitWatch::new(&session);
+    let client = Paced::new(&watch, env, config);
+    let (cookie, device) = (config.cookie.as_deref(), config.device.as_ref());
+    let comment = fetch_comment(&client, post_id, comment_id, cookie, device, config.proxy.as_ref()).await;
+    if watch.hit() {
+        start_cooldown_background(env, &state.ctx, now_secs);
+    }
+    if let Some(status) = session.flagged() {
+        report_session_background(status, env, &state.ctx);
+    }
+    let comment = comment?;
+    if let Some(ref comment) = comment {
+        set_background(Kind::Comment, &format!("{post_id}:{comment_id}"), comment, env, state);
+    }
+    Ok(comment)
+}
+
+/// A JPEG screenshot of the post page, through the cache, for embeds of
+/// `Fetched::Unreadable` posts. Needs Browser Rendering.
+///
+/// The capture gets what is left of the request's `budget_millis`, and is
+/// paced like the browser backend. One that fails marks the post plain
+/// missing, so it isn't tried again until the missing entry expires.
+pub async fn fetch_screenshot(post_id: &str, env: &Env, state: &AppState, budget_millis: u64) -> Result<Option<Vec<u8>>> {
+    let Some(ref creds) = state.config.browser else {
+        return Ok(None);
+    };
+    if state.config.mock_mode {
+        return Ok(None);
+    }
+    match get_cached_screenshot(post_id, env).await {
+        Ok(Some(cached)) => return Ok(Some(cached)),
+        Ok(None) => {}
+        Err(e) => console_log!("[scraper] screenshot cache error: {:?}", e),
+    }
+
+    let now_secs = Date::now().as_millis() / 1000;
+    if cooling_down(env, now_secs).await {
+        return Ok(None);
+    }
+
+    let started = Date::now().as_millis();
+    let timed = instrumented(env);
+    let paced = Paced::new(&timed, env, &state.config);
+    let client = Deadline::new(&paced, budget_millis);
+    let screenshot = capture_screenshot(&client, post_id, creds).await;
+    console_log!("[scraper] screenshot of {} took {}ms", post_id, Date::now().as_millis() - started);
+    let Some(jpeg) = screenshot.as_ref().ok().and_then(Option::as_deref).and_then(base64::decode) else {
+        if !client.expired() {
+            set_background(Kind::Missing, post_id, &false, env, state);
+        }
+        return screenshot.map(|_| None);
+    };
+    put_screenshot(post_id, &jpeg, env, &state.config).await?;
+    Ok(Some(jpeg))
+}
+
+/// Like `scrape_post_traced`, telling geo-blocked and unreadable posts apart
+/// from missing ones. A geo-blocked post is retried once through the proxy in
+/// `PROXY_GEO_RETRY_COUNTRY`, if configured.
+pub async fn scrape_post_checked<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    config: &Config,
+    attempts: &mut Vec<Attempt>,
+    partial: &Partial,
+) -> Fetched {
+    let watch = GeoBlockWatch::new(client);
+    let page = PageWatch::new(&watch, post_id);
+    if let Some(data) = scrape_post_traced(&page, post_id, config, attempts, partial).await {
+        return Fetched::Found(Box::new(data));
+    }
+    if !watch.hit() {
+        return if page.shown() { Fetched::Unreadable } else { Fetched::Missing };
+    }
+    let Some(proxy) = config.geo_retry_proxy() else {
+        console_log!("[scraper] {} is geo-blocked", post_id);
+        return Fetched::GeoBlocked;
+    };
+
+    console_log!("[scraper] {} is geo-blocked, retrying through the proxy in {}", post_id, proxy.country);
+    let retry = Config { proxy: Some(proxy), ..config.clone() };
+    match scrape_post_traced(client, post_id, &retry, attempts, partial).await {
+        Some(data) => Fetched::Found(Box::new(data)),
+        None => Fetched::GeoBlocked,
+    }
+}
+
+/// Runs the live scrape chain: embed page -> graphql -> legacy `?__a=1` -> post page -> PAPI ->
+/// Browser Rendering -> thumbnail.
+///
+/// The embed page JSON extraction gives complete data (images + videos).
+/// The embed page HTML fallback only gives thumbnails — never video URLs.
+/// So when HTML fallback is used, we always try GraphQL for better data, and
+/// merge whatever the embed page did have into its result.
+#[cfg(test)]
+pub async fn scrape_post<C: HttpClient>(client: &C, post_id: &str, config: &Config) -> Option<InstaData> {
+    scrape_post_traced(client, post_id, config, &mut Vec::new(), &Partial::default()).await
+}
+
+/// Like `scrape_post`, also recording every backend call made (outcome and
+/// latency) into `attempts`, and leaving a thumbnail-only embed page result
+/// in `partial` as soon as there is one.
+pub async fn scrape_post_traced<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    config: &Config,
+    attempts: &mut Vec<Attempt>,
+    partial: &Partial,
+) -> Option<InstaData> {
+    let cookie = config.cookie.as_deref();
+    let proxy = config.proxy.as_ref();
+    let mut record = |backend, ok, started: u64, doc_id: Option<&String>| {
+        attempts.push(Attempt {
+            backend,
+            ok,
+            millis: client.now_millis().saturating_sub(started),
+            doc_id: doc_id.cloned(),
+        });
+    };
 
     // 2. Try embed page
     let mut embed_fallback: Option<InstaData> = None;
 
-    match fetch_embed_page(post_id, env).await {
+    let started = client.now_millis();
+    let embed = fetch_embed_page(client, post_id, cookie, proxy).await;
+    record(Backend::Embed, matches!(embed, Ok(Some(_))), started, None);
+    match embed {
         Ok(Some((data, video_blocked))) => {
             // JSON extraction gets full data (including video URLs) — use directly
             // HTML fallback only gets thumbnails — always try GraphQL for better data
@@ -45,22 +708,19 @@ pub async fn fetch_post_data(post_id: &str, env: &Env) -> Result<Option<InstaDat
 
             if !video_blocked && (json_extraction || has_video_url || !data.media.is_empty()) {
                 // Check if this looks like complete data (JSON extraction) vs HTML fallback (thumbnail only)
-                // HTML fallback always produces Image type with no dimensions
-                let is_html_fallback = data.media.len() == 1
-                    && data.media[0].media_type == types::MediaType::Image
-                    && data.media[0].width.is_none()
-                    && data.media[0].height.is_none();
-
-                if !is_html_fallback {
+                if !is_html_fallback(&data) {
                     console_log!("[scraper] embed page JSON data complete for {} (username={})", post_id, data.username);
-                    let _ = set_cached(post_id, &data, env).await;
-                    return Ok(Some(data));
+                    return Some(data);
                 }
 
                 console_log!("[scraper] embed page HTML fallback for {} — trying GraphQL for richer data", post_id);
+                *partial.borrow_mut() = Some(data.clone());
                 embed_fallback = Some(data);
             } else if video_blocked {
                 console_log!("[scraper] video blocked in embed for {} — trying GraphQL", post_id);
+                // The scraped "image" is the video's poster frame; keep it as the thumbnail
+                let data = if is_html_fallback(&data) { into_blocked_video(data) } else { data };
+                *partial.borrow_mut() = Some(data.clone());
                 embed_fallback = Some(data);
             }
         }
@@ -68,43 +728,434 @@ pub async fn fetch_post_data(post_id: &str, env: &Env) -> Result<Option<InstaDat
         Err(e) => console_log!("[scraper] embed page ERROR for {}: {:?}", post_id, e),
     }
 
-    // 3. GraphQL — try for videos, incomplete data, or when embed page failed entirely
-    let doc_id = env.var("GRAPHQL_DOC_ID")
-        .map(|v| v.to_string())
-        .unwrap_or_else(|_| "25531498899829322".to_string());
-    console_log!("[scraper] trying graphql for {} with doc_id={}", post_id, doc_id);
+    // 3. GraphQL — try for videos, incomplete data, or when embed page failed entirely.
+    // Each doc_id candidate is tried in turn, in the order given by the config.
+    if config.graphql_enabled {
+        for doc_id in &config.doc_ids {
+            console_log!("[scraper] trying graphql for {} with doc_id={}", post_id, doc_id);
 
-    match fetch_graphql(post_id, &doc_id, env).await {
-        Ok(Some(data)) => {
-            console_log!("[scraper] graphql SUCCESS for {} (username={}, media_count={}, is_video={})",
-                post_id, data.username, data.media.len(), data.is_video);
-            let _ = set_cached(post_id, &data, env).await;
-            return Ok(Some(data));
+            let started = client.now_millis();
+            let graphql = fetch_graphql(client, post_id, doc_id, &config.graphql_path, &config.graphql_variables, proxy).await;
+            record(Backend::Graphql, matches!(graphql, Ok(Some(_))), started, Some(doc_id));
+            match graphql {
+                Ok(Some(data)) => {
+                    console_log!("[scraper] graphql SUCCESS for {} (username={}, media_count={}, is_video={})",
+                        post_id, data.username, data.media.len(), data.is_video);
+                    return Some(merge_fallback(data, embed_fallback));
+                }
+                Ok(None) => console_log!("[scraper] graphql returned None for {}", post_id),
+                Err(e) => console_log!("[scraper] graphql ERROR for {}: {:?}", post_id, e),
+            }
         }
-        Ok(None) => console_log!("[scraper] graphql returned None for {}", post_id),
-        Err(e) => console_log!("[scraper] graphql ERROR for {}: {:?}", post_id, e),
+    } else {
+        console_log!("[scraper] graphql disabled, skipping");
     }
 
-    // 4. Try Instagram Private API (requires IG_COOKIE secret)
-    console_log!("[scraper] trying PAPI for {}", post_id);
-    match fetch_papi(post_id, env).await {
-        Ok(Some(data)) => {
-            console_log!("[scraper] PAPI SUCCESS for {} (username={}, media_count={}, is_video={})",
-                post_id, data.username, data.media.len(), data.is_video);
-            let _ = set_cached(post_id, &data, env).await;
-            return Ok(Some(data));
+    // 4. Legacy ?__a=1 JSON endpoint — intermittent, but needs no cookie
+    if config.legacy_enabled {
+        console_log!("[scraper] trying legacy ?__a=1 for {}", post_id);
+        let started = client.now_millis();
+        let legacy = fetch_legacy(client, post_id, cookie, proxy).await;
+        record(Backend::Legacy, matches!(legacy, Ok(Some(_))), started, None);
+        match legacy {
+            Ok(Some(data)) => {
+                console_log!("[scraper] legacy SUCCESS for {} (username={}, media_count={}, is_video={})",
+                    post_id, data.username, data.media.len(), data.is_video);
+                return Some(merge_fallback(data, embed_fallback));
+            }
+            Ok(None) => console_log!("[scraper] legacy returned None for {}", post_id),
+            Err(e) => console_log!("[scraper] legacy ERROR for {}: {:?}", post_id, e),
+        }
+    } else {
+        console_log!("[scraper] legacy disabled, skipping");
+    }
+
+    // 5. The full post page, whose inline JSON often outlives a login-walled embed
+    if config.post_page_enabled {
+        console_log!("[scraper] trying post page for {}", post_id);
+        let started = client.now_millis();
+        let post_page = fetch_post_page(client, post_id, cookie, proxy).await;
+        record(Backend::PostPage, matches!(post_page, Ok(Some(_))), started, None);
+        match post_page {
+            Ok(Some(data)) => {
+                console_log!("[scraper] post page SUCCESS for {} (username={}, media_count={}, is_video={})",
+                    post_id, data.username, data.media.len(), data.is_video);
+                return Some(merge_fallback(data, embed_fallback));
+            }
+            Ok(None) => console_log!("[scraper] post page returned None for {}", post_id),
+            Err(e) => console_log!("[scraper] post page ERROR for {}: {:?}", post_id, e),
+        }
+    } else {
+        console_log!("[scraper] post page disabled, skipping");
+    }
+
+    // 6. Try Instagram Private API (requires IG_COOKIE secret)
+    if config.papi_enabled {
+        console_log!("[scraper] trying PAPI for {}", post_id);
+        let started = client.now_millis();
+        let papi = fetch_papi(client, post_id, cookie, config.device.as_ref(), proxy).await;
+        // Without a cookie PAPI never makes a call, so there's nothing to count
+        if cookie.is_some() {
+            record(Backend::Papi, matches!(papi, Ok(Some(_))), started, None);
+        }
+        match papi {
+            Ok(Some(data)) => {
+                console_log!("[scraper] PAPI SUCCESS for {} (username={}, media_count={}, is_video={})",
+                    post_id, data.username, data.media.len(), data.is_video);
+                return Some(merge_fallback(data, embed_fallback));
+            }
+            Ok(None) => console_log!("[scraper] PAPI returned None for {}", post_id),
+            Err(e) => console_log!("[scraper] PAPI ERROR for {}: {:?}", post_id, e),
+        }
+    } else {
+        console_log!("[scraper] PAPI disabled, skipping");
+    }
+
+    // 7. Load the post page in a headless browser. Billed per browser second, so last
+    if let Some(browser) = config.browser.as_ref() {
+        console_log!("[scraper] trying Browser Rendering for {}", post_id);
+        let started = client.now_millis();
+        let rendered = fetch_browser(client, post_id, browser).await;
+        record(Backend::Browser, matches!(rendered, Ok(Some(_))), started, None);
+        match rendered {
+            Ok(Some(data)) => {
+                console_log!("[scraper] Browser Rendering SUCCESS for {} (username={}, media_count={}, is_video={})",
+                    post_id, data.username, data.media.len(), data.is_video);
+                return Some(merge_fallback(data, embed_fallback));
+            }
+            Ok(None) => console_log!("[scraper] Browser Rendering returned None for {}", post_id),
+            Err(e) => console_log!("[scraper] Browser Rendering ERROR for {}: {:?}", post_id, e),
         }
-        Ok(None) => console_log!("[scraper] PAPI returned None for {}", post_id),
-        Err(e) => console_log!("[scraper] PAPI ERROR for {}: {:?}", post_id, e),
     }
 
-    // 5. Fall back to embed page thumbnail if everything else failed
+    // 8. Fall back to embed page thumbnail if everything else failed
     if let Some(data) = embed_fallback {
         console_log!("[scraper] falling back to embed page thumbnail for {}", post_id);
-        let _ = set_cached(post_id, &data, env).await;
-        return Ok(Some(data));
+        return Some(data);
     }
 
     console_log!("[scraper] all methods failed for {}", post_id);
-    Ok(None)
+    None
+}
+
+/// Fills gaps in a backend's result (counts, caption, thumbnails) from the
+/// partial embed page result, if there is one.
+fn merge_fallback(data: InstaData, embed_fallback: Option<InstaData>) -> InstaData {
+    match embed_fallback {
+        Some(fallback) => merge(data, fallback),
+        None => data,
+    }
+}
+
+/// HTML fallback always produces a single Image with no dimensions.
+fn is_html_fallback(data: &InstaData) -> bool {
+    data.media.len() == 1
+        && data.media[0].media_type == types::MediaType::Image
+        && data.media[0].width.is_none()
+        && data.media[0].height.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::http::fixtures::{block_on, FixtureClient};
+    use super::types::{MediaType, PostKind};
+    use super::*;
+    use crate::config::normalize_cookie;
+    use crate::scraper::browser::BrowserCredentials;
+    use crate::scraper::proxy::ProxyCredentials;
+
+    const EMBED: &str = "/embed/captioned/";
+    const GRAPHQL: &str = "/api/graphql";
+    const PAPI: &str = "/api/v1/media/";
+    const LEGACY: &str = "__a=1";
+    const POST_PAGE: &str = "/p/CxPage1/";
+    const BROWSER: &str = "/browser-rendering/content";
+
+    fn config(cookie: Option<&str>) -> Config {
+        Config {
+            cookie: cookie.map(normalize_cookie),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn embed_json_is_used_without_further_requests() {
+        let client = FixtureClient::new().route(EMBED, 200, include_str!("../../fixtures/embed_carousel.html"));
+        let data = block_on(scrape_post(&client, "CxCarousel1", &config(None))).unwrap();
+
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.caption.as_deref(), Some("Two cats, one sunbeam ☀️ {not json}"));
+        assert_eq!(data.like_count, Some(4213));
+        assert_eq!(data.media.len(), 2);
+        assert_eq!(data.media[1].media_type, MediaType::Video);
+        assert_eq!(data.media[1].url, "https://scontent.cdninstagram.com/o1/v/t16/carousel_2.mp4");
+        assert!(!client.requested(GRAPHQL));
+    }
+
+    #[test]
+    fn html_fallback_is_upgraded_by_graphql() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_video.json"));
+        let data = block_on(scrape_post(&client, "CxReel123", &config(None))).unwrap();
+
+        assert!(data.is_video);
+        assert_eq!(data.video_view_count, Some(98231));
+        assert_eq!(data.play_count, Some(210944));
+        assert_eq!(data.kind, Some(PostKind::Reel));
+        assert_eq!(data.full_name.as_deref(), Some("Cat Lover"));
+        assert!(data.is_verified);
+        assert_eq!(data.avatar_url.as_deref(), Some("https://scontent.cdninstagram.com/v/t51/avatar_150.jpg"));
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/o1/v/t16/reel.mp4");
+        assert_eq!(data.media[0].height, Some(1920));
+        let top_comment = data.top_comment.unwrap();
+        assert_eq!((top_comment.username.as_str(), top_comment.text.as_str()), ("catdad", "the 3am zoomies are real"));
+    }
+
+    #[test]
+    fn falls_back_to_embed_thumbnail_when_everything_else_fails() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_null.json"));
+        let data = block_on(scrape_post(&client, "CxReel123", &config(None))).unwrap();
+
+        assert_eq!(data.caption.as_deref(), Some("Zoomies at 3am"));
+        assert_eq!(data.media.len(), 1);
+        // The embed marks the post as a blocked video, so the image is its thumbnail
+        assert!(data.is_video);
+        assert!(data.media[0].is_blocked_video());
+        assert!(data.media[0]
+            .thumbnail_url
+            .as_deref()
+            .unwrap()
+            .contains("reel_cover.jpg?stp=dst-jpg&_nc_ht="));
+        // No cookie configured, so PAPI is skipped without a request.
+        assert!(!client.requested(PAPI));
+    }
+
+    #[test]
+    fn papi_is_tried_with_session_cookie() {
+        let client = FixtureClient::new()
+            .route(EMBED, 404, "")
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_null.json"))
+            .route(PAPI, 200, include_str!("../../fixtures/papi_carousel.json"));
+        let data = block_on(scrape_post(&client, "CxPapi1234", &config(Some("42%3Aabc%3A1")))).unwrap();
+
+        assert_eq!(data.caption.as_deref(), Some("Private API carousel"));
+        assert_eq!(data.top_comment.unwrap().text, "both adorable");
+        assert!(data.is_video);
+        assert_eq!(data.media.len(), 2);
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/v/t51/papi_1.jpg");
+        assert_eq!(
+            data.media[1].thumbnail_url.as_deref(),
+            Some("https://scontent.cdninstagram.com/v/t51/papi_2.jpg")
+        );
+
+        let requests = client.requests.borrow();
+        let papi = requests.iter().find(|r| r.url.contains(PAPI)).unwrap();
+        assert_eq!(papi.header_value("cookie"), Some("sessionid=42:abc:1; ds_user_id=42"));
+    }
+
+    #[test]
+    fn graphql_endpoint_and_variables_come_from_config() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route("/graphql/query", 200, include_str!("../../fixtures/graphql_video.json"));
+        let config = Config {
+            graphql_path: "/graphql/query".to_string(),
+            graphql_variables: r#"{"shortcode":"{shortcode}","__relay_internal__pv__PolarisShareMenurelayprovider":false}"#.to_string(),
+            ..config(None)
+        };
+        let data = block_on(scrape_post(&client, "CxReel123", &config)).unwrap();
+        assert!(data.is_video);
+
+        let requests = client.requests.borrow();
+        let graphql = requests.iter().find(|r| r.url.contains("/graphql/query")).unwrap();
+        assert_eq!(graphql.url, "https://www.instagram.com/graphql/query");
+        let body = graphql.body.as_deref().unwrap();
+        let (_, variables) = url::form_urlencoded::parse(body.as_bytes()).find(|(k, _)| k == "variables").unwrap();
+        assert_eq!(variables, r#"{"shortcode":"CxReel123","__relay_internal__pv__PolarisShareMenurelayprovider":false}"#);
+        assert!(!client.requested(GRAPHQL));
+    }
+
+    #[test]
+    fn legacy_endpoint_is_tried_when_graphql_is_blocked() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_null.json"))
+            .route(LEGACY, 200, include_str!("../../fixtures/legacy_a1.json"));
+        let data = block_on(scrape_post(&client, "CxReel123", &config(Some("42:abc")))).unwrap();
+
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/o1/v/t16/legacy_reel.mp4");
+        assert!(!client.requested(PAPI));
+        let requests = client.requests.borrow();
+        let legacy = requests.iter().find(|r| r.url.contains(LEGACY)).unwrap();
+        assert_eq!(legacy.url, "https://www.instagram.com/p/CxReel123/?__a=1&__d=dis");
+        assert_eq!(legacy.header_value("cookie"), Some("sessionid=42:abc"));
+    }
+
+    #[test]
+    fn geo_blocked_posts_are_retried_from_another_country() {
+        const BLOCKED: &str = "<h2>Sorry, this content isn't available in your country.</h2>";
+        let client = FixtureClient::new().route(EMBED, 200, BLOCKED);
+        let fetched = block_on(scrape_post_checked(&client, "CxGeo12345", &config(None), &mut Vec::new(), &Partial::default()));
+        assert!(matches!(fetched, Fetched::GeoBlocked));
+
+        let client = FixtureClient::new().route("brightdata.com", 200, BLOCKED);
+        let config = Config {
+            proxy: Some(ProxyCredentials {
+                username: "brd-customer-1-zone-res".to_string(),
+                password: "token".to_string(),
+                country: "us".to_string(),
+            }),
+            geo_retry_country: Some("de".to_string()),
+            ..config(None)
+        };
+        let fetched = block_on(scrape_post_checked(&client, "CxGeo12345", &config, &mut Vec::new(), &Partial::default()));
+        assert!(matches!(fetched, Fetched::GeoBlocked));
+        let requests = client.requests.borrow();
+        let countries: Vec<bool> = requests
+            .iter()
+            .filter_map(|r| r.body.as_deref())
+            .filter(|b| b.contains(r#""zone":"res""#))
+            .map(|b| b.contains(r#""country":"de""#))
+            .collect();
+        assert!(countries.contains(&false) && countries.contains(&true));
+
+        // A plain failure isn't mistaken for a geo-block
+        let client = FixtureClient::new();
+        assert!(matches!(block_on(scrape_post_checked(&client, "CxGeo12345", &config, &mut Vec::new(), &Partial::default())), Fetched::Missing));
+    }
+
+    #[test]
+    fn story_ttl_stops_at_expiry() {
+        let client = FixtureClient::new().route(EMBED, 200, include_str!("../../fixtures/embed_carousel.html"));
+        let data = block_on(scrape_post(&client, "CxCarousel1", &config(None))).unwrap();
+        let posted = data.timestamp;
+
+        assert_eq!(story_ttl(&data, 1800, posted + 60), Some(1800));
+        assert_eq!(story_ttl(&data, 1800, posted + 86400 - 600), Some(600));
+        assert_eq!(story_ttl(&data, 1800, posted + 86400 - 30), None);
+        assert_eq!(data.story_remaining_secs(posted + 90000), Some(0));
+    }
+
+    #[test]
+    fn normalize_urls_strips_trackers_from_cdn_urls() {
+        let client = FixtureClient::new().route(EMBED, 200, include_str!("../../fixtures/embed_carousel.html"));
+        let mut data = block_on(scrape_post(&client, "CxCarousel1", &config(None))).unwrap();
+        data.media[0].url = "https://scontent.cdninstagram.com/v/t51/1.jpg?stp=dst-jpg&_nc_ht=scontent.cdninstagram.com&_nc_gid=abc&oh=x&oe=y".into();
+        data.media[1].thumbnail_url = Some("https://scontent.cdninstagram.com/v/t51/2.jpg?utm_source=ig_web".into());
+        data.avatar_url = Some("https://example.com/avatar.jpg?igsh=1".into());
+        data.normalize_urls();
+
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/v/t51/1.jpg?stp=dst-jpg&_nc_ht=scontent.cdninstagram.com&oh=x&oe=y");
+        assert_eq!(data.media[1].thumbnail_url.as_deref(), Some("https://scontent.cdninstagram.com/v/t51/2.jpg"));
+        // Not Instagram's CDN, so left alone
+        assert_eq!(data.avatar_url.as_deref(), Some("https://example.com/avatar.jpg?igsh=1"));
+    }
+
+    #[test]
+    fn thumbnail_result_is_left_in_partial_before_graphql() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_video.json"));
+        let partial = Partial::default();
+        let data = block_on(scrape_post_traced(&client, "CxReel123", &config(None), &mut Vec::new(), &partial)).unwrap();
+
+        assert!(!data.media[0].is_blocked_video());
+        assert!(partial.into_inner().unwrap().media[0].is_blocked_video());
+    }
+
+    #[test]
+    fn all_backends_failing_yields_none() {
+        let client = FixtureClient::new();
+        let mut attempts = Vec::new();
+        assert!(block_on(scrape_post_traced(&client, "CxNothing1", &config(None), &mut attempts, &Partial::default())).is_none());
+        assert!(client.requested(EMBED));
+        assert!(client.requested(GRAPHQL));
+
+        // No cookie, so PAPI is skipped without a call
+        let backends: Vec<(Backend, bool)> = attempts.iter().map(|a| (a.backend, a.ok)).collect();
+        assert_eq!(
+            backends,
+            [(Backend::Embed, false), (Backend::Graphql, false), (Backend::Legacy, false), (Backend::PostPage, false)]
+        );
+        assert!(attempts.iter().all(|a| a.millis > 0));
+    }
+
+    #[test]
+    fn post_page_serves_when_the_json_endpoints_fail() {
+        // Legacy's URL starts with the post page's, so it's routed first
+        let client = FixtureClient::new()
+            .route(LEGACY, 404, "")
+            .route(POST_PAGE, 200, include_str!("../../fixtures/post_page.html"));
+        let mut attempts = Vec::new();
+        let data = block_on(scrape_post_traced(&client, "CxPage1", &config(None), &mut attempts, &Partial::default())).unwrap();
+
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.kind, Some(PostKind::Reel));
+        assert_eq!(attempts.last().map(|a| (a.backend, a.ok)), Some((Backend::PostPage, true)));
+    }
+
+    #[test]
+    fn browser_rendering_is_the_last_live_backend() {
+        let client = FixtureClient::new()
+            .route(LEGACY, 404, "")
+            .route(POST_PAGE, 200, "<html><body>Log in to Instagram</body></html>")
+            .route(BROWSER, 200, include_str!("../../fixtures/browser_content.json"));
+        let rendering = Config {
+            browser: Some(BrowserCredentials { account_id: "acct123".to_string(), token: "tok".to_string() }),
+            ..config(None)
+        };
+        let mut attempts = Vec::new();
+        let data = block_on(scrape_post_traced(&client, "CxPage1", &rendering, &mut attempts, &Partial::default())).unwrap();
+
+        assert_eq!(data.username, "catlover");
+        let backends: Vec<(Backend, bool)> = attempts.iter().map(|a| (a.backend, a.ok)).collect();
+        assert_eq!(backends[backends.len() - 2..], [(Backend::PostPage, false), (Backend::Browser, true)]);
+
+        // Without credentials it's never called
+        let client = FixtureClient::new();
+        block_on(scrape_post(&client, "CxPage1", &config(None)));
+        assert!(!client.requested(BROWSER));
+    }
+
+    #[test]
+    fn posts_the_browser_sees_but_cannot_read_are_unreadable() {
+        let rendering = Config {
+            browser: Some(BrowserCredentials { account_id: "acct123".to_string(), token: "tok".to_string() }),
+            ..config(None)
+        };
+        let shown = r#"{"success":true,"result":"<html><head><link rel=\"canonical\" href=\"https://www.instagram.com/p/CxPage1/\"></head></html>"}"#;
+        let client = FixtureClient::new().route(BROWSER, 200, shown);
+        let fetched = block_on(scrape_post_checked(&client, "CxPage1", &rendering, &mut Vec::new(), &Partial::default()));
+        assert!(matches!(fetched, Fetched::Unreadable));
+
+        // A "page not found" says nothing about the post
+        let client = FixtureClient::new().route(BROWSER, 200, r#"{"success":true,"result":"<html></html>"}"#);
+        let fetched = block_on(scrape_post_checked(&client, "CxPage1", &rendering, &mut Vec::new(), &Partial::default()));
+        assert!(matches!(fetched, Fetched::Missing));
+    }
+
+    #[test]
+    fn disabled_backends_are_never_requested() {
+        let client = FixtureClient::new()
+            .route(EMBED, 200, include_str!("../../fixtures/embed_html_only.html"))
+            .route(GRAPHQL, 200, include_str!("../../fixtures/graphql_video.json"))
+            .route(PAPI, 200, include_str!("../../fixtures/papi_carousel.json"));
+        let config = Config {
+            graphql_enabled: false,
+            legacy_enabled: false,
+            post_page_enabled: false,
+            papi_enabled: false,
+            ..config(Some("42:abc"))
+        };
+        let data = block_on(scrape_post(&client, "CxReel123", &config)).unwrap();
+
+        assert!(data.media[0].is_blocked_video());
+        assert!(!client.requested(GRAPHQL));
+        assert!(!client.requested(LEGACY));
+        assert!(client.requests.borrow().iter().all(|r| !r.url.ends_with("/p/CxReel123/")));
+        assert!(!client.requested(PAPI));
+    }
 }
@@ -1,8 +1,12 @@
 use worker::*;
 
-use super::proxy::proxy_fetch;
-use super::types::{InstaData, Media, MediaType};
+use super::http::{HttpClient, HttpRequest};
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::{coauthor_usernames, PapiCommentsResponse, PapiItem, PapiMedia, PapiReelsMediaResponse, PapiResponse, PapiVersion};
+use super::session::{flagged_status, DeviceIds, SessionStatus};
+use super::types::{Comment, InstaData, Media, MediaType, PollOption, PostKind, Sticker};
 use crate::utils::instagram::code_to_mediaid;
+use crate::utils::log::console_log;
 
 /// Instagram mobile app user-agent (PAPI is the mobile/private API)
 const IG_MOBILE_UA: &str = "Instagram 317.0.0.34.109 Android (31/12; 420dpi; 1080x2400; samsung; SM-G991B; o1s; exynos2100; en_US; 562530885)";
@@ -10,40 +14,22 @@ const IG_MOBILE_UA: &str = "Instagram 317.0.0.34.109 Android (31/12; 420dpi; 108
 /// Fetches post data from Instagram's Private API (mobile API).
 ///
 /// Uses `https://i.instagram.com/api/v1/media/{media_id}/info/` which
-/// requires a valid session cookie (set as `IG_COOKIE` secret).
+/// requires a valid session cookie (set as `IG_COOKIE` secret), sent along
+/// with the session's device IDs when it has any.
 /// Tries direct fetch first, then falls back to proxy.
-pub async fn fetch_papi(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
-    let raw_cookie = match env.secret("IG_COOKIE") {
-        Ok(c) => c.to_string(),
-        Err(_) => {
-            console_log!("[papi] no IG_COOKIE secret configured, skipping");
-            return Ok(None);
-        }
-    };
-
-    // URL-decode the cookie in case wrangler stored it encoded
-    let decoded_cookie = raw_cookie
-        .replace("%3A", ":")
-        .replace("%3a", ":");
-
-    // Auto-wrap raw session ID values with "sessionid=" prefix
-    let cookie = if decoded_cookie.contains('=') {
-        decoded_cookie.clone()
-    } else {
-        format!("sessionid={}", decoded_cookie)
+pub async fn fetch_papi<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    cookie: Option<&str>,
+    device: Option<&DeviceIds>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<InstaData>> {
+    let Some(cookie) = cookie else {
+        console_log!("[papi] no IG_COOKIE secret configured, skipping");
+        return Ok(None);
     };
 
-    // Extract user ID from sessionid value and add ds_user_id cookie
-    // Session format: sessionid={user_id}:{token}:{version}:{hash}
-    let full_cookie = if let Some(sid_val) = cookie.strip_prefix("sessionid=") {
-        if let Some(user_id) = sid_val.split(':').next() {
-            format!("{}; ds_user_id={}", cookie, user_id)
-        } else {
-            cookie.clone()
-        }
-    } else {
-        cookie.clone()
-    };
+    let full_cookie = with_ds_user_id(cookie);
     console_log!("[papi] cookie starts with: {}", &full_cookie[..full_cookie.len().min(50)]);
 
     // Convert shortcode to numeric media ID
@@ -58,38 +44,18 @@ pub async fn fetch_papi(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
     let url = format!("https://i.instagram.com/api/v1/media/{media_id}/info/");
     console_log!("[papi] fetching media_id={} for shortcode={}", media_id, post_id);
 
-    // Try direct fetch first
-    let text = match papi_direct_fetch(&url, &full_cookie).await {
-        Ok(t) if !t.contains("not-logged-in") && !t.contains("Page Not Found") => {
-            console_log!("[papi] direct fetch succeeded");
-            t
-        }
-        Ok(_) => {
-            console_log!("[papi] direct fetch returned login/404, trying via proxy");
-            // Fall back to proxy
-            match papi_proxy_fetch(&url, &full_cookie, env).await {
-                Ok(t) => t,
-                Err(e) => {
-                    console_log!("[papi] proxy fetch error: {:?}", e);
-                    return Ok(None);
-                }
-            }
-        }
-        Err(e) => {
-            console_log!("[papi] direct fetch error: {:?}, trying proxy", e);
-            match papi_proxy_fetch(&url, &full_cookie, env).await {
-                Ok(t) => t,
-                Err(e) => {
-                    console_log!("[papi] proxy fetch error: {:?}", e);
-                    return Ok(None);
-                }
-            }
-        }
+    let Some(text) = papi_fetch(client, &url, &full_cookie, device, proxy).await else {
+        return Ok(None);
     };
 
     console_log!("[papi] response_len={} first_200={}", text.len(), &text[..text.len().min(200)]);
 
-    let json: serde_json::Value = match serde_json::from_str(&text) {
+    parse_papi_response(&text, post_id)
+}
+
+/// Parses a `media/{id}/info/` response body into InstaData.
+pub(super) fn parse_papi_response(text: &str, post_id: &str) -> Result<Option<InstaData>> {
+    let resp: PapiResponse = match serde_json::from_str(text) {
         Ok(v) => v,
         Err(e) => {
             console_log!("[papi] JSON parse error: {}", e);
@@ -97,30 +63,194 @@ pub async fn fetch_papi(post_id: &str, env: &Env) -> Result<Option<InstaData>> {
         }
     };
 
-    let items = match json.get("items").and_then(|i| i.as_array()) {
-        Some(items) if !items.is_empty() => items,
-        _ => {
-            console_log!("[papi] no items in response");
+    let Some(item) = resp.items.into_iter().next() else {
+        console_log!("[papi] no items in response");
+        return Ok(None);
+    };
+
+    parse_papi_item(item, post_id)
+}
+
+/// Fetches a story item from its author's current stories
+/// (`feed/reels_media/?reel_ids={user_pk}`), for story items that
+/// `media/{id}/info/` doesn't return. `user_pk` is the author's numeric ID
+/// (`Profile::pk`). Requires `IG_COOKIE`, like `fetch_papi`.
+pub async fn fetch_story_item<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    user_pk: &str,
+    cookie: Option<&str>,
+    device: Option<&DeviceIds>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<InstaData>> {
+    let Some(cookie) = cookie else {
+        console_log!("[papi] no IG_COOKIE secret configured, skipping story reel");
+        return Ok(None);
+    };
+    let Some(media_id) = code_to_mediaid(post_id) else {
+        console_log!("[papi] failed to convert shortcode {} to media ID", post_id);
+        return Ok(None);
+    };
+
+    let url = format!("https://i.instagram.com/api/v1/feed/reels_media/?reel_ids={user_pk}");
+    console_log!("[papi] fetching story reel of user {} for media_id={}", user_pk, media_id);
+
+    let Some(text) = papi_fetch(client, &url, &with_ds_user_id(cookie), device, proxy).await else {
+        return Ok(None);
+    };
+
+    let resp: PapiReelsMediaResponse = match serde_json::from_str(&text) {
+        Ok(v) => v,
+        Err(e) => {
+            console_log!("[papi] reels JSON parse error: {}", e);
             return Ok(None);
         }
     };
 
-    let item = &items[0];
-    parse_papi_item(item, post_id)
+    let media_id = media_id.to_string();
+    let item = resp
+        .reels
+        .into_values()
+        .flat_map(|reel| reel.items)
+        .find(|item| item.pk.as_ref().is_some_and(|pk| pk.matches(&media_id)));
+    match item {
+        Some(item) => parse_papi_item(item, post_id),
+        None => {
+            console_log!("[papi] story {} not in the user's reel", media_id);
+            Ok(None)
+        }
+    }
 }
 
-/// Direct PAPI fetch from CF Worker.
-async fn papi_direct_fetch(url: &str, cookie: &str) -> Result<String> {
-    let headers = build_papi_headers(cookie)?;
+/// Fetches a single comment for a comment permalink (`/p/{code}/c/{id}/`).
+///
+/// Uses the comments endpoint with `target_comment_id`, which returns the
+/// page of comments around the target. Requires `IG_COOKIE`, like `fetch_papi`.
+pub async fn fetch_comment<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    comment_id: &str,
+    cookie: Option<&str>,
+    device: Option<&DeviceIds>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<Comment>> {
+    let Some(cookie) = cookie else {
+        console_log!("[papi] no IG_COOKIE secret configured, skipping comment");
+        return Ok(None);
+    };
+    let Some(media_id) = code_to_mediaid(post_id) else {
+        console_log!("[papi] failed to convert shortcode {} to media ID", post_id);
+        return Ok(None);
+    };
+
+    let url = format!(
+        "https://i.instagram.com/api/v1/media/{media_id}/comments/?can_support_threading=true&permalink_enabled=true&target_comment_id={comment_id}"
+    );
+    console_log!("[papi] fetching comment_id={} for shortcode={}", comment_id, post_id);
+
+    let Some(text) = papi_fetch(client, &url, &with_ds_user_id(cookie), device, proxy).await else {
+        return Ok(None);
+    };
+
+    let resp: PapiCommentsResponse = match serde_json::from_str(&text) {
+        Ok(v) => v,
+        Err(e) => {
+            console_log!("[papi] comments JSON parse error: {}", e);
+            return Ok(None);
+        }
+    };
+
+    let comment = resp
+        .comments
+        .into_iter()
+        .find(|c| c.pk.as_ref().is_some_and(|pk| pk.matches(comment_id)))
+        .and_then(|c| {
+            Some(Comment {
+                id: comment_id.to_string(),
+                username: c.user.and_then(|u| u.username)?,
+                text: c.text?,
+            })
+        });
+
+    if comment.is_none() {
+        console_log!("[papi] comment {} not in response", comment_id);
+    }
+    Ok(comment)
+}
+
+/// Fetches a PAPI URL directly, falling back to the proxy if the direct
+/// fetch fails or comes back logged out. Returns `None` if both fail.
+async fn papi_fetch<C: HttpClient>(
+    client: &C,
+    url: &str,
+    cookie: &str,
+    device: Option<&DeviceIds>,
+    proxy: Option<&ProxyCredentials>,
+) -> Option<String> {
+    // Try direct fetch first
+    match papi_direct_fetch(client, url, cookie, device).await {
+        Ok(t) if !t.contains("not-logged-in") && !t.contains("Page Not Found") => {
+            console_log!("[papi] direct fetch succeeded");
+            return Some(t);
+        }
+        Ok(_) => console_log!("[papi] direct fetch returned login/404, trying via proxy"),
+        Err(e) => console_log!("[papi] direct fetch error: {:?}, trying proxy", e),
+    }
+
+    match papi_proxy_fetch(client, url, cookie, device, proxy).await {
+        Ok(t) => Some(t),
+        Err(e) => {
+            console_log!("[papi] proxy fetch error: {:?}", e);
+            None
+        }
+    }
+}
 
-    let mut init = RequestInit::new();
-    init.with_method(Method::Get).with_headers(headers);
+/// Appends the `ds_user_id` cookie PAPI expects, taken from the session ID.
+///
+/// Session format: `sessionid={user_id}:{token}:{version}:{hash}`
+fn with_ds_user_id(cookie: &str) -> String {
+    match cookie.strip_prefix("sessionid=").and_then(|sid| sid.split(':').next()) {
+        Some(user_id) => format!("{}; ds_user_id={}", cookie, user_id),
+        None => cookie.to_string(),
+    }
+}
 
-    let request = Request::new_with_init(url, &init)?;
-    let mut resp = Fetch::Request(request).send().await?;
+/// Checks whether the session cookie is logged in, by fetching the current
+/// user directly (no proxy).
+pub async fn check_cookie<C: HttpClient>(client: &C, cookie: &str) -> Result<bool> {
+    Ok(check_session(client, cookie, None).await? == SessionStatus::Active)
+}
+
+/// Like `check_cookie`, telling a challenged session apart from a logged
+/// out one. Cheap enough to double as a keep-alive.
+pub async fn check_session<C: HttpClient>(
+    client: &C,
+    cookie: &str,
+    device: Option<&DeviceIds>,
+) -> Result<SessionStatus> {
+    let url = "https://i.instagram.com/api/v1/accounts/current_user/?edit=true";
+    let resp = client.send(build_papi_request(url, &with_ds_user_id(cookie), device)).await?;
+    console_log!("[papi] cookie check status={}", resp.status);
+
+    Ok(match flagged_status(&resp) {
+        Some(status) => status,
+        None if resp.status == 200 && resp.body.contains("\"user\"") => SessionStatus::Active,
+        None => SessionStatus::LoggedOut,
+    })
+}
 
-    let status = resp.status_code();
-    let text = resp.text().await?;
+/// Direct PAPI fetch from CF Worker.
+async fn papi_direct_fetch<C: HttpClient>(
+    client: &C,
+    url: &str,
+    cookie: &str,
+    device: Option<&DeviceIds>,
+) -> Result<String> {
+    let resp = client.send(build_papi_request(url, cookie, device)).await?;
+
+    let status = resp.status;
+    let text = resp.body;
     console_log!("[papi] direct status={} len={} body={}", status, text.len(), &text[..text.len().min(500)]);
 
     if status != 200 {
@@ -130,13 +260,17 @@ async fn papi_direct_fetch(url: &str, cookie: &str) -> Result<String> {
 }
 
 /// PAPI fetch via Bright Data proxy (passes cookie in headers).
-async fn papi_proxy_fetch(url: &str, cookie: &str, env: &Env) -> Result<String> {
-    let headers = build_papi_headers(cookie)?;
-
-    let mut resp = proxy_fetch(url, Method::Get, headers, None, env).await?;
-
-    let status = resp.status_code();
-    let text = resp.text().await?;
+async fn papi_proxy_fetch<C: HttpClient>(
+    client: &C,
+    url: &str,
+    cookie: &str,
+    device: Option<&DeviceIds>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<String> {
+    let resp = proxy_fetch(client, build_papi_request(url, cookie, device), proxy).await?;
+
+    let status = resp.status;
+    let text = resp.body;
     console_log!("[papi] proxy status={} len={}", status, text.len());
 
     if status != 200 {
@@ -145,50 +279,47 @@ async fn papi_proxy_fetch(url: &str, cookie: &str, env: &Env) -> Result<String>
     Ok(text)
 }
 
-fn build_papi_headers(cookie: &str) -> Result<Headers> {
-    let headers = Headers::new();
-    headers.set("User-Agent", IG_MOBILE_UA)?;
-    headers.set("Accept", "*/*")?;
-    headers.set("Accept-Language", "en-US,en;q=0.9")?;
-    headers.set("X-Ig-App-Id", "567067343352427")?; // Instagram Android app ID
-    headers.set("Cookie", cookie)?;
-    Ok(headers)
+fn build_papi_request(url: &str, cookie: &str, device: Option<&DeviceIds>) -> HttpRequest {
+    let request = HttpRequest::get(url)
+        .header("User-Agent", IG_MOBILE_UA)
+        .header("Accept", "*/*")
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .header("X-Ig-App-Id", "567067343352427") // Instagram Android app ID
+        .header("Cookie", cookie);
+    match device {
+        Some(device) => device.apply(request),
+        None => request,
+    }
 }
 
-/// Parses a single media item from the PAPI response.
-fn parse_papi_item(item: &serde_json::Value, post_id: &str) -> Result<Option<InstaData>> {
-    let username = item
-        .get("user")
-        .and_then(|u| u.get("username"))
-        .and_then(|u| u.as_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    let caption = item
-        .get("caption")
-        .and_then(|c| c.get("text"))
-        .and_then(|t| t.as_str())
-        .map(String::from);
-
-    let like_count = item.get("like_count").and_then(|l| l.as_u64());
-    let comment_count = item.get("comment_count").and_then(|c| c.as_u64());
-    let timestamp = item.get("taken_at").and_then(|t| t.as_u64()).unwrap_or(0);
+/// Converts a single media item from the PAPI response into `InstaData`.
+pub(super) fn parse_papi_item(mut item: PapiItem, post_id: &str) -> Result<Option<InstaData>> {
+    let sensitive = item.is_sensitive();
+    let kind = item.product_type.as_deref().and_then(PostKind::from_product_type);
+    let stickers = parse_stickers(&mut item);
+    let user = item.user.unwrap_or_default();
+    let username = user.username.unwrap_or_else(|| "unknown".to_string());
+
+    let caption = item.caption.and_then(|c| c.text);
+
+    let top_comment = Comment::most_liked(item.preview_comments.into_iter().filter_map(|c| {
+        let comment = Comment {
+            id: c.pk?.into_string(),
+            username: c.user?.username?,
+            text: c.text?,
+        };
+        Some((comment, c.comment_like_count.unwrap_or(0)))
+    }));
+
+    let is_video = item.media.video_versions.is_some();
 
     // Check for carousel (multiple media items)
-    let media_items = if let Some(carousel) = item.get("carousel_media").and_then(|c| c.as_array()) {
-        carousel.iter().filter_map(|m| parse_papi_media(m)).collect()
-    } else {
-        // Single media item
-        match parse_papi_media(item) {
-            Some(m) => vec![m],
-            None => Vec::new(),
-        }
+    let media_items: Vec<Media> = match item.carousel_media {
+        Some(carousel) => carousel.into_iter().filter_map(parse_papi_media).collect(),
+        None => parse_papi_media(item.media).into_iter().collect(),
     };
 
-    let is_video = item.get("video_versions").is_some()
-        || media_items.iter().any(|m| m.media_type == MediaType::Video);
-
-    let video_view_count = item.get("view_count").and_then(|v| v.as_u64());
+    let is_video = is_video || media_items.iter().any(|m| m.media_type == MediaType::Video);
 
     console_log!("[papi] parsed: username={} media_count={} is_video={}", username, media_items.len(), is_video);
 
@@ -197,56 +328,181 @@ fn parse_papi_item(item: &serde_json::Value, post_id: &str) -> Result<Option<Ins
         username,
         caption,
         media: media_items,
-        like_count,
-        comment_count,
+        like_count: item.like_count,
+        comment_count: item.comment_count,
         is_video,
-        video_view_count,
-        timestamp,
+        video_view_count: item.view_count,
+        play_count: item.ig_play_count.or(item.play_count),
+        timestamp: item.taken_at.unwrap_or(0),
+        top_comment,
+        full_name: user.full_name.filter(|n| !n.is_empty()),
+        is_verified: user.is_verified,
+        avatar_url: user.profile_pic_url,
+        coauthors: coauthor_usernames(item.coauthor_producers),
+        sensitive,
+        kind,
+        stickers,
+        hashtag: None,
+        backend: None,
+        scraped_at: 0,
+        cached: false,
+        canary: false,
     }))
 }
 
-/// Parses a single media node from PAPI response format.
-fn parse_papi_media(node: &serde_json::Value) -> Option<Media> {
+/// Takes a story's poll, question and link stickers off the item. Stickers
+/// missing their text are skipped.
+fn parse_stickers(item: &mut PapiItem) -> Vec<Sticker> {
+    let polls = std::mem::take(&mut item.story_polls).into_iter().filter_map(|p| {
+        let poll = p.poll_sticker?;
+        let options = poll
+            .tallies
+            .into_iter()
+            .filter_map(|t| Some(PollOption { text: t.text?, votes: t.count }))
+            .collect();
+        Some(Sticker::Poll { question: poll.question.unwrap_or_default(), options })
+    });
+    let questions = std::mem::take(&mut item.story_questions)
+        .into_iter()
+        .filter_map(|q| Some(Sticker::Question { text: q.question_sticker?.question? }));
+    let links = std::mem::take(&mut item.story_link_stickers).into_iter().filter_map(|l| {
+        let link = l.story_link?;
+        Some(Sticker::Link { url: link.url?, label: link.display_url })
+    });
+    polls.chain(questions).chain(links).collect()
+}
+
+/// Converts a single media node from PAPI response format.
+fn parse_papi_media(node: PapiMedia) -> Option<Media> {
+    let alt_text = node.accessibility_caption.filter(|alt| !alt.trim().is_empty());
+    let best_image = node
+        .image_versions2
+        .and_then(|i| i.candidates.into_iter().next());
+
     // Video: video_versions array has URL
-    if let Some(video_versions) = node.get("video_versions").and_then(|v| v.as_array()) {
-        if let Some(best) = video_versions.first() {
-            let url = best.get("url").and_then(|u| u.as_str()).unwrap_or_default().to_string();
-            let width = best.get("width").and_then(|w| w.as_u64()).map(|w| w as u32);
-            let height = best.get("height").and_then(|h| h.as_u64()).map(|h| h as u32);
-            let thumbnail_url = node
-                .get("image_versions2")
-                .and_then(|i| i.get("candidates"))
-                .and_then(|c| c.as_array())
-                .and_then(|arr| arr.first())
-                .and_then(|img| img.get("url"))
-                .and_then(|u| u.as_str())
-                .map(String::from);
-            return Some(Media {
-                media_type: MediaType::Video,
-                url,
-                thumbnail_url,
-                width,
-                height,
-            });
-        }
+    if let Some(best) = node.video_versions.and_then(|v| v.into_iter().next()) {
+        return Some(Media {
+            media_type: MediaType::Video,
+            url: best.url.unwrap_or_default(),
+            thumbnail_url: best_image.and_then(|img| img.url),
+            width: best.width,
+            height: best.height,
+            alt_text,
+        });
     }
 
     // Image: image_versions2.candidates array
-    let candidates = node
-        .get("image_versions2")
-        .and_then(|i| i.get("candidates"))
-        .and_then(|c| c.as_array())?;
-
-    let best = candidates.first()?;
-    let url = best.get("url").and_then(|u| u.as_str()).unwrap_or_default().to_string();
-    let width = best.get("width").and_then(|w| w.as_u64()).map(|w| w as u32);
-    let height = best.get("height").and_then(|h| h.as_u64()).map(|h| h as u32);
+    let PapiVersion { url, width, height } = best_image?;
 
     Some(Media {
         media_type: MediaType::Image,
-        url,
+        url: url.unwrap_or_default(),
         thumbnail_url: None,
         width,
         height,
+        alt_text,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn ds_user_id_is_taken_from_session_id() {
+        assert_eq!(with_ds_user_id("sessionid=42:abc:1"), "sessionid=42:abc:1; ds_user_id=42");
+        assert_eq!(with_ds_user_id("csrftoken=x"), "csrftoken=x");
+    }
+
+    #[test]
+    fn cookie_check_requires_logged_in_user() {
+        let ok = FixtureClient::new().route("current_user", 200, r#"{"user":{"pk":42},"status":"ok"}"#);
+        assert!(block_on(check_cookie(&ok, "sessionid=42:abc")).unwrap());
+
+        let expired = FixtureClient::new()
+            .route("current_user", 200, r#"{"message":"login_required","status":"fail"}"#);
+        assert!(!block_on(check_cookie(&expired, "sessionid=42:abc")).unwrap());
+    }
+
+    #[test]
+    fn session_check_tells_challenges_from_logouts() {
+        let challenged = FixtureClient::new()
+            .route("current_user", 400, r#"{"message":"challenge_required","status":"fail"}"#);
+        let device = DeviceIds::from_bytes([7; 24]);
+        let status = block_on(check_session(&challenged, "sessionid=42:abc", Some(&device))).unwrap();
+        assert_eq!(status, SessionStatus::Challenge);
+        assert_eq!(challenged.requests.borrow()[0].header_value("X-Ig-Device-Id"), Some(device.device_id.as_str()));
+
+        let logged_out = FixtureClient::new().route("current_user", 302, "");
+        assert_eq!(block_on(check_session(&logged_out, "sessionid=42:abc", None)).unwrap(), SessionStatus::LoggedOut);
+    }
+
+    #[test]
+    fn comment_is_picked_by_id() {
+        let client = FixtureClient::new().route(
+            "/comments/",
+            200,
+            r#"{"comments":[
+                {"pk":"17900000000000001","text":"first!","user":{"username":"early"}},
+                {"pk":"17900000000000002","text":"this one","user":{"username":"target"}}
+            ]}"#,
+        );
+        let comment = block_on(fetch_comment(&client, "B", "17900000000000002", Some("sessionid=42:abc"), None, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(comment, Comment { id: "17900000000000002".into(), username: "target".into(), text: "this one".into() });
+        assert!(client.requested("target_comment_id=17900000000000002"));
+
+        let missing = block_on(fetch_comment(&client, "B", "1", Some("sessionid=42:abc"), None, None)).unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn story_item_is_picked_from_the_reel() {
+        let post_id = crate::utils::instagram::mediaid_to_code(3141592653589793300);
+        let client = FixtureClient::new().route(
+            "reels_media",
+            200,
+            r#"{"reels":{"1234567":{"items":[
+                {"pk":"3141592653589793299","product_type":"story","image_versions2":{"candidates":[{"url":"https://cdn.example.com/old.jpg"}]}},
+                {"pk":3141592653589793300,"product_type":"story","user":{"username":"catlover"},
+                 "image_versions2":{"candidates":[{"url":"https://cdn.example.com/s.jpg"}]}}
+            ]}}}"#,
+        );
+        let data = block_on(fetch_story_item(&client, &post_id, "1234567", Some("sessionid=42:abc"), None, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.media[0].url, "https://cdn.example.com/s.jpg");
+        assert!(client.requested("reel_ids=1234567"));
+
+        let gone = crate::utils::instagram::mediaid_to_code(1);
+        assert!(block_on(fetch_story_item(&client, &gone, "1234567", Some("sessionid=42:abc"), None, None)).unwrap().is_none());
+    }
+
+    #[test]
+    fn story_stickers_are_parsed() {
+        let body = r#"{"items":[{"user":{"username":"cat"},"product_type":"story",
+            "image_versions2":{"candidates":[{"url":"https://cdn.example.com/s.jpg"}]},
+            "story_polls":[{"poll_sticker":{"question":"Wet food?","tallies":[{"text":"Yes","count":9},{"text":"No","count":1}]}}],
+            "story_questions":[{"question_sticker":{"question":"Ask me anything"}},{"question_sticker":{}}],
+            "story_link_stickers":[{"story_link":{"url":"https://example.com/shop?ref=ig","display_url":"example.com/shop"}}]}]}"#;
+        let data = parse_papi_response(body, "S").unwrap().unwrap();
+        assert_eq!(data.kind, Some(PostKind::Story));
+        assert_eq!(
+            data.stickers,
+            vec![
+                Sticker::Poll {
+                    question: "Wet food?".into(),
+                    options: vec![
+                        PollOption { text: "Yes".into(), votes: Some(9) },
+                        PollOption { text: "No".into(), votes: Some(1) },
+                    ],
+                },
+                Sticker::Question { text: "Ask me anything".into() },
+                Sticker::Link { url: "https://example.com/shop?ref=ig".into(), label: Some("example.com/shop".into()) },
+            ]
+        );
+    }
+}
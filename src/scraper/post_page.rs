@@ -0,0 +1,121 @@
+use serde::de::DeserializeOwned;
+use worker::*;
+
+use super::embed_page::parse_shortcode_media;
+use super::http::{HttpClient, HttpRequest};
+use super::papi::parse_papi_item;
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::{LegacyResponse, PapiResponse, ShortcodeMedia};
+use super::types::InstaData;
+use crate::utils::log::console_log;
+
+const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+
+/// Key of the PAPI-shaped `{"items": [...]}` payload inside the page's
+/// `require` data.
+const WEB_INFO_KEY: &str = "\"xdt_api__v1__media__shortcode__web_info\":";
+
+/// Key of the GraphQL-shaped media object, on pages that still ship it.
+const SHORTCODE_MEDIA_KEY: &str = "\"xdt_shortcode_media\":";
+
+/// Older pages hand the post to `window.__additionalDataLoaded('/p/…/', {...})`.
+const ADDITIONAL_DATA_CALL: &str = "__additionalDataLoaded(";
+
+/// Fetches the full post page (`/p/{shortcode}/`) through the proxy and
+/// extracts the post from its inline JSON.
+///
+/// The page embeds the same data the web app renders from, in `<script
+/// type="application/json">` `require` payloads or, on older pages, an
+/// `__additionalDataLoaded` call. It often still has it when the embed
+/// endpoint is login-walled. Sends the session cookie when one is
+/// configured.
+pub async fn fetch_post_page<C: HttpClient>(
+    client: &C,
+    post_id: &str,
+    cookie: Option<&str>,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<InstaData>> {
+    let url = format!("https://www.instagram.com/p/{post_id}/");
+    let mut request = HttpRequest::get(&url)
+        .header("User-Agent", CHROME_UA)
+        .header("Accept", "text/html,application/xhtml+xml")
+        .header("Accept-Language", "en-US,en;q=0.9");
+    if let Some(cookie) = cookie {
+        request = request.header("Cookie", cookie);
+    }
+
+    let resp = proxy_fetch(client, request, proxy).await?;
+    console_log!("[post_page] status={} len={}", resp.status, resp.body.len());
+    if resp.status != 200 {
+        return Ok(None);
+    }
+    Ok(parse_post_page(&resp.body, post_id))
+}
+
+/// Tries each inline payload in turn, most complete first.
+pub(super) fn parse_post_page(html: &str, post_id: &str) -> Option<InstaData> {
+    if let Some(item) = json_after::<PapiResponse>(html, WEB_INFO_KEY).and_then(|r| r.items.into_iter().next()) {
+        return parse_papi_item(item, post_id).ok().flatten();
+    }
+    if let Some(media) = json_after::<ShortcodeMedia>(html, SHORTCODE_MEDIA_KEY) {
+        return parse_shortcode_media(media, post_id);
+    }
+    if let Some(resp) = additional_data(html) {
+        if let Some(media) = resp.graphql.and_then(|g| g.into_media()) {
+            return parse_shortcode_media(media, post_id);
+        }
+        if let Some(item) = resp.items.into_iter().next() {
+            return parse_papi_item(item, post_id).ok().flatten();
+        }
+    }
+    console_log!("[post_page] no post data in page");
+    None
+}
+
+/// Deserializes the JSON value at the start of `text`, ignoring whatever
+/// follows it (the rest of the page).
+fn leading_json<T: DeserializeOwned>(text: &str) -> Option<T> {
+    serde_json::Deserializer::from_str(text).into_iter::<T>().next()?.ok()
+}
+
+/// The JSON value right after the first occurrence of `marker`.
+fn json_after<T: DeserializeOwned>(text: &str, marker: &str) -> Option<T> {
+    let start = text.find(marker)? + marker.len();
+    leading_json(&text[start..])
+}
+
+/// The object passed to `__additionalDataLoaded`, after its path argument.
+fn additional_data(html: &str) -> Option<LegacyResponse> {
+    let args = &html[html.find(ADDITIONAL_DATA_CALL)? + ADDITIONAL_DATA_CALL.len()..];
+    leading_json(&args[args.find('{')?..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_require_payload() {
+        let data = parse_post_page(include_str!("../../fixtures/post_page.html"), "CxPage1").unwrap();
+        assert_eq!(data.username, "catlover");
+        assert_eq!(data.caption.as_deref(), Some("Window watch"));
+        assert_eq!(data.media.len(), 1);
+        assert_eq!(data.media[0].url, "https://scontent.cdninstagram.com/o1/v/t16/page.mp4");
+        assert_eq!(data.like_count, Some(812));
+    }
+
+    #[test]
+    fn reads_additional_data_loaded() {
+        let html = r#"<script>window.__additionalDataLoaded('/p/CxOld1/',{"graphql":{"shortcode_media":
+            {"owner":{"username":"oldcat"},"display_url":"https://cdn.example.com/old.jpg"}}});</script>"#;
+        let data = parse_post_page(html, "CxOld1").unwrap();
+        assert_eq!(data.username, "oldcat");
+        assert_eq!(data.media[0].url, "https://cdn.example.com/old.jpg");
+    }
+
+    #[test]
+    fn login_wall_yields_none() {
+        assert!(parse_post_page("<html><body>Log in to Instagram</body></html>", "CxPage1").is_none());
+    }
+}
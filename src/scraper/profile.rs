@@ -0,0 +1,131 @@
+use worker::*;
+
+use super::http::{HttpClient, HttpRequest};
+use super::proxy::{proxy_fetch, ProxyCredentials};
+use super::schema::{ProfileUser, WebProfileInfoResponse};
+use super::types::{Profile, ProfilePost};
+use crate::utils::log::console_log;
+
+const CHROME_UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+/// Instagram web app ID; `web_profile_info` answers anonymous requests carrying it.
+const IG_APP_ID: &str = "936619743392459";
+
+/// Fetches a user's profile from `api/v1/users/web_profile_info/`.
+///
+/// Works without a session from many IPs. Tries direct fetch first, then
+/// falls back to the proxy.
+pub async fn fetch_profile<C: HttpClient>(
+    client: &C,
+    username: &str,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<Option<Profile>> {
+    let url = format!("https://i.instagram.com/api/v1/users/web_profile_info/?username={username}");
+    let request = HttpRequest::get(&url)
+        .header("User-Agent", CHROME_UA)
+        .header("Accept", "*/*")
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .header("X-Ig-App-Id", IG_APP_ID);
+
+    console_log!("[profile] trying direct fetch for {}", username);
+    match client.send(request.clone()).await {
+        Ok(resp) if resp.status == 200 => {
+            if let Some(profile) = parse_profile_response(&resp.body) {
+                return Ok(Some(profile));
+            }
+        }
+        Ok(resp) => console_log!("[profile] direct status={}", resp.status),
+        Err(e) => console_log!("[profile] direct fetch error: {:?}", e),
+    }
+
+    console_log!("[profile] trying via proxy");
+    let resp = proxy_fetch(client, request, proxy).await?;
+    console_log!("[profile] proxy status={} len={}", resp.status, resp.body.len());
+    if resp.status != 200 {
+        return Ok(None);
+    }
+    Ok(parse_profile_response(&resp.body))
+}
+
+pub(super) fn parse_profile_response(text: &str) -> Option<Profile> {
+    let resp: WebProfileInfoResponse = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            console_log!("[profile] JSON parse error: {}", e);
+            return None;
+        }
+    };
+
+    let Some(user) = resp.data.and_then(|d| d.user) else {
+        console_log!("[profile] user object is null or missing");
+        return None;
+    };
+    parse_profile_user(user)
+}
+
+/// Converts a `web_profile_info` user into a `Profile`.
+///
+/// Returns `None` if the username or numeric ID is missing.
+fn parse_profile_user(user: ProfileUser) -> Option<Profile> {
+    let timeline = user.edge_owner_to_timeline_media;
+    let post_count = timeline.as_ref().and_then(|t| t.count);
+    let recent_posts = timeline
+        .map(|t| t.edges)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|edge| {
+            let post = edge.node;
+            Some(ProfilePost {
+                shortcode: post.shortcode?,
+                thumbnail_url: post.thumbnail_src.or(post.display_url)?,
+                is_video: post.is_video.unwrap_or(false),
+                timestamp: post.taken_at_timestamp.unwrap_or(0),
+            })
+        })
+        .collect();
+
+    Some(Profile {
+        pk: user.id?.into_string(),
+        username: user.username?,
+        full_name: user.full_name.filter(|n| !n.is_empty()),
+        biography: user.biography.filter(|b| !b.is_empty()),
+        avatar_url: user.profile_pic_url_hd.or(user.profile_pic_url),
+        is_private: user.is_private,
+        is_verified: user.is_verified,
+        follower_count: user.edge_followed_by.and_then(|c| c.count),
+        following_count: user.edge_follow.and_then(|c| c.count),
+        post_count,
+        recent_posts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn parses_web_profile_info() {
+        let client = FixtureClient::new().route("web_profile_info", 200, include_str!("../../fixtures/web_profile_info.json"));
+        let profile = block_on(fetch_profile(&client, "catlover", None)).unwrap().unwrap();
+
+        assert_eq!(profile.pk, "1234567");
+        assert_eq!(profile.full_name.as_deref(), Some("Cat Lover"));
+        assert_eq!(profile.avatar_url.as_deref(), Some("https://scontent.cdninstagram.com/v/t51/avatar_320.jpg"));
+        assert_eq!(profile.follower_count, Some(48213));
+        assert_eq!(profile.post_count, Some(873));
+        assert_eq!(profile.recent_posts.len(), 2);
+        assert_eq!(profile.recent_posts[0].shortcode, "CxReel123");
+        assert!(profile.recent_posts[0].thumbnail_url.ends_with("reel_cover_640.jpg"));
+
+        let requests = client.requests.borrow();
+        assert_eq!(requests[0].header_value("x-ig-app-id"), Some(IG_APP_ID));
+    }
+
+    #[test]
+    fn missing_user_falls_back_to_proxy_then_none() {
+        let client = FixtureClient::new().route("web_profile_info", 200, r#"{"data":{"user":null},"status":"ok"}"#);
+        assert!(block_on(fetch_profile(&client, "nobody", None)).unwrap().is_none());
+        assert_eq!(client.requests.borrow().len(), 2);
+    }
+}
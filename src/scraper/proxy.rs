@@ -1,58 +1,70 @@
 use worker::*;
 
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::utils::log::console_log;
+
+/// Bright Data credentials, loaded by `Config::from_env`.
+#[derive(Debug, Clone)]
+pub struct ProxyCredentials {
+    /// `PROXY_USERNAME` (e.g. "brd-customer-XXX-zone-ZONE_NAME")
+    pub username: String,
+    /// `PROXY_PASSWORD`: Bright Data API token
+    pub password: String,
+    /// Country the request exits from, as a lowercase ISO code.
+    pub country: String,
+}
+
+/// Exit country used unless a geo-block retry picks another.
+pub const DEFAULT_COUNTRY: &str = "us";
+
+/// Bright Data's REST API, which every proxied request is sent to.
+const PROXY_ENDPOINT: &str = "https://api.brightdata.com/request";
+
 /// Makes a fetch request through a residential proxy if configured.
 ///
-/// Expects these env secrets:
-/// - PROXY_HOST: proxy hostname (e.g. "brd.superproxy.io")
-/// - PROXY_PORT: proxy port (e.g. "22225")
-/// - PROXY_USERNAME: proxy username
-/// - PROXY_PASSWORD: proxy password
-///
 /// Since CF Workers can't use HTTP CONNECT proxies for HTTPS targets,
 /// this uses Bright Data's REST API at api.brightdata.com/request
 /// with the zone name extracted from the proxy username.
 ///
-/// If secrets are not set, falls back to direct fetch.
-pub async fn proxy_fetch(
-    target_url: &str,
-    method: Method,
-    headers: Headers,
-    body: Option<String>,
-    env: &Env,
-) -> Result<worker::Response> {
-    let username = env.secret("PROXY_USERNAME").ok().map(|s| s.to_string());
-    let password = env.secret("PROXY_PASSWORD").ok().map(|s| s.to_string());
-
-    match (username, password) {
-        (Some(user), Some(pass)) => {
-            residential_proxy_fetch(target_url, method, headers, body, &user, &pass).await
-        }
-        _ => {
+/// If no credentials are given, falls back to direct fetch.
+pub async fn proxy_fetch<C: HttpClient>(
+    client: &C,
+    request: HttpRequest,
+    proxy: Option<&ProxyCredentials>,
+) -> Result<HttpResponse> {
+    match proxy {
+        Some(creds) => residential_proxy_fetch(client, request, creds).await,
+        None => {
             console_log!("[proxy] no proxy config, fetching directly");
-            direct_fetch(target_url, method, headers, body).await
+            client.send(request).await
         }
     }
 }
 
+/// Checks the credentials by fetching Instagram's robots.txt through the proxy.
+pub async fn check_proxy<C: HttpClient>(client: &C, creds: &ProxyCredentials) -> Result<bool> {
+    let request = HttpRequest::get("https://www.instagram.com/robots.txt");
+    let resp = residential_proxy_fetch(client, request, creds).await?;
+    console_log!("[proxy] credential check status={}", resp.status);
+    Ok(resp.status == 200)
+}
+
 /// Fetch via residential proxy using Bright Data's REST API.
 ///
 /// Extracts the zone name from the proxy username (format: brd-customer-XXX-zone-ZONE_NAME)
 /// and uses it with the REST API at api.brightdata.com/request.
-async fn residential_proxy_fetch(
-    target_url: &str,
-    method: Method,
-    original_headers: Headers,
-    body: Option<String>,
-    username: &str,
-    password: &str,
-) -> Result<worker::Response> {
-    console_log!("[proxy] routing through residential proxy: {}", target_url);
+async fn residential_proxy_fetch<C: HttpClient>(
+    client: &C,
+    request: HttpRequest,
+    creds: &ProxyCredentials,
+) -> Result<HttpResponse> {
+    console_log!("[proxy] routing through residential proxy: {}", request.url);
 
     // Extract zone name from username (brd-customer-XXX-zone-ZONE_NAME or just use as-is)
-    let zone = extract_zone(username).unwrap_or_else(|| "residential".to_string());
+    let zone = extract_zone(&creds.username).unwrap_or_else(|| "residential".to_string());
     console_log!("[proxy] using zone: {}", zone);
 
-    let method_str = match method {
+    let method_str = match request.method {
         Method::Get => "GET",
         Method::Post => "POST",
         _ => "GET",
@@ -63,31 +75,31 @@ async fn residential_proxy_fetch(
     let forward_keys = [
         "User-Agent", "Accept", "Accept-Language", "Cookie",
         "Content-Type", "Origin", "Referer",
-        "X-Ig-App-Id", "X-Fb-Lsd", "X-Asbd-Id", "X-Fb-Friendly-Name",
+        "X-Ig-App-Id", "X-Ig-Device-Id", "X-Ig-Android-Id", "X-Fb-Lsd", "X-Asbd-Id", "X-Fb-Friendly-Name",
         "X-Requested-With",
         "Sec-Fetch-Dest", "Sec-Fetch-Mode", "Sec-Fetch-Site",
         "Sec-Ch-Ua", "Sec-Ch-Ua-Mobile", "Sec-Ch-Ua-Platform",
     ];
     for key in &forward_keys {
-        if let Ok(Some(val)) = original_headers.get(key) {
-            proxy_headers.insert(key.to_string(), serde_json::Value::String(val));
+        if let Some(val) = request.header_value(key) {
+            proxy_headers.insert(key.to_string(), serde_json::Value::String(val.to_string()));
         }
     }
 
     let mut payload = serde_json::json!({
         "zone": zone,
-        "url": target_url,
+        "url": request.url,
         "format": "raw",
         "method": method_str,
-        "country": "us",
+        "country": creds.country,
     });
 
     if !proxy_headers.is_empty() {
         payload["headers"] = serde_json::Value::Object(proxy_headers);
     }
 
-    if let Some(ref b) = body {
-        payload["body"] = serde_json::Value::String(b.clone());
+    if let Some(b) = request.body {
+        payload["body"] = serde_json::Value::String(b);
     }
 
     let payload_str = serde_json::to_string(&payload)
@@ -96,25 +108,28 @@ async fn residential_proxy_fetch(
     console_log!("[proxy] payload: {}", &payload_str[..payload_str.len().min(300)]);
 
     // REST API at api.brightdata.com/request always uses Bearer token
-    let auth_header = format!("Bearer {}", password);
-    console_log!("[proxy] auth: Bearer {}...", &password[..password.len().min(10)]);
-
-    let headers = Headers::new();
-    headers.set("Authorization", &auth_header)?;
-    headers.set("Content-Type", "application/json")?;
+    console_log!("[proxy] auth: Bearer {}...", &creds.password[..creds.password.len().min(10)]);
 
-    let mut init = RequestInit::new();
-    init.with_method(Method::Post)
-        .with_headers(headers)
-        .with_body(Some(payload_str.into()));
+    let proxy_request = HttpRequest::post(PROXY_ENDPOINT, payload_str)
+        .header("Authorization", format!("Bearer {}", creds.password))
+        .header("Content-Type", "application/json");
 
-    let request = Request::new_with_init("https://api.brightdata.com/request", &init)?;
-    let resp = Fetch::Request(request).send().await?;
+    let resp = client.send(proxy_request).await?;
 
-    console_log!("[proxy] response status={}", resp.status_code());
+    console_log!("[proxy] response status={}", resp.status);
     Ok(resp)
 }
 
+/// The URL a request made by `residential_proxy_fetch` fetches through the
+/// proxy, or `None` for any other request.
+pub fn proxied_url(request: &HttpRequest) -> Option<String> {
+    if request.url != PROXY_ENDPOINT {
+        return None;
+    }
+    let payload: serde_json::Value = serde_json::from_str(request.body.as_deref()?).ok()?;
+    payload["url"].as_str().map(str::to_string)
+}
+
 /// Extract zone name from Bright Data proxy username.
 /// Format: "brd-customer-XXXXX-zone-ZONE_NAME" or "brd-customer-XXXXX-zone-ZONE_NAME-..."
 fn extract_zone(username: &str) -> Option<String> {
@@ -131,45 +146,3 @@ fn extract_zone(username: &str) -> Option<String> {
         Some(zone.to_string())
     }
 }
-
-/// Simple base64 encoding for Basic auth.
-fn base64_encode(input: &[u8]) -> String {
-    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let mut result = String::new();
-    for chunk in input.chunks(3) {
-        let b0 = chunk[0] as u32;
-        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
-        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
-        let triple = (b0 << 16) | (b1 << 8) | b2;
-        result.push(CHARS[((triple >> 18) & 0x3F) as usize] as char);
-        result.push(CHARS[((triple >> 12) & 0x3F) as usize] as char);
-        if chunk.len() > 1 {
-            result.push(CHARS[((triple >> 6) & 0x3F) as usize] as char);
-        } else {
-            result.push('=');
-        }
-        if chunk.len() > 2 {
-            result.push(CHARS[(triple & 0x3F) as usize] as char);
-        } else {
-            result.push('=');
-        }
-    }
-    result
-}
-
-/// Direct fetch without proxy.
-async fn direct_fetch(
-    target_url: &str,
-    method: Method,
-    headers: Headers,
-    body: Option<String>,
-) -> Result<worker::Response> {
-    let mut init = RequestInit::new();
-    init.with_method(method).with_headers(headers);
-    if let Some(b) = body {
-        init.with_body(Some(b.into()));
-    }
-
-    let request = Request::new_with_init(target_url, &init)?;
-    Fetch::Request(request).send().await
-}
@@ -0,0 +1,155 @@
+//! Global backoff after Instagram rate-limits us.
+//!
+//! A 429, or a "Please wait a few minutes" error page, from any backend starts a
+//! cooldown shared by every isolate through KV. Until it ends, live scrapes
+//! are skipped and requests are served from cache or fall back to Instagram,
+//! rather than hammering the endpoints and prolonging the block. Each hit
+//! shortly after the last cooldown doubles the next one.
+
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use worker::Result;
+
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::utils::log::console_log;
+
+/// Length of the first cooldown.
+const BASE_COOLDOWN_SECS: u64 = 60;
+
+/// Cap on an escalated cooldown.
+const MAX_COOLDOWN_SECS: u64 = 30 * 60;
+
+/// A hit within this long after the previous cooldown ended counts as a
+/// repeat offence and escalates.
+const ESCALATION_WINDOW_SECS: u64 = 60 * 60;
+
+/// The global cooldown, stored in KV as one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Cooldown {
+    /// Unix time (seconds) the cooldown ends.
+    pub until: u64,
+    /// Consecutive rate-limit hits, 1 for the first.
+    pub strikes: u32,
+}
+
+impl Cooldown {
+    pub fn active(&self, now_secs: u64) -> bool {
+        now_secs < self.until
+    }
+
+    /// The cooldown following a new hit, doubling per consecutive strike.
+    pub fn after_hit(previous: Option<Cooldown>, now_secs: u64) -> Self {
+        let strikes = match previous {
+            Some(p) if now_secs < p.until + ESCALATION_WINDOW_SECS => p.strikes + 1,
+            _ => 1,
+        };
+        let secs = BASE_COOLDOWN_SECS
+            .saturating_mul(1 << (strikes - 1).min(16))
+            .min(MAX_COOLDOWN_SECS);
+        Self { until: now_secs + secs, strikes }
+    }
+
+    /// KV expiration: kept through the escalation window, so strikes carry over.
+    pub fn ttl(&self, now_secs: u64) -> u64 {
+        self.until.saturating_sub(now_secs) + ESCALATION_WINDOW_SECS
+    }
+}
+
+/// Returns `true` if Instagram answered with a rate-limit response.
+///
+/// The wait page only counts as an error page: a successful or JSON response
+/// may carry the phrase in a caption or comment.
+pub fn is_rate_limited(resp: &HttpResponse) -> bool {
+    let json = matches!(resp.body.trim_start().as_bytes().first(), Some(b'{' | b'['));
+    resp.status == 429 || (resp.status != 200 && !json && resp.body.contains("Please wait a few minutes"))
+}
+
+/// Wraps a client and notes whether any response was a rate-limit response.
+pub struct RateLimitWatch<'a, C> {
+    inner: &'a C,
+    hit: Cell<bool>,
+}
+
+impl<'a, C: HttpClient> RateLimitWatch<'a, C> {
+    pub fn new(inner: &'a C) -> Self {
+        Self { inner, hit: Cell::new(false) }
+    }
+
+    pub fn hit(&self) -> bool {
+        self.hit.get()
+    }
+}
+
+impl<C: HttpClient> HttpClient for RateLimitWatch<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let resp = self.inner.send(request).await?;
+        if is_rate_limited(&resp) {
+            console_log!("[ratelimit] rate-limited (status={})", resp.status);
+            self.hit.set(true);
+        }
+        Ok(resp)
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+    use crate::scraper::scrape_post;
+
+    #[test]
+    fn consecutive_hits_double_the_cooldown() {
+        let first = Cooldown::after_hit(None, 1000);
+        assert_eq!(first, Cooldown { until: 1060, strikes: 1 });
+        assert!(first.active(1059));
+        assert!(!first.active(1060));
+
+        let second = Cooldown::after_hit(Some(first), 1100);
+        assert_eq!(second, Cooldown { until: 1220, strikes: 2 });
+
+        let capped = Cooldown::after_hit(Some(Cooldown { until: 1000, strikes: 40 }), 1000);
+        assert_eq!(capped.until, 1000 + MAX_COOLDOWN_SECS);
+    }
+
+    #[test]
+    fn strikes_reset_after_a_quiet_hour() {
+        let old = Cooldown { until: 1000, strikes: 5 };
+        assert_eq!(Cooldown::after_hit(Some(old), 1000 + ESCALATION_WINDOW_SECS).strikes, 1);
+        assert_eq!(old.ttl(900), 100 + ESCALATION_WINDOW_SECS);
+    }
+
+    #[test]
+    fn watch_spots_429s_and_wait_pages() {
+        let client = FixtureClient::new().route("/embed/captioned/", 429, "");
+        let watch = RateLimitWatch::new(&client);
+        assert!(block_on(scrape_post(&watch, "CxReel123", &Config::default())).is_none());
+        assert!(watch.hit());
+
+        let client = FixtureClient::new().route("/api/graphql", 400, "Please wait a few minutes before you try again.");
+        let watch = RateLimitWatch::new(&client);
+        block_on(scrape_post(&watch, "CxReel123", &Config::default()));
+        assert!(watch.hit());
+
+        let client = FixtureClient::new();
+        let watch = RateLimitWatch::new(&client);
+        block_on(scrape_post(&watch, "CxReel123", &Config::default()));
+        assert!(!watch.hit());
+    }
+
+    #[test]
+    fn the_wait_phrase_in_post_data_is_not_a_rate_limit() {
+        let page = |status, body: &str| HttpResponse { status, body: body.to_string() };
+        let caption = r#"{"caption":"Please wait a few minutes for the zoomies"}"#;
+        assert!(!is_rate_limited(&page(200, caption)));
+        assert!(!is_rate_limited(&page(400, caption)));
+        assert!(!is_rate_limited(&page(200, "<p>Please wait a few minutes</p>")));
+        assert!(is_rate_limited(&page(400, "<p>Please wait a few minutes</p>")));
+        assert!(is_rate_limited(&page(429, caption)));
+    }
+}
@@ -0,0 +1,504 @@
+//! Typed shapes of the JSON returned by Instagram's embed page, GraphQL and
+//! Private API endpoints.
+//!
+//! Everything is `#[serde(default)]` and nullable fields are `Option`s, so a
+//! missing or `null` field degrades to an empty value instead of failing the
+//! whole parse. Only the fields we actually use are declared.
+
+use serde::Deserialize;
+
+/// `{"count": N}` wrapper used for likes and comments.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Count {
+    pub count: Option<u64>,
+}
+
+/// `{"edges": [{"node": ...}]}` connection wrapper.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EdgeList<T> {
+    pub edges: Vec<Edge<T>>,
+}
+
+impl<T> Default for EdgeList<T> {
+    fn default() -> Self {
+        Self { edges: Vec::new() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Edge<T> {
+    pub node: T,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Owner {
+    pub username: Option<String>,
+    pub full_name: Option<String>,
+    pub is_verified: bool,
+    pub profile_pic_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CaptionNode {
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Dimensions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// A node of `edge_media_to_parent_comment`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CommentNode {
+    pub id: Option<Id>,
+    pub text: Option<String>,
+    pub owner: Option<Owner>,
+    pub edge_liked_by: Option<Count>,
+}
+
+/// A single image or video node (a carousel child, or the post itself).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MediaNode {
+    /// `null` on some responses and missing on others; see `is_video()`.
+    pub is_video: Option<bool>,
+    pub display_url: Option<String>,
+    pub video_url: Option<String>,
+    pub dimensions: Option<Dimensions>,
+    /// Alt text, written by the author or generated by Instagram.
+    pub accessibility_caption: Option<String>,
+}
+
+impl MediaNode {
+    /// The node's `is_video` flag. When Instagram sends it as `null` or
+    /// leaves it out, a video URL is what makes the node a video.
+    pub fn is_video(&self) -> bool {
+        self.is_video.unwrap_or(self.video_url.is_some())
+    }
+}
+
+/// The `shortcode_media` / `xdt_shortcode_media` object.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ShortcodeMedia {
+    pub owner: Option<Owner>,
+    pub edge_media_to_caption: Option<EdgeList<CaptionNode>>,
+    pub taken_at_timestamp: Option<u64>,
+    pub edge_media_preview_like: Option<Count>,
+    pub edge_media_to_comment: Option<Count>,
+    /// Where `xdt_shortcode_media` puts the comment count, in place of
+    /// `edge_media_to_comment`.
+    pub edge_media_preview_comment: Option<Count>,
+    pub edge_media_to_parent_comment: Option<EdgeList<CommentNode>>,
+    pub video_view_count: Option<u64>,
+    pub video_play_count: Option<u64>,
+    pub edge_sidecar_to_children: Option<EdgeList<MediaNode>>,
+    pub sharing_friction_info: Option<SharingFrictionInfo>,
+    pub media_overlay_info: Option<MediaOverlayInfo>,
+    pub product_type: Option<String>,
+    /// A collab post's co-authors, besides `owner`.
+    pub coauthor_producers: Option<Vec<Owner>>,
+    #[serde(flatten)]
+    pub node: MediaNode,
+}
+
+impl ShortcodeMedia {
+    pub fn is_sensitive(&self) -> bool {
+        is_sensitive(self.sharing_friction_info.as_ref(), self.media_overlay_info.as_ref())
+    }
+}
+
+/// `sharing_friction_info`: set on posts shown behind a warning.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SharingFrictionInfo {
+    pub should_have_sharing_friction: bool,
+}
+
+/// `media_overlay_info`: the "Sensitive content" cover. `null` on ordinary posts.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MediaOverlayInfo {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Either marker means Instagram covers the media until the viewer opts in.
+fn is_sensitive(friction: Option<&SharingFrictionInfo>, overlay: Option<&MediaOverlayInfo>) -> bool {
+    friction.is_some_and(|f| f.should_have_sharing_friction) || overlay.is_some()
+}
+
+/// The usernames of a collab post's `coauthor_producers`.
+pub fn coauthor_usernames(producers: Option<Vec<Owner>>) -> Vec<String> {
+    producers.unwrap_or_default().into_iter().filter_map(|owner| owner.username).collect()
+}
+
+/// Object holding either media key; GraphQL and the embed `contextJSON`
+/// (under `gql_data`) both use this shape.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ShortcodeMediaHolder {
+    pub xdt_shortcode_media: Option<ShortcodeMedia>,
+    pub shortcode_media: Option<ShortcodeMedia>,
+}
+
+impl ShortcodeMediaHolder {
+    /// Returns whichever media key is present, preferring `xdt_shortcode_media`.
+    pub fn into_media(self) -> Option<ShortcodeMedia> {
+        self.xdt_shortcode_media.or(self.shortcode_media)
+    }
+}
+
+/// Top-level GraphQL response: `{"data": {"xdt_shortcode_media": ...}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GraphqlResponse {
+    pub data: Option<ShortcodeMediaHolder>,
+}
+
+/// Decoded embed page `contextJSON`: `{"gql_data": {"shortcode_media": ...}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ContextJson {
+    pub gql_data: Option<ShortcodeMediaHolder>,
+}
+
+/// Legacy `/p/{shortcode}/?__a=1&__d=dis` response, in either of its shapes.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LegacyResponse {
+    pub graphql: Option<ShortcodeMediaHolder>,
+    pub items: Vec<PapiItem>,
+}
+
+/// Top-level PAPI `media/{id}/info/` response.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiResponse {
+    pub items: Vec<PapiItem>,
+}
+
+/// Top-level PAPI `feed/reels_media/` response: each requested user's
+/// current stories, keyed by user ID.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiReelsMediaResponse {
+    pub reels: std::collections::HashMap<String, PapiReel>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiReel {
+    pub items: Vec<PapiItem>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiItem {
+    /// The numeric media ID.
+    pub pk: Option<Id>,
+    pub user: Option<Owner>,
+    pub caption: Option<CaptionNode>,
+    pub like_count: Option<u64>,
+    pub comment_count: Option<u64>,
+    pub taken_at: Option<u64>,
+    pub view_count: Option<u64>,
+    pub play_count: Option<u64>,
+    /// Instagram's own plays; `play_count` may include Facebook's.
+    pub ig_play_count: Option<u64>,
+    pub preview_comments: Vec<PapiComment>,
+    pub carousel_media: Option<Vec<PapiMedia>>,
+    pub sharing_friction_info: Option<SharingFrictionInfo>,
+    pub media_overlay_info: Option<MediaOverlayInfo>,
+    pub product_type: Option<String>,
+    /// A collab post's co-authors, besides `user`.
+    pub coauthor_producers: Option<Vec<Owner>>,
+    pub story_polls: Vec<PapiPoll>,
+    pub story_questions: Vec<PapiQuestion>,
+    pub story_link_stickers: Vec<PapiLinkSticker>,
+    #[serde(flatten)]
+    pub media: PapiMedia,
+}
+
+impl PapiItem {
+    pub fn is_sensitive(&self) -> bool {
+        is_sensitive(self.sharing_friction_info.as_ref(), self.media_overlay_info.as_ref())
+    }
+}
+
+/// A `story_polls` entry.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiPoll {
+    pub poll_sticker: Option<PollSticker>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PollSticker {
+    pub question: Option<String>,
+    pub tallies: Vec<PollTally>,
+}
+
+/// One poll option; `count` is only sent once the viewer could see results.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PollTally {
+    pub text: Option<String>,
+    pub count: Option<u64>,
+}
+
+/// A `story_questions` entry (the "Ask me a question" box).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiQuestion {
+    pub question_sticker: Option<QuestionSticker>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct QuestionSticker {
+    pub question: Option<String>,
+}
+
+/// A `story_link_stickers` entry.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiLinkSticker {
+    pub story_link: Option<StoryLink>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StoryLink {
+    pub url: Option<String>,
+    /// The text the sticker shows, e.g. `example.com/shop`.
+    pub display_url: Option<String>,
+}
+
+/// A PAPI media node (the item itself, or a `carousel_media` entry).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiMedia {
+    pub video_versions: Option<Vec<PapiVersion>>,
+    pub image_versions2: Option<PapiImageVersions>,
+    pub accessibility_caption: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiImageVersions {
+    pub candidates: Vec<PapiVersion>,
+}
+
+/// One rendition of an image or video; the first entry is the largest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiVersion {
+    pub url: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Top-level PAPI `media/{id}/comments/` response.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiCommentsResponse {
+    pub comments: Vec<PapiComment>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PapiComment {
+    pub pk: Option<Id>,
+    pub text: Option<String>,
+    pub user: Option<Owner>,
+    pub comment_like_count: Option<u64>,
+}
+
+/// Top-level `users/web_profile_info/` response: `{"data": {"user": ...}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WebProfileInfoResponse {
+    pub data: Option<WebProfileData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WebProfileData {
+    pub user: Option<ProfileUser>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileUser {
+    pub id: Option<Id>,
+    pub username: Option<String>,
+    pub full_name: Option<String>,
+    pub biography: Option<String>,
+    pub profile_pic_url: Option<String>,
+    pub profile_pic_url_hd: Option<String>,
+    pub is_private: bool,
+    pub is_verified: bool,
+    pub edge_followed_by: Option<Count>,
+    pub edge_follow: Option<Count>,
+    pub edge_owner_to_timeline_media: Option<TimelineMedia>,
+}
+
+/// The profile's post grid: total count plus the first page of posts.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TimelineMedia {
+    pub count: Option<u64>,
+    pub edges: Vec<Edge<TimelinePost>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TimelinePost {
+    pub shortcode: Option<String>,
+    pub display_url: Option<String>,
+    pub thumbnail_src: Option<String>,
+    /// `null` or missing means a photo.
+    pub is_video: Option<bool>,
+    pub taken_at_timestamp: Option<u64>,
+}
+
+/// `api/v1/tags/web_info/` response: a hashtag page's post grids.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagWebInfoResponse {
+    pub data: Option<TagData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagData {
+    pub top: Option<TagGrid>,
+    pub recent: Option<TagGrid>,
+}
+
+/// One of a hashtag page's grids, in rows ("sections") of posts.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagGrid {
+    pub sections: Vec<TagSection>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagSection {
+    pub layout_content: Option<TagLayout>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagLayout {
+    pub medias: Vec<TagMediaItem>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagMediaItem {
+    pub media: Option<TagMedia>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagMedia {
+    pub code: Option<String>,
+}
+
+/// A numeric ID, which Instagram sends as either a string or a number.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    String(String),
+    Number(u64),
+}
+
+impl Id {
+    pub fn into_string(self) -> String {
+        match self {
+            Id::String(s) => s,
+            Id::Number(n) => n.to_string(),
+        }
+    }
+
+    pub fn matches(&self, id: &str) -> bool {
+        match self {
+            Id::String(s) => s == id,
+            Id::Number(n) => n.to_string() == id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcode_media_tolerates_missing_and_null_fields() {
+        let media: ShortcodeMedia = serde_json::from_str(
+            r#"{"owner":{"username":"cat"},"video_url":null,"edge_media_preview_like":null}"#,
+        )
+        .unwrap();
+        assert_eq!(media.owner.unwrap().username.as_deref(), Some("cat"));
+        assert!(!media.node.is_video());
+        assert!(media.node.video_url.is_none());
+        assert!(media.edge_media_preview_like.is_none());
+    }
+
+    #[test]
+    fn null_is_video_defaults_from_the_video_url() {
+        let photo: MediaNode = serde_json::from_str(r#"{"is_video":null,"display_url":"a.jpg"}"#).unwrap();
+        assert!(!photo.is_video());
+
+        let video: MediaNode = serde_json::from_str(r#"{"is_video":null,"video_url":"v.mp4"}"#).unwrap();
+        assert!(video.is_video());
+
+        let flagged: MediaNode = serde_json::from_str(r#"{"is_video":false,"video_url":"v.mp4"}"#).unwrap();
+        assert!(!flagged.is_video());
+
+        let post: TimelinePost = serde_json::from_str(r#"{"shortcode":"CxA","is_video":null}"#).unwrap();
+        assert_eq!(post.is_video, None);
+    }
+
+    #[test]
+    fn graphql_null_media_is_none() {
+        let resp: GraphqlResponse =
+            serde_json::from_str(r#"{"data":{"xdt_shortcode_media":null},"status":"ok"}"#).unwrap();
+        assert!(resp.data.unwrap().into_media().is_none());
+    }
+
+    #[test]
+    fn papi_item_flattens_media_fields() {
+        let resp: PapiResponse = serde_json::from_str(
+            r#"{"items":[{"user":{"username":"cat"},"video_versions":[{"url":"v.mp4","width":720,"height":1280}]}]}"#,
+        )
+        .unwrap();
+        let item = &resp.items[0];
+        let versions = item.media.video_versions.as_ref().unwrap();
+        assert_eq!(versions[0].url.as_deref(), Some("v.mp4"));
+        assert_eq!(versions[0].height, Some(1280));
+    }
+
+    #[test]
+    fn comment_ids_match_as_string_or_number() {
+        let resp: PapiCommentsResponse = serde_json::from_str(
+            r#"{"comments":[{"pk":"17900000000000001","text":"a"},{"pk":17900000000000002,"text":"b"}]}"#,
+        )
+        .unwrap();
+        assert!(resp.comments[0].pk.as_ref().unwrap().matches("17900000000000001"));
+        assert!(resp.comments[1].pk.as_ref().unwrap().matches("17900000000000002"));
+        assert!(!resp.comments[1].pk.as_ref().unwrap().matches("1"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Health of the Instagram session behind `IG_COOKIE`.
+//!
+//! An account that Instagram sends to a challenge or checkpoint keeps
+//! failing every cookie-bearing request until someone clears it in the app,
+//! and hammering it meanwhile tends to get it locked for good. Responses are
+//! watched for those markers so the session can be benched (see
+//! `crate::session`) instead of retried on every scrape.
+
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use worker::Result;
+
+use super::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::utils::log::console_log;
+
+/// What Instagram last said about the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Active,
+    /// Logged out: the cookie has to be replaced.
+    LoggedOut,
+    /// Held at a challenge or checkpoint: the account owner has to clear it
+    /// in the Instagram app, after which the same cookie works again.
+    Challenge,
+}
+
+impl SessionStatus {
+    /// Returns `true` if the cookie shouldn't be sent until this clears.
+    pub fn is_flagged(self) -> bool {
+        self != SessionStatus::Active
+    }
+}
+
+/// Device identity sent with mobile API requests. Instagram ties a session
+/// to the device it logged in from, so these stay fixed for a cookie's life.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceIds {
+    /// `X-Ig-Device-Id`: a UUID.
+    pub device_id: String,
+    /// `X-Ig-Android-Id`: `android-` and 16 hex digits.
+    pub android_id: String,
+}
+
+impl DeviceIds {
+    /// Builds a set of IDs from 24 random bytes.
+    pub fn from_bytes(bytes: [u8; 24]) -> Self {
+        let mut uuid = [0u8; 16];
+        uuid.copy_from_slice(&bytes[..16]);
+        // Version 4, RFC 4122 variant
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+        Self {
+            device_id: format!(
+                "{}-{}-{}-{}-{}",
+                hex(&uuid[..4]),
+                hex(&uuid[4..6]),
+                hex(&uuid[6..8]),
+                hex(&uuid[8..10]),
+                hex(&uuid[10..])
+            ),
+            android_id: format!("android-{}", hex(&bytes[16..])),
+        }
+    }
+
+    /// Adds the device headers to a request, builder-style.
+    pub fn apply(&self, request: HttpRequest) -> HttpRequest {
+        request
+            .header("X-Ig-Device-Id", self.device_id.as_str())
+            .header("X-Ig-Android-Id", self.android_id.as_str())
+    }
+}
+
+/// Error messages Instagram's JSON APIs use for a session that needs the
+/// owner's attention, worst first.
+const MARKERS: [(&str, SessionStatus); 3] = [
+    ("\"challenge_required\"", SessionStatus::Challenge),
+    ("\"checkpoint_required\"", SessionStatus::Challenge),
+    ("\"login_required\"", SessionStatus::LoggedOut),
+];
+
+/// The problem a response reports with the session that made it, if any.
+pub fn flagged_status(resp: &HttpResponse) -> Option<SessionStatus> {
+    MARKERS
+        .iter()
+        .find(|(marker, _)| resp.body.contains(marker))
+        .map(|&(_, status)| status)
+}
+
+/// Returns `true` if a request carries the session cookie, directly or
+/// inside a proxy payload.
+fn carries_session(request: &HttpRequest) -> bool {
+    request.header_value("Cookie").is_some_and(|c| c.contains("sessionid="))
+        || request.body.as_deref().is_some_and(|b| b.contains("sessionid="))
+}
+
+/// Wraps a client and notes the worst problem any cookie-bearing response
+/// reported with the session.
+pub struct SessionWatch<'a, C> {
+    inner: &'a C,
+    flagged: Cell<Option<SessionStatus>>,
+}
+
+impl<'a, C: HttpClient> SessionWatch<'a, C> {
+    pub fn new(inner: &'a C) -> Self {
+        Self { inner, flagged: Cell::new(None) }
+    }
+
+    pub fn flagged(&self) -> Option<SessionStatus> {
+        self.flagged.get()
+    }
+}
+
+impl<C: HttpClient> HttpClient for SessionWatch<'_, C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let with_session = carries_session(&request);
+        let resp = self.inner.send(request).await?;
+        if let Some(status) = flagged_status(&resp).filter(|_| with_session) {
+            console_log!("[session] response flags the session as {:?} (status={})", status, resp.status);
+            self.flagged.set(self.flagged.get().max(Some(status)));
+        }
+        Ok(resp)
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.inner.now_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+
+    #[test]
+    fn device_ids_are_well_formed() {
+        let ids = DeviceIds::from_bytes([0xab; 24]);
+        assert_eq!(ids.device_id, "abababab-abab-4bab-abab-abababababab");
+        assert_eq!(ids.android_id, "android-abababababababab");
+    }
+
+    #[test]
+    fn only_cookie_bearing_responses_flag_the_session() {
+        let client = FixtureClient::new()
+            .route("/graphql", 200, r#"{"message":"login_required","status":"fail"}"#)
+            .route("/media/", 400, r#"{"message":"challenge_required","challenge":{"url":"https://i.instagram.com/challenge/"}}"#);
+        let watch = SessionWatch::new(&client);
+
+        block_on(watch.send(HttpRequest::get("https://www.instagram.com/graphql"))).unwrap();
+        assert_eq!(watch.flagged(), None);
+
+        let papi = HttpRequest::get("https://i.instagram.com/api/v1/media/1/info/").header("Cookie", "sessionid=42:abc");
+        block_on(watch.send(papi)).unwrap();
+        assert_eq!(watch.flagged(), Some(SessionStatus::Challenge));
+    }
+
+    #[test]
+    fn challenge_outranks_logout() {
+        let client = FixtureClient::new()
+            .route("/a", 400, r#"{"message":"checkpoint_required"}"#)
+            .route("/b", 403, r#"{"message":"login_required"}"#);
+        let watch = SessionWatch::new(&client);
+        for path in ["/a", "/b"] {
+            let request = HttpRequest::get(format!("https://i.instagram.com{path}")).header("Cookie", "sessionid=42:abc");
+            block_on(watch.send(request)).unwrap();
+        }
+        assert_eq!(watch.flagged(), Some(SessionStatus::Challenge));
+    }
+}
@@ -0,0 +1,194 @@
+//! Shadow mode: a second backend's opinion on a post, after the response.
+//!
+//! With `SHADOW_BACKEND` set, a share (`SHADOW_PERCENT`) of the live post
+//! scrapes that found their post ask that backend alone for the same post, in
+//! `wait_until`. Its result is compared with the one served, and any
+//! differences in media, URLs and counts are logged as `[shadow]`, so a new
+//! scraping method can be judged on real traffic before it is promoted.
+//!
+//! Shadow calls go through the same pacing, rate-limit detection and session
+//! lease as any other, so a PAPI shadow never sends a cookie the session
+//! object has flagged, and reports one Instagram challenges. They aren't
+//! counted in backend stats and never reach the cache.
+
+use worker::*;
+
+use super::browser::fetch_browser;
+use super::cache::start_cooldown;
+use super::deadline::Deadline;
+use super::embed_page::fetch_embed_page;
+use super::graphql::fetch_graphql;
+use super::http::HttpClient;
+use super::instrument::instrumented;
+use super::legacy::fetch_legacy;
+use super::papi::fetch_papi;
+use super::post_page::fetch_post_page;
+use super::ratelimit::RateLimitWatch;
+use super::session::SessionWatch;
+use super::stats::Backend;
+use super::types::InstaData;
+use super::with_leased_session;
+use crate::config::Config;
+use crate::pacing::Paced;
+use crate::session::report_session;
+use crate::utils::log::console_log;
+
+/// Asks one backend, and only that one, for a post.
+async fn fetch_from<C: HttpClient>(client: &C, backend: Backend, post_id: &str, config: &Config) -> Result<Option<InstaData>> {
+    let cookie = config.cookie.as_deref();
+    let proxy = config.proxy.as_ref();
+    match backend {
+        Backend::Embed => Ok(fetch_embed_page(client, post_id, cookie, proxy).await?.map(|(data, _)| data)),
+        Backend::Graphql => {
+            for doc_id in &config.doc_ids {
+                let data = fetch_graphql(client, post_id, doc_id, &config.graphql_path, &config.graphql_variables, proxy).await?;
+                if data.is_some() {
+                    return Ok(data);
+                }
+            }
+            Ok(None)
+        }
+        Backend::Legacy => fetch_legacy(client, post_id, cookie, proxy).await,
+        Backend::PostPage => fetch_post_page(client, post_id, cookie, proxy).await,
+        Backend::Papi => fetch_papi(client, post_id, cookie, config.device.as_ref(), proxy).await,
+        Backend::Browser => match config.browser.as_ref() {
+            Some(browser) => fetch_browser(client, post_id, browser).await,
+            None => Ok(None),
+        },
+    }
+}
+
+fn count(value: Option<u64>) -> String {
+    value.map_or_else(|| "none".to_string(), |n| n.to_string())
+}
+
+fn presence(present: bool) -> &'static str {
+    if present {
+        "present"
+    } else {
+        "missing"
+    }
+}
+
+/// The differences between the result served and the shadow backend's, as
+/// `"what: served vs shadow"` lines. Empty when they agree.
+pub fn compare(served: &InstaData, shadow: &InstaData) -> Vec<String> {
+    let mut differences = Vec::new();
+    if served.username != shadow.username {
+        differences.push(format!("username: {} vs {}", served.username, shadow.username));
+    }
+    if served.media.len() != shadow.media.len() {
+        differences.push(format!("media count: {} vs {}", served.media.len(), shadow.media.len()));
+    }
+    for (i, (a, b)) in served.media.iter().zip(&shadow.media).enumerate() {
+        let slide = i + 1;
+        if a.media_type != b.media_type {
+            differences.push(format!("slide {slide} type: {:?} vs {:?}", a.media_type, b.media_type));
+        }
+        if a.url.is_empty() != b.url.is_empty() {
+            differences.push(format!("slide {slide} url: {} vs {}", presence(!a.url.is_empty()), presence(!b.url.is_empty())));
+        }
+        if a.thumbnail_url.is_some() != b.thumbnail_url.is_some() {
+            differences.push(format!(
+                "slide {slide} thumbnail: {} vs {}",
+                presence(a.thumbnail_url.is_some()),
+                presence(b.thumbnail_url.is_some())
+            ));
+        }
+    }
+    if served.caption.is_some() != shadow.caption.is_some() {
+        differences.push(format!("caption: {} vs {}", presence(served.caption.is_some()), presence(shadow.caption.is_some())));
+    }
+    let counts = [
+        ("likes", served.like_count, shadow.like_count),
+        ("comments", served.comment_count, shadow.comment_count),
+        ("views", served.video_view_count, shadow.video_view_count),
+        ("plays", served.play_count, shadow.play_count),
+    ];
+    for (name, a, b) in counts {
+        if a != b {
+            differences.push(format!("{name}: {} vs {}", count(a), count(b)));
+        }
+    }
+    differences
+}
+
+/// Asks `backend` for the post in `served` after the response has been sent,
+/// and logs how its answer differs from what `served_by` found.
+pub fn compare_background(
+    backend: Backend,
+    served_by: Option<Backend>,
+    served: &InstaData,
+    config: &Config,
+    env: &Env,
+    ctx: &Context,
+) {
+    let (served, config, env) = (served.clone(), config.clone(), env.clone());
+    let served_by = served_by.map_or("unknown", Backend::name);
+
+    ctx.wait_until(async move {
+        let post_id = &served.post_id;
+        let leased = with_leased_session(&config, &env).await;
+        let config = leased.as_ref().unwrap_or(&config);
+        let timed = instrumented(&env);
+        let session = SessionWatch::new(&timed);
+        let watch = RateLimitWatch::new(&session);
+        let paced = Paced::new(&watch, &env, config);
+        let client = Deadline::new(&paced, config.scrape_budget_ms);
+        let fetched = fetch_from(&client, backend, post_id, config).await;
+        if watch.hit() {
+            start_cooldown(&env, Date::now().as_millis() / 1000).await;
+        }
+        if let Some(status) = session.flagged() {
+            report_session(status, &env).await;
+        }
+
+        match fetched {
+            Ok(Some(mut shadow)) => {
+                shadow.normalize_urls();
+                let differences = compare(&served, &shadow);
+                if differences.is_empty() {
+                    console_log!("[shadow] {}: {} agrees with {}", post_id, backend.name(), served_by);
+                } else {
+                    console_log!(
+                        "[shadow] {}: {} differs from {} ({}): {}",
+                        post_id,
+                        backend.name(),
+                        served_by,
+                        differences.len(),
+                        differences.join("; ")
+                    );
+                }
+            }
+            Ok(None) => console_log!("[shadow] {}: {} found nothing, {} did", post_id, backend.name(), served_by),
+            Err(e) => console_log!("[shadow] {}: {} failed: {:?}", post_id, backend.name(), e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::mock::mock_post;
+
+    #[test]
+    fn identical_results_agree() {
+        let post = mock_post("CxPapi1234").unwrap();
+        assert!(compare(&post, &post.clone()).is_empty());
+    }
+
+    #[test]
+    fn reports_media_urls_and_counts() {
+        let served = mock_post("CxPapi1234").unwrap();
+        let mut shadow = served.clone();
+        shadow.media.truncate(1);
+        shadow.media[0].url.clear();
+        shadow.like_count = None;
+
+        let differences = compare(&served, &shadow);
+        assert!(differences.contains(&format!("media count: {} vs 1", served.media.len())));
+        assert!(differences.contains(&"slide 1 url: present vs missing".to_string()));
+        assert!(differences.contains(&format!("likes: {} vs none", served.like_count.unwrap())));
+        assert_eq!(differences.len(), 3);
+    }
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+/// Seconds per stats bucket.
+pub const BUCKET_SECS: u64 = 3600;
+
+/// Hourly buckets kept (and shown on `/admin/backends`).
+pub const BUCKETS: u64 = 24;
+
+/// A live scrape backend, in the order `scrape_post` tries them. Serialized
+/// as its config name (see `from_key`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Embed,
+    Graphql,
+    Legacy,
+    PostPage,
+    Papi,
+    Browser,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 6] =
+        [Backend::Embed, Backend::Graphql, Backend::Legacy, Backend::PostPage, Backend::Papi, Backend::Browser];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Embed => "Embed page",
+            Backend::Graphql => "GraphQL",
+            Backend::Legacy => "Legacy ?__a=1",
+            Backend::PostPage => "Post page",
+            Backend::Papi => "Private API",
+            Backend::Browser => "Browser Rendering",
+        }
+    }
+
+    /// The backend's config name, e.g. `post_page`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Backend::Embed => "embed",
+            Backend::Graphql => "graphql",
+            Backend::Legacy => "legacy",
+            Backend::PostPage => "post_page",
+            Backend::Papi => "papi",
+            Backend::Browser => "browser",
+        }
+    }
+
+    /// Parses a backend's config name: `embed`, `graphql`, `legacy`,
+    /// `post_page`, `papi` or `browser`.
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key.trim().to_ascii_lowercase().as_str() {
+            "embed" => Some(Backend::Embed),
+            "graphql" => Some(Backend::Graphql),
+            "legacy" => Some(Backend::Legacy),
+            "post_page" => Some(Backend::PostPage),
+            "papi" => Some(Backend::Papi),
+            "browser" => Some(Backend::Browser),
+            _ => None,
+        }
+    }
+}
+
+/// Which counters a scrape's attempts go to: the stable path's, or the
+/// canary's (see `super::canary`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Track {
+    Stable,
+    Canary,
+}
+
+/// One backend call made while scraping a post.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub backend: Backend,
+    /// The backend returned post data.
+    pub ok: bool,
+    pub millis: u64,
+    /// The GraphQL doc_id used, for ranking `GRAPHQL_DOC_IDS` candidates.
+    pub doc_id: Option<String>,
+}
+
+/// Outcome counts and summed latency for one backend.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counter {
+    pub ok: u64,
+    pub failed: u64,
+    pub total_millis: u64,
+}
+
+impl Counter {
+    pub fn total(&self) -> u64 {
+        self.ok + self.failed
+    }
+
+    /// Mean latency per call, or `None` with no calls.
+    pub fn avg_millis(&self) -> Option<u64> {
+        (self.total() > 0).then(|| self.total_millis / self.total())
+    }
+
+    /// Share of calls that succeeded, 0–100.
+    pub fn success_percent(&self) -> Option<u64> {
+        (self.total() > 0).then(|| self.ok * 100 / self.total())
+    }
+
+    pub fn add(&mut self, other: &Counter) {
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.total_millis += other.total_millis;
+    }
+}
+
+/// Per-backend counters for one hourly bucket, stored in KV as `stats:{hour}`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackendStats {
+    #[serde(default)]
+    pub embed: Counter,
+    #[serde(default)]
+    pub graphql: Counter,
+    #[serde(default)]
+    pub legacy: Counter,
+    #[serde(default)]
+    pub post_page: Counter,
+    #[serde(default)]
+    pub papi: Counter,
+    #[serde(default)]
+    pub browser: Counter,
+}
+
+impl BackendStats {
+    pub fn get(&self, backend: Backend) -> &Counter {
+        match backend {
+            Backend::Embed => &self.embed,
+            Backend::Graphql => &self.graphql,
+            Backend::Legacy => &self.legacy,
+            Backend::PostPage => &self.post_page,
+            Backend::Papi => &self.papi,
+            Backend::Browser => &self.browser,
+        }
+    }
+
+    fn get_mut(&mut self, backend: Backend) -> &mut Counter {
+        match backend {
+            Backend::Embed => &mut self.embed,
+            Backend::Graphql => &mut self.graphql,
+            Backend::Legacy => &mut self.legacy,
+            Backend::PostPage => &mut self.post_page,
+            Backend::Papi => &mut self.papi,
+            Backend::Browser => &mut self.browser,
+        }
+    }
+
+    pub fn record(&mut self, attempt: &Attempt) {
+        let counter = self.get_mut(attempt.backend);
+        if attempt.ok {
+            counter.ok += 1;
+        } else {
+            counter.failed += 1;
+        }
+        counter.total_millis += attempt.millis;
+    }
+
+    /// Sums buckets, e.g. for the 24 hour totals.
+    pub fn sum<'a>(buckets: impl IntoIterator<Item = &'a BackendStats>) -> Self {
+        let mut total = Self::default();
+        for bucket in buckets {
+            for backend in Backend::ALL {
+                total.get_mut(backend).add(bucket.get(backend));
+            }
+        }
+        total
+    }
+}
+
+/// The bucket (hours since the epoch) a time falls in.
+pub fn bucket_of(now_secs: u64) -> u64 {
+    now_secs / BUCKET_SECS
+}
+
+/// The last `BUCKETS` buckets up to and including the current one, oldest first.
+pub fn recent_buckets(now_secs: u64) -> impl Iterator<Item = u64> {
+    let current = bucket_of(now_secs);
+    current.saturating_sub(BUCKETS - 1)..=current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(backend: Backend, ok: bool, millis: u64) -> Attempt {
+        Attempt { backend, ok, millis, doc_id: None }
+    }
+
+    #[test]
+    fn records_outcomes_and_latency_per_backend() {
+        let mut stats = BackendStats::default();
+        stats.record(&attempt(Backend::Embed, false, 300));
+        stats.record(&attempt(Backend::Graphql, true, 900));
+        stats.record(&attempt(Backend::Graphql, false, 100));
+
+        assert_eq!(stats.embed, Counter { ok: 0, failed: 1, total_millis: 300 });
+        assert_eq!(stats.graphql.avg_millis(), Some(500));
+        assert_eq!(stats.graphql.success_percent(), Some(50));
+        assert_eq!(stats.papi.avg_millis(), None);
+    }
+
+    #[test]
+    fn config_names_round_trip_and_match_serde() {
+        for backend in Backend::ALL {
+            assert_eq!(Backend::from_key(backend.key()), Some(backend));
+            assert_eq!(serde_json::to_value(backend).unwrap(), backend.key());
+        }
+    }
+
+    #[test]
+    fn sums_buckets() {
+        let mut a = BackendStats::default();
+        a.record(&attempt(Backend::Papi, true, 200));
+        let mut b = BackendStats::default();
+        b.record(&attempt(Backend::Papi, true, 400));
+
+        let total = BackendStats::sum([&a, &b]);
+        assert_eq!(total.papi, Counter { ok: 2, failed: 0, total_millis: 600 });
+    }
+
+    #[test]
+    fn recent_buckets_cover_the_last_day() {
+        let buckets: Vec<u64> = recent_buckets(1700000000).collect();
+        assert_eq!(buckets.len(), 24);
+        assert_eq!(*buckets.last().unwrap(), 472222);
+        assert_eq!(buckets[0], 472222 - 23);
+    }
+}
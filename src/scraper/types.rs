@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use super::stats::Backend;
+use crate::utils::instagram::{cdn_url_expired, code_to_mediaid, normalize_media_url};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum MediaType {
@@ -18,6 +21,79 @@ pub struct Media {
     pub width: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub height: Option<u32>,
+    /// A description of the image: Instagram's `accessibility_caption`, or
+    /// one from Workers AI (`AI_ALT_TEXT`) for posts without a caption.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alt_text: Option<String>,
+}
+
+impl Media {
+    /// A video whose stream URL Instagram withheld (the "Watch on Instagram"
+    /// embed). Only the thumbnail, if any, is usable.
+    pub fn is_blocked_video(&self) -> bool {
+        self.media_type == MediaType::Video && self.url.is_empty()
+    }
+
+    /// The stream URL, for a video that wasn't withheld.
+    pub fn video_url(&self) -> Option<&str> {
+        (self.media_type == MediaType::Video && !self.url.is_empty()).then_some(self.url.as_str())
+    }
+
+    /// The slide as a still image: the image itself, or a video's cover
+    /// frame when known.
+    pub fn still_url(&self) -> Option<&str> {
+        match self.media_type {
+            MediaType::Image => Some(self.url.as_str()).filter(|url| !url.is_empty()),
+            MediaType::Video => self.thumbnail_url.as_deref(),
+        }
+    }
+}
+
+/// What a shortcode is on Instagram. Reels and IGTV videos are reachable
+/// under `/p/` too, so the URL alone doesn't say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostKind {
+    Post,
+    Reel,
+    Igtv,
+    Story,
+}
+
+impl PostKind {
+    /// From the `product_type` Instagram sends with a post.
+    pub fn from_product_type(product_type: &str) -> Option<Self> {
+        match product_type {
+            "feed" | "carousel_container" => Some(PostKind::Post),
+            "clips" => Some(PostKind::Reel),
+            "igtv" => Some(PostKind::Igtv),
+            "story" => Some(PostKind::Story),
+            _ => None,
+        }
+    }
+
+    /// From the route a post was requested on, for scrapes that don't say.
+    /// `None` for `/p/` and other routes every kind can arrive on.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let first = path.trim_start_matches('/').split('/').next()?;
+        match first {
+            "reel" | "reels" => Some(PostKind::Reel),
+            "tv" => Some(PostKind::Igtv),
+            "stories" => Some(PostKind::Story),
+            _ => None,
+        }
+    }
+
+    /// How titles name the kind, as in "Reel by @user". Feed posts are
+    /// the default and go unnamed.
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            PostKind::Post => None,
+            PostKind::Reel => Some("Reel"),
+            PostKind::Igtv => Some("IGTV video"),
+            PostKind::Story => Some("Story"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,5 +110,267 @@ pub struct InstaData {
     pub is_video: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub video_view_count: Option<u64>,
+    /// Plays, which reels often report instead of views.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub play_count: Option<u64>,
     pub timestamp: u64,
+    /// Most-liked comment from the backend's comment preview, shown with `?comments=1`.
+    /// Defaulted so cache entries written before it existed still load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_comment: Option<Comment>,
+    /// Owner's display name, when the backend provides it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default)]
+    pub is_verified: bool,
+    /// Owner's profile picture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    /// Usernames of a collab post's co-authors, besides the owner.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coauthors: Vec<String>,
+    /// Instagram shows the post behind a "Sensitive content" overlay.
+    #[serde(default)]
+    pub sensitive: bool,
+    /// From the backend's `product_type`, else the requested route.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<PostKind>,
+    /// Story stickers, in the order polls, questions, links.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stickers: Vec<Sticker>,
+    /// The hashtag this post was embedded as the top post of. Set per
+    /// request, never scraped or cached.
+    #[serde(skip)]
+    pub hashtag: Option<String>,
+    /// The backend the data was scraped from, cached along with it.
+    /// Defaulted so cache entries written before it existed still load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    /// Unix time (seconds) the data was scraped, cached along with it: the
+    /// data's `Last-Modified`. 0 for canned posts and entries cached before
+    /// it was recorded.
+    #[serde(default)]
+    pub scraped_at: u64,
+    /// This request found the data in the cache. Set per request.
+    #[serde(skip)]
+    pub cached: bool,
+    /// This request scraped the data through the canary path (see
+    /// `super::canary`). Set per request; such data is never cached.
+    #[serde(skip)]
+    pub canary: bool,
+}
+
+/// An interactive story sticker. On many stories it's the whole content,
+/// so embeds spell it out in the description.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Sticker {
+    Poll { question: String, options: Vec<PollOption> },
+    Question { text: String },
+    Link { url: String, label: Option<String> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PollOption {
+    pub text: String,
+    /// Votes so far, when Instagram shares them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub votes: Option<u64>,
+}
+
+/// A single comment, shown on comment permalink embeds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Comment {
+    pub id: String,
+    pub username: String,
+    pub text: String,
+}
+
+/// How long Instagram keeps a story up.
+pub const STORY_LIFETIME_SECS: u64 = 24 * 60 * 60;
+
+impl InstaData {
+    /// Returns `true` if any media or thumbnail URL has passed its signed
+    /// expiry (see `cdn_url_expired`).
+    pub fn media_expired(&self, now_secs: u64) -> bool {
+        self.media.iter().any(|item| {
+            cdn_url_expired(&item.url, now_secs)
+                || item.thumbnail_url.as_deref().is_some_and(|t| cdn_url_expired(t, now_secs))
+        })
+    }
+
+    /// Where the data came from, as sent in `X-Cattgram-Source`: the
+    /// backend's config name, with `; cached` if this request found it in
+    /// the cache, e.g. `graphql; cached`. `unknown` for canned posts and
+    /// entries cached before the backend was recorded.
+    pub fn source(&self) -> String {
+        let backend = self.backend.map_or("unknown", Backend::key);
+        if self.cached {
+            format!("{backend}; cached")
+        } else {
+            backend.to_string()
+        }
+    }
+
+    /// The post's own URL on Instagram, in the form Instagram links it:
+    /// reels under `/reel/`, IGTV under `/tv/`, stories under their author.
+    pub fn instagram_url(&self) -> String {
+        match self.kind {
+            Some(PostKind::Reel) => format!("https://www.instagram.com/reel/{}/", self.post_id),
+            Some(PostKind::Igtv) => format!("https://www.instagram.com/tv/{}/", self.post_id),
+            Some(PostKind::Story) => match code_to_mediaid(&self.post_id) {
+                Some(media_id) => format!("https://www.instagram.com/stories/{}/{}/", self.username, media_id),
+                None => format!("https://www.instagram.com/stories/{}/", self.username),
+            },
+            Some(PostKind::Post) | None => format!("https://www.instagram.com/p/{}/", self.post_id),
+        }
+    }
+
+    /// Strips tracking parameters from every CDN URL in the post (see
+    /// `normalize_media_url`).
+    pub fn normalize_urls(&mut self) {
+        for item in &mut self.media {
+            item.url = normalize_media_url(&item.url);
+            if let Some(thumbnail) = item.thumbnail_url.as_mut() {
+                *thumbnail = normalize_media_url(thumbnail);
+            }
+        }
+        if let Some(avatar) = self.avatar_url.as_mut() {
+            *avatar = normalize_media_url(avatar);
+        }
+    }
+
+    /// Seconds until a story posted at `timestamp` disappears, or `None` if
+    /// the timestamp is unknown. Zero once it has expired.
+    pub fn story_remaining_secs(&self, now_secs: u64) -> Option<u64> {
+        (self.timestamp > 0).then(|| (self.timestamp + STORY_LIFETIME_SECS).saturating_sub(now_secs))
+    }
+}
+
+impl Comment {
+    /// Picks the most-liked comment, preferring the earliest on ties.
+    pub fn most_liked(candidates: impl IntoIterator<Item = (Comment, u64)>) -> Option<Comment> {
+        candidates
+            .into_iter()
+            .reduce(|best, next| if next.1 > best.1 { next } else { best })
+            .map(|(comment, _)| comment)
+    }
+}
+
+/// A user profile, from `web_profile_info`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Numeric user ID, as used by the private API.
+    pub pk: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub biography: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    pub is_private: bool,
+    pub is_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follower_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub following_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_count: Option<u64>,
+    /// Most recent posts first. Empty for private profiles.
+    #[serde(default)]
+    pub recent_posts: Vec<ProfilePost>,
+}
+
+impl Profile {
+    /// Strips tracking parameters from the avatar and grid thumbnail URLs.
+    pub fn normalize_urls(&mut self) {
+        if let Some(avatar) = self.avatar_url.as_mut() {
+            *avatar = normalize_media_url(avatar);
+        }
+        for post in &mut self.recent_posts {
+            post.thumbnail_url = normalize_media_url(&post.thumbnail_url);
+        }
+    }
+
+    /// The newest post. Pinned posts head the grid whatever their age, so
+    /// this goes by timestamp rather than position.
+    pub fn latest_post(&self) -> Option<&ProfilePost> {
+        self.recent_posts.iter().max_by_key(|post| post.timestamp)
+    }
+}
+
+/// One entry of a profile's post grid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfilePost {
+    pub shortcode: String,
+    pub thumbnail_url: String,
+    pub is_video: bool,
+    pub timestamp: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::image;
+
+    fn media(media_type: MediaType, url: &str, thumbnail_url: Option<&str>) -> Media {
+        Media {
+            media_type,
+            thumbnail_url: thumbnail_url.map(str::to_string),
+            ..image(url)
+        }
+    }
+
+    #[test]
+    fn slides_fall_back_across_media_types() {
+        let image = media(MediaType::Image, "https://cdn/1.jpg", None);
+        assert_eq!((image.video_url(), image.still_url()), (None, Some("https://cdn/1.jpg")));
+
+        let video = media(MediaType::Video, "https://cdn/2.mp4", Some("https://cdn/2.jpg"));
+        assert_eq!((video.video_url(), video.still_url()), (Some("https://cdn/2.mp4"), Some("https://cdn/2.jpg")));
+
+        let blocked = media(MediaType::Video, "", Some("https://cdn/3.jpg"));
+        assert_eq!((blocked.video_url(), blocked.still_url()), (None, Some("https://cdn/3.jpg")));
+        assert_eq!(media(MediaType::Video, "", None).still_url(), None);
+    }
+
+    #[test]
+    fn latest_post_skips_older_pinned_posts() {
+        let post = |shortcode: &str, timestamp| ProfilePost {
+            shortcode: shortcode.to_string(),
+            thumbnail_url: String::new(),
+            is_video: false,
+            timestamp,
+        };
+        let profile = Profile {
+            pk: "1".to_string(),
+            username: "cat".to_string(),
+            full_name: None,
+            biography: None,
+            avatar_url: None,
+            is_private: false,
+            is_verified: false,
+            follower_count: None,
+            following_count: None,
+            post_count: None,
+            recent_posts: vec![post("Pinned", 100), post("Newest", 300), post("Older", 200)],
+        };
+        assert_eq!(profile.latest_post().map(|p| p.shortcode.as_str()), Some("Newest"));
+    }
+
+    #[test]
+    fn product_type_names_the_kind() {
+        assert_eq!(PostKind::from_product_type("clips"), Some(PostKind::Reel));
+        assert_eq!(PostKind::from_product_type("carousel_container"), Some(PostKind::Post));
+        assert_eq!(PostKind::from_product_type("ad"), None);
+    }
+
+    #[test]
+    fn only_kind_specific_routes_name_the_kind() {
+        assert_eq!(PostKind::from_path("/reels/ABC"), Some(PostKind::Reel));
+        assert_eq!(PostKind::from_path("/tv/ABC"), Some(PostKind::Igtv));
+        assert_eq!(PostKind::from_path("/stories/cat/123"), Some(PostKind::Story));
+        assert_eq!(PostKind::from_path("/p/ABC"), None);
+    }
 }
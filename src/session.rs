@@ -0,0 +1,401 @@
+//! The Instagram session, kept in a Durable Object (`SESSIONS`).
+//!
+//! Without it every isolate sends `IG_COOKIE` blind: a session Instagram has
+//! put behind a challenge keeps getting hit from everywhere until someone
+//! notices. With it, one object owns the session's state (device IDs,
+//! status, timestamps) and decides whether the cookie may be used:
+//!
+//! - Scrapes take a lease first and report back what Instagram said about
+//!   the cookie. A challenge or logout flags the session, and while it is
+//!   flagged leases say so, and scrapes carry on without a cookie. A lease
+//!   isn't exclusive and isn't handed back: it only says whether to send
+//!   the cookie, and which device IDs to send with it. Each isolate reuses
+//!   one for `LEASE_REUSE_MS`, so a burst of scrapes costs one round trip.
+//! - An alarm makes a cheap keep-alive request every `KEEPALIVE_SECS` while
+//!   the session is in use. It notices problems without a scrape tripping
+//!   over them, and clears the flag once the owner has resolved the
+//!   challenge in the app.
+//! - A newly flagged session raises an alert (see `crate::alert`).
+//! - Replacing the `IG_COOKIE` secret starts a fresh session.
+//!
+//! The cookie itself stays in the secret. The object stores only a
+//! fingerprint of it, to notice when it is replaced.
+//!
+//! Without the binding, scrapes use `IG_COOKIE` directly, as before.
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::alert::{send_alert, Alert};
+use crate::config::Config;
+use crate::scraper::instrument::instrumented;
+use crate::scraper::papi::check_session;
+use crate::scraper::session::{DeviceIds, SessionStatus};
+use crate::utils::http::fingerprint;
+use crate::utils::log::console_log;
+
+/// The Durable Object namespace binding.
+pub const BINDING: &str = "SESSIONS";
+
+/// There's one `IG_COOKIE`, so there's one object.
+const OBJECT_NAME: &str = "ig-session";
+
+/// Storage key of the `SessionState`.
+const STATE_KEY: &str = "session";
+
+/// Gap between keep-alive checks.
+const KEEPALIVE_SECS: u64 = 30 * 60;
+
+/// Keep-alives stop once the session hasn't been leased for this long, and
+/// resume with the next lease.
+const IDLE_SECS: u64 = 24 * 60 * 60;
+
+/// `last_used` only decides when keep-alives pause, so it moves on at most
+/// this often, and most leases don't need a storage write.
+const LAST_USED_STEP_SECS: u64 = 60 * 60;
+
+/// How long an isolate reuses a lease before asking again. A flag raised by
+/// another isolate reaches this one within that time.
+const LEASE_REUSE_MS: u64 = 60_000;
+
+thread_local! {
+    /// This isolate's last lease, with when it was taken.
+    static LEASED: RefCell<Option<(u64, Lease)>> = RefCell::default();
+}
+
+/// What one scrape may do with `IG_COOKIE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lease {
+    /// Send no cookie: Instagram is holding the session at a challenge or
+    /// has logged it out.
+    pub flagged: bool,
+    /// Device IDs to send with the cookie.
+    pub device: DeviceIds,
+}
+
+/// Everything the object persists about the session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SessionState {
+    /// Of `IG_COOKIE`, which is never stored.
+    cookie_fingerprint: u64,
+    device: DeviceIds,
+    status: SessionStatus,
+    /// When `status` last changed.
+    since: u64,
+    last_used: u64,
+    last_checked: Option<u64>,
+}
+
+/// The session's state minus its secrets, for `/config-check`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionReport {
+    pub status: SessionStatus,
+    pub since: u64,
+    pub last_used: u64,
+    pub last_checked: Option<u64>,
+}
+
+impl SessionState {
+    fn new(cookie_fingerprint: u64, device: DeviceIds, now_secs: u64) -> Self {
+        Self {
+            cookie_fingerprint,
+            device,
+            status: SessionStatus::Active,
+            since: now_secs,
+            last_used: now_secs,
+            last_checked: None,
+        }
+    }
+
+    /// Counts as use even while flagged, so keep-alives carry on checking
+    /// whether the flag has cleared.
+    fn lease(&mut self, now_secs: u64) -> Lease {
+        if now_secs.saturating_sub(self.last_used) >= LAST_USED_STEP_SECS {
+            self.last_used = now_secs;
+        }
+        Lease {
+            flagged: self.status.is_flagged(),
+            device: self.device.clone(),
+        }
+    }
+
+    /// Records a new status. Returns `true` if it newly flags the session.
+    fn update(&mut self, status: SessionStatus, now_secs: u64) -> bool {
+        if status == self.status {
+            return false;
+        }
+        console_log!("[session] {:?} -> {:?}", self.status, status);
+        let newly_flagged = !self.status.is_flagged() && status.is_flagged();
+        self.status = status;
+        self.since = now_secs;
+        newly_flagged
+    }
+
+    fn idle(&self, now_secs: u64) -> bool {
+        now_secs.saturating_sub(self.last_used) >= IDLE_SECS
+    }
+
+    fn report(&self) -> SessionReport {
+        SessionReport {
+            status: self.status,
+            since: self.since,
+            last_used: self.last_used,
+            last_checked: self.last_checked,
+        }
+    }
+}
+
+/// Owns the session. Requests are handled one at a time, so leases and
+/// reports never race each other.
+#[durable_object]
+pub struct SessionStore {
+    state: State,
+    env: Env,
+    /// The stored state, once read.
+    saved: RefCell<Option<SessionState>>,
+}
+
+impl DurableObject for SessionStore {
+    fn new(state: State, env: Env) -> Self {
+        Self { state, env, saved: RefCell::default() }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        let now_secs = Date::now().as_millis() / 1000;
+        let saved = self.saved().await;
+        let Some(mut session) = self.current(saved.clone(), now_secs) else {
+            return Response::error("IG_COOKIE is not configured", 404);
+        };
+
+        let response = match (req.method(), req.path().as_str()) {
+            (Method::Get, "/lease") => {
+                let lease = session.lease(now_secs);
+                if self.state.storage().get_alarm().await?.is_none() {
+                    self.state.storage().set_alarm(Duration::from_secs(KEEPALIVE_SECS)).await?;
+                }
+                Response::from_json(&lease)?
+            }
+            (Method::Post, "/report") => {
+                let status: SessionStatus = req.json().await?;
+                if session.update(status, now_secs) {
+                    self.alert(status).await;
+                }
+                Response::empty()?
+            }
+            (Method::Get, "/status") => Response::from_json(&session.report())?,
+            _ => return Response::error("Not Found", 404),
+        };
+
+        self.save(&session, saved.as_ref()).await?;
+        Ok(response)
+    }
+
+    /// The keep-alive.
+    async fn alarm(&self) -> Result<Response> {
+        let now_secs = Date::now().as_millis() / 1000;
+        let saved = self.saved().await;
+        let (Some(mut session), Some(cookie)) = (self.current(saved.clone(), now_secs), Config::from_env(&self.env).cookie)
+        else {
+            return Response::empty();
+        };
+
+        match check_session(&instrumented(&self.env), &cookie, Some(&session.device)).await {
+            Ok(status) => {
+                session.last_checked = Some(now_secs);
+                if session.update(status, now_secs) {
+                    self.alert(status).await;
+                }
+            }
+            Err(e) => console_log!("[session] keep-alive failed: {:?}", e),
+        }
+        self.save(&session, saved.as_ref()).await?;
+
+        if session.idle(now_secs) {
+            console_log!("[session] idle, pausing keep-alives");
+        } else {
+            self.state.storage().set_alarm(Duration::from_secs(KEEPALIVE_SECS)).await?;
+        }
+        Response::empty()
+    }
+}
+
+impl SessionStore {
+    /// The stored session, read from storage only by the object's first
+    /// request. Entries from before cookies were fingerprinted don't parse,
+    /// and are replaced by a fresh session.
+    async fn saved(&self) -> Option<SessionState> {
+        if let Some(session) = self.saved.borrow().clone() {
+            return Some(session);
+        }
+        self.state.storage().get(STATE_KEY).await.ok().flatten()
+    }
+
+    /// The saved session, or a fresh one when `IG_COOKIE` has changed since
+    /// it was saved. `None` without `IG_COOKIE`.
+    fn current(&self, saved: Option<SessionState>, now_secs: u64) -> Option<SessionState> {
+        let cookie_fingerprint = fingerprint(&Config::from_env(&self.env).cookie?);
+        Some(match saved {
+            Some(session) if session.cookie_fingerprint == cookie_fingerprint => session,
+            _ => {
+                console_log!("[session] starting a session for the current IG_COOKIE");
+                let bytes = std::array::from_fn(|_| (js_sys::Math::random() * 256.0) as u8);
+                SessionState::new(cookie_fingerprint, DeviceIds::from_bytes(bytes), now_secs)
+            }
+        })
+    }
+
+    /// Writes the session to storage, if it changed.
+    async fn save(&self, session: &SessionState, saved: Option<&SessionState>) -> Result<()> {
+        if saved != Some(session) {
+            self.state.storage().put(STATE_KEY, session).await?;
+        }
+        *self.saved.borrow_mut() = Some(session.clone());
+        Ok(())
+    }
+
+    async fn alert(&self, status: SessionStatus) {
+        let config = Config::from_env(&self.env);
+        let Some(webhook_url) = config.alert_webhook_url else {
+            return;
+        };
+        let alert = match status {
+            SessionStatus::Challenge => Alert::SessionChallenge,
+            SessionStatus::LoggedOut | SessionStatus::Active => Alert::CookieDead,
+        };
+        send_alert(alert, &self.env, &webhook_url, &alert.message(&config.site_name, 0)).await;
+    }
+}
+
+fn session_stub(env: &Env) -> Option<Stub> {
+    let namespace = env.durable_object(BINDING).ok()?;
+    match namespace.get_by_name(OBJECT_NAME) {
+        Ok(stub) => Some(stub),
+        Err(e) => {
+            console_log!("[session] stub error: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Leases the session for one scrape, reusing this isolate's last lease
+/// for up to `LEASE_REUSE_MS`. `None` when `SESSIONS` isn't bound or can't
+/// be reached, in which case `IG_COOKIE` is used as configured.
+pub async fn lease_session(env: &Env) -> Option<Lease> {
+    let now_ms = Date::now().as_millis();
+    let reused = LEASED.with_borrow(|leased| {
+        leased.clone().filter(|(taken_ms, _)| now_ms.saturating_sub(*taken_ms) < LEASE_REUSE_MS)
+    });
+    if let Some((_, lease)) = reused {
+        return Some(lease);
+    }
+
+    let stub = session_stub(env)?;
+    let lease = async { stub.fetch_with_str("https://session/lease").await?.json::<Lease>().await };
+    match lease.await {
+        Ok(lease) => {
+            LEASED.set(Some((now_ms, lease.clone())));
+            Some(lease)
+        }
+        Err(e) => {
+            console_log!("[session] lease failed: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Tells the session object what a scrape found, from `wait_until`. A flag
+/// also drops this isolate's lease, so its next scrape asks again.
+pub fn report_session_background(status: SessionStatus, env: &Env, ctx: &Context) {
+    let env = env.clone();
+
+    ctx.wait_until(async move {
+        report_session(status, &env).await;
+    });
+}
+
+/// Like `report_session_background`, for work already running after the
+/// response.
+pub async fn report_session(status: SessionStatus, env: &Env) {
+    let Some(stub) = session_stub(env) else {
+        return;
+    };
+    if status.is_flagged() {
+        LEASED.set(None);
+    }
+    let report = async {
+        let body = serde_json::to_string(&status)?;
+        let mut init = RequestInit::new();
+        init.with_method(Method::Post).with_body(Some(body.into()));
+        stub.fetch_with_request(Request::new_with_init("https://session/report", &init)?).await
+    };
+    if let Err(e) = report.await {
+        console_log!("[session] report failed: {:?}", e);
+    }
+}
+
+/// The session's state for `/config-check`. `None` without the binding.
+pub async fn session_report(env: &Env) -> Result<Option<SessionReport>> {
+    let Some(stub) = session_stub(env) else {
+        return Ok(None);
+    };
+    let mut resp = stub.fetch_with_str("https://session/status").await?;
+    if resp.status_code() != 200 {
+        return Ok(None);
+    }
+    Ok(Some(resp.json().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> SessionState {
+        SessionState::new(fingerprint("sessionid=42:abc"), DeviceIds::from_bytes([1; 24]), 1000)
+    }
+
+    #[test]
+    fn flagged_sessions_are_leased_as_flagged() {
+        let mut session = session();
+        assert!(!session.lease(1100).flagged);
+        assert!(session.update(SessionStatus::Challenge, 1200));
+        assert!(session.lease(1300).flagged);
+    }
+
+    #[test]
+    fn last_used_moves_on_hourly() {
+        let mut session = session();
+        session.lease(1000 + LAST_USED_STEP_SECS - 1);
+        assert_eq!(session.last_used, 1000);
+        session.lease(1000 + LAST_USED_STEP_SECS);
+        assert_eq!(session.last_used, 1000 + LAST_USED_STEP_SECS);
+    }
+
+    #[test]
+    fn the_cookie_is_not_stored() {
+        let json = serde_json::to_string(&session()).unwrap();
+        assert!(!json.contains("sessionid"), "{json}");
+    }
+
+    #[test]
+    fn only_the_first_problem_is_news() {
+        let mut session = session();
+        assert!(session.update(SessionStatus::LoggedOut, 1100));
+        assert!(!session.update(SessionStatus::LoggedOut, 1200));
+        // Escalating from one flag to another doesn't alert again
+        assert!(!session.update(SessionStatus::Challenge, 1300));
+        assert_eq!(session.since, 1300);
+
+        assert!(!session.update(SessionStatus::Active, 1400));
+        assert!(!session.lease(1500).flagged);
+    }
+
+    #[test]
+    fn keepalives_pause_when_idle() {
+        let session = session();
+        assert!(!session.idle(1000 + IDLE_SECS - 1));
+        assert!(session.idle(1000 + IDLE_SECS));
+    }
+}
@@ -0,0 +1,158 @@
+//! Short links: compact `/s/{code}` URLs that pin a post, a slide and
+//! display options.
+//!
+//! Codes are minted through `POST /api/shortlinks`, which always needs an
+//! API key, and stored in KV for good. `/s/{code}` redirects to the embed URL
+//! the link stands for, so it previews and opens like the long form.
+
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+use worker::*;
+
+use crate::i18n::Lang;
+use crate::utils::instagram::is_shortcode;
+use crate::utils::random::random_uuid;
+
+/// Characters per code. 62^8 codes leave collisions to chance alone.
+const CODE_LEN: usize = 8;
+
+const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Carousels hold at most 20 items.
+const MAX_SLIDE: usize = 20;
+
+/// What a short link points at. Also the request body that mints one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShortLink {
+    pub post_id: String,
+    /// The carousel slide to pin, 1-based.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub img_index: Option<usize>,
+    /// Embed videos as GIFs (`?gif=true`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gif: bool,
+    /// Append the top comment (`?comments=1`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub top_comment: bool,
+    /// Translate the caption into this language (`?lang=`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+impl ShortLink {
+    /// Checks a link before it is stored, normalizing its language tag.
+    /// Returns what's wrong with it otherwise.
+    pub fn validate(mut self) -> std::result::Result<Self, &'static str> {
+        if !is_shortcode(&self.post_id) {
+            return Err("post_id must be an Instagram shortcode");
+        }
+        if self.img_index.is_some_and(|i| i == 0 || i > MAX_SLIDE) {
+            return Err("img_index must be between 1 and 20");
+        }
+        if let Some(tag) = &self.lang {
+            match Lang::from_tag(tag) {
+                Some(lang) => self.lang = Some(lang.code().to_string()),
+                None => return Err("lang is not a supported language"),
+            }
+        }
+        Ok(self)
+    }
+
+    /// The embed path and query the link stands for, e.g.
+    /// `/p/ABC123/?img_index=2&gif=true`.
+    pub fn target(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(index) = self.img_index {
+            query.append_pair("img_index", &index.to_string());
+        }
+        if self.gif {
+            query.append_pair("gif", "true");
+        }
+        if self.top_comment {
+            query.append_pair("comments", "1");
+        }
+        if let Some(lang) = &self.lang {
+            query.append_pair("lang", lang);
+        }
+        let query = query.finish();
+        if query.is_empty() {
+            format!("/p/{}/", self.post_id)
+        } else {
+            format!("/p/{}/?{}", self.post_id, query)
+        }
+    }
+}
+
+/// A stored short link, with who minted it for when one is abused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredLink {
+    #[serde(flatten)]
+    pub link: ShortLink,
+    /// The minting API key's name.
+    pub created_by: String,
+    pub created_at: u64,
+}
+
+/// A fresh random code.
+pub fn new_code() -> Result<String> {
+    let uuid = random_uuid()?;
+    code_from_uuid(&uuid).ok_or_else(|| Error::RustError(format!("unexpected UUID format: {uuid}")))
+}
+
+/// Turns a random UUID into a code: its last 48 bits, all random, in base 62.
+pub fn code_from_uuid(uuid: &str) -> Option<String> {
+    let hex: String = uuid.chars().filter(|c| *c != '-').collect();
+    let mut bits = u128::from_str_radix(&hex, 16).ok()?;
+    let mut code = String::with_capacity(CODE_LEN);
+    for _ in 0..CODE_LEN {
+        code.push(ALPHABET[(bits % 62) as usize] as char);
+        bits /= 62;
+    }
+    Some(code)
+}
+
+/// Returns `true` if `code` could have been minted here, so lookups of
+/// anything else skip KV.
+pub fn is_valid_code(code: &str) -> bool {
+    code.len() == CODE_LEN && code.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(post_id: &str) -> ShortLink {
+        ShortLink { post_id: post_id.to_string(), img_index: None, gif: false, top_comment: false, lang: None }
+    }
+
+    #[test]
+    fn target_carries_the_pinned_options() {
+        assert_eq!(link("ABC123").target(), "/p/ABC123/");
+
+        let pinned = ShortLink { img_index: Some(3), gif: true, top_comment: true, lang: Some("de".to_string()), ..link("ABC123") };
+        assert_eq!(pinned.target(), "/p/ABC123/?img_index=3&gif=true&comments=1&lang=de");
+    }
+
+    #[test]
+    fn validation_rejects_bad_links_and_normalizes_lang() {
+        assert!(link("ABC123").validate().is_ok());
+        assert!(link("").validate().is_err());
+        assert!(link("../admin").validate().is_err());
+        assert!(ShortLink { img_index: Some(0), ..link("ABC123") }.validate().is_err());
+        assert!(ShortLink { img_index: Some(21), ..link("ABC123") }.validate().is_err());
+        assert!(ShortLink { lang: Some("xx".to_string()), ..link("ABC123") }.validate().is_err());
+
+        let valid = ShortLink { lang: Some("pt-BR".to_string()), ..link("ABC123") }.validate().unwrap();
+        assert_eq!(valid.lang.as_deref(), Some("pt"));
+    }
+
+    #[test]
+    fn codes_come_from_uuids() {
+        let code = code_from_uuid("0f8e2b4c-6d1a-4e3f-9b7c-5a2d8e1f0c3b").unwrap();
+        assert!(is_valid_code(&code));
+        assert_eq!(code, code_from_uuid("0f8e2b4c-6d1a-4e3f-9b7c-5a2d8e1f0c3b").unwrap());
+        assert_ne!(code, code_from_uuid("0f8e2b4c-6d1a-4e3f-9b7c-5a2d8e1f0c3c").unwrap());
+        assert!(code_from_uuid("not a uuid").is_none());
+        assert!(!is_valid_code("short"));
+    }
+}
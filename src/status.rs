@@ -0,0 +1,270 @@
+//! The public `/status` snapshot.
+//!
+//! Lets people tell "Instagram is blocking this instance right now" apart
+//! from "the instance is down" without admin access. The snapshot is built
+//! from the recent backend stats, the rate-limit cooldown and one live check
+//! each of the cookie and the proxy, then kept in KV for `REFRESH_SECS`, so
+//! visitors can't turn the page into a stream of requests to Instagram.
+//! Nothing in it reveals credentials.
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::config::Config;
+use crate::scraper::cache::{get_backend_stats, get_cooldown, get_status_snapshot, put_status_snapshot};
+use crate::scraper::instrument::instrumented;
+use crate::scraper::papi::check_cookie;
+use crate::scraper::proxy::check_proxy;
+use crate::scraper::stats::{bucket_of, Backend, BackendStats, Counter, Track};
+use crate::session::session_report;
+use crate::utils::log::console_log;
+
+/// How long a snapshot is served before the next visitor rebuilds it.
+pub const REFRESH_SECS: u64 = 5 * 60;
+
+/// Hourly stats buckets summed per backend: the current and the previous
+/// hour, so the picture doesn't reset on the hour.
+const WINDOW_BUCKETS: u64 = 2;
+
+/// Success rates at or above this are healthy...
+const UP_PERCENT: u64 = 80;
+
+/// ...and below this, the backend is effectively down.
+const DOWN_PERCENT: u64 = 20;
+
+/// The state of one component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Up,
+    Degraded,
+    Down,
+    /// Enabled, but nothing to go on: no recent scrapes, or the check failed.
+    Unknown,
+    /// Disabled or not configured on this instance.
+    Off,
+}
+
+impl Health {
+    /// From a backend's recent calls.
+    pub fn from_counter(counter: &Counter, enabled: bool) -> Self {
+        if !enabled {
+            return Health::Off;
+        }
+        match counter.success_percent() {
+            None => Health::Unknown,
+            Some(percent) if percent >= UP_PERCENT => Health::Up,
+            Some(percent) if percent >= DOWN_PERCENT => Health::Degraded,
+            Some(_) => Health::Down,
+        }
+    }
+
+    fn from_check(result: Result<bool>) -> Self {
+        match result {
+            Ok(true) => Health::Up,
+            Ok(false) => Health::Down,
+            Err(e) => {
+                console_log!("[status] check failed: {:?}", e);
+                Health::Unknown
+            }
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Health::Up => "Working",
+            Health::Degraded => "Partly failing",
+            Health::Down => "Failing",
+            Health::Unknown => "No recent data",
+            Health::Off => "Not in use",
+        }
+    }
+}
+
+/// One backend's line on the page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendHealth {
+    pub name: String,
+    pub health: Health,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_percent: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusSnapshot {
+    /// Unix time the snapshot was built.
+    pub checked_at: u64,
+    pub backends: Vec<BackendHealth>,
+    pub cookie: Health,
+    pub proxy: Health,
+    /// End of the rate-limit cooldown, while one is running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_until: Option<u64>,
+}
+
+/// The headline of the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Operational,
+    /// Some backends fail, or Instagram is rate limiting: embeds may be
+    /// missing videos or fall back to thumbnails.
+    Degraded,
+    /// Every backend in use is failing.
+    Blocked,
+}
+
+impl Verdict {
+    pub fn headline(self) -> &'static str {
+        match self {
+            Verdict::Operational => "Everything is working",
+            Verdict::Degraded => "Instagram is blocking some requests",
+            Verdict::Blocked => "Instagram is blocking this instance right now",
+        }
+    }
+}
+
+impl StatusSnapshot {
+    /// Summarizes the snapshot at `now_secs`. Backends without recent data
+    /// don't count either way.
+    pub fn verdict(&self, now_secs: u64) -> Verdict {
+        let known: Vec<Health> = self
+            .backends
+            .iter()
+            .map(|b| b.health)
+            .filter(|h| matches!(h, Health::Up | Health::Degraded | Health::Down))
+            .collect();
+        if !known.is_empty() && known.iter().all(|&h| h == Health::Down) {
+            return Verdict::Blocked;
+        }
+        let cooling_down = self.cooldown_until.is_some_and(|until| now_secs < until);
+        let failing = known.iter().any(|&h| h != Health::Up) || self.cookie == Health::Down || self.proxy == Health::Down;
+        if cooling_down || failing {
+            Verdict::Degraded
+        } else {
+            Verdict::Operational
+        }
+    }
+
+    fn stale(&self, now_secs: u64) -> bool {
+        now_secs.saturating_sub(self.checked_at) >= REFRESH_SECS
+    }
+}
+
+fn backend_enabled(backend: Backend, config: &Config) -> bool {
+    match backend {
+        Backend::Embed => true,
+        Backend::Graphql => config.graphql_enabled,
+        Backend::Legacy => config.legacy_enabled,
+        Backend::PostPage => config.post_page_enabled,
+        Backend::Papi => config.papi_enabled && config.cookie.is_some(),
+        Backend::Browser => config.browser.is_some(),
+    }
+}
+
+async fn cookie_health(config: &Config, env: &Env) -> Health {
+    let Some(cookie) = config.cookie.as_deref() else {
+        return Health::Off;
+    };
+    // The session object already knows, and checking again would spend the session
+    match session_report(env).await {
+        Ok(Some(report)) if report.status.is_flagged() => return Health::Down,
+        Ok(Some(_)) => return Health::Up,
+        Ok(None) => {}
+        Err(e) => console_log!("[status] session report failed: {:?}", e),
+    }
+    Health::from_check(check_cookie(&instrumented(env), cookie).await)
+}
+
+async fn build(config: &Config, env: &Env, now_secs: u64) -> StatusSnapshot {
+    let current = bucket_of(now_secs);
+    let mut buckets = Vec::new();
+    for bucket in current.saturating_sub(WINDOW_BUCKETS - 1)..=current {
+        buckets.push(get_backend_stats(bucket, Track::Stable, env).await.ok().flatten().unwrap_or_default());
+    }
+    let stats = BackendStats::sum(&buckets);
+
+    let proxy = match config.proxy.as_ref() {
+        Some(creds) => Health::from_check(check_proxy(&instrumented(env), creds).await),
+        None => Health::Off,
+    };
+
+    StatusSnapshot {
+        checked_at: now_secs,
+        backends: Backend::ALL
+            .into_iter()
+            .map(|backend| BackendHealth {
+                name: backend.name().to_string(),
+                health: Health::from_counter(stats.get(backend), backend_enabled(backend, config)),
+                success_percent: stats.get(backend).success_percent(),
+            })
+            .collect(),
+        cookie: cookie_health(config, env).await,
+        proxy,
+        cooldown_until: get_cooldown(env).await.ok().flatten().map(|c| c.until),
+    }
+}
+
+/// The current snapshot: the stored one while fresh, otherwise a new one,
+/// which is stored for the visitors after.
+pub async fn current_status(config: &Config, env: &Env, now_secs: u64) -> StatusSnapshot {
+    match get_status_snapshot(env).await {
+        Ok(Some(snapshot)) if !snapshot.stale(now_secs) => return snapshot,
+        Ok(_) => {}
+        Err(e) => console_log!("[status] snapshot read failed: {:?}", e),
+    }
+    let snapshot = build(config, env, now_secs).await;
+    if let Err(e) = put_status_snapshot(&snapshot, env, REFRESH_SECS).await {
+        console_log!("[status] snapshot write failed: {:?}", e);
+    }
+    snapshot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(ok: u64, failed: u64) -> Counter {
+        Counter { ok, failed, total_millis: 0 }
+    }
+
+    fn snapshot(backends: &[Health]) -> StatusSnapshot {
+        StatusSnapshot {
+            checked_at: 1000,
+            backends: backends
+                .iter()
+                .map(|&health| BackendHealth { name: "Backend".to_string(), health, success_percent: None })
+                .collect(),
+            cookie: Health::Up,
+            proxy: Health::Off,
+            cooldown_until: None,
+        }
+    }
+
+    #[test]
+    fn backend_health_follows_the_success_rate() {
+        assert_eq!(Health::from_counter(&counter(9, 1), true), Health::Up);
+        assert_eq!(Health::from_counter(&counter(1, 1), true), Health::Degraded);
+        assert_eq!(Health::from_counter(&counter(1, 9), true), Health::Down);
+        assert_eq!(Health::from_counter(&counter(0, 0), true), Health::Unknown);
+        assert_eq!(Health::from_counter(&counter(9, 1), false), Health::Off);
+    }
+
+    #[test]
+    fn verdict_tells_blocking_from_trouble() {
+        assert_eq!(snapshot(&[Health::Up, Health::Unknown, Health::Off]).verdict(1000), Verdict::Operational);
+        assert_eq!(snapshot(&[Health::Up, Health::Down]).verdict(1000), Verdict::Degraded);
+        assert_eq!(snapshot(&[Health::Down, Health::Down, Health::Off]).verdict(1000), Verdict::Blocked);
+
+        let mut cooling = snapshot(&[Health::Up]);
+        cooling.cooldown_until = Some(1200);
+        assert_eq!(cooling.verdict(1100), Verdict::Degraded);
+        assert_eq!(cooling.verdict(1200), Verdict::Operational);
+    }
+
+    #[test]
+    fn snapshots_go_stale() {
+        let snapshot = snapshot(&[]);
+        assert!(!snapshot.stale(1000 + REFRESH_SECS - 1));
+        assert!(snapshot.stale(1000 + REFRESH_SECS));
+    }
+}
@@ -0,0 +1,133 @@
+//! Caption summaries for embeds.
+//!
+//! Embeds cut captions at `CAPTION_MAX_LEN`, which leaves long-form posts
+//! with a preview that stops mid-sentence. With `SUMMARIZE_CAPTIONS` set,
+//! such captions get a one-sentence "TL;DR" from Workers AI in front of
+//! them, in the reader's language. Needs the same `AI` binding as
+//! translation.
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::i18n::Lang;
+use crate::scraper::cache::{self, set_background, Kind};
+use crate::templates::embed_html::{truncate, CAPTION_MAX_LEN};
+use crate::translate::BINDING;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+const MODEL: &str = "@cf/meta/llama-3.1-8b-instruct";
+
+/// Longest summary kept, so most of the description is still the caption.
+const MAX_SUMMARY_LEN: usize = 160;
+
+/// Comfortably more than a sentence; the model stops on its own before.
+const MAX_TOKENS: u32 = 80;
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct SummaryInput<'a> {
+    messages: [Message<'a>; 2],
+    max_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct SummaryOutput {
+    response: String,
+}
+
+/// Returns `true` if the embed would cut the caption short.
+pub fn needs_summary(caption: &str) -> bool {
+    caption.len() > CAPTION_MAX_LEN
+}
+
+fn language_name(lang: Lang) -> &'static str {
+    match lang {
+        Lang::En => "English",
+        Lang::Es => "Spanish",
+        Lang::Pt => "Portuguese",
+        Lang::De => "German",
+        Lang::Fr => "French",
+        Lang::Ru => "Russian",
+    }
+}
+
+fn instructions(lang: Lang) -> String {
+    format!(
+        "Summarize the Instagram caption you are given in one short sentence, in {}. \
+         Reply with the sentence only, without hashtags, quotes or a preamble.",
+        language_name(lang)
+    )
+}
+
+/// Tidies the model's reply into a single line, dropping a "TL;DR" the
+/// model may have added itself. `None` if nothing is left.
+fn clean(response: &str) -> Option<String> {
+    let line = response.split_whitespace().collect::<Vec<_>>().join(" ");
+    let line = line.strip_prefix("TL;DR:").unwrap_or(&line).trim().trim_matches('"').trim();
+    (!line.is_empty()).then(|| truncate(line, MAX_SUMMARY_LEN))
+}
+
+/// The summary line followed by the original caption, which the embed then
+/// truncates as usual.
+fn with_summary(summary: &str, caption: &str) -> String {
+    format!("TL;DR: {summary}\n\n{caption}")
+}
+
+/// The caption with a summary in `lang` in front, or `None` if
+/// summarizing failed.
+pub async fn summarize_caption(caption: &str, post_id: &str, lang: Lang, env: &Env, state: &AppState) -> Option<String> {
+    let ai = env.ai(BINDING).ok()?;
+
+    match cache::get::<String>(Kind::Summary, &format!("{post_id}:{}", lang.code()), env).await {
+        Ok(Some(cached)) => return Some(with_summary(&cached, caption)),
+        Ok(None) => {}
+        Err(e) => console_log!("[summarize] cache read failed: {:?}", e),
+    }
+
+    let system = instructions(lang);
+    let input = SummaryInput {
+        messages: [Message { role: "system", content: &system }, Message { role: "user", content: caption }],
+        max_tokens: MAX_TOKENS,
+    };
+    match ai.run::<_, SummaryOutput>(MODEL, input).await {
+        Ok(output) => {
+            let summary = clean(&output.response)?;
+            set_background(Kind::Summary, &format!("{post_id}:{}", lang.code()), &summary, env, state);
+            Some(with_summary(&summary, caption))
+        }
+        Err(e) => {
+            console_log!("[summarize] {} failed for {}: {:?}", lang.code(), post_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_captions_the_embed_would_cut_are_summarized() {
+        assert!(!needs_summary(&"a".repeat(CAPTION_MAX_LEN)));
+        assert!(needs_summary(&"a".repeat(CAPTION_MAX_LEN + 1)));
+    }
+
+    #[test]
+    fn replies_are_tidied_into_one_line() {
+        assert_eq!(clean("  TL;DR: \"A cat\nnaps all day.\" ").as_deref(), Some("A cat naps all day."));
+        assert_eq!(clean(" \n "), None);
+        assert!(clean(&"word ".repeat(100)).unwrap().len() <= MAX_SUMMARY_LEN + 3);
+    }
+
+    #[test]
+    fn summary_leads_the_caption() {
+        assert_eq!(with_summary("Naps.", "Long story"), "TL;DR: Naps.\n\nLong story");
+        assert!(instructions(Lang::De).contains("in German"));
+    }
+}
@@ -0,0 +1,243 @@
+use maud::{html, Markup, DOCTYPE};
+
+use crate::config::Config;
+use crate::metrics::{Route, RouteStats};
+use crate::scraper::stats::{Backend, BackendStats, Counter, BUCKET_SECS};
+
+/// Chart geometry: one bar slot per hourly bucket.
+const SLOT_WIDTH: u64 = 20;
+const BAR_WIDTH: u64 = 16;
+const CHART_HEIGHT: u64 = 100;
+
+/// Renders the backend health dashboard.
+///
+/// `buckets` are `(bucket, stats)` pairs, oldest first, with empty stats for
+/// hours that saw no scrapes. `canary` totals the canary path's scrapes over
+/// the same window, when one is configured; they aren't in `buckets`.
+/// `routes` are the requests served over the same window. Charts are inline SVG, so the page needs no scripts or external
+/// assets beyond the stylesheet.
+pub fn render_backends(
+    buckets: &[(u64, BackendStats)],
+    canary: Option<&BackendStats>,
+    routes: &RouteStats,
+    config: &Config,
+) -> String {
+    let totals = BackendStats::sum(buckets.iter().map(|(_, stats)| stats));
+
+    html! {
+        (DOCTYPE)
+        html lang="en" data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { "Backends · " (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { "Backends" }
+                        p { "Live scrapes over the last 24 hours (UTC). Cache hits aren't counted." }
+                    }
+
+                    table {
+                        thead {
+                            tr { th { "Backend" } th { "Succeeded" } th { "Failed" } th { "Success rate" } th { "Avg latency" } }
+                        }
+                        tbody {
+                            @for backend in Backend::ALL {
+                                (summary_row(backend.name(), totals.get(backend)))
+                            }
+                        }
+                    }
+
+                    @if let (Some(canary), Some(settings)) = (canary, &config.canary) {
+                        section {
+                            h2 { "Canary" }
+                            p { (settings.percent) "% of live scrapes take the canary path. Its calls are counted here, not above." }
+                            table {
+                                thead {
+                                    tr { th { "Backend" } th { "Succeeded" } th { "Failed" } th { "Success rate" } th { "Avg latency" } }
+                                }
+                                tbody {
+                                    @for backend in Backend::ALL {
+                                        (summary_row(backend.name(), canary.get(backend)))
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    @for backend in Backend::ALL {
+                        section {
+                            h2 { (backend.name()) }
+                            (chart(buckets, backend))
+                        }
+                    }
+
+                    p {
+                        small {
+                            span style="color: #2e7d32" { "■" } " succeeded · "
+                            span style="color: #c62828" { "■" } " failed · "
+                            span style="color: #1565c0" { "—" } " avg latency (scaled per chart)"
+                        }
+                    }
+
+                    section {
+                        h2 { "Traffic" }
+                        p { "Requests served per route, cache hits included. Errors are 5xx answers." }
+                        table {
+                            thead {
+                                tr { th { "Route" } th { "Requests" } th { "Errors" } th { "Avg latency" } }
+                            }
+                            tbody {
+                                @for route in Route::ALL {
+                                    (route_row(route.name(), routes.get(route)))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+fn summary_row(name: &str, counter: &Counter) -> Markup {
+    html! {
+        tr {
+            td { (name) }
+            td { (counter.ok) }
+            td { (counter.failed) }
+            td {
+                @match counter.success_percent() {
+                    Some(percent) => { (percent) "%" }
+                    None => "—",
+                }
+            }
+            td {
+                @match counter.avg_millis() {
+                    Some(ms) => { (ms) " ms" }
+                    None => "—",
+                }
+            }
+        }
+    }
+}
+
+fn route_row(name: &str, counter: &Counter) -> Markup {
+    html! {
+        tr {
+            td { (name) }
+            td { (counter.total()) }
+            td { (counter.failed) }
+            td {
+                @match counter.avg_millis() {
+                    Some(ms) => { (ms) " ms" }
+                    None => "—",
+                }
+            }
+        }
+    }
+}
+
+/// Stacked success/failure bars per hour, with average latency as a line.
+fn chart(buckets: &[(u64, BackendStats)], backend: Backend) -> Markup {
+    let counters: Vec<&Counter> = buckets.iter().map(|(_, stats)| stats.get(backend)).collect();
+    let max_total = counters.iter().map(|c| c.total()).max().unwrap_or(0).max(1);
+    let max_latency = counters.iter().filter_map(|c| c.avg_millis()).max().unwrap_or(0).max(1);
+    let scale = |value: u64, max: u64| value * CHART_HEIGHT / max;
+
+    let latency_points: Vec<String> = counters
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let ms = c.avg_millis()?;
+            Some(format!("{},{}", i as u64 * SLOT_WIDTH + SLOT_WIDTH / 2, CHART_HEIGHT - scale(ms, max_latency)))
+        })
+        .collect();
+
+    html! {
+        svg xmlns="http://www.w3.org/2000/svg" role="img" width="100%"
+            viewBox={ "0 0 " (counters.len() as u64 * SLOT_WIDTH) " " (CHART_HEIGHT) } preserveAspectRatio="none" {
+            @for (i, ((bucket, _), counter)) in buckets.iter().zip(&counters).enumerate() {
+                @let x = i as u64 * SLOT_WIDTH + (SLOT_WIDTH - BAR_WIDTH) / 2;
+                @let ok_height = scale(counter.ok, max_total);
+                @let failed_height = scale(counter.failed, max_total);
+                g {
+                    title {
+                        (format!("{:02}:00", bucket * BUCKET_SECS / 3600 % 24)) " UTC: "
+                        (counter.ok) " ok, " (counter.failed) " failed"
+                        @if let Some(ms) = counter.avg_millis() { ", " (ms) " ms avg" }
+                    }
+                    rect x=(x) y=(CHART_HEIGHT - ok_height) width=(BAR_WIDTH) height=(ok_height) fill="#2e7d32" {}
+                    rect x=(x) y=(CHART_HEIGHT - ok_height - failed_height) width=(BAR_WIDTH) height=(failed_height) fill="#c62828" {}
+                }
+            }
+            @if latency_points.len() > 1 {
+                polyline points=(latency_points.join(" ")) fill="none" stroke="#1565c0" stroke-width="2" {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::canary::Canary;
+    use crate::scraper::stats::Attempt;
+
+    fn buckets() -> Vec<(u64, BackendStats)> {
+        let mut busy = BackendStats::default();
+        for ok in [true, true, true, false] {
+            busy.record(&Attempt { backend: Backend::Graphql, ok, millis: 400, doc_id: None });
+        }
+        busy.record(&Attempt { backend: Backend::Embed, ok: false, millis: 200, doc_id: None });
+        vec![(472221, BackendStats::default()), (472222, busy)]
+    }
+
+    #[test]
+    fn summarizes_each_backend() {
+        let html = render_backends(&buckets(), None, &RouteStats::default(), &Config::default());
+        assert!(html.contains("<td>GraphQL</td><td>3</td><td>1</td><td>75%</td><td>400 ms</td>"));
+        assert!(html.contains("<td>Private API</td><td>0</td><td>0</td><td>—</td><td>—</td>"));
+    }
+
+    #[test]
+    fn shows_the_canary_apart() {
+        let mut canary = BackendStats::default();
+        canary.record(&Attempt { backend: Backend::Graphql, ok: false, millis: 900, doc_id: None });
+        assert!(!render_backends(&buckets(), Some(&canary), &RouteStats::default(), &Config::default()).contains("Canary"));
+
+        let config = Config {
+            canary: Some(Canary { percent: 5, doc_ids: Some(vec!["999".to_string()]), graphql_path: None, proxy: None }),
+            ..Config::default()
+        };
+        let html = render_backends(&buckets(), Some(&canary), &RouteStats::default(), &config);
+        assert!(html.contains("5% of live scrapes take the canary path"));
+        assert!(html.contains("<td>GraphQL</td><td>0</td><td>1</td><td>0%</td><td>900 ms</td>"));
+        // The stable totals are unchanged
+        assert!(html.contains("<td>GraphQL</td><td>3</td><td>1</td><td>75%</td><td>400 ms</td>"));
+    }
+
+    #[test]
+    fn lists_traffic_per_route() {
+        let mut routes = RouteStats::default();
+        routes.record(Route::Videos, false, 900);
+        routes.record(Route::Videos, true, 100);
+        let html = render_backends(&buckets(), None, &routes, &Config::default());
+        assert!(html.contains("<td>/videos</td><td>2</td><td>1</td><td>500 ms</td>"));
+        assert!(html.contains("<td>/oembed</td><td>0</td><td>0</td><td>—</td>"));
+    }
+
+    #[test]
+    fn bars_scale_to_the_busiest_hour() {
+        let chart = chart(&buckets(), Backend::Graphql).into_string();
+        // 3 of 4 calls succeeded in the busiest hour: 75px green under 25px red
+        assert!(chart.contains(r##"<rect x="22" y="25" width="16" height="75" fill="#2e7d32">"##));
+        assert!(chart.contains(r##"<rect x="22" y="0" width="16" height="25" fill="#c62828">"##));
+        assert!(chart.contains("22:00 UTC: 3 ok, 1 failed, 400 ms avg"));
+    }
+}
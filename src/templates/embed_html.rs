@@ -1,8 +1,27 @@
-use crate::scraper::types::{InstaData, MediaType};
-use crate::utils::escape::escape_html;
+use maud::{html, PreEscaped, DOCTYPE};
 
-/// Truncates a string to `max_len` characters, appending "..." if truncated.
-fn truncate(s: &str, max_len: usize) -> String {
+use crate::config::Config;
+use crate::scraper::browser::{SCREENSHOT_HEIGHT, SCREENSHOT_WIDTH};
+use crate::scraper::types::{Comment, InstaData, Media, MediaType, PollOption, PostKind, Sticker};
+use crate::utils::bot_detect::Platform;
+use crate::utils::transform::{animated_preview_url, muted_video_url};
+
+use super::meta::{Card, MetaTags};
+
+/// Description note for posts Instagram covers with a sensitivity warning.
+const SENSITIVE_NOTE: &str = "⚠️ Sensitive content";
+
+/// Most slides shown at once for a carousel; Discord lays out four as a 2×2 gallery.
+const GALLERY_MAX: usize = 4;
+
+/// Longest caption an embed shows, in bytes.
+pub const CAPTION_MAX_LEN: usize = 300;
+
+/// Title suffix for videos whose stream URL Instagram withheld.
+const BLOCKED_VIDEO_TITLE: &str = "▶ Video — open on Instagram";
+
+/// Truncates a string to `max_len` bytes, appending "..." if truncated.
+pub fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
     } else {
@@ -16,11 +35,11 @@ fn truncate(s: &str, max_len: usize) -> String {
 }
 
 /// Formats a number with comma separators (e.g. 1234567 -> "1,234,567").
-fn format_number(n: u64) -> String {
+pub fn format_number(n: u64) -> String {
     let s = n.to_string();
     let mut result = String::with_capacity(s.len() + s.len() / 3);
     for (i, ch) in s.chars().enumerate() {
-        if i > 0 && (s.len() - i) % 3 == 0 {
+        if i > 0 && (s.len() - i).is_multiple_of(3) {
             result.push(',');
         }
         result.push(ch);
@@ -28,14 +47,43 @@ fn format_number(n: u64) -> String {
     result
 }
 
+/// Formats an account for titles: `"Jane Doe (@janedoe) ✓"`, or just
+/// `"@janedoe"` when the display name is unknown.
+pub fn author_label(full_name: Option<&str>, username: &str, verified: bool) -> String {
+    let badge = if verified { " ✓" } else { "" };
+    match full_name.filter(|n| !n.is_empty()) {
+        Some(name) => format!("{} (@{}){}", name, username, badge),
+        None => format!("@{}{}", username, badge),
+    }
+}
+
+/// Formats a post's author for titles, crediting a collab's co-authors:
+/// `"Jane Doe (@janedoe) with @johndoe"`.
+pub fn post_author(data: &InstaData) -> String {
+    let author = author_label(data.full_name.as_deref(), &data.username, data.is_verified);
+    if data.coauthors.is_empty() {
+        return author;
+    }
+    let coauthors: Vec<String> = data.coauthors.iter().map(|username| format!("@{username}")).collect();
+    format!("{author} with {}", coauthors.join(", "))
+}
+
 /// Builds the stats suffix for the og:title tag.
 fn build_stats_suffix(data: &InstaData, media_count: usize, img_index: Option<usize>) -> String {
     let mut parts = Vec::new();
+    let current = img_index.unwrap_or(1).clamp(1, media_count.max(1));
 
-    if data.is_video {
-        if let Some(views) = data.video_view_count {
-            parts.push(format!("{} views", format_number(views)));
-        }
+    // In a carousel, view counts go with its video slides only
+    let video = match data.media.get(current - 1) {
+        Some(media) if media_count > 1 => media.media_type == MediaType::Video,
+        _ => data.is_video,
+    };
+    if video {
+        let views = data.video_view_count.map(|n| format!("{} views", format_number(n)));
+        let plays = data.play_count.map(|n| format!("{} plays", format_number(n)));
+        // Reels count plays; views, when present, lag far behind
+        let metric = if data.kind == Some(PostKind::Reel) { plays.or(views) } else { views.or(plays) };
+        parts.extend(metric);
     }
 
     if let Some(likes) = data.like_count {
@@ -47,8 +95,7 @@ fn build_stats_suffix(data: &InstaData, media_count: usize, img_index: Option<us
     }
 
     if media_count > 1 {
-        let idx = img_index.unwrap_or(1);
-        parts.push(format!("Slide {}/{}", idx, media_count));
+        parts.push(format!("Slide {}/{}", current, media_count));
     }
 
     if parts.is_empty() {
@@ -58,18 +105,86 @@ fn build_stats_suffix(data: &InstaData, media_count: usize, img_index: Option<us
     }
 }
 
-/// Appends a `<meta>` tag to the HTML buffer.
-fn push_meta(buf: &mut String, attr: &str, name: &str, content: &str) {
-    buf.push_str(&format!(
-        "<meta {}=\"{}\" content=\"{}\">\n",
-        attr, name, content,
-    ));
+/// Formats a poll's options, with each one's share of the votes once any
+/// are known: `"Yes 90% · No 10%"`.
+fn poll_results(options: &[PollOption]) -> String {
+    let total: u64 = options.iter().filter_map(|o| o.votes).sum();
+    options
+        .iter()
+        .map(|o| match o.votes {
+            Some(votes) if total > 0 => format!("{} {}%", o.text, (votes * 100 + total / 2) / total),
+            _ => o.text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+/// One description line per story sticker, e.g. `"❓ Ask me anything"`.
+fn sticker_lines(stickers: &[Sticker]) -> Option<String> {
+    let lines: Vec<String> = stickers
+        .iter()
+        .map(|sticker| match sticker {
+            Sticker::Poll { question, options } if question.is_empty() => format!("📊 {}", poll_results(options)),
+            Sticker::Poll { question, options } => format!("📊 {} — {}", question, poll_results(options)),
+            Sticker::Question { text } => format!("❓ {}", text),
+            Sticker::Link { url, label } => format!("🔗 {}", label.as_deref().unwrap_or(url)),
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Builds the carousel navigation line for the description, e.g.
+/// `"◀ ?img_index=1 · ▶ ?img_index=3"`, so chat users know other slides exist.
+///
+/// `current` is 1-based. Returns `None` for single-media posts.
+fn slide_hint(current: usize, media_count: usize) -> Option<String> {
+    if media_count <= 1 {
+        return None;
+    }
+
+    let mut links = Vec::new();
+    if current > 1 {
+        links.push(format!("◀ ?img_index={}", current - 1));
+    }
+    if current < media_count {
+        links.push(format!("▶ ?img_index={}", current + 1));
+    }
+    Some(links.join(" · "))
+}
+
+/// How the requesting app gets videos: `gif` swaps in a muted copy, for
+/// GIF-style looping clips, and `platform` picks the fallback (see `VideoPlan`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Playback {
+    pub gif: bool,
+    pub platform: Platform,
+}
+
+/// Everything about an embed request that changes the page, besides the
+/// post and the config.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbedOptions<'a> {
+    /// This instance's host, for the URLs pointing back at it.
+    pub host: &'a str,
+    /// 1-based. If `None` or out of range, the first media item is shown.
+    pub img_index: Option<usize>,
+    /// Decides how a selected video is embedded.
+    pub playback: Playback,
+    /// Turns the page into a comment permalink card: the comment replaces
+    /// the caption, over the post's media.
+    pub comment: Option<&'a Comment>,
+    /// Appends the post's most-liked comment under the caption.
+    pub show_top_comment: bool,
 }
 
 /// Renders a full HTML embed page with OpenGraph and Twitter Card meta tags.
+/// A pure function of its arguments, which the golden files under
+/// `fixtures/golden/` pin down (see `super::golden`).
 ///
-/// `img_index` is 1-based. If `None` or out of range, defaults to the first media item.
-pub fn render_embed(data: &InstaData, host: &str, img_index: Option<usize>) -> String {
+/// Returned as `[head, body]` chunks, so the `<head>` (all a crawler actually
+/// reads) can be streamed out before the body.
+pub fn render_embed_chunks(data: &InstaData, config: &Config, options: &EmbedOptions) -> [String; 2] {
+    let EmbedOptions { host, img_index, playback, comment, show_top_comment } = *options;
     let media_count = data.media.len();
 
     // Resolve the target media item (img_index is 1-based)
@@ -79,111 +194,452 @@ pub fn render_embed(data: &InstaData, host: &str, img_index: Option<usize>) -> S
         .min(media_count.saturating_sub(1));
 
     let media_item = data.media.get(resolved_index);
+    let video = media_item
+        .filter(|m| m.media_type == MediaType::Video)
+        .map(|m| video_plan(m, data, resolved_index + 1, config, host, playback));
 
-    let username = escape_html(&data.username);
-    let post_id = escape_html(&data.post_id);
-
+    // Without a caption, a description of the image is the next best thing
     let caption = data
         .caption
         .as_deref()
-        .map(|c| escape_html(&truncate(c, 300)))
+        .filter(|c| !c.trim().is_empty())
+        .or_else(|| media_item.and_then(|m| m.alt_text.as_deref()))
+        .map(|c| truncate(c, CAPTION_MAX_LEN))
         .unwrap_or_default();
 
-    let stats_suffix = escape_html(&build_stats_suffix(data, media_count, img_index));
-    let title = format!("@{}{}", username, stats_suffix);
+    let caption = match comment {
+        Some(comment) => truncate(&format!("@{}: {}", comment.username, comment.text), CAPTION_MAX_LEN),
+        None => caption,
+    };
 
-    let instagram_url = format!("https://www.instagram.com/p/{}/", post_id);
-    let oembed_url = format!(
-        "https://{}/oembed?text=@{}&amp;url=https://instagram.com/p/{}",
-        escape_html(host),
-        username,
-        post_id,
-    );
+    let caption = match sticker_lines(&data.stickers).filter(|_| comment.is_none()) {
+        Some(lines) if caption.is_empty() => lines,
+        Some(lines) => format!("{caption}\n\n{lines}"),
+        None => caption,
+    };
 
-    let mut html = String::with_capacity(4096);
+    let caption = match data.top_comment.as_ref().filter(|_| show_top_comment && comment.is_none()) {
+        Some(top) => {
+            let line = truncate(&format!("💬 @{}: {}", top.username, top.text), 200);
+            if caption.is_empty() {
+                line
+            } else {
+                format!("{caption}\n\n{line}")
+            }
+        }
+        None => caption,
+    };
+
+    let description = match slide_hint(resolved_index + 1, media_count) {
+        Some(hint) if caption.is_empty() => hint,
+        Some(hint) => format!("{caption}\n\n{hint}"),
+        None => caption,
+    };
+
+    let description = if data.sensitive {
+        format!("{SENSITIVE_NOTE}\n\n{description}").trim_end().to_string()
+    } else {
+        description
+    };
 
-    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    // A blocked video can't play here, so say so instead of showing stats
+    let author = post_author(data);
+    let author = match data.kind.and_then(PostKind::label) {
+        Some(kind) => format!("{kind} by {author}"),
+        None => author,
+    };
+    // Hashtag embeds say which hashtag's top post this is
+    let author = match &data.hashtag {
+        Some(tag) => format!("#{tag} · {author}"),
+        None => author,
+    };
+    let title = if let Some(comment) = comment {
+        format!("{} | 💬 Comment by @{}", author, comment.username)
+    } else if media_item.is_some_and(Media::is_blocked_video) {
+        format!("{} | {}", author, BLOCKED_VIDEO_TITLE)
+    } else {
+        format!("{}{}", author, build_stats_suffix(data, media_count, img_index))
+    };
+
+    let instagram_url = match comment {
+        Some(comment) => format!("https://www.instagram.com/p/{}/c/{}/", data.post_id, comment.id),
+        None => data.instagram_url(),
+    };
+    let mut oembed_url = format!(
+        "https://{}/oembed?text=@{}&url=https://instagram.com/p/{}",
+        host, data.username, data.post_id,
+    );
+    if let Some(avatar) = &data.avatar_url {
+        oembed_url.push_str("&avatar=");
+        oembed_url.extend(url::form_urlencoded::byte_serialize(avatar.as_bytes()));
+    }
 
-    // Core OG tags
-    push_meta(&mut html, "property", "theme-color", "#E1306C");
-    push_meta(&mut html, "property", "og:site_name", "Cattgram");
-    push_meta(&mut html, "property", "og:title", &title);
-    push_meta(&mut html, "property", "og:description", &caption);
-    push_meta(&mut html, "property", "og:url", &instagram_url);
+    let mut tags = MetaTags::default();
+    tags.property("theme-color", &config.theme_color)
+        .property("og:site_name", &config.site_name)
+        .property("og:title", &title)
+        .property("og:description", &description)
+        .property("og:url", &instagram_url)
+        .property("og:type", og_type(video.as_ref()));
 
     // Media-specific tags
-    if let Some(media) = media_item {
-        let width_str = media.width.unwrap_or(0).to_string();
-        let height_str = media.height.unwrap_or(0).to_string();
-
-        match media.media_type {
-            MediaType::Image => {
-                let image_url = escape_html(&media.url);
-                push_meta(&mut html, "property", "og:image", &image_url);
-                push_meta(&mut html, "property", "og:image:width", &width_str);
-                push_meta(&mut html, "property", "og:image:height", &height_str);
-                push_meta(&mut html, "name", "twitter:card", "summary_large_image");
-                push_meta(&mut html, "name", "twitter:image", &image_url);
+    if let Some(slides) = gallery(&data.media, img_index) {
+        gallery_meta(&mut tags, slides);
+    } else if let Some(media) = media_item {
+        media_meta(&mut tags, media, config, host, video.as_ref());
+    } else if let Some(avatar) = &data.avatar_url {
+        // Text-only post: the author's avatar stands in for media
+        tags.property("og:image", avatar).card(Card::Summary { image: None });
+    }
+
+    // The <html> element spans both chunks, so its tags are emitted raw.
+    let head = html! {
+        (DOCTYPE)
+        (PreEscaped("<html lang=\"en\">"))
+        head {
+            meta charset="utf-8";
+            (tags)
+            link rel="canonical" href=(instagram_url);
+            link rel="alternate" href=(oembed_url) type="application/json+oembed";
+            meta http-equiv="refresh" content={ "0;url=" (instagram_url) };
+            title { (config.site_name) }
+        }
+    };
+
+    let view_url = format!("https://{}/view/{}", host, data.post_id);
+    let body = html! {
+        body {
+            p { "Redirecting to Instagram..." }
+            p { a href=(view_url) { "View on " (config.site_name) } }
+        }
+        (PreEscaped("</html>"))
+    };
+
+    [head.into_string(), body.into_string()]
+}
+
+/// Renders the card for a story that is past its 24 hours, or can no longer be
+/// scraped. Crawlers get an explanation instead of a broken or missing preview.
+pub fn render_expired_story(username: &str, config: &Config) -> String {
+    let title = if username.is_empty() {
+        "Story expired".to_string()
+    } else {
+        format!("@{} | Story expired", username)
+    };
+    let instagram_url = format!("https://www.instagram.com/stories/{}/", username);
+    render_notice(
+        &title,
+        "This story is no longer available. Instagram stories disappear after 24 hours.",
+        &instagram_url,
+        None,
+        config,
+    )
+}
+
+/// Renders the card for a post Instagram restricts in the region it was
+/// fetched from, in place of a bare redirect.
+pub fn render_geo_blocked(post_id: &str, config: &Config) -> String {
+    render_notice(
+        "Not available in this region",
+        "Instagram restricts this post in some countries, so no preview could be loaded. It may still open on Instagram where you are.",
+        &format!("https://www.instagram.com/p/{}/", post_id),
+        None,
+        config,
+    )
+}
+
+/// Renders the degraded card sent when the scrape ran out of time
+/// (`SCRAPE_BUDGET_MS`), so the crawler gets an answer before it gives up.
+pub fn render_timed_out(instagram_url: &str, config: &Config) -> String {
+    render_notice(
+        "Preview unavailable right now",
+        "Instagram took too long to answer, so no preview could be loaded. Try sharing the link again in a minute.",
+        instagram_url,
+        None,
+        config,
+    )
+}
+
+/// Renders the card bots get for any embed in maintenance mode, pointing
+/// people at the status page so they don't think their link is wrong.
+pub fn render_maintenance(instagram_url: &str, status_url: &str, config: &Config) -> String {
+    render_notice(
+        "Down for maintenance",
+        &format!(
+            "{} is temporarily down for maintenance, so no preview could be loaded. The link is fine and still opens on Instagram. Status: {}",
+            config.site_name, status_url
+        ),
+        instagram_url,
+        None,
+        config,
+    )
+}
+
+/// `"Slide 5 does not exist (post has 3 slides)"`, for strict `img_index`.
+pub fn missing_slide_message(requested: usize, media_count: usize) -> String {
+    let plural = if media_count == 1 { "" } else { "s" };
+    format!("Slide {requested} does not exist (post has {media_count} slide{plural})")
+}
+
+/// Renders the card for an `img_index` past the post's last slide, in
+/// strict mode (see `missing_slide`).
+pub fn render_missing_slide(instagram_url: &str, requested: usize, media_count: usize, config: &Config) -> String {
+    render_notice(
+        &missing_slide_message(requested, media_count),
+        "The link asks for a slide this post doesn't have. Slides are numbered from 1.",
+        instagram_url,
+        None,
+        config,
+    )
+}
+
+/// Renders the card for a post nothing could be scraped from, showing a
+/// screenshot of its page (served from `image_url`) instead of the media.
+pub fn render_screenshot(post_id: &str, image_url: &str, config: &Config) -> String {
+    render_notice(
+        "View on Instagram",
+        "The post's media couldn't be loaded, so this is a snapshot of its page.",
+        &format!("https://www.instagram.com/p/{}/", post_id),
+        Some(image_url),
+        config,
+    )
+}
+
+/// A card explaining why there's no preview, redirecting browsers
+/// to `instagram_url`. Text-only unless given an `image`, which is assumed
+/// to be a Browser Rendering screenshot.
+fn render_notice(title: &str, description: &str, instagram_url: &str, image: Option<&str>, config: &Config) -> String {
+    let mut tags = MetaTags::default();
+    tags.property("theme-color", &config.theme_color)
+        .property("og:site_name", &config.site_name)
+        .property("og:title", title)
+        .property("og:description", description)
+        .property("og:url", instagram_url);
+    match image {
+        Some(image) => {
+            tags.property("og:image", image)
+                .property("og:image:width", SCREENSHOT_WIDTH)
+                .property("og:image:height", SCREENSHOT_HEIGHT)
+                .card(Card::LargeImage { image });
+        }
+        None => {
+            tags.card(Card::Summary { image: None });
+        }
+    }
+
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                (tags)
+                link rel="canonical" href=(instagram_url);
+                meta http-equiv="refresh" content={ "0;url=" (instagram_url) };
+                title { (config.site_name) }
             }
-            MediaType::Video => {
-                let video_url = escape_html(&media.url);
-                push_meta(&mut html, "property", "og:video", &video_url);
-                push_meta(&mut html, "property", "og:video:type", "video/mp4");
-                push_meta(&mut html, "property", "og:video:width", &width_str);
-                push_meta(&mut html, "property", "og:video:height", &height_str);
-                push_meta(&mut html, "name", "twitter:card", "player");
-                push_meta(&mut html, "name", "twitter:player:stream", &video_url);
-                push_meta(
-                    &mut html,
-                    "name",
-                    "twitter:player:stream:content_type",
-                    "video/mp4",
-                );
-
-                if let Some(ref thumbnail) = media.thumbnail_url {
-                    push_meta(&mut html, "property", "og:image", &escape_html(thumbnail));
-                }
+            body {
+                p { "Redirecting to Instagram..." }
             }
         }
     }
+    .into_string()
+}
 
-    html.push_str(&format!(
-        "<link rel=\"alternate\" href=\"{}\" type=\"application/json+oembed\">\n",
-        oembed_url,
-    ));
-    html.push_str(&format!(
-        "<meta http-equiv=\"refresh\" content=\"0;url={}\">\n",
-        instagram_url,
-    ));
-    html.push_str("<title>Cattgram</title>\n</head>\n<body>\n");
-    html.push_str("<p>Redirecting to Instagram...</p>\n");
-    html.push_str("</body>\n</html>");
+/// Renders the page search engine crawlers get instead of an embed: no post
+/// data, marked `noindex`, pointing at the original on Instagram.
+pub fn render_search_stub(instagram_url: &str, config: &Config) -> String {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="robots" content="noindex, nofollow";
+                link rel="canonical" href=(instagram_url);
+                title { (config.site_name) }
+            }
+            body {
+                p { a href=(instagram_url) { (instagram_url) } }
+            }
+        }
+    }
+    .into_string()
+}
+
+/// The slides to show together: the first `GALLERY_MAX` of an image-only
+/// carousel, unless `img_index` picks one. A video can't share the card, so
+/// carousels with any get the single selected slide.
+fn gallery(media: &[Media], img_index: Option<usize>) -> Option<&[Media]> {
+    let image_only = media.iter().all(|m| m.media_type == MediaType::Image);
+    (img_index.is_none() && media.len() > 1 && image_only).then(|| &media[..media.len().min(GALLERY_MAX)])
+}
+
+/// Adds one `og:image` per slide, in order.
+fn gallery_meta(tags: &mut MetaTags, slides: &[Media]) {
+    for media in slides {
+        tags.property("og:image", &media.url);
+        if let Some((w, h)) = known_dimensions(media) {
+            tags.property("og:image:width", w).property("og:image:height", h);
+        }
+        if let Some(alt) = &media.alt_text {
+            tags.property("og:image:alt", alt);
+        }
+    }
+    if let Some(first) = slides.first() {
+        tags.card(Card::LargeImage { image: &first.url });
+        if let Some(alt) = &first.alt_text {
+            tags.name("twitter:image:alt", alt);
+        }
+    }
+}
+
+/// Returns the media's `(width, height)` if both are known and non-zero.
+///
+/// Size hints are only worth emitting when real: a wrong aspect ratio is
+/// worse than none, since clients reserve space before loading the media.
+fn known_dimensions(media: &Media) -> Option<(u32, u32)> {
+    match (media.width, media.height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+        _ => None,
+    }
+}
+
+/// How the embed presents the selected video. The first option the
+/// platform plays wins, in this order: the stream itself, the stream
+/// proxied through `/videos` on this host, an iframe player, an animated
+/// preview, then the thumbnail alone.
+#[derive(Debug, Clone, PartialEq)]
+enum VideoPlan {
+    /// `og:video` with this URL: Instagram's, or this host's.
+    Stream(String),
+    /// `twitter:player` with Instagram's embed page.
+    Player(String),
+    AnimatedPreview(String),
+    Thumbnail,
+}
+
+/// Picks the `VideoPlan` for `media`, the post's `media_num`th item (1-based).
+fn video_plan(media: &Media, data: &InstaData, media_num: usize, config: &Config, host: &str, playback: Playback) -> VideoPlan {
+    let platform = playback.platform;
+    if !media.is_blocked_video() {
+        // GIF mode serves a silent transform of the video; clients loop short muted clips
+        let stream_url = if playback.gif { muted_video_url(host, &media.url) } else { media.url.clone() };
+        if platform.plays_stream(&stream_url) {
+            return VideoPlan::Stream(stream_url);
+        }
+        if platform.plays_proxied_stream() {
+            return VideoPlan::Stream(format!("https://{host}/videos/{}/{media_num}?proxy=true", data.post_id));
+        }
+    }
+    // A player card needs a size and an image to fall back on
+    if platform.plays_iframe() && known_dimensions(media).is_some() && media.thumbnail_url.is_some() {
+        return VideoPlan::Player(format!("https://www.instagram.com/p/{}/embed/", data.post_id));
+    }
+    if !media.is_blocked_video() && config.animated_preview {
+        return VideoPlan::AnimatedPreview(animated_preview_url(host, &media.url));
+    }
+    VideoPlan::Thumbnail
+}
+
+/// `video.other` when the embed plays the selected video, else `article`:
+/// a reel whose stream Instagram withheld previews as a picture.
+fn og_type(video: Option<&VideoPlan>) -> &'static str {
+    match video {
+        Some(VideoPlan::Stream(_) | VideoPlan::Player(_)) => "video.other",
+        _ => "article",
+    }
+}
+
+/// Adds the thumbnail as a large-image card, the fallback when the video
+/// itself can't be embedded.
+fn thumbnail_card(tags: &mut MetaTags, media: &Media) {
+    if let Some(thumbnail) = &media.thumbnail_url {
+        tags.property("og:image", thumbnail);
+        if let Some((w, h)) = known_dimensions(media) {
+            tags.property("og:image:width", w).property("og:image:height", h);
+        }
+        tags.card(Card::LargeImage { image: thumbnail });
+    }
+}
+
+/// Adds the image or video tags for the selected media item. `video` is
+/// set for videos.
+fn media_meta(tags: &mut MetaTags, media: &Media, config: &Config, host: &str, video: Option<&VideoPlan>) {
+    let width = media.width.unwrap_or(0);
+    let height = media.height.unwrap_or(0);
+
+    match video {
+        None => {
+            tags.property("og:image", &media.url)
+                .property("og:image:width", width)
+                .property("og:image:height", height)
+                .card(Card::LargeImage { image: &media.url });
+            if let Some((w, h)) = known_dimensions(media) {
+                tags.name("twitter:image:width", w).name("twitter:image:height", h);
+            }
+            if let Some(alt) = &media.alt_text {
+                tags.property("og:image:alt", alt).name("twitter:image:alt", alt);
+            }
+        }
+        Some(VideoPlan::Stream(video_url)) => {
+            tags.property("og:video", video_url).property("og:video:url", video_url);
+            // Telegram and some Matrix previewers only inline the secure_url variant
+            if video_url.starts_with("https://") {
+                tags.property("og:video:secure_url", video_url);
+            }
+            tags.property("og:video:type", "video/mp4")
+                .property("og:video:width", width)
+                .property("og:video:height", height)
+                .card(Card::Stream { url: video_url });
+            if let Some((w, h)) = known_dimensions(media) {
+                tags.name("twitter:player:width", w).name("twitter:player:height", h);
+            }
 
-    html
+            // Listed first so platforms that won't inline the video still show motion;
+            // the static thumbnail follows as a fallback.
+            if config.animated_preview {
+                tags.property("og:image", animated_preview_url(host, &media.url))
+                    .property("og:image:type", "video/mp4");
+            }
+            if let Some(thumbnail) = &media.thumbnail_url {
+                tags.property("og:image", thumbnail);
+                // The thumbnail is a frame of the video, so it shares its aspect ratio
+                if let Some((w, h)) = known_dimensions(media) {
+                    tags.property("og:image:width", w).property("og:image:height", h);
+                }
+            }
+        }
+        Some(VideoPlan::Player(player_url)) => match (known_dimensions(media), &media.thumbnail_url) {
+            (Some((width, height)), Some(image)) => {
+                tags.card(Card::Player { url: player_url, width, height, image }).property("og:image", image);
+            }
+            _ => thumbnail_card(tags, media),
+        },
+        Some(VideoPlan::AnimatedPreview(preview_url)) => {
+            tags.property("og:image", preview_url).property("og:image:type", "video/mp4");
+            thumbnail_card(tags, media);
+        }
+        Some(VideoPlan::Thumbnail) => thumbnail_card(tags, media),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scraper::http::fixtures::{image, post, video};
     use crate::scraper::types::{InstaData, Media, MediaType};
 
+    fn render_embed(data: &InstaData, host: &str, img_index: Option<usize>) -> String {
+        render_embed_chunks(data, &Config::default(), &EmbedOptions { host, img_index, ..EmbedOptions::default() }).concat()
+    }
+
     fn sample_image_data() -> InstaData {
+        let slide = Media { width: Some(1080), height: Some(1080), ..image("https://cdn.example.com/image.jpg") };
         InstaData {
-            post_id: "ABC123".to_string(),
-            username: "testuser".to_string(),
             caption: Some("Hello world!".to_string()),
-            media: vec![Media {
-                media_type: MediaType::Image,
-                url: "https://cdn.example.com/image.jpg".to_string(),
-                thumbnail_url: None,
-                width: Some(1080),
-                height: Some(1080),
-            }],
             like_count: Some(42),
             comment_count: Some(5),
-            is_video: false,
-            video_view_count: None,
             timestamp: 1700000000,
+            ..post("ABC123", "testuser", vec![slide])
         }
     }
 
@@ -202,6 +658,32 @@ mod tests {
         assert!(html.contains(r#"twitter:card" content="summary_large_image"#));
     }
 
+    #[test]
+    fn alt_text_describes_the_image() {
+        let mut data = sample_image_data();
+        data.media[0].alt_text = Some("A cat on a sofa".to_string());
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:image:alt" content="A cat on a sofa""#));
+        assert!(html.contains(r#"twitter:image:alt" content="A cat on a sofa""#));
+        assert!(html.contains(r#"og:description" content="Hello world!""#));
+
+        // Stands in for a missing caption
+        data.caption = None;
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:description" content="A cat on a sofa""#));
+    }
+
+    #[test]
+    fn text_only_post_falls_back_to_avatar() {
+        let mut data = sample_image_data();
+        data.media.clear();
+        data.avatar_url = Some("https://scontent.cdninstagram.com/v/avatar.jpg?oh=1&oe=2".to_string());
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:image" content="https://scontent.cdninstagram.com/v/avatar.jpg?oh=1&amp;oe=2"#));
+        assert!(html.contains(r#"twitter:card" content="summary""#));
+        assert!(html.contains("&amp;avatar=https%3A%2F%2Fscontent.cdninstagram.com%2Fv%2Favatar.jpg%3Foh%3D1%26oe%3D2"));
+    }
+
     #[test]
     fn embed_contains_oembed_link() {
         let data = sample_image_data();
@@ -219,6 +701,14 @@ mod tests {
         assert!(html.contains("&lt;script&gt;"));
     }
 
+    #[test]
+    fn embed_escapes_quotes_in_attributes() {
+        let mut data = sample_image_data();
+        data.caption = Some(r#"say "hi" & bye"#.to_string());
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"content="say &quot;hi&quot; &amp; bye""#));
+    }
+
     #[test]
     fn embed_truncates_long_caption() {
         let mut data = sample_image_data();
@@ -234,32 +724,388 @@ mod tests {
         data.is_video = true;
         data.video_view_count = Some(1000);
         data.media = vec![Media {
-            media_type: MediaType::Video,
-            url: "https://cdn.example.com/video.mp4".to_string(),
             thumbnail_url: Some("https://cdn.example.com/thumb.jpg".to_string()),
             width: Some(1920),
             height: Some(1080),
+            ..video("https://cdn.example.com/video.mp4")
         }];
         let html = render_embed(&data, "cattgram.com", None);
         assert!(html.contains(r#"og:video" content="https://cdn.example.com/video.mp4"#));
+        assert!(html.contains(r#"og:video:url" content="https://cdn.example.com/video.mp4"#));
+        assert!(html.contains(r#"og:video:secure_url" content="https://cdn.example.com/video.mp4"#));
+        assert!(html.contains(r#"og:type" content="video.other"#));
         assert!(html.contains(r#"twitter:card" content="player"#));
         assert!(html.contains(r#"og:image" content="https://cdn.example.com/thumb.jpg"#));
         assert!(html.contains("1,000 views"));
     }
 
+    #[test]
+    fn blocked_video_renders_thumbnail_card() {
+        let mut data = sample_image_data();
+        data.is_video = true;
+        data.media[0].media_type = MediaType::Video;
+        data.media[0].thumbnail_url = Some(std::mem::take(&mut data.media[0].url));
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:title" content="@testuser | ▶ Video — open on Instagram""#));
+        assert!(html.contains(r#"twitter:card" content="summary_large_image"#));
+        assert!(html.contains(r#"og:image" content="https://cdn.example.com/image.jpg"#));
+        assert!(!html.contains("og:video"));
+        assert!(!html.contains(r#"content="player""#));
+        assert!(html.contains(r#"og:type" content="article"#));
+    }
+
+    #[test]
+    fn reels_prefer_plays_over_views() {
+        let mut data = sample_image_data();
+        data.is_video = true;
+        data.play_count = Some(12345);
+        assert!(build_stats_suffix(&data, 1, None).starts_with(" | 12,345 plays, 42 likes"));
+
+        data.video_view_count = Some(100);
+        assert!(build_stats_suffix(&data, 1, None).starts_with(" | 100 views"));
+        data.kind = Some(PostKind::Reel);
+        assert!(build_stats_suffix(&data, 1, None).starts_with(" | 12,345 plays"));
+    }
+
+    #[test]
+    fn story_stickers_join_the_description() {
+        let mut data = sample_image_data();
+        data.caption = None;
+        data.stickers = vec![
+            Sticker::Poll {
+                question: "Wet food?".to_string(),
+                options: vec![
+                    PollOption { text: "Yes".to_string(), votes: Some(2) },
+                    PollOption { text: "No".to_string(), votes: Some(1) },
+                ],
+            },
+            Sticker::Link { url: "https://example.com/shop".to_string(), label: None },
+        ];
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains("og:description\" content=\"📊 Wet food? — Yes 67% · No 33%\n🔗 https://example.com/shop\""));
+    }
+
+    #[test]
+    fn poll_without_votes_lists_options() {
+        let options = vec![
+            PollOption { text: "A".to_string(), votes: None },
+            PollOption { text: "B".to_string(), votes: Some(0) },
+        ];
+        assert_eq!(poll_results(&options), "A · B");
+    }
+
+    #[test]
+    fn hashtag_embeds_name_the_hashtag() {
+        let mut data = sample_image_data();
+        data.kind = Some(PostKind::Reel);
+        data.hashtag = Some("cats".to_string());
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r##"og:title" content="#cats · Reel by @testuser | 42 likes"##));
+    }
+
+    #[test]
+    fn title_names_the_post_kind() {
+        let mut data = sample_image_data();
+        data.kind = Some(PostKind::Reel);
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:title" content="Reel by @testuser | 42 likes"#));
+
+        data.kind = Some(PostKind::Post);
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:title" content="@testuser | 42 likes"#));
+    }
+
+    #[test]
+    fn animated_preview_precedes_thumbnail_when_enabled() {
+        let mut data = sample_image_data();
+        data.media[0].media_type = MediaType::Video;
+        data.media[0].url = "https://cdn.example.com/video.mp4".to_string();
+        data.media[0].thumbnail_url = Some("https://cdn.example.com/thumb.jpg".to_string());
+
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(!html.contains("/cdn-cgi/media/"));
+
+        let config = Config {
+            animated_preview: true,
+            ..Config::default()
+        };
+        let html = render_embed_chunks(&data, &config, &EmbedOptions { host: "cattgram.com", ..EmbedOptions::default() }).concat();
+        let preview = html
+            .find(r#"og:image" content="https://cattgram.com/cdn-cgi/media/"#)
+            .unwrap();
+        let thumbnail = html.find(r#"og:image" content="https://cdn.example.com/thumb.jpg"#).unwrap();
+        assert!(preview < thumbnail);
+    }
+
+    #[test]
+    fn gif_mode_swaps_in_muted_video() {
+        let mut data = sample_image_data();
+        data.media[0].media_type = MediaType::Video;
+        data.media[0].url = "https://cdn.example.com/video.mp4".to_string();
+        let playback = Playback { gif: true, ..Playback::default() };
+        let html = render_embed_chunks(&data, &Config::default(), &EmbedOptions { host: "cattgram.com", playback, ..EmbedOptions::default() }).concat();
+        let muted = "https://cattgram.com/cdn-cgi/media/mode=video,audio=false/https://cdn.example.com/video.mp4";
+        assert!(html.contains(&format!(r#"og:video" content="{muted}""#)));
+        assert!(html.contains(&format!(r#"twitter:player:stream" content="{muted}""#)));
+    }
+
+    #[test]
+    fn embed_emits_dimension_hints_only_when_known() {
+        let mut data = sample_image_data();
+        data.media[0].media_type = MediaType::Video;
+        data.media[0].thumbnail_url = Some("https://cdn.example.com/thumb.jpg".to_string());
+        data.media[0].width = Some(720);
+        data.media[0].height = Some(1280);
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"twitter:player:width" content="720"#));
+        assert!(html.contains(r#"twitter:player:height" content="1280"#));
+        assert!(html.contains(r#"og:image:height" content="1280"#));
+
+        let mut data = sample_image_data();
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"twitter:image:width" content="1080"#));
+
+        data.media[0].width = None;
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(!html.contains("twitter:image:width"));
+    }
+
     #[test]
     fn embed_carousel_shows_slide_info() {
         let mut data = sample_image_data();
-        data.media.push(Media {
-            media_type: MediaType::Image,
-            url: "https://cdn.example.com/image2.jpg".to_string(),
-            thumbnail_url: None,
-            width: Some(1080),
-            height: Some(1080),
-        });
+        data.media.push(Media { width: Some(1080), height: Some(1080), ..image("https://cdn.example.com/image2.jpg") });
         let html = render_embed(&data, "cattgram.com", Some(2));
         assert!(html.contains("Slide 2/2"));
         assert!(html.contains("image2.jpg"));
+        assert!(html.contains("Hello world!\n\n◀ ?img_index=1\""));
+    }
+
+    #[test]
+    fn image_carousel_shows_up_to_four_slides() {
+        let mut data = sample_image_data();
+        for n in 2..=5 {
+            let mut slide = data.media[0].clone();
+            slide.url = format!("https://cdn.example.com/image{n}.jpg");
+            data.media.push(slide);
+        }
+        let html = render_embed(&data, "cattgram.com", None);
+        assert_eq!(html.matches(r#"property="og:image""#).count(), 4);
+        assert!(html.contains("image4.jpg"));
+        assert!(!html.contains("image5.jpg"));
+
+        let html = render_embed(&data, "cattgram.com", Some(5));
+        assert_eq!(html.matches(r#"property="og:image""#).count(), 1);
+        assert!(html.contains("image5.jpg"));
+
+        data.media[1].media_type = MediaType::Video;
+        let html = render_embed(&data, "cattgram.com", None);
+        assert_eq!(html.matches(r#"property="og:image""#).count(), 1);
+        assert!(!html.contains("image3.jpg"));
+    }
+
+    #[test]
+    fn mixed_carousel_cards_follow_the_selected_slide() {
+        let mut data = sample_image_data();
+        data.video_view_count = Some(100);
+        data.media.push(Media {
+            thumbnail_url: Some("https://cdn.example.com/clip.jpg".to_string()),
+            width: Some(720),
+            height: Some(1280),
+            ..video("https://cdn.example.com/clip.mp4")
+        });
+
+        let html = render_embed(&data, "cattgram.com", Some(1));
+        assert!(html.contains(r#"og:image" content="https://cdn.example.com/image.jpg"#));
+        assert!(!html.contains("og:video"));
+        assert!(html.contains(r#"og:title" content="@testuser | 42 likes, 5 comments, Slide 1/2""#));
+
+        let html = render_embed(&data, "cattgram.com", Some(2));
+        assert!(html.contains(r#"og:video" content="https://cdn.example.com/clip.mp4"#));
+        assert!(html.contains(r#"og:title" content="@testuser | 100 views, 42 likes, 5 comments, Slide 2/2""#));
+
+        // Out of range picks the last slide, and says so
+        assert!(render_embed(&data, "cattgram.com", Some(9)).contains("Slide 2/2"));
+    }
+
+    #[test]
+    fn video_falls_back_per_platform() {
+        let mut data = sample_image_data();
+        data.media[0].media_type = MediaType::Video;
+        data.media[0].url = "https://video-ams4-1.xx.fbcdn.net/o1/reel.mp4".to_string();
+        data.media[0].thumbnail_url = Some("https://cdn.example.com/cover.jpg".to_string());
+        let media = &data.media[0];
+        let plan = |platform, config: &Config| {
+            video_plan(media, &data, 1, config, "cattgram.com", Playback { gif: false, platform })
+        };
+        let config = Config::default();
+
+        assert_eq!(plan(Platform::Other, &config), VideoPlan::Stream(media.url.clone()));
+        assert_eq!(
+            plan(Platform::Telegram, &config),
+            VideoPlan::Stream("https://cattgram.com/videos/ABC123/1?proxy=true".to_string())
+        );
+        assert_eq!(
+            plan(Platform::Twitter, &config),
+            VideoPlan::Player("https://www.instagram.com/p/ABC123/embed/".to_string())
+        );
+        // Without a cover to fall back on, X gets no player card
+        let coverless = Media { thumbnail_url: None, ..media.clone() };
+        let plan_coverless = video_plan(&coverless, &data, 1, &config, "cattgram.com", Playback { gif: false, platform: Platform::Twitter });
+        assert_eq!(plan_coverless, VideoPlan::Thumbnail);
+
+        let mut blocked = media.clone();
+        blocked.url.clear();
+        let plan = video_plan(&blocked, &data, 1, &config, "cattgram.com", Playback { gif: false, platform: Platform::Telegram });
+        assert_eq!(plan, VideoPlan::Thumbnail);
+    }
+
+    #[test]
+    fn slide_hint_links_neighbouring_slides() {
+        assert_eq!(slide_hint(1, 1), None);
+        assert_eq!(slide_hint(1, 3).as_deref(), Some("▶ ?img_index=2"));
+        assert_eq!(slide_hint(2, 3).as_deref(), Some("◀ ?img_index=1 · ▶ ?img_index=3"));
+        assert_eq!(slide_hint(3, 3).as_deref(), Some("◀ ?img_index=2"));
+    }
+
+    #[test]
+    fn comment_permalink_replaces_caption() {
+        let data = sample_image_data();
+        let comment = Comment {
+            id: "17900000000000002".to_string(),
+            username: "commenter".to_string(),
+            text: "best cat".to_string(),
+        };
+        let html = render_embed_chunks(&data, &Config::default(), &EmbedOptions { host: "cattgram.com", comment: Some(&comment), ..EmbedOptions::default() }).concat();
+        assert!(html.contains(r#"og:title" content="@testuser | 💬 Comment by @commenter""#));
+        assert!(html.contains(r#"og:description" content="@commenter: best cat""#));
+        assert!(html.contains("instagram.com/p/ABC123/c/17900000000000002/"));
+        assert!(html.contains("image.jpg"));
+    }
+
+    #[test]
+    fn canonical_url_keeps_the_reel_path() {
+        let mut data = sample_image_data();
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"<link rel="canonical" href="https://www.instagram.com/p/ABC123/">"#));
+
+        data.kind = Some(PostKind::Reel);
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:url" content="https://www.instagram.com/reel/ABC123/""#));
+        assert!(html.contains(r#"<link rel="canonical" href="https://www.instagram.com/reel/ABC123/">"#));
+
+        data.kind = Some(PostKind::Story);
+        data.post_id = "B".to_string();
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"href="https://www.instagram.com/stories/testuser/1/""#));
+    }
+
+    #[test]
+    fn top_comment_is_opt_in() {
+        let mut data = sample_image_data();
+        data.top_comment = Some(Comment {
+            id: "1".to_string(),
+            username: "fan".to_string(),
+            text: "so fluffy".to_string(),
+        });
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(!html.contains("so fluffy"));
+
+        let html = render_embed_chunks(&data, &Config::default(), &EmbedOptions { host: "cattgram.com", show_top_comment: true, ..EmbedOptions::default() }).concat();
+        assert!(html.contains("Hello world!\n\n💬 @fan: so fluffy\""));
+    }
+
+    #[test]
+    fn expired_story_card_explains_itself() {
+        let html = render_expired_story("testuser", &Config::default());
+        assert!(html.contains(r#"og:title" content="@testuser | Story expired""#));
+        assert!(html.contains("instagram.com/stories/testuser/"));
+        assert!(!html.contains("og:image"));
+    }
+
+    #[test]
+    fn sensitive_posts_carry_a_note() {
+        let mut data = sample_image_data();
+        data.sensitive = true;
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains("og:description\" content=\"⚠️ Sensitive content\n\nHello world!\""));
+    }
+
+    #[test]
+    fn geo_blocked_card_explains_itself() {
+        let html = render_geo_blocked("ABC123", &Config::default());
+        assert!(html.contains(r#"og:title" content="Not available in this region""#));
+        assert!(html.contains("instagram.com/p/ABC123/"));
+        assert!(!html.contains("og:image"));
+    }
+
+    #[test]
+    fn timed_out_card_links_to_instagram() {
+        let html = render_timed_out("https://www.instagram.com/p/CxSlow12345/", &Config::default());
+        assert!(html.contains(r#"og:title" content="Preview unavailable right now""#));
+        assert!(html.contains("0;url=https://www.instagram.com/p/CxSlow12345/"));
+    }
+
+    #[test]
+    fn maintenance_card_links_the_status_page() {
+        let html = render_maintenance("https://www.instagram.com/p/ABC123/", "https://cattgram.com/status", &Config::default());
+        assert!(html.contains(r#"og:title" content="Down for maintenance""#));
+        assert!(html.contains("Cattgram is temporarily down for maintenance"));
+        assert!(html.contains("Status: https://cattgram.com/status"));
+        assert!(html.contains("0;url=https://www.instagram.com/p/ABC123/"));
+    }
+
+    #[test]
+    fn missing_slide_card_names_the_slide_count() {
+        assert_eq!(missing_slide_message(1, 1), "Slide 1 does not exist (post has 1 slide)");
+        let html = render_missing_slide("https://www.instagram.com/p/ABC123/", 5, 3, &Config::default());
+        assert!(html.contains(r#"og:title" content="Slide 5 does not exist (post has 3 slides)""#));
+        assert!(!html.contains("og:image"));
+    }
+
+    #[test]
+    fn screenshot_card_is_a_large_image() {
+        let html = render_screenshot("ABC123", "https://cattgram.com/screenshot/ABC123", &Config::default());
+        assert!(html.contains(r#"og:image" content="https://cattgram.com/screenshot/ABC123""#));
+        assert!(html.contains(r#"og:image:width" content="1200""#));
+        assert!(html.contains(r#"twitter:card" content="summary_large_image""#));
+        assert!(html.contains("0;url=https://www.instagram.com/p/ABC123/"));
+    }
+
+    #[test]
+    fn search_stub_is_noindex_and_canonical_to_instagram() {
+        let html = render_search_stub("https://www.instagram.com/p/ABC123/", &Config::default());
+        assert!(html.contains(r#"<meta name="robots" content="noindex, nofollow">"#));
+        assert!(html.contains(r#"<link rel="canonical" href="https://www.instagram.com/p/ABC123/">"#));
+        assert!(!html.contains("og:"));
+    }
+
+    #[test]
+    fn title_shows_full_name_and_verified_badge() {
+        let mut data = sample_image_data();
+        data.full_name = Some("Test User".to_string());
+        data.is_verified = true;
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:title" content="Test User (@testuser) ✓ | 42 likes"#));
+
+        assert_eq!(author_label(Some(""), "cat", false), "@cat");
+    }
+
+    #[test]
+    fn title_credits_collab_coauthors() {
+        let mut data = sample_image_data();
+        data.coauthors = vec!["dogperson".to_string(), "birdfan".to_string()];
+        let html = render_embed(&data, "cattgram.com", None);
+        assert!(html.contains(r#"og:title" content="@testuser with @dogperson, @birdfan | 42 likes"#));
+    }
+
+    #[test]
+    fn embed_chunks_split_head_and_body() {
+        let data = sample_image_data();
+        let [head, body] = render_embed_chunks(&data, &Config::default(), &EmbedOptions { host: "cattgram.com", ..EmbedOptions::default() });
+        assert!(head.starts_with("<!DOCTYPE html>"));
+        assert!(head.ends_with("</head>"));
+        assert!(body.starts_with("<body>"));
+        assert!(body.ends_with("</html>"));
     }
 
     #[test]
@@ -269,4 +1115,18 @@ mod tests {
         assert_eq!(format_number(1000), "1,000");
         assert_eq!(format_number(1234567), "1,234,567");
     }
+
+    #[test]
+    fn embed_uses_configured_branding() {
+        let data = sample_image_data();
+        let config = Config {
+            site_name: "Kittygram".to_string(),
+            theme_color: "#000000".to_string(),
+            ..Config::default()
+        };
+        let html = render_embed_chunks(&data, &config, &EmbedOptions { host: "kitty.example", ..EmbedOptions::default() }).concat();
+        assert!(html.contains(r#"og:site_name" content="Kittygram""#));
+        assert!(html.contains(r##"theme-color" content="#000000""##));
+        assert!(html.contains("<title>Kittygram</title>"));
+    }
 }
@@ -0,0 +1,105 @@
+//! Golden files for the embed page.
+//!
+//! Each case renders a canned post (see `scraper::mock`) and compares the
+//! page with `fixtures/golden/{name}.html`, so any change to what crawlers
+//! see shows up as a diff in review. After an intended change, rewrite the
+//! files with `UPDATE_GOLDEN=1 cargo test golden` and commit them.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::scraper::mock::mock_post;
+use crate::utils::bot_detect::Platform;
+
+use super::embed_html::{render_embed_chunks, EmbedOptions, Playback};
+
+const HOST: &str = "cattgram.com";
+
+/// How a case asks for the page.
+#[derive(Debug, Clone, Copy)]
+enum Variant {
+    Plain,
+    Slide(usize),
+    On(Platform),
+    Gif,
+    TopComment,
+    Permalink,
+}
+
+/// `(golden file, shortcode, variant)`.
+const CASES: [(&str, &str, Variant); 11] = [
+    ("carousel", "CxCarousel1", Variant::Plain),
+    ("carousel_slide_2", "CxCarousel1", Variant::Slide(2)),
+    ("reel", "CxReel123", Variant::Plain),
+    ("reel_telegram", "CxReel123", Variant::On(Platform::Telegram)),
+    ("reel_twitter", "CxReel123", Variant::On(Platform::Twitter)),
+    ("reel_gif", "CxReel123", Variant::Gif),
+    ("reel_top_comment", "CxReel123", Variant::TopComment),
+    ("reel_comment_permalink", "CxReel123", Variant::Permalink),
+    ("collab", "CxCollab01", Variant::Plain),
+    ("no_caption", "CxNoCap001", Variant::Plain),
+    ("emoji", "CxEmoji001", Variant::Plain),
+];
+
+fn render(post_id: &str, variant: Variant) -> String {
+    let data = mock_post(post_id).unwrap_or_else(|| panic!("{post_id} isn't a canned post"));
+    let base = EmbedOptions { host: HOST, ..EmbedOptions::default() };
+    let options = match variant {
+        Variant::Plain => base,
+        Variant::Slide(index) => EmbedOptions { img_index: Some(index), ..base },
+        Variant::On(platform) => EmbedOptions { playback: Playback { platform, ..Playback::default() }, ..base },
+        Variant::Gif => EmbedOptions { playback: Playback { gif: true, ..Playback::default() }, ..base },
+        Variant::TopComment => EmbedOptions { show_top_comment: true, ..base },
+        Variant::Permalink => EmbedOptions { comment: data.top_comment.as_ref(), ..base },
+    };
+    // One tag per line, so a diff points at the tag that changed
+    render_embed_chunks(&data, &Config::default(), &options).concat().replace("><", ">\n<") + "\n"
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/golden")
+}
+
+#[test]
+fn embed_pages_match_their_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+
+    for (name, post_id, variant) in CASES {
+        let html = render(post_id, variant);
+        let path = golden_dir().join(format!("{name}.html"));
+        if update {
+            fs::create_dir_all(golden_dir()).unwrap();
+            fs::write(&path, &html).unwrap();
+            continue;
+        }
+
+        let golden = fs::read_to_string(&path).unwrap_or_else(|_| panic!("{} is missing", path.display()));
+        if html != golden {
+            let line = html.lines().zip(golden.lines()).position(|(a, b)| a != b);
+            let line = line.unwrap_or_else(|| html.lines().count().min(golden.lines().count()));
+            mismatches.push(format!(
+                "{name} differs at line {}:\n  rendered: {}\n  golden:   {}",
+                line + 1,
+                html.lines().nth(line).unwrap_or("<end>"),
+                golden.lines().nth(line).unwrap_or("<end>")
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\n\nIf the change is intended, rerun with UPDATE_GOLDEN=1 and commit the files.",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn every_golden_file_has_a_case() {
+    for entry in fs::read_dir(golden_dir()).unwrap() {
+        let file = entry.unwrap().file_name().into_string().unwrap();
+        let name = file.trim_end_matches(".html");
+        assert!(CASES.iter().any(|(case, _, _)| *case == name), "{file} has no case");
+    }
+}
@@ -1,53 +1,79 @@
+use maud::{html, DOCTYPE};
+
+use crate::config::Config;
+use crate::i18n::Lang;
+
+/// Supported embed routes, listed on the homepage. Labels are in `Strings::routes`.
+const ROUTES: [&str; 8] = [
+    "/p/:postID",
+    "/reel/:postID",
+    "/reels/:postID",
+    "/tv/:postID",
+    "/stories/:username/:storyID",
+    "/gif/:postID",
+    "/media/:mediaID",
+    "/explore/tags/:tag",
+];
+
+/// Supported query parameters, listed on the homepage. Descriptions are in
+/// `Strings::query_params`.
+const QUERY_PARAMS: [&str; 5] = ["?direct=true", "?img_index=N", "?comments=1", "?gif=true", "?preview=true"];
+
 /// Renders the static homepage HTML.
-pub fn render_home() -> String {
-    r#"<!DOCTYPE html>
-<html lang="en" data-theme="light">
-<head>
-<meta charset="utf-8">
-<meta name="viewport" content="width=device-width, initial-scale=1">
-<title>Cattgram</title>
-<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css">
-</head>
-<body>
-<main class="container">
-<hgroup>
-<h1>Cattgram</h1>
-<p>Fix Instagram embeds for Discord and Telegram</p>
-</hgroup>
+pub fn render_home(config: &Config, lang: Lang) -> String {
+    let t = lang.strings();
+    html! {
+        (DOCTYPE)
+        html lang=(lang.code()) data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { (config.site_name) }
+                        p { (t.tagline) }
+                    }
 
-<section>
-<h2>Usage</h2>
-<p>Replace <code>instagram.com</code> with <code>cattgram.com</code> (or whatever domain you deploy to) in any Instagram link.</p>
-<p><strong>Example:</strong></p>
-<pre><code>https://cattgram.com/p/ABC123/</code></pre>
-</section>
+                    section {
+                        h2 { (t.usage) }
+                        p {
+                            (t.replace[0]) code { "instagram.com" } (t.replace[1]) code { "cattgram.com" }
+                            (t.replace[2])
+                        }
+                        p { strong { (t.example) } }
+                        pre { code { "https://cattgram.com/p/ABC123/" } }
+                    }
 
-<section>
-<h2>Supported URL Formats</h2>
-<ul>
-<li><code>/p/:postID</code> &mdash; Posts</li>
-<li><code>/reel/:postID</code> &mdash; Reels</li>
-<li><code>/reels/:postID</code> &mdash; Reels (alternate)</li>
-<li><code>/tv/:postID</code> &mdash; IGTV</li>
-<li><code>/stories/:username/:storyID</code> &mdash; Stories</li>
-</ul>
-</section>
+                    section {
+                        h2 { (t.url_formats) }
+                        ul {
+                            @for (route, label) in ROUTES.iter().zip(t.routes) {
+                                li { code { (route) } " — " (label) }
+                            }
+                        }
+                    }
 
-<section>
-<h2>Query Parameters</h2>
-<ul>
-<li><code>?direct=true</code> &mdash; Redirect directly to the media file (image or video URL)</li>
-<li><code>?img_index=N</code> &mdash; Select a specific slide in a carousel post (1-based index)</li>
-</ul>
-</section>
+                    section {
+                        h2 { (t.query_parameters) }
+                        ul {
+                            @for (param, description) in QUERY_PARAMS.iter().zip(t.query_params) {
+                                li { code { (param) } " — " (description) }
+                            }
+                        }
+                    }
 
-<footer>
-<p><small>Powered by Cloudflare Workers</small></p>
-</footer>
-</main>
-</body>
-</html>"#
-        .to_string()
+                    footer {
+                        p { small { (t.powered_by) } }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
 }
 
 #[cfg(test)]
@@ -56,19 +82,19 @@ mod tests {
 
     #[test]
     fn home_contains_title() {
-        let html = render_home();
+        let html = render_home(&Config::default(), Lang::En);
         assert!(html.contains("<title>Cattgram</title>"));
     }
 
     #[test]
     fn home_contains_pico_css() {
-        let html = render_home();
+        let html = render_home(&Config::default(), Lang::En);
         assert!(html.contains("picocss/pico@2"));
     }
 
     #[test]
     fn home_contains_supported_formats() {
-        let html = render_home();
+        let html = render_home(&Config::default(), Lang::En);
         assert!(html.contains("/p/:postID"));
         assert!(html.contains("/reel/:postID"));
         assert!(html.contains("/stories/:username/:storyID"));
@@ -76,8 +102,16 @@ mod tests {
 
     #[test]
     fn home_contains_query_params() {
-        let html = render_home();
+        let html = render_home(&Config::default(), Lang::En);
         assert!(html.contains("?direct=true"));
         assert!(html.contains("?img_index=N"));
     }
+
+    #[test]
+    fn home_follows_the_language() {
+        let html = render_home(&Config::default(), Lang::De);
+        assert!(html.contains(r#"<html lang="de""#));
+        assert!(html.contains("Unterstützte URL-Formate"));
+        assert!(html.contains("/reel/:postID"));
+    }
 }
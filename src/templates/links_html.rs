@@ -0,0 +1,177 @@
+use maud::{html, DOCTYPE};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::i18n::Lang;
+use crate::scraper::types::{InstaData, Media, MediaType};
+
+/// One media item of a post, with proxy links through this worker.
+#[derive(Debug, Serialize)]
+pub struct MediaLink {
+    /// 1-based position in the post, as used by `/images` and `/videos`.
+    pub index: usize,
+    #[serde(rename = "type")]
+    pub media_type: MediaType,
+    pub url: String,
+    pub download_url: String,
+    /// Video cover frame, served by `/images`. `None` for images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+/// Every media item of a post, as served by `/links/:postID`.
+#[derive(Debug, Serialize)]
+pub struct PostLinks {
+    pub post_id: String,
+    pub username: String,
+    pub instagram_url: String,
+    pub media: Vec<MediaLink>,
+}
+
+impl PostLinks {
+    pub fn new(data: &InstaData, host: &str) -> Self {
+        Self {
+            post_id: data.post_id.clone(),
+            username: data.username.clone(),
+            instagram_url: data.instagram_url(),
+            media: data
+                .media
+                .iter()
+                .enumerate()
+                .map(|(i, media)| media_link(&data.post_id, i + 1, media, host))
+                .collect(),
+        }
+    }
+}
+
+fn media_link(post_id: &str, index: usize, media: &Media, host: &str) -> MediaLink {
+    let image_url = format!("https://{host}/images/{post_id}/{index}.jpg");
+    let (url, thumbnail_url) = match media.media_type {
+        MediaType::Image => (image_url, None),
+        MediaType::Video => (format!("https://{host}/videos/{post_id}/{index}.mp4"), Some(image_url)),
+    };
+
+    MediaLink {
+        index,
+        media_type: media.media_type.clone(),
+        download_url: format!("{url}?download=true"),
+        url,
+        thumbnail_url,
+        width: media.width,
+        height: media.height,
+    }
+}
+
+/// Renders the human-readable media list.
+pub fn render_links(links: &PostLinks, config: &Config, lang: Lang) -> String {
+    let t = lang.strings();
+    html! {
+        (DOCTYPE)
+        html lang=(lang.code()) data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { "@" (links.username) " · " (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { "@" (links.username) }
+                        p { a href=(links.instagram_url) { (links.instagram_url) } }
+                    }
+
+                    table {
+                        thead {
+                            tr { th { "#" } th { (t.type_heading) } th { (t.size_heading) } th { (t.links_heading) } }
+                        }
+                        tbody {
+                            @for link in &links.media {
+                                tr {
+                                    td { (link.index) }
+                                    td {
+                                        @match link.media_type {
+                                            MediaType::Image => (t.image),
+                                            MediaType::Video => (t.video),
+                                        }
+                                    }
+                                    td {
+                                        @if let (Some(w), Some(h)) = (link.width, link.height) {
+                                            (w) "×" (h)
+                                        } @else {
+                                            "—"
+                                        }
+                                    }
+                                    td {
+                                        a href=(link.url) { (t.open) }
+                                        " · "
+                                        a href=(link.download_url) { (t.download) }
+                                        @if let Some(thumbnail) = &link.thumbnail_url {
+                                            " · "
+                                            a href=(thumbnail) { (t.thumbnail) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    footer {
+                        p { small { (config.site_name) } }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{image, post, video};
+
+    fn sample_data() -> InstaData {
+        let media = vec![
+            Media { width: Some(1080), height: Some(1350), ..image("https://cdn.example.com/image.jpg") },
+            Media {
+                thumbnail_url: Some("https://cdn.example.com/thumb.jpg".to_string()),
+                ..video("https://cdn.example.com/video.mp4")
+            },
+        ];
+        InstaData { timestamp: 1700000000, ..post("ABC123", "testuser", media) }
+    }
+
+    #[test]
+    fn links_point_at_proxy_routes() {
+        let links = PostLinks::new(&sample_data(), "cattgram.com");
+        assert_eq!(links.media[0].url, "https://cattgram.com/images/ABC123/1.jpg");
+        assert_eq!(links.media[0].download_url, "https://cattgram.com/images/ABC123/1.jpg?download=true");
+        assert_eq!(links.media[1].url, "https://cattgram.com/videos/ABC123/2.mp4");
+        assert_eq!(links.media[1].thumbnail_url.as_deref(), Some("https://cattgram.com/images/ABC123/2.jpg"));
+    }
+
+    #[test]
+    fn json_omits_unknown_fields() {
+        let links = PostLinks::new(&sample_data(), "cattgram.com");
+        let json = serde_json::to_value(&links).unwrap();
+        assert_eq!(json["media"][0]["type"], "image");
+        assert_eq!(json["media"][0]["width"], 1080);
+        assert!(json["media"][0].get("thumbnail_url").is_none());
+        assert!(json["media"][1].get("width").is_none());
+    }
+
+    #[test]
+    fn html_lists_every_item() {
+        let links = PostLinks::new(&sample_data(), "cattgram.com");
+        let html = render_links(&links, &Config::default(), Lang::En);
+        assert!(html.contains("<title>@testuser · Cattgram</title>"));
+        assert!(html.contains("1080×1350"));
+        assert!(html.contains(r#"href="https://cattgram.com/videos/ABC123/2.mp4?download=true""#));
+    }
+}
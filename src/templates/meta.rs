@@ -0,0 +1,140 @@
+//! Link preview tags: OpenGraph `property` tags and Twitter Card `name` tags.
+//!
+//! Templates collect them in a `MetaTags` and render it once, inside
+//! `<head>`. Every value goes through maud's escaping, and each `Card` type
+//! carries the tags it can't do without, so a `player` card without a player,
+//! its size or a fallback image doesn't compile. A page has one card: only
+//! the first `card` call counts.
+
+use maud::{html, Markup, Render};
+
+/// `twitter:card`, with the tags its type requires.
+#[derive(Debug, Clone, Copy)]
+pub enum Card<'a> {
+    /// Text with a small thumbnail, if there is one.
+    Summary { image: Option<&'a str> },
+    /// A large image over the text.
+    LargeImage { image: &'a str },
+    /// A video played inline, straight from this MP4 URL.
+    Stream { url: &'a str },
+    /// A video played in an iframe of this page, `width`×`height`, with
+    /// `image` shown where iframes aren't played.
+    Player { url: &'a str, width: u32, height: u32, image: &'a str },
+}
+
+/// Which attribute names a tag: OpenGraph uses `property`, Twitter `name`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Attr {
+    Property,
+    Name,
+}
+
+/// Preview tags in the order they were added.
+#[derive(Debug, Default)]
+pub struct MetaTags {
+    tags: Vec<(Attr, &'static str, String)>,
+}
+
+impl MetaTags {
+    /// Adds a `<meta property>` tag, e.g. `og:image`.
+    pub fn property(&mut self, key: &'static str, content: impl ToString) -> &mut Self {
+        self.tags.push((Attr::Property, key, content.to_string()));
+        self
+    }
+
+    /// Adds a `<meta name>` tag, e.g. `twitter:image:alt`.
+    pub fn name(&mut self, key: &'static str, content: impl ToString) -> &mut Self {
+        self.tags.push((Attr::Name, key, content.to_string()));
+        self
+    }
+
+    /// Adds `twitter:card` and the tags that make up its type. Ignored if a
+    /// card was already added: with two, the type would be up to the client.
+    pub fn card(&mut self, card: Card) -> &mut Self {
+        if self.tags.iter().any(|&(_, key, _)| key == "twitter:card") {
+            return self;
+        }
+        match card {
+            Card::Summary { image } => {
+                self.name("twitter:card", "summary");
+                if let Some(image) = image {
+                    self.name("twitter:image", image);
+                }
+            }
+            Card::LargeImage { image } => {
+                self.name("twitter:card", "summary_large_image").name("twitter:image", image);
+            }
+            Card::Stream { url } => {
+                self.name("twitter:card", "player")
+                    .name("twitter:player:stream", url)
+                    .name("twitter:player:stream:content_type", "video/mp4");
+            }
+            Card::Player { url, width, height, image } => {
+                self.name("twitter:card", "player")
+                    .name("twitter:player", url)
+                    .name("twitter:player:width", width)
+                    .name("twitter:player:height", height)
+                    .name("twitter:image", image);
+            }
+        }
+        self
+    }
+}
+
+impl Render for MetaTags {
+    fn render(&self) -> Markup {
+        html! {
+            @for (attr, key, content) in &self.tags {
+                @match attr {
+                    Attr::Property => meta property=(key) content=(content);,
+                    Attr::Name => meta name=(key) content=(content);,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_in_order_and_escaped() {
+        let mut tags = MetaTags::default();
+        tags.property("og:title", r#"<b>"Tom & Jerry"</b>"#).property("og:image:width", 1080);
+        assert_eq!(
+            tags.render().into_string(),
+            r#"<meta property="og:title" content="&lt;b&gt;&quot;Tom &amp; Jerry&quot;&lt;/b&gt;"><meta property="og:image:width" content="1080">"#
+        );
+    }
+
+    #[test]
+    fn cards_bring_their_required_tags() {
+        let mut tags = MetaTags::default();
+        tags.card(Card::Stream { url: "https://cdn.example.com/v.mp4" });
+        let html = tags.render().into_string();
+        assert!(html.starts_with(r#"<meta name="twitter:card" content="player">"#));
+        assert!(html.contains(r#"<meta name="twitter:player:stream" content="https://cdn.example.com/v.mp4">"#));
+        assert!(html.contains(r#"twitter:player:stream:content_type" content="video/mp4""#));
+
+        let mut tags = MetaTags::default();
+        tags.card(Card::Summary { image: None });
+        assert_eq!(tags.render().into_string(), r#"<meta name="twitter:card" content="summary">"#);
+
+        let mut tags = MetaTags::default();
+        tags.card(Card::Player { url: "https://example.com/embed/", width: 1080, height: 1920, image: "https://cdn.example.com/t.jpg" });
+        let html = tags.render().into_string();
+        assert!(html.contains(r#"<meta name="twitter:player:width" content="1080"><meta name="twitter:player:height" content="1920">"#));
+        assert!(html.ends_with(r#"<meta name="twitter:image" content="https://cdn.example.com/t.jpg">"#));
+    }
+
+    #[test]
+    fn a_second_card_is_ignored() {
+        let mut tags = MetaTags::default();
+        tags.card(Card::LargeImage { image: "https://cdn.example.com/a.jpg" })
+            .card(Card::Summary { image: Some("https://cdn.example.com/b.jpg") });
+        let html = tags.render().into_string();
+        assert_eq!(html.matches("twitter:card").count(), 1);
+        assert!(html.contains("summary_large_image") && !html.contains("b.jpg"));
+    }
+}
@@ -1,2 +1,12 @@
+pub mod backends_html;
 pub mod embed_html;
+#[cfg(test)]
+mod golden;
 pub mod home_html;
+pub mod links_html;
+mod meta;
+pub mod profile_html;
+pub mod rss_xml;
+pub mod status_html;
+pub mod turnstile_html;
+pub mod view_html;
@@ -0,0 +1,132 @@
+use maud::{html, DOCTYPE};
+
+use crate::config::Config;
+use crate::scraper::types::Profile;
+use crate::templates::embed_html::{author_label, format_number};
+use crate::utils::mosaic::MIN_TILES;
+
+use super::meta::{Card, MetaTags};
+
+/// Builds the profile stats line, e.g. `"48,213 followers · 312 following · 873 posts"`.
+fn stats_line(profile: &Profile) -> String {
+    [
+        (profile.follower_count, "followers"),
+        (profile.following_count, "following"),
+        (profile.post_count, "posts"),
+    ]
+    .into_iter()
+    .filter_map(|(count, label)| count.map(|n| format!("{} {}", format_number(n), label)))
+    .collect::<Vec<_>>()
+    .join(" · ")
+}
+
+/// Renders a profile card: avatar, name, stats and bio.
+///
+/// With enough public posts, the main image is a grid of recent post
+/// thumbnails served by `/grid/:username`, and the avatar follows it.
+pub fn render_profile(profile: &Profile, config: &Config, host: &str) -> String {
+    let title = author_label(profile.full_name.as_deref(), &profile.username, profile.is_verified);
+
+    let stats = stats_line(profile);
+    let description = match &profile.biography {
+        Some(bio) if stats.is_empty() => bio.clone(),
+        Some(bio) => format!("{stats}\n\n{bio}"),
+        None => stats,
+    };
+    let instagram_url = format!("https://www.instagram.com/{}/", profile.username);
+    let grid_url = (!profile.is_private && profile.recent_posts.len() >= MIN_TILES)
+        .then(|| format!("https://{}/grid/{}.jpg", host, profile.username));
+
+    let mut tags = MetaTags::default();
+    tags.property("theme-color", &config.theme_color)
+        .property("og:site_name", &config.site_name)
+        .property("og:type", "profile")
+        .property("og:title", &title)
+        .property("og:description", &description)
+        .property("og:url", &instagram_url)
+        .property("profile:username", &profile.username);
+    if let Some(grid) = &grid_url {
+        tags.property("og:image", grid)
+            .property("og:image:type", "image/jpeg")
+            .card(Card::LargeImage { image: grid });
+        if let Some(avatar) = &profile.avatar_url {
+            tags.property("og:image", avatar);
+        }
+    } else {
+        if let Some(avatar) = &profile.avatar_url {
+            tags.property("og:image", avatar);
+        }
+        tags.card(Card::Summary { image: profile.avatar_url.as_deref() });
+    }
+
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                (tags)
+                link rel="canonical" href=(instagram_url);
+                meta http-equiv="refresh" content={ "0;url=" (instagram_url) };
+                title { (config.site_name) }
+            }
+            body {
+                p { "Redirecting to Instagram..." }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::types::ProfilePost;
+
+    fn sample_profile() -> Profile {
+        Profile {
+            pk: "1234567".to_string(),
+            username: "catlover".to_string(),
+            full_name: Some("Cat Lover".to_string()),
+            biography: Some("Professional napper".to_string()),
+            avatar_url: Some("https://cdn.example.com/avatar.jpg".to_string()),
+            is_private: false,
+            is_verified: true,
+            follower_count: Some(48213),
+            following_count: Some(312),
+            post_count: None,
+            recent_posts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn profile_card_has_name_stats_and_avatar() {
+        let html = render_profile(&sample_profile(), &Config::default(), "cattgram.com");
+        assert!(html.contains(r#"og:title" content="Cat Lover (@catlover) ✓""#));
+        assert!(html.contains("48,213 followers · 312 following\n\nProfessional napper"));
+        assert!(html.contains(r#"og:image" content="https://cdn.example.com/avatar.jpg""#));
+        assert!(html.contains("instagram.com/catlover/"));
+        assert!(!html.contains("/grid/"));
+    }
+
+    #[test]
+    fn recent_posts_grid_leads_the_card() {
+        let mut profile = sample_profile();
+        profile.recent_posts = (0..4)
+            .map(|i| ProfilePost {
+                shortcode: format!("Cx{i}"),
+                thumbnail_url: format!("https://cdn.example.com/{i}.jpg"),
+                is_video: false,
+                timestamp: 0,
+            })
+            .collect();
+        let html = render_profile(&profile, &Config::default(), "cattgram.com");
+        let grid = html.find(r#"og:image" content="https://cattgram.com/grid/catlover.jpg""#).unwrap();
+        let avatar = html.find(r#"og:image" content="https://cdn.example.com/avatar.jpg""#).unwrap();
+        assert!(grid < avatar);
+        assert!(html.contains("summary_large_image"));
+
+        profile.is_private = true;
+        let html = render_profile(&profile, &Config::default(), "cattgram.com");
+        assert!(!html.contains("/grid/"));
+    }
+}
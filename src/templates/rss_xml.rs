@@ -0,0 +1,68 @@
+use maud::{html, PreEscaped};
+
+use crate::config::Config;
+use crate::scraper::types::Profile;
+use crate::templates::embed_html::author_label;
+use crate::utils::http::http_date;
+
+/// Renders an RSS 2.0 feed of a profile's recent posts, newest first.
+///
+/// Items link to the posts' embeds on `host`, so a feed reader posting them
+/// to a chat gets a full preview. Private profiles have an empty feed.
+pub fn render_feed(profile: &Profile, config: &Config, host: &str) -> String {
+    let title = author_label(profile.full_name.as_deref(), &profile.username, profile.is_verified);
+    let instagram_url = format!("https://www.instagram.com/{}/", profile.username);
+    let mut posts: Vec<_> = profile.recent_posts.iter().collect();
+    posts.sort_by_key(|post| std::cmp::Reverse(post.timestamp));
+
+    html! {
+        (PreEscaped(r#"<?xml version="1.0" encoding="UTF-8"?>"#))
+        rss version="2.0" {
+            channel {
+                title { (title) " on Instagram" }
+                link { (instagram_url) }
+                description { (profile.biography.as_deref().unwrap_or_default()) }
+                generator { (config.site_name) }
+                @if let Some(avatar) = &profile.avatar_url {
+                    image {
+                        url { (avatar) }
+                        title { (title) " on Instagram" }
+                        link { (instagram_url) }
+                    }
+                }
+                @for post in posts {
+                    item {
+                        title { @if post.is_video { "Video" } @else { "Post" } " by @" (profile.username) }
+                        link { "https://" (host) "/p/" (post.shortcode) "/" }
+                        guid isPermaLink="true" { "https://www.instagram.com/p/" (post.shortcode) "/" }
+                        @if post.timestamp > 0 {
+                            pubDate { (http_date(post.timestamp)) }
+                        }
+                        enclosure url=(post.thumbnail_url) type="image/jpeg" length="0" {}
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::mock::mock_profile;
+
+    #[test]
+    fn feed_lists_recent_posts_newest_first() {
+        let profile = mock_profile("catlover").unwrap();
+        let feed = render_feed(&profile, &Config::default(), "cattgram.com");
+
+        assert!(feed.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0">"#));
+        assert!(feed.contains("<title>Cat Lover (@catlover) ✓ on Instagram</title>"));
+        let reel = feed.find("<link>https://cattgram.com/p/CxReel123/</link>").unwrap();
+        let carousel = feed.find("<link>https://cattgram.com/p/CxCarousel1/</link>").unwrap();
+        assert!(reel < carousel);
+        assert!(feed.contains("<title>Video by @catlover</title>"));
+        assert!(feed.contains("<pubDate>Tue, 14 Nov 2023 22:15:00 GMT</pubDate>"));
+    }
+}
@@ -0,0 +1,117 @@
+use maud::{html, Markup, DOCTYPE};
+
+use crate::config::Config;
+use crate::status::{Health, StatusSnapshot, Verdict};
+
+fn health_color(health: Health) -> &'static str {
+    match health {
+        Health::Up => "#2e7d32",
+        Health::Degraded => "#ef6c00",
+        Health::Down => "#c62828",
+        Health::Unknown | Health::Off => "#757575",
+    }
+}
+
+fn health_cell(health: Health) -> Markup {
+    html! {
+        td { span style={ "color: " (health_color(health)) } { "● " } (health.label()) }
+    }
+}
+
+/// Renders the public status page for a snapshot, as seen at `now_secs`.
+pub fn render_status(snapshot: &StatusSnapshot, config: &Config, now_secs: u64) -> String {
+    let verdict = snapshot.verdict(now_secs);
+    let age_mins = now_secs.saturating_sub(snapshot.checked_at) / 60;
+
+    html! {
+        (DOCTYPE)
+        html lang="en" data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { "Status · " (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { (verdict.headline()) }
+                        p {
+                            "The instance is up. "
+                            @match age_mins {
+                                0 => "Checked less than a minute ago.",
+                                1 => "Checked a minute ago.",
+                                mins => { "Checked " (mins) " minutes ago." }
+                            }
+                        }
+                    }
+                    @if config.maintenance {
+                        p {
+                            "This instance is down for maintenance. Links open on Instagram until it's back, and embeds say so. "
+                            "Nothing needs to be done on your side."
+                        }
+                    }
+                    @if verdict != Verdict::Operational {
+                        p {
+                            "Embeds may show a thumbnail instead of the video, or no preview at all, until Instagram lets up. "
+                            "Nothing needs to be done on your side."
+                        }
+                    }
+                    @if let Some(until) = snapshot.cooldown_until.filter(|&until| now_secs < until) {
+                        p { "Instagram is rate limiting this instance; scrapes resume in about " ((until - now_secs).div_ceil(60)) " min." }
+                    }
+
+                    table {
+                        thead {
+                            tr { th { "Component" } th { "State" } th { "Success rate (2h)" } }
+                        }
+                        tbody {
+                            @for backend in &snapshot.backends {
+                                tr {
+                                    td { (backend.name) }
+                                    (health_cell(backend.health))
+                                    td {
+                                        @match backend.success_percent {
+                                            Some(percent) => { (percent) "%" }
+                                            None => "—",
+                                        }
+                                    }
+                                }
+                            }
+                            tr { td { "Instagram session" } (health_cell(snapshot.cookie)) td { "—" } }
+                            tr { td { "Proxy" } (health_cell(snapshot.proxy)) td { "—" } }
+                        }
+                    }
+                    footer {
+                        p { small { (config.site_name) } }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::BackendHealth;
+
+    #[test]
+    fn blocked_instance_says_so() {
+        let snapshot = StatusSnapshot {
+            checked_at: 1000,
+            backends: vec![BackendHealth { name: "GraphQL".to_string(), health: Health::Down, success_percent: Some(3) }],
+            cookie: Health::Off,
+            proxy: Health::Up,
+            cooldown_until: Some(1500),
+        };
+        let html = render_status(&snapshot, &Config::default(), 1130);
+        assert!(html.contains("<h1>Instagram is blocking this instance right now</h1>"));
+        assert!(html.contains("Checked 2 minutes ago."));
+        assert!(html.contains("resume in about 7 min"));
+        assert!(html.contains("<td>GraphQL</td>"));
+        assert!(html.contains("3%"));
+    }
+}
@@ -0,0 +1,56 @@
+use maud::{html, PreEscaped, DOCTYPE};
+
+use crate::config::Config;
+use crate::i18n::Lang;
+
+/// Renders the Turnstile challenge shown before `/view` and `/links`.
+///
+/// The form posts back to the page at `action`, its canonical path and query.
+/// The widget submits it as soon as it's solved; the button is there for
+/// when that callback doesn't run.
+pub fn render_challenge(site_key: &str, action: &str, config: &Config, lang: Lang) -> String {
+    let t = lang.strings();
+    html! {
+        (DOCTYPE)
+        html lang=(lang.code()) data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { (t.checking_browser) " · " (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+                script src="https://challenges.cloudflare.com/turnstile/v0/api.js" async defer {}
+                script { (PreEscaped("function passed(){document.getElementById('challenge').submit()}")) }
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { (t.one_moment) }
+                        p { (t.confirm_human) }
+                    }
+                    form #challenge method="post" action=(action) {
+                        div class="cf-turnstile" data-sitekey=(site_key) data-callback="passed" {}
+                        button type="submit" { (t.continue_button) }
+                    }
+                    footer {
+                        p { small { (config.site_name) } }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widget_posts_back_to_the_page() {
+        let html = render_challenge("0x4AAAAAAA", "/view/CxAbc?img_index=2", &Config::default(), Lang::En);
+        assert!(html.contains(r#"<div class="cf-turnstile" data-sitekey="0x4AAAAAAA" data-callback="passed">"#));
+        assert!(html.contains(r#"<form id="challenge" method="post" action="/view/CxAbc?img_index=2">"#));
+        assert!(html.contains("challenges.cloudflare.com/turnstile/v0/api.js"));
+    }
+}
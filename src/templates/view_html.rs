@@ -0,0 +1,157 @@
+use maud::{html, Markup, DOCTYPE};
+
+use crate::config::Config;
+use crate::i18n::{Lang, Strings};
+use crate::scraper::types::{InstaData, Media, MediaType};
+use crate::templates::embed_html::post_author;
+
+/// Renders the human-facing viewer for one slide of a post.
+///
+/// `img_index` is 1-based and clamped like the embed's. Media is served through
+/// `/images` and `/videos`, so the page keeps working after Instagram's signed
+/// CDN URLs expire. Carousel controls are plain links, no script needed.
+pub fn render_view(data: &InstaData, config: &Config, img_index: Option<usize>, lang: Lang) -> String {
+    let t = lang.strings();
+    let media_count = data.media.len();
+    let current = img_index.unwrap_or(1).clamp(1, media_count.max(1));
+    let author = post_author(data);
+    let instagram_url = data.instagram_url();
+    let slide_url = |n: usize| format!("/view/{}?img_index={}", data.post_id, n);
+    let qr_url = format!("/qr/{}?img_index={}", data.post_id, current);
+
+    html! {
+        (DOCTYPE)
+        html lang=(lang.code()) data-theme="light" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { (author) " · " (config.site_name) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css";
+                style { "figure img, figure video { width: 100%; max-height: 80vh; object-fit: contain; }" }
+            }
+            body {
+                main class="container" {
+                    hgroup {
+                        h1 { (author) }
+                        p { a href=(instagram_url) { (instagram_url) } }
+                    }
+
+                    @if let Some(media) = data.media.get(current - 1) {
+                        figure { (media_element(&data.post_id, current, media, t)) }
+                    }
+
+                    @if media_count > 1 {
+                        nav {
+                            ul {
+                                li {
+                                    @if current > 1 {
+                                        a href=(slide_url(current - 1)) role="button" class="secondary" { (t.previous) }
+                                    }
+                                }
+                            }
+                            ul { li { (current) " / " (media_count) } }
+                            ul {
+                                li {
+                                    @if current < media_count {
+                                        a href=(slide_url(current + 1)) role="button" class="secondary" { (t.next) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    @if let Some(caption) = data.caption.as_deref().filter(|c| !c.is_empty()) {
+                        // Line breaks as <br>, since minification collapses newlines
+                        p {
+                            @for (i, line) in caption.lines().enumerate() {
+                                @if i > 0 { br; }
+                                (line)
+                            }
+                        }
+                    }
+
+                    p { a href=(instagram_url) role="button" { (t.open_in_instagram) } }
+
+                    figure {
+                        img src=(qr_url) alt="" width="148" height="148";
+                        figcaption { small { (t.scan_to_open) } }
+                    }
+
+                    footer {
+                        p { small { (config.site_name) } }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+/// The `<img>` or `<video>` for one slide, pointing at this worker's media routes.
+fn media_element(post_id: &str, index: usize, media: &Media, t: &Strings) -> Markup {
+    let image_url = format!("/images/{post_id}/{index}.jpg");
+
+    html! {
+        @match media.media_type {
+            MediaType::Video if media.is_blocked_video() => {
+                img src=(image_url) alt=(t.video_cover);
+                figcaption { (t.video_only_on_instagram) }
+            }
+            MediaType::Video => {
+                video src={ "/videos/" (post_id) "/" (index) ".mp4" } poster=(image_url)
+                    controls playsinline preload="metadata" width=[media.width] height=[media.height] {}
+            }
+            MediaType::Image => {
+                img src=(image_url) alt=(media.alt_text.as_deref().unwrap_or_default()) width=[media.width] height=[media.height];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{image, post, video};
+
+    fn sample_data() -> InstaData {
+        let sized = |slide: Media| Media { width: Some(1080), height: Some(1350), ..slide };
+        let media = vec![
+            sized(image("https://cdn.example.com/1.jpg")),
+            sized(video("https://cdn.example.com/2.mp4")),
+            sized(image("https://cdn.example.com/3.jpg")),
+        ];
+        InstaData {
+            caption: Some("Hello <world>\nSecond line".to_string()),
+            timestamp: 1700000000,
+            ..post("ABC123", "testuser", media)
+        }
+    }
+
+    #[test]
+    fn first_slide_links_forward_only() {
+        let html = render_view(&sample_data(), &Config::default(), None, Lang::En);
+        assert!(html.contains(r#"<img src="/images/ABC123/1.jpg""#));
+        assert!(html.contains(r#"href="/view/ABC123?img_index=2""#));
+        assert!(!html.contains("Previous"));
+        assert!(html.contains("1 / 3"));
+        assert!(html.contains("Hello &lt;world&gt;<br>Second line"));
+        assert!(html.contains(r#"href="https://www.instagram.com/p/ABC123/" role="button""#));
+        assert!(html.contains(r#"<img src="/qr/ABC123?img_index=1""#));
+    }
+
+    #[test]
+    fn video_slide_plays_through_proxy() {
+        let html = render_view(&sample_data(), &Config::default(), Some(2), Lang::En);
+        assert!(html.contains(r#"<video src="/videos/ABC123/2.mp4" poster="/images/ABC123/2.jpg""#));
+        assert!(html.contains(r#"href="/view/ABC123?img_index=1""#));
+        assert!(html.contains(r#"href="/view/ABC123?img_index=3""#));
+    }
+
+    #[test]
+    fn out_of_range_index_clamps_to_last_slide() {
+        let html = render_view(&sample_data(), &Config::default(), Some(9), Lang::En);
+        assert!(html.contains("3 / 3"));
+        assert!(!html.contains("Next"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Caption translation for embeds.
+//!
+//! When the reader's language (`?lang=`, else the crawler's
+//! `Accept-Language`) differs from the language the caption is written in,
+//! the embed description is translated with Workers AI. Only the embed is
+//! translated: `/view` keeps the original caption. Needs the `AI` binding;
+//! without it, captions are left as they are.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+use worker::*;
+
+use crate::i18n::{preferred, Lang};
+use crate::scraper::cache::{self, set_background, Kind};
+use crate::templates::embed_html::truncate;
+use crate::utils::log::console_log;
+use crate::AppState;
+
+/// The Workers AI binding name.
+pub const BINDING: &str = "AI";
+
+const MODEL: &str = "@cf/meta/m2m100-1.2b";
+
+/// Longest translated text kept, before the language note. Embeds cut
+/// captions at 300 bytes, and the note must survive that.
+const MAX_TRANSLATED_LEN: usize = 270;
+
+/// Detection needs at least this many common words to go on.
+const MIN_STOPWORDS: usize = 2;
+
+#[derive(Serialize)]
+struct TranslationInput<'a> {
+    text: &'a str,
+    source_lang: &'static str,
+    target_lang: &'static str,
+}
+
+#[derive(Deserialize)]
+struct TranslationOutput {
+    translated_text: String,
+}
+
+/// Common short words, enough to tell the Latin-script languages apart.
+fn stopwords(lang: Lang) -> &'static [&'static str] {
+    match lang {
+        Lang::En => &["the", "and", "is", "of", "to", "with", "for", "this", "my", "you", "it", "in"],
+        Lang::Es => &["el", "la", "los", "las", "que", "y", "es", "con", "por", "para", "una", "mi"],
+        Lang::Pt => &["o", "os", "as", "que", "e", "é", "com", "não", "para", "uma", "meu", "do"],
+        Lang::De => &["der", "die", "das", "und", "ist", "mit", "nicht", "ein", "eine", "ich", "für", "auf"],
+        Lang::Fr => &["le", "la", "les", "et", "est", "avec", "pour", "une", "je", "pas", "du", "des"],
+        Lang::Ru => &[],
+    }
+}
+
+/// Guesses the language of a caption. `None` when the text is too short or
+/// too mixed to call.
+pub fn detect_language(text: &str) -> Option<Lang> {
+    let letters = text.chars().filter(|c| c.is_alphabetic());
+    let (cyrillic, total) = letters.fold((0, 0), |(cyr, all), c| {
+        (cyr + usize::from(('\u{0400}'..='\u{04FF}').contains(&c)), all + 1)
+    });
+    if total > 0 && cyrillic * 2 > total {
+        return Some(Lang::Ru);
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut scores: Vec<(Lang, usize)> = Lang::ALL
+        .into_iter()
+        .map(|lang| (lang, words.iter().filter(|w| stopwords(lang).contains(&w.as_str())).count()))
+        .collect();
+    scores.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    match scores.as_slice() {
+        [(lang, best), (_, second), ..] if *best >= MIN_STOPWORDS && best > second => Some(*lang),
+        _ => None,
+    }
+}
+
+/// The language to translate into: `?lang=`, else the first supported
+/// language the client names in `Accept-Language`.
+pub fn translation_target(url: &Url, accept_language: &str) -> Option<Lang> {
+    let param = url.query_pairs().find(|(k, _)| k == "lang").map(|(_, v)| v.into_owned());
+    match param {
+        Some(tag) => Lang::from_tag(&tag),
+        None => preferred(accept_language),
+    }
+}
+
+/// Appends which languages a translation went between, so readers know the
+/// caption isn't the author's own words.
+fn with_note(translated: &str, from: Lang, to: Lang) -> String {
+    format!(
+        "{}\n\n🌐 {} → {}",
+        truncate(translated, MAX_TRANSLATED_LEN),
+        from.code().to_uppercase(),
+        to.code().to_uppercase()
+    )
+}
+
+/// The caption translated into `target`, with a language note, or `None`
+/// if there's nothing to do or the translation failed.
+pub async fn translate_caption(caption: &str, post_id: &str, target: Lang, env: &Env, state: &AppState) -> Option<String> {
+    let source = detect_language(caption).filter(|&source| source != target)?;
+    let ai = env.ai(BINDING).ok()?;
+
+    match cache::get::<String>(Kind::Translation, &format!("{post_id}:{}", target.code()), env).await {
+        Ok(Some(cached)) => return Some(with_note(&cached, source, target)),
+        Ok(None) => {}
+        Err(e) => console_log!("[translate] cache read failed: {:?}", e),
+    }
+
+    let input = TranslationInput { text: caption, source_lang: source.code(), target_lang: target.code() };
+    match ai.run::<_, TranslationOutput>(MODEL, input).await {
+        Ok(output) if !output.translated_text.trim().is_empty() => {
+            let translated = output.translated_text.trim().to_string();
+            set_background(Kind::Translation, &format!("{post_id}:{}", target.code()), &translated, env, state);
+            Some(with_note(&translated, source, target))
+        }
+        Ok(_) => None,
+        Err(e) => {
+            console_log!("[translate] {} -> {} failed for {}: {:?}", source.code(), target.code(), post_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_caption_languages() {
+        assert_eq!(detect_language("Sunset at the beach with my dog"), Some(Lang::En));
+        assert_eq!(detect_language("Un día increíble con los amigos en la playa"), Some(Lang::Es));
+        assert_eq!(detect_language("Der Hund ist müde und schläft auf dem Sofa"), Some(Lang::De));
+        assert_eq!(detect_language("Закат на море, лучший день лета"), Some(Lang::Ru));
+    }
+
+    #[test]
+    fn too_little_text_is_not_guessed() {
+        assert_eq!(detect_language("🔥🔥🔥 #summer"), None);
+        assert_eq!(detect_language("la playa"), None);
+    }
+
+    #[test]
+    fn lang_param_wins_over_accept_language() {
+        let url = Url::parse("https://cattgram.com/p/ABC/?lang=pt-BR").unwrap();
+        assert_eq!(translation_target(&url, "de"), Some(Lang::Pt));
+
+        let url = Url::parse("https://cattgram.com/p/ABC/").unwrap();
+        assert_eq!(translation_target(&url, "ja, fr;q=0.8"), Some(Lang::Fr));
+        assert_eq!(translation_target(&url, ""), None);
+    }
+
+    #[test]
+    fn note_names_both_languages() {
+        assert_eq!(with_note("Hallo", Lang::Es, Lang::De), "Hallo\n\n🌐 ES → DE");
+    }
+}
@@ -0,0 +1,198 @@
+//! Optional Cloudflare Turnstile check in front of the human-facing pages.
+//!
+//! `/view` and `/links` hand out working media links for any post, which
+//! makes a public instance an easy download backend for scripts that send a
+//! browser user-agent. With `TURNSTILE_SITE_KEY` and `TURNSTILE_SECRET_KEY`
+//! set, those pages first serve a Turnstile widget. The widget posts its
+//! token back to the page's canonical URL; once Cloudflare's siteverify endpoint accepts
+//! it, the visitor gets a pass cookie, backed by a KV marker, good for
+//! `PASS_TTL_SECS`.
+//!
+//! Embeds and the media routes stay open: chat apps fetch them and can't
+//! solve challenges, and so do `/links` JSON clients. `/links` is left to
+//! API keys when `REQUIRE_API_KEY` is on.
+
+use serde::Deserialize;
+use url::{form_urlencoded, Url};
+use worker::*;
+
+use crate::config::Config;
+use crate::i18n::request_lang;
+use crate::scraper::cache::{human_pass_valid, store_human_pass};
+use crate::scraper::http::{HttpClient, HttpRequest};
+use crate::scraper::instrument::instrumented;
+use crate::templates::turnstile_html::render_challenge;
+use crate::utils::http::CachePolicy;
+use crate::utils::log::console_log;
+use crate::utils::minify::minify_html;
+use crate::utils::path::normalize_path;
+use crate::utils::random::random_uuid;
+
+const SITEVERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Cookie carrying the pass token.
+const PASS_COOKIE: &str = "cattgram_pass";
+
+/// How long one solved challenge lasts.
+const PASS_TTL_SECS: u64 = 60 * 60;
+
+/// Form field the widget fills in with its token.
+const RESPONSE_FIELD: &str = "cf-turnstile-response";
+
+#[derive(Deserialize)]
+struct Siteverify {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+/// Asks Turnstile whether a widget token is genuine and unused.
+pub async fn verify_token<C: HttpClient>(
+    client: &C,
+    secret: &str,
+    token: &str,
+    remote_ip: Option<&str>,
+) -> Result<bool> {
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    form.append_pair("secret", secret).append_pair("response", token);
+    if let Some(ip) = remote_ip {
+        form.append_pair("remoteip", ip);
+    }
+    let request = HttpRequest::post(SITEVERIFY_URL, form.finish())
+        .header("Content-Type", "application/x-www-form-urlencoded");
+
+    let resp = client.send(request).await?;
+    let verdict: Siteverify = serde_json::from_str(&resp.body)
+        .map_err(|e| Error::RustError(format!("siteverify returned {}: {e}", resp.status)))?;
+    if !verdict.success {
+        console_log!("[turnstile] token rejected: {:?}", verdict.error_codes);
+    }
+    Ok(verdict.success)
+}
+
+/// The pass token in a `Cookie` header, if there is one.
+fn pass_token(cookie_header: &str) -> Option<&str> {
+    cookie_header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == PASS_COOKIE)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+fn pass_cookie(token: &str) -> String {
+    format!("{PASS_COOKIE}={token}; Max-Age={PASS_TTL_SECS}; Path=/; HttpOnly; Secure; SameSite=Lax")
+}
+
+/// Returns `true` if the request carries a valid pass. A failed lookup
+/// counts as no pass: the visitor is challenged again rather than let
+/// through unchecked.
+async fn has_pass(req: &Request, env: &Env) -> Result<bool> {
+    let cookies = req.headers().get("Cookie")?.unwrap_or_default();
+    let Some(token) = pass_token(&cookies) else {
+        return Ok(false);
+    };
+    Ok(human_pass_valid(token, env).await.unwrap_or_else(|e| {
+        console_log!("[turnstile] pass lookup failed: {:?}", e);
+        false
+    }))
+}
+
+/// Checks a posted widget token, and on success hands out a pass and sends
+/// the visitor back to the page. `None` if the token was rejected.
+async fn redeem(req: &mut Request, env: &Env, secret: &str) -> Result<Option<Response>> {
+    let form = req.form_data().await?;
+    let Some(FormEntry::Field(token)) = form.get(RESPONSE_FIELD) else {
+        return Ok(None);
+    };
+    let remote_ip = req.headers().get("CF-Connecting-IP")?;
+    if !verify_token(&instrumented(env), secret, &token, remote_ip.as_deref()).await? {
+        return Ok(None);
+    }
+
+    let pass = random_uuid()?;
+    store_human_pass(&pass, env, PASS_TTL_SECS).await?;
+    let resp = Response::redirect_with_status(req.url()?, 303)?;
+    // Redirect responses have immutable headers
+    let headers = resp.headers().clone();
+    headers.set("Set-Cookie", &pass_cookie(&pass))?;
+    CachePolicy::NoStore.apply(resp.with_headers(headers)).map(Some)
+}
+
+/// Where the challenge form posts: the page's normalized path, so the post
+/// isn't rewritten on its way back.
+fn form_action(url: &Url) -> String {
+    let path = normalize_path(url.path());
+    match url.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    }
+}
+
+/// Guards a human-facing page. Returns the response to send instead (the
+/// challenge, or the redirect after solving it), or `None` to serve the
+/// page: Turnstile is off, or the visitor has a pass.
+pub async fn guard(req: &mut Request, env: &Env, config: &Config) -> Result<Option<Response>> {
+    let Some((site_key, secret)) = config.turnstile() else {
+        return Ok(None);
+    };
+    if has_pass(req, env).await? {
+        return Ok(None);
+    }
+    if req.method() == Method::Post {
+        if let Some(redirect) = redeem(req, env, secret).await? {
+            return Ok(Some(redirect));
+        }
+    }
+
+    let html = render_challenge(site_key, &form_action(&req.url()?), config, request_lang(req, config)?);
+    let resp = Response::from_html(minify_html(&html))?.with_status(403);
+    CachePolicy::NoStore.apply(resp).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{block_on, FixtureClient};
+    use crate::utils::path::reroute;
+
+    #[test]
+    fn pass_is_read_from_the_cookie_header() {
+        assert_eq!(pass_token("theme=dark; cattgram_pass=abc-123"), Some("abc-123"));
+        assert_eq!(pass_token("cattgram_pass="), None);
+        assert_eq!(pass_token("other_pass=abc"), None);
+        assert!(pass_cookie("abc").starts_with("cattgram_pass=abc; Max-Age=3600;"));
+    }
+
+    #[test]
+    fn siteverify_gets_the_secret_token_and_ip() {
+        let client = FixtureClient::new().route("siteverify", 200, r#"{"success":true,"error-codes":[]}"#);
+        assert!(block_on(verify_token(&client, "s3cret", "tok en", Some("203.0.113.7"))).unwrap());
+
+        let requests = client.requests.borrow();
+        assert_eq!(requests[0].body.as_deref(), Some("secret=s3cret&response=tok+en&remoteip=203.0.113.7"));
+    }
+
+    #[test]
+    fn rejected_tokens_fail_verification() {
+        let client = FixtureClient::new()
+            .route("siteverify", 200, r#"{"success":false,"error-codes":["timeout-or-duplicate"]}"#);
+        assert!(!block_on(verify_token(&client, "s3cret", "reused", None)).unwrap());
+
+        let broken = FixtureClient::new().route("siteverify", 500, "oops");
+        assert!(block_on(verify_token(&broken, "s3cret", "token", None)).is_err());
+    }
+
+    #[test]
+    fn challenge_form_posted_to_a_trailing_slash_url_reaches_the_page() {
+        let page = Url::parse("https://cattgram.com/view/CxAbc/?img_index=2").unwrap();
+        assert_eq!(form_action(&page), "/view/CxAbc?img_index=2");
+
+        // A post that comes in on the trailing-slash URL anyway keeps its form
+        let form = "cf-turnstile-response=token".to_string();
+        let rerouted = reroute(page, Method::Post, Some(form.clone())).unwrap();
+        assert_eq!(rerouted.url.path(), "/view/CxAbc");
+        assert_eq!(rerouted.body, Some(form));
+        assert_eq!(form_action(&rerouted.url), "/view/CxAbc?img_index=2");
+    }
+}
@@ -32,16 +32,122 @@ const BOT_SIGNATURES: [&str; 31] = [
     "dataprovider",
 ];
 
+/// Search engine crawlers. They index pages rather than preview links, so
+/// they never need post data.
+const SEARCH_CRAWLER_SIGNATURES: [&str; 14] = [
+    "googlebot",
+    "google-inspectiontool",
+    "googleother",
+    "storebot-google",
+    "bingbot",
+    "bingpreview",
+    "yandexbot",
+    "baiduspider",
+    "duckduckbot",
+    "applebot",
+    "slurp",
+    "petalbot",
+    "seznambot",
+    "sogou",
+];
+
 /// Returns `true` if the user-agent string matches any known bot signature.
 pub fn is_bot(user_agent: &str) -> bool {
     let ua_lower = user_agent.to_ascii_lowercase();
     BOT_SIGNATURES.iter().any(|sig| ua_lower.contains(sig))
 }
 
+/// Returns `true` for search engine crawlers, as opposed to link-preview bots.
+pub fn is_search_crawler(user_agent: &str) -> bool {
+    let ua_lower = user_agent.to_ascii_lowercase();
+    SEARCH_CRAWLER_SIGNATURES.iter().any(|sig| ua_lower.contains(sig))
+}
+
+/// Returns `true` for Telegram's link preview bot (`TelegramBot (like TwitterBot)`).
+pub fn is_telegram(user_agent: &str) -> bool {
+    user_agent.to_ascii_lowercase().contains("telegrambot")
+}
+
+/// The app a link preview is for, where it changes how videos are embedded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    Telegram,
+    /// X's card crawler, which only plays videos through a `twitter:player` iframe.
+    Twitter,
+    #[default]
+    Other,
+}
+
+impl Platform {
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        let ua_lower = user_agent.to_ascii_lowercase();
+        // Telegram's UA says "like TwitterBot", so it goes first
+        if ua_lower.contains("telegrambot") {
+            Platform::Telegram
+        } else if ua_lower.contains("twitterbot") {
+            Platform::Twitter
+        } else {
+            Platform::Other
+        }
+    }
+
+    /// A short name, for cache keys.
+    pub fn class(self) -> &'static str {
+        match self {
+            Platform::Telegram => "telegram",
+            Platform::Twitter => "twitter",
+            Platform::Other => "other",
+        }
+    }
+
+    /// Whether `og:video` pointing straight at `video_url` plays. Telegram
+    /// fails on videos from Facebook's `fbcdn.net` hosts.
+    pub fn plays_stream(self, video_url: &str) -> bool {
+        match self {
+            Platform::Telegram => !url::Url::parse(video_url)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| host.ends_with(".fbcdn.net")))
+                .unwrap_or(false),
+            Platform::Twitter => false,
+            Platform::Other => true,
+        }
+    }
+
+    /// Whether `og:video` served from the worker's own host plays.
+    pub fn plays_proxied_stream(self) -> bool {
+        self != Platform::Twitter
+    }
+
+    /// Whether a `twitter:player` iframe plays.
+    pub fn plays_iframe(self) -> bool {
+        self == Platform::Twitter
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn platform_comes_from_the_user_agent() {
+        assert_eq!(Platform::from_user_agent("TelegramBot (like TwitterBot)"), Platform::Telegram);
+        assert_eq!(Platform::from_user_agent("Twitterbot/1.0"), Platform::Twitter);
+        assert_eq!(Platform::from_user_agent("Mozilla/5.0 (compatible; Discordbot/2.0)"), Platform::Other);
+    }
+
+    #[test]
+    fn telegram_skips_facebook_cdn_streams() {
+        assert!(!Platform::Telegram.plays_stream("https://video-ams4-1.xx.fbcdn.net/o1/v/t2/reel.mp4"));
+        assert!(Platform::Telegram.plays_stream("https://scontent.cdninstagram.com/o1/v/t16/reel.mp4"));
+        assert!(Platform::Other.plays_stream("https://video-ams4-1.xx.fbcdn.net/o1/v/t2/reel.mp4"));
+    }
+
+    #[test]
+    fn detects_telegram() {
+        assert!(is_telegram("TelegramBot (like TwitterBot)"));
+        assert!(!is_telegram("Mozilla/5.0 (compatible; Discordbot/2.0)"));
+    }
+
     #[test]
     fn detects_common_bots() {
         assert!(is_bot("Twitterbot/1.0"));
@@ -57,6 +163,16 @@ mod tests {
         assert!(is_bot("redditbot/1.0"));
     }
 
+    #[test]
+    fn tells_search_crawlers_from_preview_bots() {
+        assert!(is_search_crawler("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"));
+        assert!(is_search_crawler("Mozilla/5.0 (compatible; bingbot/2.0; +http://www.bing.com/bingbot.htm)"));
+        assert!(is_search_crawler("Mozilla/5.0 (compatible; YandexBot/3.0)"));
+        assert!(!is_search_crawler("Mozilla/5.0 (compatible; Discordbot/2.0)"));
+        assert!(!is_search_crawler("facebookexternalhit/1.1"));
+        assert!(!is_search_crawler("Twitterbot/1.0"));
+    }
+
     #[test]
     fn detects_case_insensitive() {
         assert!(is_bot("DISCORDBOT"));
@@ -0,0 +1,97 @@
+//! Per-isolate count buffers, flushed in batches.
+//!
+//! Counting every request straight into KV or D1 would cost a write per
+//! request. Instead each isolate adds its counts to a buffer, and hands the
+//! whole buffer to `wait_until` once enough requests have piled up or the
+//! oldest is a minute old. Counts still buffered when an isolate is evicted
+//! are lost, and flushes from different isolates can race, so buffered
+//! numbers are for trends and capacity planning, not for billing.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::thread::LocalKey;
+
+/// Flush once the oldest buffered count is this old.
+pub const FLUSH_AFTER_SECS: u64 = 60;
+
+/// Counts waiting to be written, by key.
+#[derive(Debug)]
+pub struct CountBuffer<K, V> {
+    counts: BTreeMap<K, V>,
+    /// Requests recorded since the last flush.
+    requests: u64,
+    /// When the oldest buffered count was recorded.
+    since: Option<u64>,
+    /// Flush once this many requests are buffered.
+    flush_after: u64,
+}
+
+impl<K: Ord, V: Default> CountBuffer<K, V> {
+    pub const fn new(flush_after: u64) -> Self {
+        Self { counts: BTreeMap::new(), requests: 0, since: None, flush_after }
+    }
+
+    /// Counts one request under `key`.
+    pub fn record(&mut self, key: K, now_secs: u64, count: impl FnOnce(&mut V)) {
+        count(self.counts.entry(key).or_default());
+        self.requests += 1;
+        self.since.get_or_insert(now_secs);
+    }
+
+    pub fn due(&self, now_secs: u64) -> bool {
+        self.requests >= self.flush_after
+            || self.since.is_some_and(|since| now_secs.saturating_sub(since) >= FLUSH_AFTER_SECS)
+    }
+
+    pub fn take(&mut self) -> BTreeMap<K, V> {
+        self.requests = 0;
+        self.since = None;
+        std::mem::take(&mut self.counts)
+    }
+}
+
+/// Counts one request into a `thread_local!` buffer, and returns the
+/// buffer's contents when a flush is due.
+pub fn record<K: Ord + 'static, V: Default + 'static>(
+    buffer: &'static LocalKey<RefCell<CountBuffer<K, V>>>,
+    key: K,
+    now_secs: u64,
+    count: impl FnOnce(&mut V),
+) -> Option<BTreeMap<K, V>> {
+    buffer.with_borrow_mut(|buffer| {
+        buffer.record(key, now_secs, count);
+        buffer.due(now_secs).then(|| buffer.take())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_up_counts_per_key_until_taken() {
+        let mut buffer = CountBuffer::<&str, u64>::new(10);
+        buffer.record("a", 1000, |n| *n += 1);
+        buffer.record("a", 1000, |n| *n += 1);
+        buffer.record("b", 1000, |n| *n += 1);
+
+        assert_eq!(buffer.take().into_iter().collect::<Vec<_>>(), [("a", 2), ("b", 1)]);
+        assert!(buffer.counts.is_empty() && buffer.requests == 0 && buffer.since.is_none());
+    }
+
+    #[test]
+    fn is_due_by_size_or_age() {
+        let mut buffer = CountBuffer::<&str, u64>::new(3);
+        assert!(!buffer.due(1000));
+
+        buffer.record("a", 1000, |n| *n += 1);
+        assert!(!buffer.due(1000 + FLUSH_AFTER_SECS - 1));
+        assert!(buffer.due(1000 + FLUSH_AFTER_SECS));
+
+        let mut buffer = CountBuffer::<&str, u64>::new(3);
+        for _ in 0..3 {
+            buffer.record("a", 1000, |n| *n += 1);
+        }
+        assert!(buffer.due(1000));
+    }
+}
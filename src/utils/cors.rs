@@ -0,0 +1,19 @@
+use worker::{Cors, Method};
+
+use crate::config::Config;
+
+/// Methods every route accepts.
+pub const ALLOWED_METHODS: [Method; 3] = [Method::Get, Method::Head, Method::Options];
+
+/// Builds the CORS configuration applied to every response.
+///
+/// Allowed origins come from `Config::cors_origins`, which defaults to `*`
+/// since every route serves public data.
+pub fn build_cors(config: &Config) -> Cors {
+    Cors::new()
+        .with_origins(config.cors_origins.iter())
+        .with_methods(ALLOWED_METHODS)
+        .with_allowed_headers(["Api-Version", "Content-Type", "If-None-Match", "X-Api-Key"])
+        .with_exposed_headers(["Api-Version", "Deprecation", "ETag", "Link", "X-Cache"])
+        .with_max_age(86400)
+}
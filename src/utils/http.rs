@@ -0,0 +1,425 @@
+use worker::{Headers, Request, Response, Result};
+
+use crate::i18n::Lang;
+use crate::scraper::types::InstaData;
+use crate::utils::bot_detect::Platform;
+
+/// Caching behaviour for each kind of response.
+///
+/// `Cache-Control` is what clients and crawlers see; `CDN-Cache-Control`
+/// governs Cloudflare's edge cache and takes precedence there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CachePolicy {
+    /// Static documents (OpenAPI): long-lived everywhere.
+    Home,
+    /// The homepage, in the visitor's language. Browsers honour
+    /// `Vary: Accept-Language`; Cloudflare's edge doesn't, so it must not store it.
+    Localized,
+    /// Embed routes: crawlers may keep them for an hour, but the same URL
+    /// redirects browsers to Instagram, so shared caches must not store it.
+    Embed,
+    /// `/images` and `/videos` redirects: short, since signed CDN URLs expire.
+    MediaRedirect,
+    /// `/latest` embeds: kept out of shared caches like embeds, and only for
+    /// minutes, since the next post changes the answer.
+    Latest,
+    /// oEmbed JSON is a pure function of its query string.
+    OEmbed,
+    /// `/links` pages: HTML or JSON depending on the client, so kept out of
+    /// shared caches. Also `/view` pages behind Turnstile, which are per
+    /// visitor, and API posts, whose keyed requests must reach the quota check.
+    Links,
+    /// `/view` pages: media goes through the proxy routes, so they stay
+    /// valid for a while. Localized like the homepage, so kept off the edge.
+    Viewer,
+    /// `/status`: the snapshot behind it only changes every few minutes.
+    Status,
+    /// RSS feeds: readers poll them, and a new post can wait a quarter hour.
+    Feed,
+    /// Diagnostics (`/config-check`): never cached anywhere.
+    NoStore,
+}
+
+impl CachePolicy {
+    fn cache_control(self) -> &'static str {
+        match self {
+            CachePolicy::Home => "public, max-age=86400",
+            CachePolicy::Localized => "public, max-age=86400",
+            CachePolicy::Embed => "private, max-age=3600",
+            CachePolicy::MediaRedirect => "public, max-age=300",
+            CachePolicy::Latest => "private, max-age=300",
+            CachePolicy::OEmbed => "public, max-age=86400",
+            CachePolicy::Links => "private, max-age=300",
+            CachePolicy::Viewer => "public, max-age=300",
+            CachePolicy::Status => "public, max-age=60",
+            CachePolicy::Feed => "public, max-age=900",
+            CachePolicy::NoStore => "no-store",
+        }
+    }
+
+    fn cdn_cache_control(self) -> &'static str {
+        match self {
+            CachePolicy::Home => "max-age=86400",
+            CachePolicy::Localized => "no-store",
+            CachePolicy::Embed => "no-store",
+            CachePolicy::MediaRedirect => "max-age=300",
+            CachePolicy::Latest => "no-store",
+            CachePolicy::OEmbed => "max-age=86400",
+            CachePolicy::Links => "no-store",
+            CachePolicy::Viewer => "no-store",
+            CachePolicy::Status => "max-age=60",
+            CachePolicy::Feed => "max-age=900",
+            CachePolicy::NoStore => "no-store",
+        }
+    }
+
+    /// Sets `Cache-Control` and `CDN-Cache-Control` on a response.
+    ///
+    /// Works on a copy of the headers, since `Response.redirect()` responses
+    /// have immutable headers.
+    pub fn apply(self, resp: Response) -> Result<Response> {
+        let headers = resp.headers().clone();
+        headers.set("Cache-Control", self.cache_control())?;
+        headers.set("CDN-Cache-Control", self.cdn_cache_control())?;
+        Ok(resp.with_headers(headers))
+    }
+}
+
+/// Where a response's post came from, sent as `X-Cache` so integrators can
+/// see the KV cache at work. Embeds whose rendered HTML is cached report that
+/// cache instead, as `Hit` or `Miss`. Cloudflare's edge reports its own
+/// outcome in `CF-Cache-Status`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheStatus {
+    /// Read from the cache.
+    Hit,
+    /// Scraped (or, for embed HTML, rendered) for this request.
+    Miss,
+    /// Read from the cache, though its signed CDN URLs have expired. The
+    /// media routes re-scrape such posts, so for them this means the
+    /// re-scrape failed and the old URLs were used anyway.
+    Stale,
+}
+
+impl CacheStatus {
+    pub const HEADER: &str = "X-Cache";
+
+    pub fn of(data: &InstaData, now_secs: u64) -> Self {
+        match (data.cached, data.media_expired(now_secs)) {
+            (false, _) => CacheStatus::Miss,
+            (true, false) => CacheStatus::Hit,
+            (true, true) => CacheStatus::Stale,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Stale => "STALE",
+        }
+    }
+
+    /// Adds `X-Cache` to a response, redirects included (see `CachePolicy::apply`).
+    pub fn apply(self, resp: Response) -> Result<Response> {
+        let headers = resp.headers().clone();
+        headers.set(Self::HEADER, self.as_str())?;
+        Ok(resp.with_headers(headers))
+    }
+}
+
+/// Paths search engines may index. Everything else mirrors Instagram.
+const INDEXABLE_PATHS: [&str; 1] = ["/"];
+
+/// Returns `true` if a (normalized) path may be indexed by search engines.
+pub fn is_indexable(path: &str) -> bool {
+    INDEXABLE_PATHS.contains(&path)
+}
+
+/// Adds `X-Robots-Tag: noindex` to a response. Covers what a `<meta>` tag
+/// can't: redirects, JSON and media.
+pub fn noindex(resp: Response) -> Result<Response> {
+    // Redirect responses have immutable headers
+    let headers = resp.headers().clone();
+    headers.set("X-Robots-Tag", "noindex, nofollow")?;
+    Ok(resp.with_headers(headers))
+}
+
+/// Computes a weak ETag for an embed response.
+///
+/// Covers everything the rendered page depends on that can change between
+/// scrapes: the post, its timestamp, the media set, and the selected slide.
+/// Also the platform class, since pages differ per platform (Telegram's get
+/// downscaled images), so one client's 304 never confirms another's page.
+pub fn embed_etag(data: &InstaData, img_index: Option<usize>, platform: Platform) -> String {
+    let mut hash = Fnv1a::new();
+    hash.write(platform.class().as_bytes());
+    hash.write(data.post_id.as_bytes());
+    hash.write(&data.timestamp.to_le_bytes());
+    for media in &data.media {
+        hash.write(media.url.as_bytes());
+        hash.write(media.thumbnail_url.as_deref().unwrap_or_default().as_bytes());
+    }
+    hash.write(&(img_index.unwrap_or(0) as u64).to_le_bytes());
+
+    format!("W/\"{:016x}\"", hash.finish())
+}
+
+/// Everything a rendered embed depends on, for caching its HTML: two
+/// requests with the same variant get the same page.
+pub struct EmbedVariant<'a> {
+    /// The embed's ETag, which covers the post data and the slide.
+    pub etag: &'a str,
+    pub img_index: Option<usize>,
+    pub platform: Platform,
+    pub gif: bool,
+    pub top_comment: bool,
+    /// The language the caption is translated into, if any.
+    pub lang: Option<Lang>,
+    pub host: &'a str,
+}
+
+impl EmbedVariant<'_> {
+    /// `{slide}:{platform}:{hash}`: readable where it can be, hashed where
+    /// it can't.
+    pub fn key(&self) -> String {
+        let mut hash = Fnv1a::new();
+        hash.write(self.etag.as_bytes());
+        hash.write(&[u8::from(self.gif), u8::from(self.top_comment)]);
+        hash.write(self.lang.map_or("", Lang::code).as_bytes());
+        hash.write(self.host.as_bytes());
+
+        format!("{}:{}:{:016x}", self.img_index.unwrap_or(1), self.platform.class(), hash.finish())
+    }
+}
+
+/// Returns `true` if an `If-None-Match` header value matches `etag`.
+///
+/// Uses weak comparison (the `W/` prefix is ignored on both sides) and
+/// accepts `*` and comma-separated lists.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats Unix seconds as an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`).
+pub fn http_date(secs: u64) -> String {
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses an HTTP date in the preferred IMF-fixdate form into Unix seconds.
+/// The obsolete RFC 850 and asctime forms aren't accepted.
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    let (_, rest) = value.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month_name)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (h, m, s) = (clock.next()??, clock.next()??, clock.next()??);
+    if parts.next()? != "GMT" || year < 1970 || !(1..=31).contains(&day) || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + h * 3600 + m * 60 + s)
+}
+
+/// `(year, month, day)` of a day count since 1970-01-01 (Howard Hinnant's algorithm).
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of `civil_from_days`, for years from 1970.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Whether a conditional request's copy is current. `If-None-Match` is
+/// checked against `etag` when sent (and fails without one); otherwise
+/// `If-Modified-Since` against `scraped_at`, when that's known.
+///
+/// Not the post's publication time: counts, captions and signed media URLs
+/// change long after it, but cached data only changes with a new scrape.
+pub fn not_modified(if_none_match: Option<&str>, if_modified_since: Option<&str>, etag: Option<&str>, scraped_at: u64) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        return etag.is_some_and(|etag| etag_matches(if_none_match, etag));
+    }
+    scraped_at > 0 && if_modified_since.and_then(parse_http_date).is_some_and(|since| since >= scraped_at)
+}
+
+/// Sets the validators for a response about a post on `headers`: `ETag`
+/// when given, and `Last-Modified` from when the post was scraped
+/// (`InstaData::scraped_at`), when known. Returns `true` if the request's
+/// conditional headers make it a 304.
+pub fn revalidate(req: &Request, headers: &Headers, etag: Option<&str>, scraped_at: u64) -> Result<bool> {
+    if let Some(etag) = etag {
+        headers.set("ETag", etag)?;
+    }
+    if scraped_at > 0 {
+        headers.set("Last-Modified", &http_date(scraped_at))?;
+    }
+    let if_none_match = req.headers().get("If-None-Match")?;
+    let if_modified_since = req.headers().get("If-Modified-Since")?;
+    Ok(not_modified(if_none_match.as_deref(), if_modified_since.as_deref(), etag, scraped_at))
+}
+
+/// A stable 64-bit fingerprint of a value, to tell whether it has changed
+/// without keeping the value itself.
+pub fn fingerprint(value: &str) -> u64 {
+    let mut hash = Fnv1a::new();
+    hash.write(value.as_bytes());
+    hash.finish()
+}
+
+/// 64-bit FNV-1a hasher — tiny, deterministic, and good enough for cache validators.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Field separator so ("ab", "c") and ("a", "bc") hash differently
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{image, post};
+
+    fn sample_data() -> InstaData {
+        let slide = image("https://cdn.example.com/image.jpg");
+        InstaData { timestamp: 1700000000, ..post("ABC123", "testuser", vec![slide]) }
+    }
+
+    #[test]
+    fn cache_status_follows_the_cache_and_media_expiry() {
+        let mut data = sample_data();
+        data.media[0].url = "https://scontent.cdninstagram.com/v/t51/1.jpg?oh=00_AYB&oe=66F1A2B3".to_string();
+        let fresh = 0x66F1A2B3 - 3600;
+        assert_eq!(CacheStatus::of(&data, fresh), CacheStatus::Miss);
+        data.cached = true;
+        assert_eq!(CacheStatus::of(&data, fresh), CacheStatus::Hit);
+        assert_eq!(CacheStatus::of(&data, 0x66F1A2B3), CacheStatus::Stale);
+    }
+
+    #[test]
+    fn only_the_homepage_is_indexable() {
+        assert!(is_indexable("/"));
+        assert!(!is_indexable("/p/ABC123"));
+        assert!(!is_indexable("/view/ABC123"));
+        assert!(!is_indexable("/instagram"));
+    }
+
+    #[test]
+    fn http_dates_round_trip() {
+        assert_eq!(http_date(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(1709251199), "Thu, 29 Feb 2024 23:59:59 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+        assert_eq!(parse_http_date(&http_date(1700000000)), Some(1700000000));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn if_modified_since_only_counts_without_if_none_match() {
+        let since = "Tue, 14 Nov 2023 22:13:20 GMT"; // 1700000000
+        assert!(not_modified(None, Some(since), None, 1700000000));
+        assert!(!not_modified(None, Some(since), None, 1700000001));
+        assert!(!not_modified(None, Some(since), None, 0));
+        assert!(!not_modified(Some("W/\"other\""), Some(since), Some("W/\"etag\""), 1700000000));
+        assert!(not_modified(Some("W/\"etag\""), None, Some("W/\"etag\""), 1700000000));
+    }
+
+    #[test]
+    fn embed_variants_split_by_platform_and_language() {
+        let etag = embed_etag(&sample_data(), Some(2), Platform::Other);
+        let variant = |platform, lang| {
+            EmbedVariant { etag: &etag, img_index: Some(2), platform, gif: false, top_comment: false, lang, host: "cattgram.com" }.key()
+        };
+
+        let discord = variant(Platform::Other, None);
+        assert!(discord.starts_with("2:other:"));
+        assert_eq!(discord, variant(Platform::Other, None));
+        assert!(variant(Platform::Telegram, None).starts_with("2:telegram:"));
+        assert_ne!(discord, variant(Platform::Other, Some(Lang::De)));
+    }
+
+    #[test]
+    fn etag_is_weak_and_stable() {
+        let data = sample_data();
+        let etag = embed_etag(&data, None, Platform::Other);
+        assert!(etag.starts_with("W/\""));
+        assert_eq!(etag, embed_etag(&data, None, Platform::Other));
+    }
+
+    #[test]
+    fn etag_changes_with_media_and_slide() {
+        let data = sample_data();
+        let mut other = sample_data();
+        other.media[0].url = "https://cdn.example.com/other.jpg".to_string();
+        assert_ne!(embed_etag(&data, None, Platform::Other), embed_etag(&other, None, Platform::Other));
+        assert_ne!(embed_etag(&data, None, Platform::Other), embed_etag(&data, Some(2), Platform::Other));
+    }
+
+    #[test]
+    fn etag_changes_with_platform() {
+        let data = sample_data();
+        assert_ne!(embed_etag(&data, None, Platform::Other), embed_etag(&data, None, Platform::Telegram));
+    }
+
+    #[test]
+    fn if_none_match_comparison() {
+        let etag = "W/\"abc\"";
+        assert!(etag_matches("W/\"abc\"", etag));
+        assert!(etag_matches("\"abc\"", etag));
+        assert!(etag_matches("\"x\", W/\"abc\"", etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches("W/\"abd\"", etag));
+    }
+}
@@ -4,17 +4,29 @@ const INSTAGRAM_BASE64: &[u8; 64] =
     b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
 
 /// Allowed query parameters on Instagram CDN URLs.
-const ALLOWED_CDN_PARAMS: [&str; 8] = [
+///
+/// `oh` is a signature over the path and the other parameters in this list
+/// (and `oe` its expiry), so dropping any of them makes the CDN answer 403
+/// "URL signature mismatch".
+const ALLOWED_CDN_PARAMS: [&str; 12] = [
     "stp",
     "dst",
     "_nc_cat",
     "_nc_ohc",
+    "_nc_ht",
+    "_nc_oc",
+    "_nc_zt",
+    "_nc_sid",
     "ccb",
     "oh",
     "oe",
-    "_nc_sid",
+    "vs",
 ];
 
+/// Query parameters Instagram's share links carry to identify the share.
+/// `/share/` links sometimes only resolve to the right post with them.
+const SHARE_PARAMS: [&str; 2] = ["igsh", "igshid"];
+
 /// Converts a numeric Instagram media ID to a shortcode.
 ///
 /// Uses Instagram's custom base64 alphabet, dividing repeatedly by 64
@@ -47,11 +59,35 @@ pub fn code_to_mediaid(code: &str) -> Option<u64> {
     Some(id)
 }
 
+/// Returns `true` if `code` could be a shortcode: 1 to 64 characters of
+/// Instagram's base64 alphabet. Long private-post codes are still shorter.
+pub fn is_shortcode(code: &str) -> bool {
+    !code.is_empty() && code.len() <= 64 && code.bytes().all(|b| INSTAGRAM_BASE64.contains(&b))
+}
+
+/// Parses a numeric media ID as returned by the private API.
+///
+/// Accepts both the bare ID and the `<mediaID>_<ownerID>` form, with digits
+/// on both sides of the `_`. Returns `None` for anything else, e.g. a
+/// shortcode.
+pub fn parse_media_id(raw: &str) -> Option<u64> {
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let id = match raw.split_once('_') {
+        Some((id, owner)) if all_digits(owner) => id,
+        Some(_) => return None,
+        None => raw,
+    };
+    if !all_digits(id) {
+        return None;
+    }
+    id.parse().ok()
+}
+
 /// Strips tracking parameters from an Instagram CDN URL.
 ///
-/// Retains only the allowlisted query parameters (`stp`, `dst`, `_nc_cat`,
-/// `_nc_ohc`, `ccb`, `oh`, `oe`, `_nc_sid`). Returns the original URL
-/// unchanged if parsing fails.
+/// Retains only the allowlisted query parameters: the signature (`oh`,
+/// `oe`) and the parameters it covers. Returns the original URL unchanged
+/// if parsing fails.
 pub fn normalize_cdn_url(url_str: &str) -> String {
     let Ok(mut parsed) = Url::parse(url_str) else {
         return url_str.to_string();
@@ -72,6 +108,105 @@ pub fn normalize_cdn_url(url_str: &str) -> String {
     parsed.to_string()
 }
 
+/// Seconds before its expiry at which a CDN URL is treated as expired, so a
+/// redirect to it doesn't die in transit.
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// Returns when a signed CDN URL expires, in Unix seconds: its `oe`
+/// parameter, which is a hex timestamp. `None` if it has none.
+pub fn cdn_url_expiry(url_str: &str) -> Option<u64> {
+    let parsed = Url::parse(url_str).ok()?;
+    let (_, oe) = parsed.query_pairs().find(|(key, _)| key == "oe")?;
+    u64::from_str_radix(&oe, 16).ok()
+}
+
+/// Returns `true` if a signed CDN URL has expired, or is about to. URLs
+/// without an expiry never do.
+pub fn cdn_url_expired(url_str: &str, now_secs: u64) -> bool {
+    cdn_url_expiry(url_str).is_some_and(|expiry| expiry <= now_secs + EXPIRY_MARGIN_SECS)
+}
+
+/// Like `normalize_cdn_url`, but leaves anything not on Instagram's CDN
+/// (see `is_cdn_url`) untouched, so it can be run over every scraped URL.
+pub fn normalize_media_url(url_str: &str) -> String {
+    if is_cdn_url(url_str) {
+        normalize_cdn_url(url_str)
+    } else {
+        url_str.to_string()
+    }
+}
+
+/// Returns the share identifiers (`igsh`, `igshid`) of an inbound request
+/// URL, in the order given.
+pub fn share_params(url: &Url) -> Vec<(String, String)> {
+    url.query_pairs()
+        .filter(|(key, value)| SHARE_PARAMS.contains(&key.as_ref()) && !value.is_empty())
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect()
+}
+
+/// Builds the Instagram URL of a share path (`share/<id>`), carrying the
+/// share identifiers from `share_params` over.
+pub fn share_url(share_path: &str, params: &[(String, String)]) -> String {
+    let base = format!("https://www.instagram.com/{}", share_path.trim_start_matches('/'));
+    match Url::parse(&base) {
+        Ok(mut url) if !params.is_empty() => {
+            url.query_pairs_mut().extend_pairs(params);
+            url.to_string()
+        }
+        _ => base,
+    }
+}
+
+/// Whether a URL points at Instagram's CDN (`*.cdninstagram.com` or
+/// `*.fbcdn.net`) over HTTPS.
+pub fn is_cdn_url(url_str: &str) -> bool {
+    let Ok(parsed) = Url::parse(url_str) else {
+        return false;
+    };
+    parsed.scheme() == "https"
+        && parsed
+            .host_str()
+            .is_some_and(|h| h.ends_with(".cdninstagram.com") || h.ends_with(".fbcdn.net"))
+}
+
+/// Parses the hashtag of an `/explore/tags/<tag>` path segment: percent-decoded,
+/// without a leading `#`, and lowercased since hashtags are case-insensitive.
+/// Returns `None` unless it is 1 to 100 letters, digits or underscores.
+pub fn parse_hashtag(raw: &str) -> Option<String> {
+    // Form decoding would split on these, or turn `+` into a space
+    if raw.contains(['&', '=', '+']) {
+        return None;
+    }
+    let (decoded, _) = url::form_urlencoded::parse(raw.as_bytes()).next()?;
+    let tag = decoded.trim_start_matches('#').to_lowercase();
+    let valid = (1..=100).contains(&tag.chars().count()) && tag.chars().all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some(tag)
+}
+
+/// The Instagram page a (normalized) request path stands for: the post,
+/// story, hashtag or profile it embeds or serves media of. Anything else is
+/// Instagram's homepage.
+pub fn instagram_url(path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let page = match segments.as_slice() {
+        ["p", post_id, "c", comment_id] => format!("p/{post_id}/c/{comment_id}/"),
+        ["api", .., "posts", post_id] | [_, "p", post_id, ..] => format!("p/{post_id}/"),
+        ["p" | "reel" | "reels" | "tv" | "gif" | "images" | "videos" | "links" | "view" | "screenshot", post_id, ..] => {
+            format!("p/{post_id}/")
+        }
+        ["media", raw] => match parse_media_id(raw) {
+            Some(media_id) => format!("p/{}/", mediaid_to_code(media_id)),
+            None => String::new(),
+        },
+        ["stories", username, story_id] => format!("stories/{username}/{story_id}/"),
+        ["explore", "tags", tag] => format!("explore/tags/{tag}/"),
+        ["grid" | "latest", username] | [username] => format!("{username}/"),
+        _ => String::new(),
+    };
+    format!("https://www.instagram.com/{page}")
+}
+
 /// Extracts the post ID (shortcode) from an Instagram URL path.
 ///
 /// Handles paths like `/p/ABC123/`, `/reel/ABC123/`, `/tv/ABC123/`,
@@ -92,6 +227,37 @@ pub fn extract_post_id(path: &str) -> Option<String> {
 mod tests {
     use super::*;
 
+    // --- parse_hashtag ---
+
+    #[test]
+    fn hashtags_are_decoded_and_lowercased() {
+        assert_eq!(parse_hashtag("CatsOfInstagram").as_deref(), Some("catsofinstagram"));
+        assert_eq!(parse_hashtag("%23caf%C3%A9").as_deref(), Some("café"));
+        assert_eq!(parse_hashtag("ねこ").as_deref(), Some("ねこ"));
+        assert!(parse_hashtag("").is_none());
+        assert!(parse_hashtag("cats&dogs").is_none());
+        assert!(parse_hashtag("two%20words").is_none());
+    }
+
+    // --- instagram_url ---
+
+    #[test]
+    fn routes_map_to_the_instagram_page_they_stand_for() {
+        let ig = |path| instagram_url(path).replace("https://www.instagram.com", "");
+        assert_eq!(ig("/p/ABC123"), "/p/ABC123/");
+        assert_eq!(ig("/reel/ABC123"), "/p/ABC123/");
+        assert_eq!(ig("/someuser/p/ABC123"), "/p/ABC123/");
+        assert_eq!(ig("/p/ABC123/c/17900000000000002"), "/p/ABC123/c/17900000000000002/");
+        assert_eq!(ig("/images/ABC123/2"), "/p/ABC123/");
+        assert_eq!(ig("/api/v2/posts/ABC123"), "/p/ABC123/");
+        assert_eq!(ig("/media/2481276043892498677"), "/p/CJvQ2ph5iD1/");
+        assert_eq!(ig("/stories/someuser/3141592653589793238"), "/stories/someuser/3141592653589793238/");
+        assert_eq!(ig("/explore/tags/cats"), "/explore/tags/cats/");
+        assert_eq!(ig("/grid/someuser"), "/someuser/");
+        assert_eq!(ig("/someuser"), "/someuser/");
+        assert_eq!(ig("/"), "/");
+    }
+
     // --- mediaid_to_code ---
 
     #[test]
@@ -111,6 +277,31 @@ mod tests {
         assert_eq!(mediaid_to_code(64), "BA");
     }
 
+    // --- is_shortcode ---
+
+    #[test]
+    fn shortcodes_use_the_base64_alphabet() {
+        assert!(is_shortcode("CJvQ2ph5iD1"));
+        assert!(is_shortcode("C-_x"));
+        assert!(!is_shortcode(""));
+        assert!(!is_shortcode("../admin"));
+        assert!(!is_shortcode(&"A".repeat(65)));
+    }
+
+    // --- parse_media_id ---
+
+    #[test]
+    fn parses_bare_and_owner_suffixed_media_ids() {
+        assert_eq!(parse_media_id("2481276043892498677"), Some(2481276043892498677));
+        assert_eq!(parse_media_id("2481276043892498677_1234567"), Some(2481276043892498677));
+        assert_eq!(parse_media_id("CJvQ2ph5iD1"), None);
+        assert_eq!(parse_media_id("_123"), None);
+        assert_eq!(parse_media_id("2481276043892498677_"), None);
+        assert_eq!(parse_media_id("2481276043892498677_abc"), None);
+        assert_eq!(parse_media_id("2481276043892498677_12_3"), None);
+        assert_eq!(parse_media_id("99999999999999999999"), None);
+    }
+
     // --- normalize_cdn_url ---
 
     #[test]
@@ -146,6 +337,72 @@ mod tests {
         assert_eq!(normalize_cdn_url(input), "https://cdn.example.com/image.jpg");
     }
 
+    #[test]
+    fn cdn_url_keeps_signed_params() {
+        let input = "https://scontent-lhr8-1.cdninstagram.com/o1/v/t16/f2/m86/clip.mp4?efg=eyJ2ZW5j&_nc_ht=scontent-lhr8-1.cdninstagram.com&_nc_cat=104&vs=5f1b&_nc_oc=Adn&_nc_gid=xyz&utm_source=ig_web&igsh=MWQ&oh=00_AYB&oe=66F1";
+        assert_eq!(
+            normalize_cdn_url(input),
+            "https://scontent-lhr8-1.cdninstagram.com/o1/v/t16/f2/m86/clip.mp4?_nc_ht=scontent-lhr8-1.cdninstagram.com&_nc_cat=104&vs=5f1b&_nc_oc=Adn&oh=00_AYB&oe=66F1"
+        );
+    }
+
+    #[test]
+    fn media_url_only_normalizes_cdn_hosts() {
+        assert_eq!(
+            normalize_media_url("https://scontent.cdninstagram.com/v/image.jpg?stp=dst-jpg&igsh=abc"),
+            "https://scontent.cdninstagram.com/v/image.jpg?stp=dst-jpg"
+        );
+        assert_eq!(
+            normalize_media_url("https://example.com/image.jpg?igsh=abc"),
+            "https://example.com/image.jpg?igsh=abc"
+        );
+        assert_eq!(normalize_media_url(""), "");
+    }
+
+    // --- cdn_url_expiry ---
+
+    #[test]
+    fn cdn_url_expiry_is_hex_oe() {
+        let url = "https://scontent.cdninstagram.com/v/t51/1.jpg?stp=dst-jpg&oh=00_AYB&oe=66F1A2B3";
+        assert_eq!(cdn_url_expiry(url), Some(0x66F1A2B3));
+        assert!(cdn_url_expired(url, 0x66F1A2B3));
+        assert!(cdn_url_expired(url, 0x66F1A2B3 - 30));
+        assert!(!cdn_url_expired(url, 0x66F1A2B3 - 3600));
+    }
+
+    #[test]
+    fn cdn_url_without_expiry_never_expires() {
+        assert_eq!(cdn_url_expiry("https://scontent.cdninstagram.com/v/t51/1.jpg"), None);
+        assert!(!cdn_url_expired("https://scontent.cdninstagram.com/v/t51/1.jpg?oe=zzz", u64::MAX - 100));
+        assert!(!cdn_url_expired("", 0));
+    }
+
+    // --- share_params / share_url ---
+
+    #[test]
+    fn share_params_keep_only_share_identifiers() {
+        let url = Url::parse("https://example.com/p/share/BAxyz?direct=true&igsh=MWQ1ZGUx&img_index=2&igshid=&utm_source=ig").unwrap();
+        assert_eq!(share_params(&url), [("igsh".to_string(), "MWQ1ZGUx".to_string())]);
+    }
+
+    #[test]
+    fn share_url_carries_params() {
+        let params = [("igsh".to_string(), "MWQ1ZGUx".to_string())];
+        assert_eq!(share_url("share/BAxyz", &params), "https://www.instagram.com/share/BAxyz?igsh=MWQ1ZGUx");
+        assert_eq!(share_url("share/reel/BAxyz", &[]), "https://www.instagram.com/share/reel/BAxyz");
+    }
+
+    // --- is_cdn_url ---
+
+    #[test]
+    fn cdn_url_requires_instagram_host_over_https() {
+        assert!(is_cdn_url("https://scontent.cdninstagram.com/v/t51/avatar.jpg"));
+        assert!(is_cdn_url("https://scontent-lhr8-1.xx.fbcdn.net/v/t51/avatar.jpg"));
+        assert!(!is_cdn_url("http://scontent.cdninstagram.com/v/t51/avatar.jpg"));
+        assert!(!is_cdn_url("https://evil.com/cdninstagram.com.jpg"));
+        assert!(!is_cdn_url("not a url"));
+    }
+
     // --- extract_post_id ---
 
     #[test]
@@ -0,0 +1,21 @@
+/// Logs a formatted message to the Workers console.
+///
+/// Off-wasm (i.e. under `cargo test`) the console import isn't available and
+/// would panic, so this falls back to stderr. Import it explicitly
+/// (`use crate::utils::log::console_log;`) to take precedence over the
+/// `worker::*` glob export.
+#[cfg(target_arch = "wasm32")]
+macro_rules! console_log {
+    ($($t:tt)*) => {
+        worker::console_log!($($t)*)
+    };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! console_log {
+    ($($t:tt)*) => {
+        eprintln!($($t)*)
+    };
+}
+
+pub(crate) use console_log;
@@ -0,0 +1,87 @@
+/// Media file extensions we accept on `/images` and `/videos` routes, with
+/// their MIME types.
+const EXTENSIONS: [(&str, &str); 7] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("gif", "image/gif"),
+    ("mp4", "video/mp4"),
+];
+
+/// Returns the MIME type for a file extension (case-insensitive).
+pub fn from_extension(ext: &str) -> Option<&'static str> {
+    EXTENSIONS
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map(|(_, mime)| *mime)
+}
+
+/// Returns the canonical file extension for a MIME type.
+pub fn extension_for(mime: &str) -> Option<&'static str> {
+    // Ignore parameters like `; charset=...`
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    EXTENSIONS
+        .iter()
+        .find(|(_, m)| m.eq_ignore_ascii_case(mime))
+        .map(|(ext, _)| *ext)
+}
+
+/// Detects the media type from a file's leading magic bytes.
+///
+/// CDNs and transforms don't always send an accurate `Content-Type`, and some
+/// clients refuse to render media without one.
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        // ISO-BMFF: `....ftyp<brand>`
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.len() >= 4 => match &brand[..4] {
+            b"avif" | b"avis" => Some("image/avif"),
+            _ => Some("video/mp4"),
+        },
+        _ => None,
+    }
+}
+
+/// Splits a trailing extension off a path segment: `"2.jpg"` -> `("2", Some("jpg"))`.
+pub fn split_extension(segment: &str) -> (&str, Option<&str>) {
+    match segment.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => (stem, Some(ext)),
+        _ => (segment, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_extensions_both_ways() {
+        assert_eq!(from_extension("JPG"), Some("image/jpeg"));
+        assert_eq!(from_extension("mp4"), Some("video/mp4"));
+        assert_eq!(from_extension("exe"), None);
+        assert_eq!(extension_for("image/webp"), Some("webp"));
+        assert_eq!(extension_for("video/mp4; codecs=avc1"), Some("mp4"));
+    }
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        assert_eq!(sniff(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"\0\0\0\x1cftypavif"), Some("image/avif"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypmp42"), Some("video/mp4"));
+        assert_eq!(sniff(b"<html>"), None);
+    }
+
+    #[test]
+    fn splits_trailing_extension() {
+        assert_eq!(split_extension("2.jpg"), ("2", Some("jpg")));
+        assert_eq!(split_extension("2"), ("2", None));
+        assert_eq!(split_extension(".jpg"), (".jpg", None));
+    }
+}
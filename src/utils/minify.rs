@@ -0,0 +1,105 @@
+/// Strips redundant whitespace from rendered HTML.
+///
+/// Whitespace-only runs between tags are dropped, and other whitespace runs in
+/// text content collapse to a single space. Tag markup (including attribute
+/// values such as captions in `og:description`) and `<pre>` blocks are left
+/// untouched.
+pub fn minify_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pending_space = false;
+    let mut in_tag = false;
+    let mut quote: Option<char> = None;
+    let mut pre_depth: usize = 0;
+    for (i, c) in html.char_indices() {
+        if in_tag {
+            out.push(c);
+            match (quote, c) {
+                (Some(q), _) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => in_tag = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        if c == '<' {
+            // Whitespace directly before a tag is insignificant between tags
+            // but must survive between a word and an inline tag.
+            if pending_space && !out.ends_with('>') {
+                out.push(' ');
+            }
+            pending_space = false;
+
+            let rest = &html[i..];
+            if starts_with_tag(rest, "<pre") {
+                pre_depth += 1;
+            } else if starts_with_tag(rest, "</pre") {
+                pre_depth = pre_depth.saturating_sub(1);
+            }
+
+            in_tag = true;
+            out.push(c);
+            continue;
+        }
+
+        if pre_depth == 0 && c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+
+        if pending_space {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            pending_space = false;
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// Case-insensitively checks whether `s` opens with the given tag name.
+fn starts_with_tag(s: &str, tag: &str) -> bool {
+    s.len() > tag.len()
+        && s[..tag.len()].eq_ignore_ascii_case(tag)
+        && matches!(s.as_bytes()[tag.len()], b'>' | b' ' | b'\t' | b'\n' | b'/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_whitespace_between_tags() {
+        assert_eq!(
+            minify_html("<html>\n  <head>\n    <title>x</title>\n  </head>\n</html>"),
+            "<html><head><title>x</title></head></html>"
+        );
+    }
+
+    #[test]
+    fn collapses_whitespace_in_text() {
+        assert_eq!(minify_html("<p>a   b\n\tc</p>"), "<p>a b c</p>");
+    }
+
+    #[test]
+    fn keeps_space_before_inline_tag() {
+        assert_eq!(minify_html("<p>Replace \n <code>x</code></p>"), "<p>Replace <code>x</code></p>");
+    }
+
+    #[test]
+    fn preserves_attribute_values() {
+        let html = "<meta content=\"line1\n\nline2  >  x\">";
+        assert_eq!(minify_html(html), html);
+    }
+
+    #[test]
+    fn preserves_pre_blocks() {
+        assert_eq!(
+            minify_html("<pre>  a\n  b</pre>\n<p> c </p>"),
+            "<pre>  a\n  b</pre><p> c </p>"
+        );
+    }
+}
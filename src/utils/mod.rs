@@ -1,3 +1,14 @@
+pub mod base64;
 pub mod bot_detect;
-pub mod escape;
+pub mod buffer;
+pub mod cors;
+pub mod http;
 pub mod instagram;
+pub mod log;
+pub mod mime;
+pub mod minify;
+pub mod mosaic;
+pub mod path;
+pub mod qr;
+pub mod random;
+pub mod transform;
@@ -0,0 +1,133 @@
+use serde_json::{json, Value};
+
+/// Side length of one mosaic tile, in pixels.
+pub const TILE_SIZE: u32 = 400;
+
+/// Fewest images worth composing; below this the mosaic is skipped.
+pub const MIN_TILES: usize = 3;
+
+/// Most images a mosaic holds (a 3×2 grid).
+pub const MAX_TILES: usize = 6;
+
+/// One image placed on the mosaic canvas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub url: String,
+    pub left: u32,
+    pub top: u32,
+}
+
+/// A grid of square tiles composed by Cloudflare Image Resizing.
+///
+/// The first image is resized to cover the whole canvas, then every tile
+/// (the first included) is drawn over it with `draw`. No image decoding
+/// happens in the worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mosaic {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Tile>,
+}
+
+impl Mosaic {
+    /// Lays out up to `MAX_TILES` images: 3 in a row, 4–5 as 2×2, 6 as 3×2.
+    /// Returns `None` for fewer than `MIN_TILES`.
+    pub fn new(urls: &[String]) -> Option<Self> {
+        let (cols, rows) = match urls.len() {
+            n if n < MIN_TILES => return None,
+            3 => (3, 1),
+            4 | 5 => (2, 2),
+            _ => (3, 2),
+        };
+
+        let tiles = urls
+            .iter()
+            .take((cols * rows) as usize)
+            .enumerate()
+            .map(|(i, url)| Tile {
+                url: url.clone(),
+                left: (i as u32 % cols) * TILE_SIZE,
+                top: (i as u32 / cols) * TILE_SIZE,
+            })
+            .collect();
+
+        Some(Self {
+            width: cols * TILE_SIZE,
+            height: rows * TILE_SIZE,
+            tiles,
+        })
+    }
+
+    /// The image fetched as the canvas.
+    pub fn base_url(&self) -> &str {
+        &self.tiles[0].url
+    }
+
+    /// The `cf` request properties that compose the mosaic.
+    ///
+    /// Built as JSON because `worker::ResizeConfig` can't express sized `draw`
+    /// overlays (or more than one).
+    pub fn cf_properties(&self) -> Value {
+        let draw: Vec<Value> = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                json!({
+                    "url": tile.url,
+                    "width": TILE_SIZE,
+                    "height": TILE_SIZE,
+                    "fit": "cover",
+                    "left": tile.left,
+                    "top": tile.top,
+                })
+            })
+            .collect();
+
+        json!({
+            "image": {
+                "width": self.width,
+                "height": self.height,
+                "fit": "cover",
+                "format": "jpeg",
+                "draw": draw,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("https://cdn.example.com/{i}.jpg")).collect()
+    }
+
+    #[test]
+    fn lays_out_grids_by_count() {
+        assert!(Mosaic::new(&urls(2)).is_none());
+
+        let row = Mosaic::new(&urls(3)).unwrap();
+        assert_eq!((row.width, row.height), (1200, 400));
+
+        let square = Mosaic::new(&urls(5)).unwrap();
+        assert_eq!((square.width, square.height), (800, 800));
+        assert_eq!(square.tiles.len(), 4);
+        assert_eq!((square.tiles[3].left, square.tiles[3].top), (400, 400));
+
+        let full = Mosaic::new(&urls(9)).unwrap();
+        assert_eq!((full.width, full.height), (1200, 800));
+        assert_eq!(full.tiles.len(), 6);
+    }
+
+    #[test]
+    fn cf_properties_draw_every_tile() {
+        let mosaic = Mosaic::new(&urls(3)).unwrap();
+        let cf = mosaic.cf_properties();
+        assert_eq!(mosaic.base_url(), "https://cdn.example.com/1.jpg");
+        assert_eq!(cf["image"]["width"], 1200);
+        assert_eq!(cf["image"]["draw"].as_array().unwrap().len(), 3);
+        assert_eq!(cf["image"]["draw"][2]["left"], 800);
+        assert_eq!(cf["image"]["draw"][2]["url"], "https://cdn.example.com/3.jpg");
+    }
+}
@@ -0,0 +1,140 @@
+use url::Url;
+use worker::Method;
+
+/// Route keywords matched case-insensitively, so `/P/ABC123` routes like `/p/ABC123`.
+const ROUTE_KEYWORDS: [&str; 20] = [
+    "p",
+    "reel",
+    "reels",
+    "tv",
+    "stories",
+    "gif",
+    "media",
+    "images",
+    "videos",
+    "links",
+    "view",
+    "grid",
+    "oembed",
+    "s",
+    "qr",
+    "screenshot",
+    "latest",
+    "rss",
+    "explore",
+    "tags",
+];
+
+/// Normalizes a request path before routing.
+///
+/// Collapses repeated slashes, strips the trailing slash (except for the root),
+/// and lowercases the route keyword the path leads with: the first segment, or
+/// the `p` of `/:username/p/:postID`, plus the `tags` of `/explore/tags/:tag`.
+/// Every other segment is left untouched, since usernames and shortcodes can
+/// spell a keyword too and shortcodes are case-sensitive.
+pub fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let keyword_at = match segments.first().map(|s| s.to_ascii_lowercase()) {
+        Some(first) if ROUTE_KEYWORDS.contains(&first.as_str()) => Some(0),
+        Some(_) if segments.len() > 2 && segments[1].eq_ignore_ascii_case("p") => Some(1),
+        _ => None,
+    };
+    if let Some(i) = keyword_at {
+        segments[i].make_ascii_lowercase();
+        if segments[i] == "explore" && segments.get(i + 1).is_some_and(|s| s.eq_ignore_ascii_case("tags")) {
+            segments[i + 1].make_ascii_lowercase();
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+/// A request moved onto its normalized path. The router only sees the new
+/// request, so it carries the original's body as well as its method: the
+/// Turnstile form and short link minting are POSTs that read theirs.
+#[derive(Debug, PartialEq)]
+pub struct Rerouted<B> {
+    pub url: Url,
+    pub method: Method,
+    pub body: Option<B>,
+}
+
+/// Where to route a request to `url` instead, if its path isn't normalized.
+pub fn reroute<B>(mut url: Url, method: Method, body: Option<B>) -> Option<Rerouted<B>> {
+    let normalized = normalize_path(url.path());
+    if normalized == url.path() {
+        return None;
+    }
+    url.set_path(&normalized);
+    Some(Rerouted { url, method, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_is_unchanged() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path(""), "/");
+    }
+
+    #[test]
+    fn strips_trailing_slash() {
+        assert_eq!(normalize_path("/p/ABC123/"), "/p/ABC123");
+    }
+
+    #[test]
+    fn collapses_double_slashes() {
+        assert_eq!(normalize_path("//p//ABC123//"), "/p/ABC123");
+    }
+
+    #[test]
+    fn lowercases_route_keywords_only() {
+        assert_eq!(normalize_path("/P/AbC123"), "/p/AbC123");
+        assert_eq!(normalize_path("/Reel/XyZ"), "/reel/XyZ");
+        assert_eq!(normalize_path("/SomeUser/P/AbC"), "/SomeUser/p/AbC");
+    }
+
+    #[test]
+    fn keywords_past_the_leading_one_are_left_alone() {
+        assert_eq!(normalize_path("/P/Reels"), "/p/Reels");
+        assert_eq!(normalize_path("/stories/Media/123"), "/stories/Media/123");
+        assert_eq!(normalize_path("/Explore/Tags/Gif"), "/explore/tags/Gif");
+        assert_eq!(normalize_path("/Links"), "/links");
+        assert_eq!(normalize_path("/Latest"), "/latest");
+    }
+
+    #[test]
+    fn already_normal_path_is_unchanged() {
+        assert_eq!(normalize_path("/stories/user/123"), "/stories/user/123");
+    }
+
+    fn reroute_str(url: &str, method: Method, body: Option<&str>) -> Option<Rerouted<String>> {
+        reroute(Url::parse(url).unwrap(), method, body.map(str::to_string))
+    }
+
+    #[test]
+    fn normal_paths_are_not_rerouted() {
+        assert_eq!(reroute_str("https://cattgram.com/p/ABC123?img_index=2", Method::Get, None), None);
+    }
+
+    #[test]
+    fn rerouted_posts_keep_their_body() {
+        let body = r#"{"post_id":"CxAbc","img_index":2}"#;
+        let rerouted = reroute_str("https://cattgram.com/api/shortlinks/", Method::Post, Some(body)).unwrap();
+        assert_eq!(rerouted.url.as_str(), "https://cattgram.com/api/shortlinks");
+        assert_eq!(rerouted.method, Method::Post);
+        assert_eq!(rerouted.body.as_deref(), Some(body));
+
+        let form = "cf-turnstile-response=token";
+        let rerouted = reroute_str("https://cattgram.com//LINKS/CxAbc/?download=1", Method::Post, Some(form)).unwrap();
+        assert_eq!(rerouted.url.as_str(), "https://cattgram.com/links/CxAbc?download=1");
+        assert_eq!(rerouted.body.as_deref(), Some(form));
+    }
+}
@@ -0,0 +1,576 @@
+//! A small QR code encoder, for `/qr/:postID`.
+//!
+//! Byte mode only, error correction level M, versions 1 to 10 (up to 213
+//! bytes), which covers any post or embed URL. Renders to SVG, or to a
+//! 1-bit PNG with uncompressed deflate blocks, so no image or compression
+//! crate is pulled into the bundle.
+
+/// Largest version encoded. Versions 1-9 share the 8-bit length field.
+const MAX_VERSION: usize = 10;
+
+/// Data codewords per version (index 0 unused) at level M.
+const DATA_CODEWORDS: [usize; MAX_VERSION + 1] = [0, 16, 28, 44, 64, 86, 108, 124, 154, 182, 216];
+
+/// Error correction codewords per block at level M.
+const ECC_PER_BLOCK: [usize; MAX_VERSION + 1] = [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+
+/// Error correction blocks at level M.
+const BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+
+/// Level M in the format information.
+const LEVEL_M_BITS: u32 = 0;
+
+/// Light modules around the symbol, as the spec asks for.
+pub const QUIET_ZONE: usize = 4;
+
+/// An encoded QR code: a square of dark and light modules.
+#[derive(Debug, Clone)]
+pub struct QrCode {
+    version: usize,
+    /// Modules per side, without the quiet zone.
+    size: usize,
+    modules: Vec<bool>,
+    /// Finder, timing, alignment and format modules, which masks skip.
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encodes `data`, or returns `None` if it doesn't fit in version 10.
+    pub fn encode(data: &[u8]) -> Option<QrCode> {
+        let version = (1..=MAX_VERSION).find(|&v| 4 + count_bits(v) + data.len() * 8 <= DATA_CODEWORDS[v] * 8)?;
+        let size = version * 4 + 17;
+        let mut qr = QrCode { version, size, modules: vec![false; size * size], function: vec![false; size * size] };
+        qr.draw_function_patterns();
+        qr.draw_codewords(&add_ecc(version, &data_codewords(version, data)));
+
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                qr.apply_mask(mask);
+                qr.draw_format_bits(mask);
+                let penalty = qr.penalty();
+                qr.apply_mask(mask);
+                penalty
+            })
+            .unwrap_or(0);
+        qr.apply_mask(mask);
+        qr.draw_format_bits(mask);
+        Some(qr)
+    }
+
+    /// Whether the module at column `x`, row `y` is dark.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// An SVG with one unit per module, scaled by its viewer.
+    pub fn to_svg(&self) -> String {
+        let side = self.size + 2 * QUIET_ZONE;
+        let mut path = String::new();
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if self.is_dark(x, y) {
+                    path.push_str(&format!("M{},{}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE));
+                }
+            }
+        }
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {side} {side}" shape-rendering="crispEdges"><rect width="100%" height="100%" fill="#fff"/><path d="{path}" fill="#000"/></svg>"##
+        )
+    }
+
+    /// A black and white PNG, `scale` pixels per module.
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let side = (self.size + 2 * QUIET_ZONE) * scale;
+        let row_bytes = side.div_ceil(8);
+        let mut pixels = Vec::with_capacity((row_bytes + 1) * side);
+        for py in 0..side {
+            pixels.push(0); // filter: none
+            let mut row = vec![0u8; row_bytes];
+            for px in 0..side {
+                let (x, y) = ((px / scale).checked_sub(QUIET_ZONE), (py / scale).checked_sub(QUIET_ZONE));
+                let dark = match (x, y) {
+                    (Some(x), Some(y)) if x < self.size && y < self.size => self.is_dark(x, y),
+                    _ => false,
+                };
+                // In 1-bit grayscale, 1 is white
+                if !dark {
+                    row[px / 8] |= 0x80 >> (px % 8);
+                }
+            }
+            pixels.extend_from_slice(&row);
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(side as u32).to_be_bytes());
+        header.extend_from_slice(&(side as u32).to_be_bytes());
+        header.extend_from_slice(&[1, 0, 0, 0, 0]); // 1-bit grayscale, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        let i = y * self.size + x;
+        self.modules[i] = dark;
+        self.function[i] = true;
+    }
+
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        self.draw_finder(3, 3);
+        self.draw_finder(size - 4, 3);
+        self.draw_finder(3, size - 4);
+
+        let positions = alignment_positions(self.version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // The three corners are taken by finders
+                let finder = (i == 0 && (j == 0 || j == last)) || (i == last && j == 0);
+                if !finder {
+                    self.draw_alignment(x, y);
+                }
+            }
+        }
+
+        // Reserves the format modules; the real bits are drawn once the mask is chosen
+        self.draw_format_bits(0);
+        self.draw_version();
+    }
+
+    /// A finder pattern centred on (`x`, `y`), with its light separator.
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4..=4_isize {
+            for dx in -4..=4_isize {
+                let (xx, yy) = (x as isize + dx, y as isize + dy);
+                if (0..self.size as isize).contains(&xx) && (0..self.size as isize).contains(&yy) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set_function(xx as usize, yy as usize, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2..=2_isize {
+            for dx in -2..=2_isize {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                self.set_function((x as isize + dx) as usize, (y as isize + dy) as usize, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        // Around the top left finder
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        // Split between the other two finders
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Version information, from version 7 up.
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let bits = version_bits(self.version);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Places codewords in the two-column zigzag, skipping function modules.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let total_bits = codewords.len() * 8;
+        let mut i = 0;
+        let mut right = size as isize - 1;
+        while right >= 1 {
+            if right == 6 {
+                // The vertical timing pattern
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right as usize - j;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.function[y * size + x] && i < total_bits {
+                        self.modules[y * size + x] = (codewords[i / 8] >> (7 - i % 8)) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// XORs a mask onto the data modules. Applying it twice undoes it.
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let i = y * self.size + x;
+                if flip && !self.function[i] {
+                    self.modules[i] = !self.modules[i];
+                }
+            }
+        }
+    }
+
+    /// The spec's penalty score for the current mask. Lower scans better.
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let rows = (0..size).map(|y| (0..size).map(|x| self.is_dark(x, y)).collect::<Vec<_>>());
+        let cols = (0..size).map(|x| (0..size).map(|y| self.is_dark(x, y)).collect::<Vec<_>>());
+        let mut penalty: usize = rows.chain(cols).map(|line| line_penalty(&line)).sum();
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.is_dark(x, y);
+                if dark == self.is_dark(x + 1, y) && dark == self.is_dark(x, y + 1) && dark == self.is_dark(x + 1, y + 1) {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let total = self.modules.len();
+        // Every 5% away from half dark costs 10
+        penalty + (dark * 20).abs_diff(total * 10) / total * 10
+    }
+}
+
+/// Penalties for one row or column: long runs, and finder-like patterns.
+fn line_penalty(line: &[bool]) -> usize {
+    let mut penalty = 0;
+    let mut run = 1;
+    for i in 1..=line.len() {
+        if i < line.len() && line[i] == line[i - 1] {
+            run += 1;
+        } else {
+            if run >= 5 {
+                penalty += run - 2;
+            }
+            run = 1;
+        }
+    }
+
+    const FINDER_LIKE: [bool; 11] = [true, false, true, true, true, false, true, false, false, false, false];
+    for window in line.windows(11) {
+        if window == FINDER_LIKE || window.iter().rev().eq(FINDER_LIKE.iter()) {
+            penalty += 40;
+        }
+    }
+    penalty
+}
+
+/// Bits in the byte mode character count.
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+/// Mode, length and data, terminated and padded to the version's capacity.
+fn data_codewords(version: usize, data: &[u8]) -> Vec<u8> {
+    let capacity = DATA_CODEWORDS[version] * 8;
+    let mut bits = Vec::with_capacity(capacity);
+    let mut push = |value: usize, len: usize| {
+        for i in (0..len).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &byte in data {
+        push(byte as usize, 8);
+    }
+    let terminator = (capacity - bits.len()).min(4);
+    bits.resize(bits.len() + terminator, false);
+    bits.resize(bits.len().div_ceil(8) * 8, false);
+
+    let mut codewords: Vec<u8> = bits.chunks(8).map(|byte| byte.iter().fold(0, |acc, &b| acc << 1 | b as u8)).collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if codewords.len() >= DATA_CODEWORDS[version] {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+/// Splits data into blocks, appends each block's error correction and
+/// interleaves them.
+fn add_ecc(version: usize, data: &[u8]) -> Vec<u8> {
+    let blocks = BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw = DATA_CODEWORDS[version] + blocks * ecc_len;
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks;
+    let divisor = rs_divisor(ecc_len);
+
+    let mut split = Vec::with_capacity(blocks);
+    let mut start = 0;
+    for i in 0..blocks {
+        let len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let block = &data[start..start + len];
+        start += len;
+        split.push((block, rs_remainder(block, &divisor)));
+    }
+
+    let mut out = Vec::with_capacity(raw);
+    for i in 0..=short_len - ecc_len {
+        for (block, _) in &split {
+            if let Some(&byte) = block.get(i) {
+                out.push(byte);
+            }
+        }
+    }
+    for i in 0..ecc_len {
+        for (_, ecc) in &split {
+            out.push(ecc[i]);
+        }
+    }
+    out
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u16 >> i) & 1) * x as u16;
+    }
+    z as u8
+}
+
+/// The Reed-Solomon generator polynomial of `degree`, leading term omitted.
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+/// Level and mask with their BCH check bits, masked as the spec requires.
+fn format_bits(mask: u32) -> u32 {
+    let data = LEVEL_M_BITS << 3 | mask;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+/// The version with its Golay check bits.
+fn version_bits(version: usize) -> u32 {
+    let mut rem = version as u32;
+    for _ in 0..12 {
+        rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+    }
+    (version as u32) << 12 | rem
+}
+
+/// Centre coordinates of alignment patterns, on both axes.
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2;
+    let mut positions = vec![6];
+    let mut pos = version * 4 + 10;
+    for _ in 0..count - 1 {
+        positions.insert(1, pos);
+        pos -= step;
+    }
+    positions
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    while let Some(chunk) = chunks.next() {
+        out.push(u8::from(chunks.peek().is_none()));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reed_solomon_matches_the_spec_example() {
+        // "HELLO WORLD" at 1-M, from the ISO 18004 worked example
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        assert_eq!(rs_remainder(&data, &rs_divisor(10)), [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+    }
+
+    #[test]
+    fn format_and_version_bits_match_the_spec_tables() {
+        assert_eq!(format_bits(0), 0b101010000010010);
+        assert_eq!(format_bits(7), 0b100101010100000);
+        assert_eq!(version_bits(7), 0b000111110010010100);
+        assert_eq!(alignment_positions(7), [6, 22, 38]);
+    }
+
+    #[test]
+    fn picks_the_smallest_version_that_fits() {
+        assert_eq!(QrCode::encode(b"https://www.instagram.com/p/ABC123/").unwrap().version, 3);
+        let qr = QrCode::encode(&[b'a'; 213]).unwrap();
+        assert_eq!((qr.version, qr.size), (10, 57));
+        assert!(QrCode::encode(&[b'a'; 214]).is_none());
+    }
+
+    /// Reads the data codewords back out, undoing the mask named by the
+    /// format bits.
+    fn read_back(qr: &QrCode) -> Vec<u8> {
+        let format = (0..6).map(|i| qr.is_dark(8, i)).chain([qr.is_dark(8, 7), qr.is_dark(8, 8), qr.is_dark(7, 8)]);
+        let format = format.chain((9..15).map(|i| qr.is_dark(14 - i, 8)));
+        let bits = format.enumerate().fold(0, |acc, (i, dark)| acc | u32::from(dark) << i);
+        let mask = (0..8).find(|&mask| format_bits(mask) == bits).expect("valid format bits");
+
+        let mut unmasked = qr.clone();
+        unmasked.apply_mask(mask);
+
+        // Where each bit lands, found by placing it alone
+        let len = DATA_CODEWORDS[qr.version] + BLOCKS[qr.version] * ECC_PER_BLOCK[qr.version];
+        let mut read = vec![0u8; len];
+        let mut probe = QrCode { modules: vec![false; qr.modules.len()], ..qr.clone() };
+        for i in 0..len * 8 {
+            let mut single = vec![0u8; len];
+            single[i / 8] = 0x80 >> (i % 8);
+            probe.modules.fill(false);
+            probe.draw_codewords(&single);
+            let pos = probe.modules.iter().position(|&m| m).unwrap();
+            if unmasked.modules[pos] {
+                read[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        read
+    }
+
+    #[test]
+    fn encodes_data_that_reads_back() {
+        let url = b"https://cattgram.com/p/ABC123/?img_index=2";
+        let qr = QrCode::encode(url).unwrap();
+        assert!(qr.is_dark(0, 0) && qr.is_dark(qr.size - 1, 0) && qr.is_dark(0, qr.size - 1));
+        assert!((8..qr.size - 8).all(|i| qr.is_dark(i, 6) == (i % 2 == 0)));
+
+        let placed = read_back(&qr);
+        assert_eq!(placed, add_ecc(qr.version, &data_codewords(qr.version, url)));
+        assert_eq!(placed[0] >> 4, 0b0100);
+    }
+
+    #[test]
+    fn renders_svg_and_png() {
+        let qr = QrCode::encode(b"https://www.instagram.com/p/ABC123/").unwrap();
+        let svg = qr.to_svg();
+        assert!(svg.starts_with("<svg") && svg.contains(r#"viewBox="0 0 37 37""#));
+
+        let png = qr.to_png(4);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 37 * 4);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert!(png.ends_with(&[0xAE, 0x42, 0x60, 0x82]));
+    }
+}
@@ -0,0 +1,17 @@
+use worker::wasm_bindgen::JsCast;
+use worker::{js_sys, Error, Result};
+
+/// A random whole percentage, 0–99, from `Math.random()`.
+pub fn random_percent() -> u8 {
+    (js_sys::Math::random() * 100.0) as u8
+}
+
+/// A random UUID from the runtime's `crypto.randomUUID()`.
+pub fn random_uuid() -> Result<String> {
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into())?;
+    let random_uuid: js_sys::Function = js_sys::Reflect::get(&crypto, &"randomUUID".into())?.dyn_into()?;
+    random_uuid
+        .call0(&crypto)?
+        .as_string()
+        .ok_or_else(|| Error::RustError("crypto.randomUUID returned a non-string".to_string()))
+}
@@ -0,0 +1,343 @@
+//! Cloudflare Media Transformations URLs and Image Resizing options.
+//!
+//! The zone serving the worker transforms `/cdn-cgi/media/<options>/<source>`
+//! on the fly, provided Media Transformations is enabled for it and the
+//! source origin (Instagram's CDN) is allowed. Image Resizing is driven
+//! through the `cf.image` options of a subrequest instead.
+
+use url::Url;
+use worker::{ResizeConfig, ResizeFit, ResizeFormat};
+
+use crate::scraper::types::{InstaData, Media, MediaType};
+
+/// Length of the animated preview clip, in seconds.
+const PREVIEW_SECONDS: u32 = 3;
+
+/// Width of the animated preview; height follows the aspect ratio.
+const PREVIEW_WIDTH: u32 = 480;
+
+/// Builds a URL for a short, silent, downscaled clip from the start of `video_url`.
+pub fn animated_preview_url(host: &str, video_url: &str) -> String {
+    format!(
+        "https://{host}/cdn-cgi/media/mode=video,time=0s,duration={PREVIEW_SECONDS}s,width={PREVIEW_WIDTH},fit=scale-down,audio=false/{video_url}"
+    )
+}
+
+/// Builds a URL for a silent copy of `video_url`, for GIF-style playback.
+pub fn muted_video_url(host: &str, video_url: &str) -> String {
+    format!("https://{host}/cdn-cgi/media/mode=video,audio=false/{video_url}")
+}
+
+/// Builds a URL for a still frame of `video_url` at `seconds`, optionally
+/// resized/re-encoded (frame mode only outputs JPEG or PNG).
+pub fn frame_url(host: &str, video_url: &str, seconds: u32, transform: Option<ImageTransform>) -> String {
+    let mut options = format!("mode=frame,time={seconds}s");
+    if let Some(transform) = transform {
+        if let Some(width) = transform.width {
+            options.push_str(&format!(",width={width}"));
+        }
+        if let Some(height) = transform.height {
+            options.push_str(&format!(",height={height}"));
+        }
+        match transform.format {
+            Some(ImageFormat::Jpeg) => options.push_str(",format=jpg"),
+            Some(ImageFormat::Png) => options.push_str(",format=png"),
+            _ => {}
+        }
+    }
+    format!("https://{host}/cdn-cgi/media/{options}/{video_url}")
+}
+
+/// Parses the `t` query parameter: whole seconds, optionally suffixed with `s`
+/// (`12`, `12s`).
+pub fn parse_timestamp(url: &Url) -> Option<u32> {
+    url.query_pairs()
+        .find(|(k, _)| k == "t")
+        .and_then(|(_, v)| v.trim_end_matches('s').parse().ok())
+}
+
+/// Longest side Telegram previews are downscaled to. Telegram silently drops
+/// previews whose `og:image` is too large (~5MB), and full-size Instagram
+/// images can get close.
+const TELEGRAM_MAX_DIMENSION: u32 = 1280;
+
+/// Largest preview image Telegram is sure to show, in bytes. Its limit is
+/// around 5MB; this leaves headroom.
+const TELEGRAM_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Returns `true` if a slide's preview image is too large for Telegram
+/// as far as its dimensions tell. Images this passes can still be too
+/// heavy; `telegram_safe_images` also takes their `Content-Length`.
+pub fn telegram_oversized(media: &Media) -> bool {
+    match (media.width, media.height) {
+        (Some(w), Some(h)) => w.max(h) > TELEGRAM_MAX_DIMENSION,
+        _ => true,
+    }
+}
+
+/// Points oversized (or unknown-size) preview images at our own `/images`
+/// route with a downscaling transform, for Telegram.
+///
+/// `sizes` holds each slide's preview image size in bytes where known (its
+/// `Content-Length`); one heavier than `TELEGRAM_MAX_BYTES` is downscaled
+/// even when its dimensions are fine. Images get a new `url`, videos a new
+/// `thumbnail_url`; known dimensions are scaled to match so
+/// `og:image:width`/`height` stay truthful.
+pub fn telegram_safe_images(data: &mut InstaData, host: &str, sizes: &[Option<u64>]) {
+    let max = TELEGRAM_MAX_DIMENSION;
+    for (i, media) in data.media.iter_mut().enumerate() {
+        let too_heavy = sizes.get(i).copied().flatten().is_some_and(|bytes| bytes > TELEGRAM_MAX_BYTES);
+        let oversized = telegram_oversized(media) || too_heavy;
+        if !oversized {
+            continue;
+        }
+
+        let resized = format!("https://{host}/images/{}/{}?w={max}&h={max}&format=jpeg", data.post_id, i + 1);
+        match media.media_type {
+            MediaType::Image => media.url = resized,
+            MediaType::Video if media.thumbnail_url.is_some() => media.thumbnail_url = Some(resized),
+            MediaType::Video => continue,
+        }
+        scale_down(media, max);
+    }
+}
+
+/// Scales known dimensions so the longest side is at most `max`, keeping
+/// the aspect ratio.
+fn scale_down(media: &mut Media, max: u32) {
+    if let (Some(w), Some(h)) = (media.width, media.height) {
+        if w.max(h) > max {
+            let scale = max as f64 / w.max(h) as f64;
+            media.width = Some((w as f64 * scale).round() as u32);
+            media.height = Some((h as f64 * scale).round() as u32);
+        }
+    }
+}
+
+/// Blur radius for sensitive previews; Image Resizing accepts 1–250.
+const SENSITIVE_BLUR: u8 = 100;
+
+/// Replaces every media item of a sensitive post with a blurred still from
+/// our own `/images` route, so no preview shows the content itself.
+///
+/// Videos become images too: their cover frame, blurred, with no playable
+/// `og:video`. Downscaled like Telegram previews, since a blurred image
+/// needs no detail.
+pub fn blur_sensitive_media(data: &mut InstaData, host: &str) {
+    let max = TELEGRAM_MAX_DIMENSION;
+    for (i, media) in data.media.iter_mut().enumerate() {
+        media.url = format!(
+            "https://{host}/images/{}/{}?blur={SENSITIVE_BLUR}&w={max}&h={max}&format=jpeg",
+            data.post_id,
+            i + 1
+        );
+        media.media_type = MediaType::Image;
+        media.thumbnail_url = None;
+        scale_down(media, max);
+    }
+}
+
+/// Largest width or height we let clients ask for.
+const MAX_IMAGE_DIMENSION: u32 = 4096;
+
+/// Output formats accepted by the `format` query parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Avif,
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    /// Parses a `format` value or file extension.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "avif" => Some(Self::Avif),
+            "webp" => Some(Self::Webp),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    fn to_resize_format(self) -> ResizeFormat {
+        match self {
+            Self::Avif => ResizeFormat::Avif,
+            Self::Webp => ResizeFormat::Webp,
+            Self::Jpeg => ResizeFormat::Jpeg,
+            Self::Png => ResizeFormat::Png,
+        }
+    }
+}
+
+/// Resize/re-encode options requested on an `/images` route
+/// (`?w=800&h=600&format=webp&blur=50`).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageTransform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<ImageFormat>,
+    /// Blur radius, 1–250.
+    pub blur: Option<u8>,
+}
+
+impl ImageTransform {
+    /// Parses the transform from query parameters. Returns `None` when no
+    /// valid option is present, in which case the raw image is served.
+    ///
+    /// Dimensions are clamped to `MAX_IMAGE_DIMENSION`; invalid values are ignored.
+    pub fn from_query(url: &Url) -> Option<Self> {
+        let dimension = |v: &str| {
+            v.parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .map(|n| n.min(MAX_IMAGE_DIMENSION))
+        };
+
+        let mut transform = Self::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "w" | "width" => transform.width = dimension(&value),
+                "h" | "height" => transform.height = dimension(&value),
+                "format" => transform.format = ImageFormat::parse(&value),
+                "blur" => transform.blur = value.parse::<u8>().ok().filter(|&b| b > 0).map(|b| b.min(250)),
+                _ => {}
+            }
+        }
+
+        (transform != Self::default()).then_some(transform)
+    }
+
+    /// Builds the `cf.image` options. `scale-down` never enlarges the source.
+    pub fn to_resize_config(self) -> ResizeConfig {
+        ResizeConfig {
+            width: self.width.map(|w| w as usize),
+            height: self.height.map(|h| h as usize),
+            format: self.format.map(ImageFormat::to_resize_format),
+            blur: self.blur,
+            fit: Some(ResizeFit::ScaleDown),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::http::fixtures::{image, post};
+
+    #[test]
+    fn preview_url_wraps_source_url() {
+        assert_eq!(
+            animated_preview_url("cattgram.com", "https://cdn.example.com/v.mp4?oh=1&oe=2"),
+            "https://cattgram.com/cdn-cgi/media/mode=video,time=0s,duration=3s,width=480,fit=scale-down,audio=false/https://cdn.example.com/v.mp4?oh=1&oe=2"
+        );
+    }
+
+    fn query(q: &str) -> Option<ImageTransform> {
+        ImageTransform::from_query(&Url::parse(&format!("https://x.test/images/A/1?{q}")).unwrap())
+    }
+
+    #[test]
+    fn image_transform_parses_size_and_format() {
+        assert_eq!(
+            query("w=800&format=WEBP"),
+            Some(ImageTransform {
+                width: Some(800),
+                height: None,
+                format: Some(ImageFormat::Webp),
+                blur: None,
+            })
+        );
+        assert_eq!(query("h=99999").unwrap().height, Some(MAX_IMAGE_DIMENSION));
+        assert_eq!(query("blur=255").unwrap().blur, Some(250));
+    }
+
+    #[test]
+    fn image_transform_ignores_invalid_options() {
+        assert_eq!(query(""), None);
+        assert_eq!(query("w=0&h=abc&format=bmp&blur=0"), None);
+        assert_eq!(query("img_index=2"), None);
+    }
+
+    #[test]
+    fn frame_url_includes_time_and_supported_options() {
+        let transform = query("w=640&format=webp");
+        assert_eq!(
+            frame_url("cattgram.com", "https://cdn.example.com/v.mp4", 12, transform),
+            "https://cattgram.com/cdn-cgi/media/mode=frame,time=12s,width=640/https://cdn.example.com/v.mp4"
+        );
+        let transform = query("format=jpg");
+        assert!(frame_url("cattgram.com", "v", 0, transform).contains("mode=frame,time=0s,format=jpg/"));
+    }
+
+    #[test]
+    fn parses_timestamp_param() {
+        let url = |q: &str| Url::parse(&format!("https://x.test/images/A/1?{q}")).unwrap();
+        assert_eq!(parse_timestamp(&url("t=12")), Some(12));
+        assert_eq!(parse_timestamp(&url("t=7s")), Some(7));
+        assert_eq!(parse_timestamp(&url("t=-1")), None);
+        assert_eq!(parse_timestamp(&url("w=100")), None);
+    }
+
+    fn media(media_type: MediaType, width: Option<u32>, height: Option<u32>) -> Media {
+        Media {
+            media_type,
+            thumbnail_url: Some("https://cdn.example.com/thumb".to_string()),
+            width,
+            height,
+            ..image("https://cdn.example.com/full")
+        }
+    }
+
+    #[test]
+    fn telegram_downscales_only_oversized_images() {
+        let mut data = post("ABC", "cat", vec![
+            media(MediaType::Image, Some(1080), Some(1080)),
+            media(MediaType::Image, Some(1440), Some(1800)),
+            media(MediaType::Video, None, None),
+        ]);
+
+        telegram_safe_images(&mut data, "cattgram.com", &[]);
+
+        assert_eq!(data.media[0].url, "https://cdn.example.com/full");
+        assert_eq!(data.media[1].url, "https://cattgram.com/images/ABC/2?w=1280&h=1280&format=jpeg");
+        assert_eq!((data.media[1].width, data.media[1].height), (Some(1024), Some(1280)));
+        assert_eq!(data.media[2].url, "https://cdn.example.com/full");
+        assert_eq!(
+            data.media[2].thumbnail_url.as_deref(),
+            Some("https://cattgram.com/images/ABC/3?w=1280&h=1280&format=jpeg")
+        );
+    }
+
+    #[test]
+    fn telegram_downscales_heavy_images_of_any_size() {
+        let mut data = post("ABC", "cat", vec![
+            media(MediaType::Image, Some(1080), Some(1080)),
+            media(MediaType::Image, Some(1080), Some(1080)),
+        ]);
+
+        telegram_safe_images(&mut data, "cattgram.com", &[Some(6 * 1024 * 1024), Some(300 * 1024)]);
+
+        assert_eq!(data.media[0].url, "https://cattgram.com/images/ABC/1?w=1280&h=1280&format=jpeg");
+        assert_eq!((data.media[0].width, data.media[0].height), (Some(1080), Some(1080)));
+        assert_eq!(data.media[1].url, "https://cdn.example.com/full");
+    }
+
+    #[test]
+    fn sensitive_media_becomes_blurred_stills() {
+        let mut data = post("ABC", "cat", vec![
+            media(MediaType::Image, Some(1080), Some(1350)),
+            media(MediaType::Video, Some(2160), Some(3840)),
+        ]);
+
+        blur_sensitive_media(&mut data, "cattgram.com");
+
+        assert_eq!(data.media[0].url, "https://cattgram.com/images/ABC/1?blur=100&w=1280&h=1280&format=jpeg");
+        assert_eq!((data.media[0].width, data.media[0].height), (Some(1024), Some(1280)));
+        assert_eq!(data.media[1].media_type, MediaType::Image);
+        assert_eq!(data.media[1].thumbnail_url, None);
+        assert_eq!((data.media[1].width, data.media[1].height), (Some(720), Some(1280)));
+    }
+}